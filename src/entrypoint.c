@@ -2,7 +2,9 @@
 // to avoid the linker removing the static library.
 
 void R_init_iani_extendr(void *dll);
+void iani_install_hooks(void);
 
 void R_init_iani(void *dll) {
+    iani_install_hooks();
     R_init_iani_extendr(dll);
 }
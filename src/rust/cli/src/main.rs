@@ -0,0 +1,322 @@
+//! `iani` on the command line: the client behind the R package, for shell
+//! pipelines and Snakemake or Nextflow rules. Data goes to standard output
+//! or the `--out` file, summaries and logs to standard error.
+
+use anyhow::Result;
+use iani_core::backend::current_backend;
+use iani_core::download::DownloadMode;
+use iani_core::export::{export_associations, FORMATS, STDOUT};
+use iani_core::refine::Refinement;
+use iani_core::terms::require_acceptance;
+use iani_core::{logging, to_json, GwasClient, GwasFilter};
+use std::collections::{BTreeMap, BTreeSet};
+use std::process::ExitCode;
+use std::str::FromStr;
+
+const USAGE: &str = "\
+Usage: iani <command> [options]
+
+Commands:
+  associations  Write associations as NDJSON, CSV or TSV
+  download      Download the summary statistics files of a study
+
+Options of associations:
+  --study ID           Study accession, e.g. GCST000123
+  --trait ID           Trait ID, e.g. EFO_0001360
+  --variant ID         Variant ID, e.g. rs12345
+  --chr N              Chromosome; --study and --trait then filter within it
+  --p-min X, --p-max X p-value bounds
+  --bp-min N, --bp-max N
+                       Base pair range, with --chr
+  --start N            Offset of the first row (default: 0)
+  --size N             Rows per page (default: 20)
+  --pages N            Pages to fetch (default: until the last)
+  --out FILE           Output file, gzipped if it ends in .gz (default: standard output)
+  --format FORMAT      ndjson, csv or tsv (default: from --out, otherwise ndjson)
+
+Options of download:
+  --study ID           Study accession (required)
+  --out-dir DIR        Directory to download into (default: .)
+  --mode MODE          overwrite, skip, resume or verify (default: overwrite)
+  --max-concurrent N   Files downloaded at once (default: 4)
+  --max-rate BYTES     Combined download rate limit in bytes per second
+  --decompress         Decompress .gz, .bgz and .zst files as they download
+  --manifest           Write manifest.json next to the downloads
+  --accept-terms       Accept the usage terms of restricted files
+
+Common options:
+  --verbose LEVEL      Log requests to standard error: error, warn, info, debug or trace
+  -h, --help           Show this help
+";
+
+const ASSOCIATION_OPTIONS: [&str; 13] = [
+    "study", "trait", "variant", "chr", "p-min", "p-max", "bp-min", "bp-max", "start", "size",
+    "pages", "out", "format",
+];
+
+const DOWNLOAD_OPTIONS: [&str; 5] = ["study", "out-dir", "mode", "max-concurrent", "max-rate"];
+
+const DOWNLOAD_SWITCHES: [&str; 4] = ["decompress", "manifest", "accept-terms", "help"];
+
+/// The options after a command: `--name value`, `--name=value` or, for
+/// switches, a bare `--name`.
+#[derive(Debug, Default)]
+struct Args {
+    values: BTreeMap<String, String>,
+    switches: BTreeSet<String>,
+}
+
+impl Args {
+    fn parse(args: &[String], options: &[&str], switches: &[&str]) -> Result<Self> {
+        let mut parsed = Args::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let name = arg
+                .strip_prefix("--")
+                .ok_or_else(|| anyhow::anyhow!("Unexpected argument {}", arg))?;
+            let (name, inline) = match name.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (name, None),
+            };
+            if switches.contains(&name) && inline.is_none() {
+                parsed.switches.insert(name.to_string());
+            } else if options.contains(&name) || name == "verbose" {
+                let value = match inline {
+                    Some(value) => value,
+                    None => args
+                        .next()
+                        .ok_or_else(|| anyhow::anyhow!("--{} needs a value", name))?
+                        .clone(),
+                };
+                if parsed.values.insert(name.to_string(), value).is_some() {
+                    return Err(anyhow::anyhow!("--{} given more than once", name));
+                }
+            } else {
+                return Err(anyhow::anyhow!("Unknown option --{}", name));
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+
+    fn has(&self, name: &str) -> bool {
+        self.switches.contains(name)
+    }
+
+    /// The value of `--name` parsed as a `T`, if given.
+    fn number<T: FromStr>(&self, name: &str) -> Result<Option<T>> {
+        self.get(name)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("Invalid --{} {}", name, value))
+            })
+            .transpose()
+    }
+}
+
+/// The export format of `--format`, or else of the `--out` file's
+/// extension, ignoring `.gz`.
+fn output_format(format: Option<&str>, out: &str) -> Result<String> {
+    if let Some(format) = format {
+        return Ok(format.to_string());
+    }
+    let name = out.strip_suffix(".gz").unwrap_or(out);
+    let extension = name.rsplit_once('.').map_or("", |(_, extension)| extension);
+    match extension.to_ascii_lowercase().as_str() {
+        "csv" => Ok("csv".to_string()),
+        "tsv" | "txt" => Ok("tsv".to_string()),
+        "parquet" => Err(anyhow::anyhow!(
+            "Parquet output is not supported; write .ndjson, .csv or .tsv instead"
+        )),
+        _ => Ok(FORMATS[0].to_string()),
+    }
+}
+
+fn associations(args: &Args) -> Result<ExitCode> {
+    let study = args.get("study").map(str::to_string);
+    let trait_id = args.get("trait").map(str::to_string);
+    // The most specific endpoint; the other IDs become filters.
+    let (entity_type, entity_id, study, trait_id) = match (args.get("variant"), args.get("chr")) {
+        (Some(_), Some(_)) => {
+            return Err(anyhow::anyhow!("--variant and --chr exclude each other"))
+        }
+        (Some(variant), None) => (Some("variant"), Some(variant.to_string()), study, trait_id),
+        (None, Some(chr)) => (Some("chromosome"), Some(chr.to_string()), study, trait_id),
+        (None, None) => match (study, trait_id) {
+            (Some(study), trait_id) => (Some("study"), Some(study), None, trait_id),
+            (None, Some(trait_id)) => (Some("trait"), Some(trait_id), None, None),
+            (None, None) => (None, None, None, None),
+        },
+    };
+
+    let p_value_range = match (args.get("p-min"), args.get("p-max")) {
+        (None, None) => None,
+        (min, max) => {
+            for (name, value) in [("p-min", min), ("p-max", max)] {
+                if let Some(value) = value {
+                    value
+                        .parse::<f64>()
+                        .map_err(|_| anyhow::anyhow!("Invalid --{} {}", name, value))?;
+                }
+            }
+            Some((
+                min.unwrap_or("0.0").to_string(),
+                max.unwrap_or("1.0").to_string(),
+            ))
+        }
+    };
+    let bp_location_range = match (args.number("bp-min")?, args.number("bp-max")?) {
+        (Some(min), Some(max)) => Some((min, max)),
+        (None, None) => None,
+        _ => return Err(anyhow::anyhow!("--bp-min and --bp-max go together")),
+    };
+    let filter = GwasFilter {
+        p_value_range,
+        bp_location_range,
+        study,
+        trait_id,
+        reveal: None,
+        start: args.number("start")?,
+        size: args.number("size")?,
+    };
+    let pages = args.number::<usize>("pages")?.filter(|pages| *pages > 0);
+    let out = args.get("out").unwrap_or(STDOUT);
+    let format = output_format(args.get("format"), out)?;
+
+    let backend = current_backend()?;
+    let summary = export_associations(
+        backend.as_ref(),
+        entity_type,
+        entity_id.as_deref(),
+        &filter,
+        pages,
+        None,
+        &Refinement::default(),
+        &format,
+        out,
+    )?;
+    eprintln!("{}", to_json(&summary)?);
+    Ok(ExitCode::SUCCESS)
+}
+
+fn download(args: &Args) -> Result<ExitCode> {
+    let study = args
+        .get("study")
+        .ok_or_else(|| anyhow::anyhow!("download needs --study"))?;
+    let output_dir = args.get("out-dir").unwrap_or(".");
+    let mode = DownloadMode::parse(args.get("mode").unwrap_or("overwrite"))?;
+    let max_concurrent = args.number("max-concurrent")?.unwrap_or(4);
+    let max_rate = args.number::<f64>("max-rate")?;
+    if let Some(rate) = max_rate.filter(|rate| rate.is_nan() || *rate <= 0.0) {
+        return Err(anyhow::anyhow!("--max-rate must be positive, got {}", rate));
+    }
+    let client = GwasClient::new()?
+        .with_bandwidth_limit(max_rate)
+        .with_decompression(args.has("decompress"));
+
+    let (urls, paths): (Vec<String>, Vec<String>) = client
+        .study_downloads(study, output_dir)?
+        .into_iter()
+        .unzip();
+    let urls = client.use_download_mirror(urls);
+    let terms = client.files_terms(&urls, &paths, max_concurrent)?;
+    require_acceptance(&terms, args.has("accept-terms"))?;
+
+    let results = client.download_queued(
+        &urls,
+        &paths,
+        max_concurrent,
+        mode,
+        args.has("manifest"),
+        |result| eprintln!("{} {}", result.status, result.path),
+    )?;
+    println!("{}", to_json(&results)?);
+    // A workflow rule should fail unless every file is there.
+    let complete = results
+        .iter()
+        .all(|result| result.status == "ok" || result.status == "skipped");
+    Ok(if complete {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+fn run(args: &[String]) -> Result<ExitCode> {
+    let (command, rest) = match args.split_first() {
+        Some((command, rest)) => (command.as_str(), rest),
+        None => ("--help", args),
+    };
+    let args = match command {
+        "associations" => Args::parse(rest, &ASSOCIATION_OPTIONS, &["help"])?,
+        "download" => Args::parse(rest, &DOWNLOAD_OPTIONS, &DOWNLOAD_SWITCHES)?,
+        "-h" | "--help" | "help" => {
+            print!("{USAGE}");
+            return Ok(ExitCode::SUCCESS);
+        }
+        _ => return Err(anyhow::anyhow!("Unknown command {}", command)),
+    };
+    if args.has("help") {
+        print!("{USAGE}");
+        return Ok(ExitCode::SUCCESS);
+    }
+    if let Some(level) = args.get("verbose") {
+        logging::set_level(level)?;
+    }
+    match command {
+        "associations" => associations(&args),
+        _ => download(&args),
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("iani: {e}");
+            eprintln!("Run iani --help for usage.");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn options_take_values_and_switches_do_not() {
+        let args = Args::parse(
+            &strings(&["--study", "GCST1", "--mode=resume", "--manifest"]),
+            &DOWNLOAD_OPTIONS,
+            &DOWNLOAD_SWITCHES,
+        )
+        .unwrap();
+        assert_eq!(args.get("study"), Some("GCST1"));
+        assert_eq!(args.get("mode"), Some("resume"));
+        assert!(args.has("manifest"));
+        assert!(!args.has("decompress"));
+
+        for bad in [&["--study"][..], &["--colour", "red"], &["GCST1"]] {
+            assert!(Args::parse(&strings(bad), &DOWNLOAD_OPTIONS, &DOWNLOAD_SWITCHES).is_err());
+        }
+    }
+
+    #[test]
+    fn formats_follow_the_output_file() {
+        assert_eq!(output_format(None, "hits.csv.gz").unwrap(), "csv");
+        assert_eq!(output_format(None, "hits.tsv").unwrap(), "tsv");
+        assert_eq!(output_format(None, STDOUT).unwrap(), "ndjson");
+        assert_eq!(output_format(Some("tsv"), "hits.csv").unwrap(), "tsv");
+        assert!(output_format(None, "hits.parquet").is_err());
+    }
+}
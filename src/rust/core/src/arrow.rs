@@ -0,0 +1,350 @@
+//! Associations as an Arrow record batch, handed over through the Arrow C
+//! data interface (<https://arrow.apache.org/docs/format/CDataInterface.html>)
+//! so R reads the columns through nanoarrow instead of parsing JSON.
+
+use crate::export::COLUMNS;
+use crate::Association;
+use anyhow::Result;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_void};
+use std::ptr;
+
+/// `struct ArrowSchema` of the C data interface.
+#[repr(C)]
+pub struct FfiSchema {
+    pub format: *const c_char,
+    pub name: *const c_char,
+    pub metadata: *const c_char,
+    pub flags: i64,
+    pub n_children: i64,
+    pub children: *mut *mut FfiSchema,
+    pub dictionary: *mut FfiSchema,
+    pub release: Option<unsafe extern "C" fn(*mut FfiSchema)>,
+    pub private_data: *mut c_void,
+}
+
+/// `struct ArrowArray` of the C data interface.
+#[repr(C)]
+pub struct FfiArray {
+    pub length: i64,
+    pub null_count: i64,
+    pub offset: i64,
+    pub n_buffers: i64,
+    pub n_children: i64,
+    pub buffers: *mut *const c_void,
+    pub children: *mut *mut FfiArray,
+    pub dictionary: *mut FfiArray,
+    pub release: Option<unsafe extern "C" fn(*mut FfiArray)>,
+    pub private_data: *mut c_void,
+}
+
+/// Field flag: the field may hold nulls.
+const NULLABLE: i64 = 2;
+
+/// A buffer kept 8-byte aligned, as consumers may read it as `i64` or
+/// `f64` in place.
+struct Buffer {
+    words: Vec<u64>,
+}
+
+impl Buffer {
+    fn new(bytes: &[u8]) -> Self {
+        let mut words = vec![0u64; (bytes.len() + 7) / 8];
+        // Safety: `words` holds at least `bytes.len()` bytes.
+        unsafe {
+            ptr::copy_nonoverlapping(bytes.as_ptr(), words.as_mut_ptr() as *mut u8, bytes.len());
+        }
+        Self { words }
+    }
+
+    fn as_ptr(&self) -> *const c_void {
+        self.words.as_ptr() as *const c_void
+    }
+}
+
+/// One column, or the record batch itself, before it is exported.
+struct ArrayData {
+    format: &'static str,
+    name: String,
+    length: usize,
+    null_count: usize,
+    /// The validity bitmap first; `None` for a buffer that is absent.
+    buffers: Vec<Option<Buffer>>,
+    children: Vec<ArrayData>,
+}
+
+/// Validity bitmap of `valid`, least significant bit first, or `None`
+/// when every value is valid.
+fn validity(valid: &[bool]) -> (Option<Buffer>, usize) {
+    let nulls = valid.iter().filter(|valid| !**valid).count();
+    if nulls == 0 {
+        return (None, 0);
+    }
+    let mut bits = vec![0u8; (valid.len() + 7) / 8];
+    for (i, _) in valid.iter().enumerate().filter(|(_, valid)| **valid) {
+        bits[i / 8] |= 1 << (i % 8);
+    }
+    (Some(Buffer::new(&bits)), nulls)
+}
+
+fn primitive<T: Copy + Default, const N: usize>(
+    name: &str,
+    format: &'static str,
+    values: &[Option<T>],
+    to_bytes: impl Fn(T) -> [u8; N],
+) -> ArrayData {
+    let valid: Vec<bool> = values.iter().map(Option::is_some).collect();
+    let (bitmap, null_count) = validity(&valid);
+    let bytes: Vec<u8> = values
+        .iter()
+        .flat_map(|value| to_bytes(value.unwrap_or_default()))
+        .collect();
+    ArrayData {
+        format,
+        name: name.to_string(),
+        length: values.len(),
+        null_count,
+        buffers: vec![bitmap, Some(Buffer::new(&bytes))],
+        children: Vec::new(),
+    }
+}
+
+fn utf8<'a>(name: &str, values: impl Iterator<Item = Option<&'a str>>) -> ArrayData {
+    let mut valid = Vec::new();
+    let mut offsets = vec![0i32];
+    let mut data = Vec::new();
+    for value in values {
+        valid.push(value.is_some());
+        data.extend_from_slice(value.unwrap_or_default().as_bytes());
+        offsets.push(data.len() as i32);
+    }
+    let (bitmap, null_count) = validity(&valid);
+    let offsets: Vec<u8> = offsets
+        .iter()
+        .flat_map(|offset| offset.to_le_bytes())
+        .collect();
+    ArrayData {
+        format: "u",
+        name: name.to_string(),
+        length: valid.len(),
+        null_count,
+        buffers: vec![
+            bitmap,
+            Some(Buffer::new(&offsets)),
+            Some(Buffer::new(&data)),
+        ],
+        children: Vec::new(),
+    }
+}
+
+/// A list of strings per row, such as the traits of an association.
+fn utf8_list(name: &str, values: &[Option<&Vec<String>>]) -> ArrayData {
+    let valid: Vec<bool> = values.iter().map(Option::is_some).collect();
+    let (bitmap, null_count) = validity(&valid);
+    let mut offsets = vec![0i32];
+    for value in values {
+        let len = value.map_or(0, Vec::len);
+        offsets.push(offsets[offsets.len() - 1] + len as i32);
+    }
+    let items = values
+        .iter()
+        .flat_map(|value| value.map(|items| items.iter()).into_iter().flatten())
+        .map(|item| Some(item.as_str()));
+    let offsets: Vec<u8> = offsets
+        .iter()
+        .flat_map(|offset| offset.to_le_bytes())
+        .collect();
+    ArrayData {
+        format: "+l",
+        name: name.to_string(),
+        length: values.len(),
+        null_count,
+        buffers: vec![bitmap, Some(Buffer::new(&offsets))],
+        children: vec![utf8("item", items)],
+    }
+}
+
+/// The record batch of `rows`: a struct array with one child per column
+/// of `COLUMNS`, or per column of `columns` (indices into `COLUMNS`) in
+/// that order, typed as the API types them.
+fn record_batch(rows: &[Association], columns: Option<&[usize]>) -> Result<ArrayData> {
+    if i32::try_from(rows.len()).is_err() {
+        return Err(anyhow::anyhow!(
+            "{} rows are too many for one record batch",
+            rows.len()
+        ));
+    }
+    let text = |name: &str, value: fn(&Association) -> &Option<String>| {
+        utf8(name, rows.iter().map(|row| value(row).as_deref()))
+    };
+    let int32 = |name: &str, value: fn(&Association) -> Option<i32>| {
+        let values: Vec<Option<i32>> = rows.iter().map(value).collect();
+        primitive(name, "i", &values, i32::to_le_bytes)
+    };
+    let float64 = |name: &str, value: fn(&Association) -> Option<f64>| {
+        let values: Vec<Option<f64>> = rows.iter().map(value).collect();
+        primitive(name, "g", &values, f64::to_le_bytes)
+    };
+    let positions: Vec<Option<i64>> = rows.iter().map(|row| row.base_pair_location).collect();
+    let traits: Vec<Option<&Vec<String>>> = rows.iter().map(|row| row.trait_ids.as_ref()).collect();
+
+    let mut children = vec![
+        text(COLUMNS[0], |row| &row.variant_id),
+        int32(COLUMNS[1], |row| row.chromosome),
+        primitive(COLUMNS[2], "l", &positions, i64::to_le_bytes),
+        text(COLUMNS[3], |row| &row.study_accession),
+        utf8_list(COLUMNS[4], &traits),
+        float64(COLUMNS[5], |row| row.p_value),
+        int32(COLUMNS[6], |row| row.code),
+        text(COLUMNS[7], |row| &row.effect_allele),
+        text(COLUMNS[8], |row| &row.other_allele),
+        float64(COLUMNS[9], |row| row.effect_allele_frequency),
+        float64(COLUMNS[10], |row| row.odds_ratio),
+        float64(COLUMNS[11], |row| row.ci_lower),
+        float64(COLUMNS[12], |row| row.ci_upper),
+        float64(COLUMNS[13], |row| row.beta),
+        float64(COLUMNS[14], |row| row.se),
+    ];
+    if let Some(columns) = columns {
+        let mut all: Vec<Option<ArrayData>> = children.into_iter().map(Some).collect();
+        children = columns.iter().filter_map(|&i| all[i].take()).collect();
+    }
+    Ok(ArrayData {
+        format: "+s",
+        name: String::new(),
+        length: rows.len(),
+        null_count: 0,
+        buffers: vec![None],
+        children,
+    })
+}
+
+/// What a schema's `private_data` owns.
+struct SchemaPrivate {
+    format: CString,
+    name: CString,
+    children: Vec<*mut FfiSchema>,
+}
+
+/// What an array's `private_data` owns.
+struct ArrayPrivate {
+    buffers: Vec<Option<Buffer>>,
+    buffer_ptrs: Vec<*const c_void>,
+    children: Vec<*mut FfiArray>,
+}
+
+unsafe extern "C" fn release_schema(schema: *mut FfiSchema) {
+    if schema.is_null() || (*schema).release.is_none() {
+        return;
+    }
+    let private = Box::from_raw((*schema).private_data as *mut SchemaPrivate);
+    for child in &private.children {
+        if let Some(release) = (**child).release {
+            release(*child);
+        }
+        drop(Box::from_raw(*child));
+    }
+    drop(private);
+    (*schema).release = None;
+}
+
+unsafe extern "C" fn release_array(array: *mut FfiArray) {
+    if array.is_null() || (*array).release.is_none() {
+        return;
+    }
+    let private = Box::from_raw((*array).private_data as *mut ArrayPrivate);
+    for child in &private.children {
+        if let Some(release) = (**child).release {
+            release(*child);
+        }
+        drop(Box::from_raw(*child));
+    }
+    drop(private);
+    (*array).release = None;
+}
+
+fn export_schema(data: &ArrayData) -> FfiSchema {
+    let mut private = Box::new(SchemaPrivate {
+        format: CString::new(data.format).unwrap_or_default(),
+        name: CString::new(data.name.as_str()).unwrap_or_default(),
+        children: data
+            .children
+            .iter()
+            .map(|child| Box::into_raw(Box::new(export_schema(child))))
+            .collect(),
+    });
+    FfiSchema {
+        format: private.format.as_ptr(),
+        name: private.name.as_ptr(),
+        metadata: ptr::null(),
+        flags: if data.format == "+s" { 0 } else { NULLABLE },
+        n_children: private.children.len() as i64,
+        children: private.children.as_mut_ptr(),
+        dictionary: ptr::null_mut(),
+        release: Some(release_schema),
+        private_data: Box::into_raw(private) as *mut c_void,
+    }
+}
+
+fn export_array(data: ArrayData) -> FfiArray {
+    let (length, null_count) = (data.length as i64, data.null_count as i64);
+    let children: Vec<*mut FfiArray> = data
+        .children
+        .into_iter()
+        .map(|child| Box::into_raw(Box::new(export_array(child))))
+        .collect();
+    let buffer_ptrs = data
+        .buffers
+        .iter()
+        .map(|buffer| buffer.as_ref().map_or(ptr::null(), Buffer::as_ptr))
+        .collect();
+    let mut private = Box::new(ArrayPrivate {
+        buffers: data.buffers,
+        buffer_ptrs,
+        children,
+    });
+    FfiArray {
+        length,
+        null_count,
+        offset: 0,
+        n_buffers: private.buffers.len() as i64,
+        n_children: private.children.len() as i64,
+        buffers: private.buffer_ptrs.as_mut_ptr(),
+        children: private.children.as_mut_ptr(),
+        dictionary: ptr::null_mut(),
+        release: Some(release_array),
+        private_data: Box::into_raw(private) as *mut c_void,
+    }
+}
+
+/// Moves `rows` as a record batch, with only `columns` when given, into
+/// the structs at `schema` and `array`, which the caller allocated and now
+/// owns, releasing them with their `release` callbacks.
+///
+/// # Safety
+///
+/// `schema` and `array` must point to writable, released (or never
+/// initialized) `ArrowSchema` and `ArrowArray` structs.
+pub unsafe fn export_rows(
+    rows: &[Association],
+    columns: Option<&[usize]>,
+    schema: *mut FfiSchema,
+    array: *mut FfiArray,
+) -> Result<()> {
+    if schema.is_null() || array.is_null() {
+        return Err(anyhow::anyhow!("Arrow schema and array must not be null"));
+    }
+    let batch = record_batch(rows, columns)?;
+    ptr::write(schema, export_schema(&batch));
+    ptr::write(array, export_array(batch));
+    Ok(())
+}
+
+/// Reads the address nanoarrow's `nanoarrow_pointer_addr_dbl()` gives for
+/// a struct it allocated.
+pub fn address<T>(address: f64) -> Result<*mut T> {
+    if !(address > 0.0 && address.fract() == 0.0 && address < 2f64.powi(53)) {
+        return Err(anyhow::anyhow!("Invalid Arrow struct address {}", address));
+    }
+    Ok(address as usize as *mut T)
+}
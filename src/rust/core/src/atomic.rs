@@ -0,0 +1,90 @@
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Suffix of files still being written.
+pub const PARTIAL_SUFFIX: &str = ".partial";
+
+/// An output file written under a `.partial` name beside its final path
+/// and renamed into place by [`AtomicFile::commit`], so readers never see
+/// half-written exports. If it is dropped without being committed, e.g.
+/// when a writer returns early with an error or panics, the partial file
+/// is removed.
+#[derive(Debug)]
+pub struct AtomicFile {
+    path: PathBuf,
+    temporary: PathBuf,
+    file: Option<fs::File>,
+    committed: bool,
+}
+
+impl AtomicFile {
+    /// Creates `<path>.partial`, and the parent directories of `path`.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)?;
+        }
+        let mut temporary = OsString::from(path.as_os_str());
+        temporary.push(PARTIAL_SUFFIX);
+        let temporary = PathBuf::from(temporary);
+        let file = fs::File::create(&temporary)?;
+        Ok(Self {
+            path,
+            temporary,
+            file: Some(file),
+            committed: false,
+        })
+    }
+
+    /// Flushes the file to disk and moves it to its final path, replacing
+    /// any file already there.
+    pub fn commit(mut self) -> io::Result<()> {
+        if let Some(file) = self.file.take() {
+            file.sync_all()?;
+        }
+        fs::rename(&self.temporary, &self.path)?;
+        self.committed = true;
+        Ok(())
+    }
+
+    /// Unwraps a buffered writer, such as the one returned by
+    /// `GzEncoder::finish` or `BgzfWriter::finish`, flushing its buffer.
+    pub fn from_buffered(writer: BufWriter<Self>) -> io::Result<Self> {
+        writer.into_inner().map_err(|e| e.into_error())
+    }
+
+    /// Commits a file reached through a buffered writer.
+    pub fn commit_buffered(writer: BufWriter<Self>) -> io::Result<()> {
+        Self::from_buffered(writer)?.commit()
+    }
+
+    fn file(&mut self) -> io::Result<&mut fs::File> {
+        self.file
+            .as_mut()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "file already committed"))
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file()?.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file()?.flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            self.file.take();
+            let _ = fs::remove_file(&self.temporary);
+        }
+    }
+}
@@ -0,0 +1,746 @@
+use crate::breaker::is_circuit_open;
+use crate::cassette::{CassetteMode, CassetteSettings};
+use crate::ensembl::{EnsemblClient, Gene};
+use crate::http::{
+    is_not_found, redact_proxy, validate_ca_file, validate_max_response_bytes, validate_proxy,
+    validate_timeout, HeaderSettings, PoolSettings, Timeouts, TlsBackend, TlsSettings,
+    DEFAULT_MAX_RESPONSE_BYTES,
+};
+use crate::interrupt::{is_interrupted, Interrupt};
+use crate::mirror::LocalMirror;
+use crate::opengwas::OpenGwasClient;
+use crate::ordered::OrderedMap;
+use crate::paging::{associations_endpoint, merge_pages};
+use crate::throttle::{API_REQUESTS, DEFAULT_MAX_REQUESTS};
+use crate::{Association, GwasClient, GwasFilter, HalResponse};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+static CONFIG: Mutex<Option<SessionConfig>> = Mutex::new(None);
+
+pub const BACKENDS: [&str; 3] = ["ebi", "opengwas", "local"];
+
+/// Layouts of the JSON returned to R, the default first.
+pub const JSON_FORMATS: [&str; 2] = ["pretty", "compact"];
+
+pub type Associations = HalResponse<OrderedMap<Association>>;
+
+/// `result`, with a 404 from a server that has no record of the queried
+/// entity turned into an empty result marked `found: false`, so that one
+/// missing entity does not abort a batch of lookups.
+pub fn missing_as_empty(result: Result<Associations>) -> Result<Associations> {
+    match result {
+        Err(e) if is_not_found(&e) => Ok(Associations::not_found()),
+        result => result,
+    }
+}
+
+/// A provider of summary statistics. Backends answer association and entity
+/// queries with the same filter semantics and return associations in the
+/// shape of the EBI API, so R code works unchanged across providers.
+pub trait SumstatsBackend: Send + Sync {
+    fn associations(
+        &self,
+        entity_type: Option<&str>,
+        entity_id: Option<&str>,
+        filter: &GwasFilter,
+    ) -> Result<Associations>;
+
+    fn entity(&self, entity_type: &str, id: Option<&str>, filter: &GwasFilter) -> Result<String>;
+
+    /// Flag that stops paged fetches early, for backends that query over
+    /// the network.
+    fn interrupt(&self) -> Option<&Interrupt> {
+        None
+    }
+
+    /// `pages` consecutive pages of `size` associations, merged in page
+    /// order. Fetched one page at a time unless a backend can do better.
+    fn association_pages(
+        &self,
+        entity_type: Option<&str>,
+        entity_id: Option<&str>,
+        filter: &GwasFilter,
+        pages: usize,
+    ) -> Result<Associations> {
+        let start = filter.start.unwrap_or(0).max(0);
+        let size = filter.size.unwrap_or(20).max(1);
+        let mut fetched = Vec::with_capacity(pages);
+        let mut interrupted = false;
+        for page in 0..pages as i32 {
+            let page_filter = GwasFilter {
+                start: Some(start + page * size),
+                size: Some(size),
+                ..filter.clone()
+            };
+            if let Some(interrupt) = self.interrupt() {
+                interrupt.check()?;
+            }
+            let associations = match self.associations(entity_type, entity_id, &page_filter) {
+                Ok(associations) => associations,
+                Err(e) if page > 0 && is_interrupted(&e) => {
+                    interrupted = true;
+                    break;
+                }
+                Err(e) => return Err(e),
+            };
+            let n = associations
+                .embedded
+                .as_ref()
+                .and_then(|embedded| embedded.get("associations"))
+                .map_or(0, OrderedMap::len);
+            fetched.push(associations);
+            if let Some(interrupt) = self.interrupt() {
+                interrupt.progress().add_pages(1, n as u64);
+            }
+            if n < size as usize {
+                break;
+            }
+            if self.interrupt().map_or(false, Interrupt::is_requested) {
+                interrupted = true;
+                break;
+            }
+        }
+        let mut merged = merge_pages(fetched, start);
+        merged.interrupted = Some(true).filter(|_| interrupted);
+        Ok(merged)
+    }
+
+    /// The request URLs `associations`, or `association_pages` when
+    /// `pages` is over 1, would send, without sending them; see `dry_run`.
+    fn association_urls(
+        &self,
+        _entity_type: Option<&str>,
+        _entity_id: Option<&str>,
+        _filter: &GwasFilter,
+        _pages: usize,
+    ) -> Result<Vec<String>> {
+        Err(anyhow::anyhow!(
+            "dry_run is only supported by the EBI backend"
+        ))
+    }
+
+    /// The request URL `entity` would send, without sending it.
+    fn entity_urls(
+        &self,
+        _entity_type: &str,
+        _id: Option<&str>,
+        _filter: &GwasFilter,
+    ) -> Result<Vec<String>> {
+        Err(anyhow::anyhow!(
+            "dry_run is only supported by the EBI backend"
+        ))
+    }
+
+    /// The body of the single request `associations` would send, as the
+    /// server sent it; see `raw`.
+    fn association_body(
+        &self,
+        _entity_type: Option<&str>,
+        _entity_id: Option<&str>,
+        _filter: &GwasFilter,
+    ) -> Result<String> {
+        Err(anyhow::anyhow!("raw is only supported by the EBI backend"))
+    }
+
+    /// The body of the request `entity` would send, as the server sent it.
+    fn entity_body(
+        &self,
+        _entity_type: &str,
+        _id: Option<&str>,
+        _filter: &GwasFilter,
+    ) -> Result<String> {
+        Err(anyhow::anyhow!("raw is only supported by the EBI backend"))
+    }
+
+    /// The association of `variant_id` in `study`, or `None` when the
+    /// study has no record of it. Only that study is asked for; records of
+    /// other studies, from servers that ignore the filter, are passed over.
+    fn variant_in_study(&self, variant_id: &str, study: &str) -> Result<Option<Association>> {
+        let filter = GwasFilter {
+            study: Some(study.to_string()),
+            ..Default::default()
+        };
+        let found = match self.associations(Some("variant"), Some(variant_id), &filter) {
+            Ok(found) => found,
+            // The EBI API answers 404 for a variant it has no records of.
+            Err(e) if is_not_found(&e) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        Ok(found
+            .embedded
+            .into_iter()
+            .flat_map(|embedded| embedded.into_values())
+            .flat_map(|associations| associations.into_values())
+            .find(|association| association.study_accession.as_deref() == Some(study)))
+    }
+
+    /// Associations in a window around a lead variant, located through a
+    /// variant lookup.
+    fn locus(&self, variant_id: &str, window_kb: f64, filter: &GwasFilter) -> Result<Associations> {
+        let lookup_filter = GwasFilter {
+            study: filter.study.clone(),
+            ..Default::default()
+        };
+        let lead = self.associations(Some("variant"), Some(variant_id), &lookup_filter)?;
+        let (chromosome, position) = lead
+            .embedded
+            .iter()
+            .flat_map(|embedded| embedded.values())
+            .flat_map(|associations| associations.values())
+            .find_map(|a| Some((a.chromosome?, a.base_pair_location?)))
+            .ok_or_else(|| anyhow::anyhow!("Could not resolve position of {}", variant_id))?;
+
+        let window = (window_kb * 1000.0).round() as i64;
+        let locus_filter = GwasFilter {
+            bp_location_range: Some(((position - window).max(0), position + window)),
+            study: filter.study.clone(),
+            reveal: filter.reveal.clone(),
+            start: filter.start,
+            size: filter.size,
+            ..Default::default()
+        };
+
+        self.associations(
+            Some("chromosome"),
+            Some(&chromosome.to_string()),
+            &locus_filter,
+        )
+    }
+
+    /// Associations across a gene body plus flanks, with coordinates from
+    /// the Ensembl REST API.
+    fn gene_associations(
+        &self,
+        symbol: &str,
+        flank_kb: f64,
+        filter: &GwasFilter,
+    ) -> Result<Associations> {
+        let gene = EnsemblClient::new().get_gene(symbol)?;
+        self.gene_window_associations(&gene, flank_kb, filter)
+    }
+
+    /// Associations across an already resolved gene body plus flanks.
+    fn gene_window_associations(
+        &self,
+        gene: &Gene,
+        flank_kb: f64,
+        filter: &GwasFilter,
+    ) -> Result<Associations> {
+        let gene_filter = GwasFilter {
+            bp_location_range: Some(gene.window(flank_kb)),
+            ..filter.clone()
+        };
+
+        self.associations(
+            Some("chromosome"),
+            Some(&gene.seq_region_name),
+            &gene_filter,
+        )
+    }
+}
+
+impl SumstatsBackend for GwasClient {
+    fn associations(
+        &self,
+        entity_type: Option<&str>,
+        entity_id: Option<&str>,
+        filter: &GwasFilter,
+    ) -> Result<Associations> {
+        self.fetch_unified_associations(entity_type, entity_id, filter)
+    }
+
+    fn entity(&self, entity_type: &str, id: Option<&str>, filter: &GwasFilter) -> Result<String> {
+        self.get_entity(entity_type, id, filter)
+    }
+
+    fn interrupt(&self) -> Option<&Interrupt> {
+        Some(&self.interrupt)
+    }
+
+    fn association_pages(
+        &self,
+        entity_type: Option<&str>,
+        entity_id: Option<&str>,
+        filter: &GwasFilter,
+        pages: usize,
+    ) -> Result<Associations> {
+        self.fetch_association_pages(entity_type, entity_id, filter, pages)
+    }
+
+    fn association_urls(
+        &self,
+        entity_type: Option<&str>,
+        entity_id: Option<&str>,
+        filter: &GwasFilter,
+        pages: usize,
+    ) -> Result<Vec<String>> {
+        let urls = self.association_request_urls(entity_type, entity_id, filter, pages)?;
+        Ok(urls.into_iter().map(String::from).collect())
+    }
+
+    fn entity_urls(
+        &self,
+        entity_type: &str,
+        id: Option<&str>,
+        filter: &GwasFilter,
+    ) -> Result<Vec<String>> {
+        Ok(vec![self.entity_url(entity_type, id, filter)?.into()])
+    }
+
+    fn association_body(
+        &self,
+        entity_type: Option<&str>,
+        entity_id: Option<&str>,
+        filter: &GwasFilter,
+    ) -> Result<String> {
+        let endpoint = associations_endpoint(entity_type, entity_id)?;
+        self.get_body(self.build_url(&endpoint, &filter.to_params())?)
+    }
+
+    fn entity_body(
+        &self,
+        entity_type: &str,
+        id: Option<&str>,
+        filter: &GwasFilter,
+    ) -> Result<String> {
+        self.get_body(self.entity_url(entity_type, id, filter)?)
+    }
+}
+
+/// One variant's record in one study, as a single row: when the study has
+/// no record, or the lookup failed, `found` is false and only the variant
+/// and study are set.
+#[derive(Debug, Serialize)]
+pub struct VariantInStudy {
+    pub found: bool,
+    /// "ok", "failed", or "unavailable" when the lookup was not sent
+    /// because the API kept failing (see `breaker`).
+    pub status: String,
+    pub error: Option<String>,
+    #[serde(flatten)]
+    pub association: Association,
+}
+
+impl VariantInStudy {
+    pub fn new(variant_id: &str, study: &str, association: Option<Association>) -> Self {
+        match association {
+            Some(association) => Self {
+                found: true,
+                status: "ok".to_string(),
+                error: None,
+                association,
+            },
+            None => Self {
+                found: false,
+                status: "ok".to_string(),
+                error: None,
+                association: Association {
+                    variant_id: Some(variant_id.to_string()),
+                    study_accession: Some(study.to_string()),
+                    ..Default::default()
+                },
+            },
+        }
+    }
+
+    pub fn failed(variant_id: &str, study: &str, error: &anyhow::Error) -> Self {
+        let status = if is_circuit_open(error) {
+            "unavailable"
+        } else {
+            "failed"
+        };
+        Self {
+            status: status.to_string(),
+            error: Some(error.to_string()),
+            ..Self::new(variant_id, study, None)
+        }
+    }
+}
+
+/// Looks up each variant in its study, one row per pair. A failed lookup
+/// is reported on its row rather than failing the batch, so that only the
+/// failures need retrying; once the circuit breaker opens, the remaining
+/// lookups fail at once as "unavailable". A user interrupt ends the batch
+/// with the rows so far.
+pub fn variants_in_studies(
+    backend: &dyn SumstatsBackend,
+    pairs: &[(String, String)],
+) -> Vec<VariantInStudy> {
+    let mut rows = Vec::with_capacity(pairs.len());
+    for (variant_id, study) in pairs {
+        if backend.interrupt().map_or(false, |i| i.is_requested()) {
+            break;
+        }
+        match backend.variant_in_study(variant_id, study) {
+            Ok(association) => rows.push(VariantInStudy::new(variant_id, study, association)),
+            Err(e) if is_interrupted(&e) => break,
+            Err(e) => rows.push(VariantInStudy::failed(variant_id, study, &e)),
+        }
+    }
+    rows
+}
+
+/// Session-wide settings changed through `gwas_configure()`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SessionConfig {
+    pub backend: String,
+    #[serde(skip_serializing)]
+    pub token: Option<String>,
+    pub mirror_dir: Option<String>,
+    /// Prefix that summary statistics download URLs are rewritten to;
+    /// chosen by measuring throughput when unset.
+    pub download_mirror: Option<String>,
+    #[serde(flatten)]
+    pub timeouts: Timeouts,
+    /// Largest API response body read, 0 for no limit.
+    #[serde(default = "default_max_response_bytes")]
+    pub max_response_bytes: u64,
+    /// Proxy URL, or "none"; the environment's proxy when unset. Shown
+    /// without its password.
+    #[serde(serialize_with = "serialize_proxy")]
+    pub proxy: Option<String>,
+    #[serde(flatten)]
+    pub pool: PoolSettings,
+    #[serde(flatten)]
+    pub tls: TlsSettings,
+    #[serde(flatten)]
+    pub headers: HeaderSettings,
+    /// Layout of the JSON returned to R: "pretty" or "compact".
+    #[serde(default = "default_json")]
+    pub json: String,
+    /// Recording or replay of API responses; see `gwas_cassette()`.
+    #[serde(default)]
+    pub cassette: CassetteSettings,
+    /// Largest number of API requests in flight at once.
+    #[serde(default = "default_max_requests")]
+    pub max_requests: usize,
+}
+
+fn serialize_proxy<S: serde::Serializer>(
+    proxy: &Option<String>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    proxy.as_deref().map(redact_proxy).serialize(serializer)
+}
+
+fn default_max_response_bytes() -> u64 {
+    DEFAULT_MAX_RESPONSE_BYTES
+}
+
+fn default_max_requests() -> usize {
+    DEFAULT_MAX_REQUESTS
+}
+
+fn default_json() -> String {
+    JSON_FORMATS[0].to_string()
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            backend: "ebi".to_string(),
+            token: None,
+            mirror_dir: None,
+            download_mirror: None,
+            timeouts: Timeouts::default(),
+            max_response_bytes: DEFAULT_MAX_RESPONSE_BYTES,
+            proxy: None,
+            pool: PoolSettings::default(),
+            tls: TlsSettings::default(),
+            headers: HeaderSettings::default(),
+            json: default_json(),
+            cassette: CassetteSettings::default(),
+            max_requests: DEFAULT_MAX_REQUESTS,
+        }
+    }
+}
+
+pub fn session_config() -> Result<SessionConfig> {
+    Ok(CONFIG
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Session configuration poisoned"))?
+        .clone()
+        .unwrap_or_default())
+}
+
+/// Updates the session configuration; `None` leaves a setting unchanged.
+/// A `download_mirror` of "auto" clears the mirror so the next download
+/// measures the mirrors again.
+pub fn configure(
+    backend: Option<String>,
+    token: Option<String>,
+    mirror_dir: Option<String>,
+    download_mirror: Option<String>,
+) -> Result<SessionConfig> {
+    let mut config = CONFIG
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Session configuration poisoned"))?;
+    let current = config.get_or_insert_with(SessionConfig::default);
+
+    if let Some(backend) = backend {
+        if !BACKENDS.contains(&backend.as_str()) {
+            return Err(anyhow::anyhow!(
+                "Unknown backend {}; expected one of {}",
+                backend,
+                BACKENDS.join(", ")
+            ));
+        }
+        current.backend = backend;
+    }
+    if token.is_some() {
+        current.token = token;
+    }
+    if mirror_dir.is_some() {
+        current.mirror_dir = mirror_dir;
+    }
+    if let Some(mirror) = download_mirror {
+        current.download_mirror = match mirror.as_str() {
+            "auto" => None,
+            _ if mirror.starts_with("http://") || mirror.starts_with("https://") => {
+                Some(mirror.trim_end_matches('/').to_string())
+            }
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "download_mirror must be \"auto\" or an http(s) URL prefix, got {}",
+                    mirror
+                ))
+            }
+        };
+    }
+
+    Ok(current.clone())
+}
+
+/// Updates the session's HTTP timeouts, in seconds; `None` leaves a
+/// timeout unchanged, 0 or infinity removes it. Clients created afterwards
+/// use them.
+pub fn configure_timeouts(
+    connect_timeout: Option<f64>,
+    read_timeout: Option<f64>,
+    timeout: Option<f64>,
+) -> Result<SessionConfig> {
+    let checked = |name, seconds: Option<f64>| {
+        seconds
+            .map(|seconds| validate_timeout(name, seconds))
+            .transpose()
+    };
+    let (connect_timeout, read_timeout, timeout) = (
+        checked("connect_timeout", connect_timeout)?,
+        checked("read_timeout", read_timeout)?,
+        checked("timeout", timeout)?,
+    );
+    let mut config = CONFIG
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Session configuration poisoned"))?;
+    let current = config.get_or_insert_with(SessionConfig::default);
+    let timeouts = &mut current.timeouts;
+    timeouts.connect_timeout = connect_timeout.or(timeouts.connect_timeout);
+    timeouts.read_timeout = read_timeout.or(timeouts.read_timeout);
+    timeouts.timeout = timeout.or(timeouts.timeout);
+    Ok(current.clone())
+}
+
+/// Updates the session's limit on API response bodies, in bytes; `None`
+/// leaves it unchanged, 0 or infinity removes it.
+pub fn configure_max_response_bytes(bytes: Option<f64>) -> Result<SessionConfig> {
+    let bytes = bytes.map(validate_max_response_bytes).transpose()?;
+    let mut config = CONFIG
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Session configuration poisoned"))?;
+    let current = config.get_or_insert_with(SessionConfig::default);
+    if let Some(bytes) = bytes {
+        current.max_response_bytes = bytes;
+    }
+    Ok(current.clone())
+}
+
+/// Updates the session's proxy; `None` leaves it unchanged, "" returns to
+/// the environment's proxy and "none" connects directly.
+pub fn configure_proxy(proxy: Option<String>) -> Result<SessionConfig> {
+    let proxy = proxy.as_deref().map(validate_proxy).transpose()?;
+    let mut config = CONFIG
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Session configuration poisoned"))?;
+    let current = config.get_or_insert_with(SessionConfig::default);
+    if let Some(proxy) = proxy {
+        current.proxy = proxy;
+    }
+    Ok(current.clone())
+}
+
+/// Updates the session's connection pool settings; `None` leaves a
+/// setting unchanged and a `pool_idle_timeout` of 0 or infinity keeps idle
+/// connections open for as long as the session lasts.
+pub fn configure_pool(
+    pool_max_idle_per_host: Option<f64>,
+    pool_idle_timeout: Option<f64>,
+    http2: Option<bool>,
+) -> Result<SessionConfig> {
+    let pool_max_idle_per_host = match pool_max_idle_per_host {
+        Some(n) if n.fract() != 0.0 || n < 0.0 || n.is_infinite() => {
+            return Err(anyhow::anyhow!(
+                "pool_max_idle_per_host must be a whole number of connections, got {}",
+                n
+            ))
+        }
+        Some(n) => Some(n as usize),
+        None => None,
+    };
+    let pool_idle_timeout = pool_idle_timeout
+        .map(|seconds| validate_timeout("pool_idle_timeout", seconds))
+        .transpose()?;
+    let mut config = CONFIG
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Session configuration poisoned"))?;
+    let current = config.get_or_insert_with(SessionConfig::default);
+    let pool = &mut current.pool;
+    pool.pool_max_idle_per_host = pool_max_idle_per_host.or(pool.pool_max_idle_per_host);
+    pool.pool_idle_timeout = pool_idle_timeout.or(pool.pool_idle_timeout);
+    pool.http2 = http2.unwrap_or(pool.http2);
+    Ok(current.clone())
+}
+
+/// Updates the session's TLS settings; `None` leaves a setting unchanged
+/// and a `ca_file` of "" removes the extra certificates.
+pub fn configure_tls(
+    ca_file: Option<String>,
+    tls_backend: Option<String>,
+) -> Result<SessionConfig> {
+    let ca_file = ca_file.as_deref().map(validate_ca_file).transpose()?;
+    let tls_backend = tls_backend
+        .as_deref()
+        .map(|name| {
+            let backend = TlsBackend::parse(name)?;
+            backend.check().map(|_| backend)
+        })
+        .transpose()?;
+    let mut config = CONFIG
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Session configuration poisoned"))?;
+    let current = config.get_or_insert_with(SessionConfig::default);
+    if let Some(ca_file) = ca_file {
+        current.tls.ca_file = ca_file;
+    }
+    if let Some(tls_backend) = tls_backend {
+        current.tls.tls_backend = tls_backend;
+    }
+    Ok(current.clone())
+}
+
+/// Updates the session's limit on API requests in flight at once, shared
+/// by every client; `None` leaves it unchanged. Takes effect for requests
+/// not yet sent, including those of calls already running.
+pub fn configure_max_requests(max_requests: Option<f64>) -> Result<SessionConfig> {
+    let max_requests = match max_requests {
+        Some(n) if n.fract() != 0.0 || !(1.0..=1024.0).contains(&n) => {
+            return Err(anyhow::anyhow!(
+                "max_requests must be a whole number from 1 to 1024, got {}",
+                n
+            ))
+        }
+        Some(n) => Some(n as usize),
+        None => None,
+    };
+    let mut config = CONFIG
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Session configuration poisoned"))?;
+    let current = config.get_or_insert_with(SessionConfig::default);
+    if let Some(max_requests) = max_requests {
+        current.max_requests = max_requests;
+        API_REQUESTS.set_limit(max_requests);
+    }
+    Ok(current.clone())
+}
+
+/// Updates the layout of the JSON returned to R; `None` leaves it
+/// unchanged.
+pub fn configure_json(json: Option<String>) -> Result<SessionConfig> {
+    if let Some(json) = &json {
+        if !JSON_FORMATS.contains(&json.as_str()) {
+            return Err(anyhow::anyhow!(
+                "Unknown JSON format {}; expected one of {}",
+                json,
+                JSON_FORMATS.join(", ")
+            ));
+        }
+    }
+    let mut config = CONFIG
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Session configuration poisoned"))?;
+    let current = config.get_or_insert_with(SessionConfig::default);
+    if let Some(json) = json {
+        current.json = json;
+    }
+    Ok(current.clone())
+}
+
+/// Updates the headers sent with every request and returns the previous
+/// ones. `None` leaves a setting unchanged; `headers` replaces all extra
+/// headers and "" restores the default User-Agent or removes the contact.
+pub fn configure_headers(
+    headers: Option<Vec<(String, String)>>,
+    user_agent: Option<String>,
+    contact: Option<String>,
+) -> Result<HeaderSettings> {
+    let mut config = CONFIG
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Session configuration poisoned"))?;
+    let current = &mut config.get_or_insert_with(SessionConfig::default).headers;
+    let previous = current.clone();
+    let non_empty = |value: String| Some(value).filter(|value| !value.is_empty());
+    if let Some(headers) = headers {
+        current.headers = headers;
+    }
+    if let Some(user_agent) = user_agent {
+        current.user_agent = non_empty(user_agent);
+    }
+    if let Some(contact) = contact {
+        current.contact = non_empty(contact);
+    }
+    Ok(previous)
+}
+
+/// Sets whether API responses are recorded to or replayed from a
+/// cassette directory, and returns the previous setting. `None` leaves a
+/// setting unchanged.
+pub fn configure_cassette(mode: Option<String>, dir: Option<String>) -> Result<CassetteSettings> {
+    let mode = mode.as_deref().map(CassetteMode::parse).transpose()?;
+    let mut config = CONFIG
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Session configuration poisoned"))?;
+    let current = &mut config.get_or_insert_with(SessionConfig::default).cassette;
+    let mut updated = current.clone();
+    if let Some(mode) = mode {
+        updated.mode = mode;
+    }
+    if dir.is_some() {
+        updated.dir = dir.filter(|dir| !dir.is_empty());
+    }
+    updated.check()?;
+    Ok(std::mem::replace(current, updated))
+}
+
+/// The backend selected for this session.
+pub fn current_backend() -> Result<Box<dyn SumstatsBackend>> {
+    let config = session_config()?;
+    match config.backend.as_str() {
+        "ebi" => Ok(Box::new(GwasClient::new()?)),
+        "opengwas" => {
+            // Fall back to the variable ieugwasr reads the OpenGWAS JWT from.
+            let token = config
+                .token
+                .or_else(|| std::env::var("OPENGWAS_JWT").ok())
+                .filter(|token| !token.is_empty());
+            Ok(Box::new(OpenGwasClient::new(token)))
+        }
+        "local" => {
+            let dir = config.mirror_dir.ok_or_else(|| {
+                anyhow::anyhow!("The local backend needs a mirror_dir; see gwas_configure()")
+            })?;
+            Ok(Box::new(LocalMirror::open(&dir)?))
+        }
+        other => Err(anyhow::anyhow!("Unknown backend {}", other)),
+    }
+}
@@ -0,0 +1,153 @@
+//! A circuit breaker for API requests. When the API is down, each request
+//! of a large batch would otherwise wait out its own timeout in turn. After
+//! a run of consecutive failures the breaker opens and requests fail at
+//! once with [`CircuitOpen`]; once the cooldown has passed, one request is
+//! let through as a probe, and its outcome closes the breaker or opens it
+//! for another cooldown.
+
+use crate::transport::{HttpResponse, HttpTransport};
+use anyhow::Result;
+use reqwest::header::HeaderMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// Consecutive failed requests that open the breaker by default.
+pub const DEFAULT_BREAKER_FAILURES: u32 = 5;
+
+/// How long an open breaker fails requests before probing, by default.
+pub const DEFAULT_BREAKER_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Error for a request refused while the breaker is open.
+#[derive(Debug, Clone)]
+pub struct CircuitOpen {
+    pub failures: u32,
+    /// Time left until a probe is let through.
+    pub retry_in: Duration,
+}
+
+impl fmt::Display for CircuitOpen {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "API unavailable after {} consecutive failed requests; not retrying for {:.0} s",
+            self.failures,
+            self.retry_in.as_secs_f64().ceil()
+        )
+    }
+}
+
+impl std::error::Error for CircuitOpen {}
+
+/// Whether `error` is a request refused by an open [`CircuitBreaker`].
+pub fn is_circuit_open(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<CircuitOpen>().is_some()
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    failures: u32,
+    /// When the breaker last opened; `None` while closed.
+    opened: Option<Instant>,
+    /// Whether a probe is in flight, during which other requests are
+    /// still refused.
+    probing: bool,
+}
+
+/// Counts consecutive failures; see the module documentation. A threshold
+/// of 0 never opens.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, BreakerState> {
+        match self.state.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// Lets a request through, or refuses it while the breaker is open
+    /// and the cooldown runs or another request is probing.
+    pub fn admit(&self) -> Result<(), CircuitOpen> {
+        let mut state = self.state();
+        let opened = match state.opened {
+            Some(opened) => opened,
+            None => return Ok(()),
+        };
+        let elapsed = opened.elapsed();
+        if elapsed >= self.cooldown && !state.probing {
+            state.probing = true;
+            return Ok(());
+        }
+        Err(CircuitOpen {
+            failures: state.failures,
+            retry_in: self.cooldown.saturating_sub(elapsed),
+        })
+    }
+
+    /// Records the outcome of an admitted request.
+    pub fn record(&self, failed: bool) {
+        let mut state = self.state();
+        state.probing = false;
+        if !failed {
+            *state = BreakerState::default();
+            return;
+        }
+        state.failures += 1;
+        if self.threshold > 0 && state.failures >= self.threshold {
+            if state.opened.is_none() {
+                tracing::warn!(
+                    failures = state.failures,
+                    "API requests keep failing; failing fast for {} s",
+                    self.cooldown.as_secs()
+                );
+            }
+            state.opened = Some(Instant::now());
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.state().opened.is_some()
+    }
+}
+
+/// Requests through `inner` under a [`CircuitBreaker`]. Requests that get
+/// no response, and server errors (5xx), count as failures; any other
+/// answer, 404 included, shows the API is up.
+#[derive(Debug)]
+pub struct BreakerTransport {
+    inner: Arc<dyn HttpTransport>,
+    breaker: CircuitBreaker,
+}
+
+impl BreakerTransport {
+    pub fn new(inner: Arc<dyn HttpTransport>, breaker: CircuitBreaker) -> Self {
+        Self { inner, breaker }
+    }
+}
+
+impl HttpTransport for BreakerTransport {
+    fn get(&self, url: Url, headers: &HeaderMap) -> Result<HttpResponse> {
+        self.breaker.admit()?;
+        let response = self.inner.get(url, headers);
+        self.breaker.record(match &response {
+            Ok(response) => response.status.is_server_error(),
+            Err(_) => true,
+        });
+        response
+    }
+}
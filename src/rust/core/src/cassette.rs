@@ -0,0 +1,269 @@
+//! Record-and-replay of API responses. In "record" mode each API response
+//! is saved to a cassette directory, one JSON file per request, with
+//! secrets removed; in "replay" mode the saved responses are served
+//! instead, with no network access, so tests and vignettes still exercise
+//! deserialization. Downloads and the clients of other services are not
+//! recorded.
+
+use crate::backend::session_config;
+use crate::checksum::Sha256;
+use crate::http::read_body;
+use crate::transport::{HttpResponse, HttpTransport};
+use anyhow::Result;
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use url::Url;
+
+/// Query parameters whose values are never written to a cassette.
+const SECRET_PARAMS: [&str; 7] = [
+    "token",
+    "key",
+    "api_key",
+    "apikey",
+    "access_token",
+    "password",
+    "secret",
+];
+
+/// Written in place of secrets.
+pub const REDACTED: &str = "REDACTED";
+
+/// Longest request path kept in a cassette file name.
+const NAME_CHARS: usize = 80;
+
+/// Whether API responses are recorded, replayed or neither.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CassetteMode {
+    /// Requests go to the network and nothing is saved.
+    #[default]
+    Off,
+    /// Requests go to the network and each response is saved.
+    Record,
+    /// Saved responses are served; unsaved requests fail.
+    Replay,
+}
+
+impl CassetteMode {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "off" => Ok(Self::Off),
+            "record" => Ok(Self::Record),
+            "replay" => Ok(Self::Replay),
+            _ => Err(anyhow::anyhow!(
+                "Cassette mode must be \"off\", \"record\" or \"replay\", got {}",
+                name
+            )),
+        }
+    }
+}
+
+/// Where API responses are recorded to or replayed from.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CassetteSettings {
+    pub mode: CassetteMode,
+    /// Directory of the recorded responses.
+    pub dir: Option<String>,
+}
+
+impl CassetteSettings {
+    /// The cassette set with `gwas_cassette()`.
+    pub fn session() -> Self {
+        session_config()
+            .map(|config| config.cassette)
+            .unwrap_or_default()
+    }
+
+    /// Fails when a mode other than "off" has no directory.
+    pub fn check(&self) -> Result<()> {
+        if self.mode != CassetteMode::Off && self.dir.is_none() {
+            return Err(anyhow::anyhow!(
+                "A cassette directory is needed to record or replay"
+            ));
+        }
+        Ok(())
+    }
+
+    /// `network` as is, or wrapped to record to or replay from the
+    /// cassette. `secrets`, such as tokens and header values, are
+    /// replaced in recorded bodies. Recording reads whole bodies, failing
+    /// past `limit` bytes (0 for no limit) as the client would.
+    pub fn transport(
+        &self,
+        network: Arc<dyn HttpTransport>,
+        secrets: Vec<String>,
+        limit: u64,
+    ) -> Result<Arc<dyn HttpTransport>> {
+        self.check()?;
+        let cassette = || Cassette::new(self.dir.as_deref().unwrap_or_default(), secrets);
+        Ok(match self.mode {
+            CassetteMode::Off => network,
+            CassetteMode::Record => Arc::new(RecordingTransport {
+                network,
+                cassette: cassette(),
+                limit,
+            }),
+            CassetteMode::Replay => Arc::new(ReplayTransport {
+                cassette: cassette(),
+            }),
+        })
+    }
+}
+
+/// One recorded request and its response.
+#[derive(Debug, Serialize, Deserialize)]
+struct Interaction {
+    request: String,
+    status: u16,
+    content_type: Option<String>,
+    /// The body as text; API responses are JSON.
+    body: String,
+}
+
+/// A directory of recorded responses, keyed by method, path and query
+/// parameters. The host is left out, so responses recorded from one
+/// server replay for another with the same paths.
+#[derive(Debug, Clone)]
+struct Cassette {
+    dir: PathBuf,
+    secrets: Vec<String>,
+}
+
+impl Cassette {
+    fn new(dir: impl AsRef<Path>, secrets: Vec<String>) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            secrets: secrets.into_iter().filter(|s| !s.is_empty()).collect(),
+        }
+    }
+
+    /// "GET path?a=1&b=2" with the parameters sorted and secret ones
+    /// redacted.
+    fn request_key(url: &Url) -> String {
+        let params: BTreeMap<String, String> = url
+            .query_pairs()
+            .map(|(name, value)| {
+                let secret = SECRET_PARAMS.contains(&name.to_ascii_lowercase().as_str());
+                let value = if secret { REDACTED.into() } else { value };
+                (name.into_owned(), value.into_owned())
+            })
+            .collect();
+        let query: Vec<String> = params.iter().map(|(k, v)| format!("{k}={v}")).collect();
+        format!("GET {}?{}", url.path(), query.join("&"))
+    }
+
+    /// The request's path made safe for a file name, then a hash of the
+    /// whole key.
+    fn path(&self, key: &str) -> PathBuf {
+        let path = key
+            .trim_start_matches("GET ")
+            .split('?')
+            .next()
+            .unwrap_or_default();
+        let name: String = path
+            .trim_matches('/')
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .take(NAME_CHARS)
+            .collect();
+        let mut hash = Sha256::new();
+        hash.update(key.as_bytes());
+        self.dir
+            .join(format!("{}-{}.json", name, &hash.finish_hex()[..12]))
+    }
+
+    fn redact(&self, text: &str) -> String {
+        self.secrets.iter().fold(text.to_string(), |text, secret| {
+            text.replace(secret, REDACTED)
+        })
+    }
+
+    fn save(&self, interaction: &Interaction) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.path(&interaction.request);
+        let json = serde_json::to_string_pretty(interaction)?;
+        fs::write(&path, json + "\n")
+            .map_err(|e| anyhow::anyhow!("Cannot write cassette {}: {}", path.display(), e))
+    }
+
+    fn load(&self, key: &str) -> Result<Option<Interaction>> {
+        let path = self.path(key);
+        match fs::read(&path) {
+            Ok(data) => Ok(Some(serde_json::from_slice(&data).map_err(|e| {
+                anyhow::anyhow!("Cannot parse cassette {}: {}", path.display(), e)
+            })?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(anyhow::anyhow!(
+                "Cannot read cassette {}: {}",
+                path.display(),
+                e
+            )),
+        }
+    }
+}
+
+fn replayed(url: Url, interaction: Interaction) -> Result<HttpResponse> {
+    let mut headers = HeaderMap::new();
+    if let Some(content_type) = &interaction.content_type {
+        headers.insert(CONTENT_TYPE, HeaderValue::from_str(content_type)?);
+    }
+    let body = interaction.body.into_bytes();
+    Ok(HttpResponse {
+        status: StatusCode::from_u16(interaction.status)?,
+        url: url.to_string(),
+        headers,
+        content_length: Some(body.len() as u64),
+        body: Box::new(Cursor::new(body)),
+    })
+}
+
+/// Sends requests over `network` and saves each response.
+#[derive(Debug)]
+struct RecordingTransport {
+    network: Arc<dyn HttpTransport>,
+    cassette: Cassette,
+    limit: u64,
+}
+
+impl HttpTransport for RecordingTransport {
+    fn get(&self, url: Url, headers: &HeaderMap) -> Result<HttpResponse> {
+        let response = self.network.get(url.clone(), headers)?;
+        let status = response.status.as_u16();
+        let content_type = response.content_type().map(str::to_string);
+        let body = read_body(response, self.limit)?;
+        let interaction = Interaction {
+            request: Cassette::request_key(&url),
+            status,
+            content_type,
+            body: self.cassette.redact(&String::from_utf8_lossy(&body)),
+        };
+        self.cassette.save(&interaction)?;
+        replayed(url, interaction)
+    }
+}
+
+/// Serves saved responses without touching the network.
+#[derive(Debug)]
+struct ReplayTransport {
+    cassette: Cassette,
+}
+
+impl HttpTransport for ReplayTransport {
+    fn get(&self, url: Url, _headers: &HeaderMap) -> Result<HttpResponse> {
+        let key = Cassette::request_key(&url);
+        match self.cassette.load(&key)? {
+            Some(interaction) => replayed(url, interaction),
+            None => Err(anyhow::anyhow!(
+                "No recorded response for {} in {}; record it with gwas_cassette(\"record\")",
+                key,
+                self.cassette.dir.display()
+            )),
+        }
+    }
+}
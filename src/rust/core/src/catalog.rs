@@ -0,0 +1,163 @@
+use crate::http::{self, error_text, read_json, session_client, session_max_response_bytes};
+use crate::Study;
+use anyhow::Result;
+use reqwest::blocking::Client;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CatalogStudy {
+    initial_sample_size: Option<String>,
+    replication_sample_size: Option<String>,
+    publication_info: Option<PublicationInfo>,
+    disease_trait: Option<DiseaseTrait>,
+    #[serde(default)]
+    ancestries: Vec<Ancestry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PublicationInfo {
+    pubmed_id: Option<String>,
+    publication_date: Option<String>,
+    publication: Option<String>,
+    title: Option<String>,
+    author: Option<Author>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Author {
+    fullname: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DiseaseTrait {
+    #[serde(rename = "trait")]
+    trait_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Ancestry {
+    #[serde(rename = "type")]
+    stage: Option<String>,
+    number_of_individuals: Option<i64>,
+    #[serde(default)]
+    ancestral_groups: Vec<AncestralGroup>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AncestralGroup {
+    ancestral_group: Option<String>,
+}
+
+/// A summary-statistics `Study` enriched with GWAS Catalog metadata.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StudyInfo {
+    #[serde(flatten)]
+    pub study: Study,
+    pub pubmed_id: Option<String>,
+    pub publication_title: Option<String>,
+    pub journal: Option<String>,
+    pub publication_date: Option<String>,
+    pub first_author: Option<String>,
+    pub trait_label: Option<String>,
+    pub initial_sample_size: Option<String>,
+    pub replication_sample_size: Option<String>,
+    pub discovery_sample_size: Option<i64>,
+    pub discovery_ancestry: Vec<String>,
+}
+
+/// Client for the GWAS Catalog REST API, which carries the study metadata
+/// missing from the summary statistics API.
+#[derive(Debug, Clone)]
+pub struct CatalogClient {
+    client: Client,
+    base_url: String,
+}
+
+impl Default for CatalogClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CatalogClient {
+    pub fn new() -> Self {
+        Self::with_base_url("https://www.ebi.ac.uk/gwas/rest/api".to_string())
+    }
+
+    pub fn with_base_url(base_url: String) -> Self {
+        Self {
+            client: session_client(),
+            base_url,
+        }
+    }
+
+    /// The Catalog's record of `accession`, or `None` when it has none,
+    /// as for studies not yet curated.
+    fn get_catalog_study(&self, accession: &str) -> Result<Option<CatalogStudy>> {
+        let url = format!("{}/studies/{accession}", self.base_url);
+        let response = http::send(self.client.get(url).header("Accept", "application/json"))?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = error_text(response);
+            return Err(anyhow::anyhow!("GWAS Catalog HTTP {}: {}", status, text));
+        }
+        read_json(response, session_max_response_bytes()).map(Some)
+    }
+
+    pub fn study_info(&self, study: Study) -> Result<StudyInfo> {
+        let accession = study.study_accession.clone();
+        self.find_study_info(study)?
+            .ok_or_else(|| anyhow::anyhow!("GWAS Catalog has no study {}", accession))
+    }
+
+    /// Like `study_info`, but `None` for a study the Catalog has no record
+    /// of rather than an error.
+    pub fn find_study_info(&self, study: Study) -> Result<Option<StudyInfo>> {
+        let catalog = match self.get_catalog_study(&study.study_accession)? {
+            Some(catalog) => catalog,
+            None => return Ok(None),
+        };
+
+        let discovery: Vec<&Ancestry> = catalog
+            .ancestries
+            .iter()
+            .filter(|a| a.stage.as_deref() == Some("initial"))
+            .collect();
+        let discovery_sample_size = discovery
+            .iter()
+            .filter_map(|a| a.number_of_individuals)
+            .reduce(|a, b| a + b);
+        let mut discovery_ancestry: Vec<String> = discovery
+            .iter()
+            .flat_map(|a| &a.ancestral_groups)
+            .filter_map(|g| g.ancestral_group.clone())
+            .collect();
+        discovery_ancestry.sort();
+        discovery_ancestry.dedup();
+
+        let publication = catalog.publication_info;
+        Ok(Some(StudyInfo {
+            study,
+            pubmed_id: publication.as_ref().and_then(|p| p.pubmed_id.clone()),
+            publication_title: publication.as_ref().and_then(|p| p.title.clone()),
+            journal: publication.as_ref().and_then(|p| p.publication.clone()),
+            publication_date: publication
+                .as_ref()
+                .and_then(|p| p.publication_date.clone()),
+            first_author: publication.and_then(|p| p.author).and_then(|a| a.fullname),
+            trait_label: catalog.disease_trait.and_then(|t| t.trait_name),
+            initial_sample_size: catalog.initial_sample_size,
+            replication_sample_size: catalog.replication_sample_size,
+            discovery_sample_size,
+            discovery_ancestry,
+        }))
+    }
+}
@@ -0,0 +1,275 @@
+use crate::atomic::AtomicFile;
+use crate::checksum::file_sha256;
+use crate::http::is_not_found;
+use crate::{GwasClient, HalResponse, SummaryStatsFile};
+use anyhow::Result;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Requests in flight at once while harvesting, kept low as the index
+/// walks the whole API.
+const INDEX_THREADS: usize = 4;
+
+/// Traits or studies asked for per page.
+const PAGE_SIZE: i32 = 200;
+
+/// Description of the index, written to `index.json` beside the tables.
+pub const INDEX_FILE: &str = "index.json";
+
+#[derive(Debug, Serialize, Clone)]
+pub struct IndexTable {
+    pub name: String,
+    pub file: String,
+    pub columns: Vec<&'static str>,
+    pub rows: usize,
+    pub sha256: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct CatalogIndex {
+    pub out_dir: String,
+    pub base_url: String,
+    /// Seconds since the Unix epoch when the harvest finished.
+    pub built_at: u64,
+    pub traits: usize,
+    pub studies: usize,
+    pub files: usize,
+    /// Linked by `trait_id` and `study_accession`.
+    pub tables: Vec<IndexTable>,
+}
+
+/// Every item of a paged list endpoint, requesting pages until one comes
+/// back short. A 404 is an empty list, as the API answers so for entities
+/// with nothing to list.
+pub(crate) fn all_pages<T>(
+    client: &GwasClient,
+    fetch: impl Fn(BTreeMap<String, String>) -> Result<HalResponse<Vec<T>>>,
+) -> Result<Vec<T>> {
+    let mut items = Vec::new();
+    let mut start = 0;
+    loop {
+        client.interrupt().check()?;
+        let params = BTreeMap::from([
+            ("start".to_string(), start.to_string()),
+            ("size".to_string(), PAGE_SIZE.to_string()),
+        ]);
+        let page = match fetch(params) {
+            Ok(page) => page,
+            Err(e) if is_not_found(&e) => break,
+            Err(e) => return Err(e),
+        };
+        let n = items.len();
+        items.extend(
+            page.embedded
+                .into_iter()
+                .flat_map(|embedded| embedded.into_values())
+                .flatten(),
+        );
+        let fetched = items.len() - n;
+        client.interrupt().progress().add_pages(1, fetched as u64);
+        if fetched < PAGE_SIZE as usize {
+            break;
+        }
+        start += PAGE_SIZE;
+    }
+    Ok(items)
+}
+
+/// Harvests the traits, each trait's studies and each study's summary
+/// statistics files into linked CSV tables under `out_dir`, described by
+/// `index.json` with each table's row count and SHA-256. `limit` caps the
+/// traits harvested, e.g. for a trial run. Tables are written only once
+/// the harvest is complete, so an interrupted or failed run leaves any
+/// earlier index in place.
+pub fn build_catalog_index(
+    client: &GwasClient,
+    out_dir: &str,
+    limit: Option<usize>,
+) -> Result<CatalogIndex> {
+    let mut traits: Vec<String> = all_pages(client, |params| client.get_traits(params))?
+        .into_iter()
+        .map(|t| t.trait_name)
+        .collect();
+    traits.sort();
+    traits.dedup();
+    if let Some(limit) = limit {
+        traits.truncate(limit);
+    }
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(INDEX_THREADS)
+        .build()?;
+    let trait_studies: Vec<(String, Vec<String>)> = pool.install(|| {
+        traits
+            .par_iter()
+            .map(|trait_id| {
+                let studies =
+                    all_pages(client, |params| client.get_trait_studies(trait_id, params))?;
+                let mut studies: Vec<String> =
+                    studies.into_iter().map(|s| s.study_accession).collect();
+                studies.sort();
+                studies.dedup();
+                Ok((trait_id.clone(), studies))
+            })
+            .collect::<Result<_>>()
+    })?;
+
+    let studies: BTreeSet<&String> = trait_studies
+        .iter()
+        .flat_map(|(_, studies)| studies)
+        .collect();
+    let study_files: Vec<(String, Vec<SummaryStatsFile>)> = pool.install(|| {
+        studies
+            .par_iter()
+            .map(|study| {
+                client.interrupt().check()?;
+                let files = match client.get_study_summary_stats_files(study) {
+                    Ok(files) => files
+                        .embedded
+                        .into_iter()
+                        .flat_map(|embedded| embedded.into_values())
+                        .flatten()
+                        .collect(),
+                    Err(e) if is_not_found(&e) => Vec::new(),
+                    Err(e) => return Err(e),
+                };
+                client.interrupt().progress().add_pages(1, 0);
+                Ok(((*study).clone(), files))
+            })
+            .collect::<Result<_>>()
+    })?;
+
+    let mut traits_per_study: BTreeMap<&str, usize> = BTreeMap::new();
+    for (_, studies) in &trait_studies {
+        for study in studies {
+            *traits_per_study.entry(study).or_default() += 1;
+        }
+    }
+
+    let dir = Path::new(out_dir);
+    let tables = vec![
+        write_table(
+            dir,
+            "traits",
+            vec!["trait_id", "studies"],
+            trait_studies
+                .iter()
+                .map(|(trait_id, studies)| vec![trait_id.into(), studies.len().to_string().into()]),
+        )?,
+        write_table(
+            dir,
+            "trait_studies",
+            vec!["trait_id", "study_accession"],
+            trait_studies.iter().flat_map(|(trait_id, studies)| {
+                studies
+                    .iter()
+                    .map(move |study| vec![trait_id.into(), study.into()])
+            }),
+        )?,
+        write_table(
+            dir,
+            "studies",
+            vec!["study_accession", "traits", "files"],
+            study_files.iter().map(|(study, files)| {
+                vec![
+                    study.into(),
+                    traits_per_study
+                        .get(study.as_str())
+                        .copied()
+                        .unwrap_or(0)
+                        .to_string()
+                        .into(),
+                    files.len().to_string().into(),
+                ]
+            }),
+        )?,
+        write_table(
+            dir,
+            "files",
+            vec![
+                "study_accession",
+                "trait_id",
+                "file_path",
+                "file_type",
+                "file_size",
+                "download_url",
+            ],
+            study_files.iter().flat_map(|(study, files)| {
+                files.iter().map(move |file| {
+                    let download_url = file.download_url.clone().or_else(|| {
+                        file.links
+                            .as_ref()
+                            .and_then(|links| links.get("download"))
+                            .map(|link| link.href.clone())
+                    });
+                    vec![
+                        study.into(),
+                        file.trait_id.clone().unwrap_or_default().into(),
+                        file.file_path.as_str().into(),
+                        file.file_type.clone().unwrap_or_default().into(),
+                        file.file_size
+                            .map_or_else(String::new, |size| size.to_string())
+                            .into(),
+                        download_url.unwrap_or_default().into(),
+                    ]
+                })
+            }),
+        )?,
+    ];
+
+    let index = CatalogIndex {
+        out_dir: out_dir.to_string(),
+        base_url: client.base_url.clone(),
+        built_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        traits: trait_studies.len(),
+        studies: study_files.len(),
+        files: study_files.iter().map(|(_, files)| files.len()).sum(),
+        tables,
+    };
+    let mut file = AtomicFile::create(dir.join(INDEX_FILE))?;
+    file.write_all(serde_json::to_string_pretty(&index)?.as_bytes())?;
+    file.commit()?;
+    Ok(index)
+}
+
+/// Writes `<name>.csv` and returns its description.
+fn write_table<'a>(
+    dir: &Path,
+    name: &str,
+    columns: Vec<&'static str>,
+    rows: impl Iterator<Item = Vec<Cow<'a, str>>>,
+) -> Result<IndexTable> {
+    let file_name = format!("{name}.csv");
+    let path = dir.join(&file_name);
+    let mut writer = BufWriter::new(AtomicFile::create(&path)?);
+    writeln!(writer, "{}", columns.join(","))?;
+    let mut count = 0;
+    for row in rows {
+        let fields: Vec<Cow<str>> = row.iter().map(|field| csv_field(field)).collect();
+        writeln!(writer, "{}", fields.join(","))?;
+        count += 1;
+    }
+    AtomicFile::commit_buffered(writer)?;
+    let (_, sha256) = file_sha256(&path.to_string_lossy())?;
+    Ok(IndexTable {
+        name: name.to_string(),
+        file: file_name,
+        columns,
+        rows: count,
+        sha256,
+    })
+}
+
+/// Quotes a CSV field holding a comma, quote or line break (RFC 4180).
+pub(crate) fn csv_field(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
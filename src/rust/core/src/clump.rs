@@ -0,0 +1,101 @@
+use crate::ld::PlinkFileset;
+use crate::Association;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone)]
+pub struct ClumpSettings {
+    pub p_threshold: f64,
+    pub window_bp: i64,
+    pub r2_threshold: f64,
+}
+
+impl Default for ClumpSettings {
+    fn default() -> Self {
+        Self {
+            p_threshold: 5e-8,
+            window_bp: 500_000,
+            r2_threshold: 0.1,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ClumpedSignal {
+    #[serde(flatten)]
+    pub index: Association,
+    pub clumped_variants: Vec<String>,
+}
+
+/// Greedy distance-based clumping: the most significant remaining variant
+/// becomes an index variant and absorbs every variant within the window on
+/// the same chromosome. With an LD reference, only variants with r² at or
+/// above the threshold are absorbed; variants missing from the reference fall
+/// back to distance alone.
+pub fn clump(
+    associations: &[Association],
+    settings: &ClumpSettings,
+    reference: Option<&PlinkFileset>,
+) -> Result<Vec<ClumpedSignal>> {
+    let mut candidates: Vec<&Association> = associations
+        .iter()
+        .filter(|a| a.chromosome.is_some() && a.base_pair_location.is_some())
+        .filter(|a| a.p_value.map_or(false, |p| p <= settings.p_threshold))
+        .collect();
+
+    candidates.sort_by(|a, b| {
+        a.p_value
+            .unwrap_or(1.0)
+            .total_cmp(&b.p_value.unwrap_or(1.0))
+            .then(a.chromosome.cmp(&b.chromosome))
+            .then(a.base_pair_location.cmp(&b.base_pair_location))
+    });
+
+    let mut signals: Vec<ClumpedSignal> = Vec::new();
+    for candidate in candidates {
+        let mut owner = None;
+        for (i, signal) in signals.iter().enumerate() {
+            let in_window = signal.index.chromosome == candidate.chromosome
+                && (signal.index.base_pair_location.unwrap_or(0)
+                    - candidate.base_pair_location.unwrap_or(0))
+                .abs()
+                    <= settings.window_bp;
+            if in_window && in_ld(reference, &signal.index, candidate, settings)? {
+                owner = Some(i);
+                break;
+            }
+        }
+
+        match owner {
+            Some(i) => {
+                if let Some(id) = &candidate.variant_id {
+                    signals[i].clumped_variants.push(id.clone());
+                }
+            }
+            None => signals.push(ClumpedSignal {
+                index: candidate.clone(),
+                clumped_variants: Vec::new(),
+            }),
+        }
+    }
+
+    Ok(signals)
+}
+
+fn in_ld(
+    reference: Option<&PlinkFileset>,
+    index: &Association,
+    candidate: &Association,
+    settings: &ClumpSettings,
+) -> Result<bool> {
+    let reference = match reference {
+        Some(reference) => reference,
+        None => return Ok(true),
+    };
+    match (reference.locate(index), reference.locate(candidate)) {
+        (Some(a), Some(b)) => Ok(reference
+            .r2(a, b)?
+            .map_or(false, |r2| r2 >= settings.r2_threshold)),
+        _ => Ok(true),
+    }
+}
@@ -0,0 +1,223 @@
+//! Conditional requests for API resources fetched again and again in a
+//! session, such as the trait and study listings. A response carrying an
+//! `ETag` or `Last-Modified` validator is kept, and the next request for
+//! the same URL sends `If-None-Match` or `If-Modified-Since`; a 304 answer
+//! is served from the kept body instead of downloading it again.
+//!
+//! Association pages are left out: they are large, rarely fetched twice,
+//! and would hold memory for the rest of the session. The bodies kept are
+//! bounded in total by [`MAX_VALIDATED_TOTAL_BYTES`], dropping the least
+//! recently used first, so a long sync or trait dump cannot grow memory
+//! without limit.
+
+use crate::stats;
+use crate::transport::{HttpResponse, HttpTransport};
+use anyhow::Result;
+use reqwest::header::{
+    HeaderMap, HeaderValue, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+};
+use reqwest::StatusCode;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Cursor, Read};
+use std::sync::{Arc, Mutex};
+use url::Url;
+
+/// Largest body kept for revalidation; larger ones are passed through.
+pub const MAX_VALIDATED_BYTES: u64 = 8 * 1024 * 1024;
+
+/// Largest total size of the bodies kept for the session.
+pub const MAX_VALIDATED_TOTAL_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Kept responses by URL, shared by every client for the session.
+static VALIDATED: Mutex<Option<ValidatedCache>> = Mutex::new(None);
+
+/// A kept response and the validators to revalidate it with.
+#[derive(Debug, Clone)]
+pub(crate) struct Validated {
+    pub(crate) etag: Option<HeaderValue>,
+    pub(crate) last_modified: Option<HeaderValue>,
+    pub(crate) content_type: Option<HeaderValue>,
+    pub(crate) body: Arc<[u8]>,
+}
+
+/// Kept responses within a budget of body bytes, evicting the least
+/// recently used when a new one does not fit.
+#[derive(Debug)]
+pub(crate) struct ValidatedCache {
+    budget: u64,
+    bytes: u64,
+    /// Bumped on every use, to order entries by recency.
+    clock: u64,
+    entries: HashMap<String, (Validated, u64)>,
+    by_use: BTreeMap<u64, String>,
+}
+
+impl ValidatedCache {
+    pub(crate) fn new(budget: u64) -> Self {
+        Self {
+            budget,
+            bytes: 0,
+            clock: 0,
+            entries: HashMap::new(),
+            by_use: BTreeMap::new(),
+        }
+    }
+
+    fn touch(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    pub(crate) fn get(&mut self, key: &str) -> Option<Validated> {
+        let now = self.touch();
+        let (validated, used) = self.entries.get_mut(key)?;
+        self.by_use.remove(used);
+        *used = now;
+        self.by_use.insert(now, key.to_string());
+        Some(validated.clone())
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some((validated, used)) = self.entries.remove(key) {
+            self.by_use.remove(&used);
+            self.bytes -= validated.body.len() as u64;
+        }
+    }
+
+    /// Keeps `validated` under `key`, replacing any earlier response; one
+    /// larger than the whole budget is not kept.
+    pub(crate) fn insert(&mut self, key: String, validated: Validated) {
+        self.remove(&key);
+        let size = validated.body.len() as u64;
+        if size > self.budget {
+            return;
+        }
+        while self.bytes + size > self.budget {
+            let oldest = match self.by_use.values().next() {
+                Some(oldest) => oldest.clone(),
+                None => break,
+            };
+            self.remove(&oldest);
+        }
+        let now = self.touch();
+        self.bytes += size;
+        self.by_use.insert(now, key.clone());
+        self.entries.insert(key, (validated, now));
+    }
+
+    #[cfg(test)]
+    pub(crate) fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    #[cfg(test)]
+    pub(crate) fn contains(&self, key: &str) -> bool {
+        self.entries.contains_key(key)
+    }
+}
+
+impl Validated {
+    fn response(&self, url: String) -> HttpResponse {
+        let mut headers = HeaderMap::new();
+        if let Some(content_type) = &self.content_type {
+            headers.insert(CONTENT_TYPE, content_type.clone());
+        }
+        HttpResponse {
+            status: StatusCode::OK,
+            url,
+            headers,
+            content_length: Some(self.body.len() as u64),
+            body: Box::new(Cursor::new(self.body.clone())),
+        }
+    }
+}
+
+/// Whether responses for `url` are kept and revalidated: every API
+/// resource but association pages.
+pub fn revalidates(url: &Url) -> bool {
+    url.path_segments()
+        .map_or(false, |mut segments| segments.all(|s| s != "associations"))
+}
+
+/// Requests through `inner`, revalidating the responses it has kept
+/// rather than fetching them again; see the module documentation.
+#[derive(Debug)]
+pub struct ConditionalTransport {
+    inner: Arc<dyn HttpTransport>,
+}
+
+impl ConditionalTransport {
+    pub fn new(inner: Arc<dyn HttpTransport>) -> Self {
+        Self { inner }
+    }
+}
+
+fn kept(key: &str) -> Option<Validated> {
+    VALIDATED.lock().ok()?.as_mut()?.get(key)
+}
+
+fn keep(key: String, validated: Validated) {
+    if let Ok(mut kept) = VALIDATED.lock() {
+        kept.get_or_insert_with(|| ValidatedCache::new(MAX_VALIDATED_TOTAL_BYTES))
+            .insert(key, validated);
+    }
+}
+
+impl HttpTransport for ConditionalTransport {
+    fn get(&self, url: Url, headers: &HeaderMap) -> Result<HttpResponse> {
+        if !revalidates(&url) {
+            return self.inner.get(url, headers);
+        }
+        let key = url.to_string();
+        let previous = kept(&key);
+        let mut headers = headers.clone();
+        if let Some(previous) = &previous {
+            if let Some(etag) = &previous.etag {
+                headers.insert(IF_NONE_MATCH, etag.clone());
+            }
+            if let Some(last_modified) = &previous.last_modified {
+                headers.insert(IF_MODIFIED_SINCE, last_modified.clone());
+            }
+        }
+
+        let mut response = self.inner.get(url, &headers)?;
+        if let Some(previous) = &previous {
+            if response.status == StatusCode::NOT_MODIFIED {
+                stats::cache_hits(1);
+                return Ok(previous.response(response.url));
+            }
+            stats::cache_misses(1);
+        }
+
+        let etag = response.headers.get(ETAG).cloned();
+        let last_modified = response.headers.get(LAST_MODIFIED).cloned();
+        let fits = response
+            .content_length
+            .map_or(true, |length| length <= MAX_VALIDATED_BYTES);
+        if response.status != StatusCode::OK || (etag.is_none() && last_modified.is_none()) || !fits
+        {
+            return Ok(response);
+        }
+
+        // Read the body to keep it, up to the limit; a longer one is handed
+        // on whole, its start followed by the rest.
+        let mut body = Vec::new();
+        (&mut response.body)
+            .take(MAX_VALIDATED_BYTES + 1)
+            .read_to_end(&mut body)?;
+        if body.len() as u64 > MAX_VALIDATED_BYTES {
+            response.body = Box::new(Cursor::new(body).chain(response.body));
+            return Ok(response);
+        }
+        let validated = Validated {
+            etag,
+            last_modified,
+            content_type: response.headers.get(CONTENT_TYPE).cloned(),
+            body: body.into(),
+        };
+        response.content_length = Some(validated.body.len() as u64);
+        response.body = Box::new(Cursor::new(validated.body.clone()));
+        keep(key, validated);
+        Ok(response)
+    }
+}
@@ -0,0 +1,73 @@
+//! Counting the associations a query matches without keeping them. The API
+//! reports no totals, so pages of the largest size are fetched and their
+//! rows counted and dropped, which holds one page in memory at a time.
+
+use crate::backend::SumstatsBackend;
+use crate::interrupt::is_interrupted;
+use crate::paging::{PagePrefetcher, PREFETCH_PAGES};
+use crate::refine::Refinement;
+use crate::{GwasFilter, MAX_PAGE_SIZE};
+use anyhow::Result;
+use serde::Serialize;
+
+/// How many associations a query matched.
+#[derive(Debug, Default, Serialize)]
+pub struct AssociationCount {
+    /// Rows matching the query and the refinement.
+    pub count: u64,
+    /// Rows the server sent, before the refinement.
+    pub fetched: u64,
+    pub pages: u64,
+    /// Set when Ctrl-C stopped the count, which is then a lower bound.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interrupted: Option<bool>,
+}
+
+/// Counts the associations matching `filter` from its start, and of those
+/// the rows `refinement` keeps. The filter's size is ignored; pages of
+/// `MAX_PAGE_SIZE` are fetched in batches of up to `PREFETCH_PAGES` until
+/// a short page or one past the end of the results (HTTP 404).
+pub fn count_associations(
+    backend: &dyn SumstatsBackend,
+    entity_type: Option<&str>,
+    entity_id: Option<&str>,
+    filter: &GwasFilter,
+    refinement: &Refinement,
+) -> Result<AssociationCount> {
+    let filter = GwasFilter {
+        size: Some(MAX_PAGE_SIZE),
+        ..filter.clone()
+    };
+    let mut count = AssociationCount::default();
+    for response in PagePrefetcher::new(
+        backend,
+        entity_type,
+        entity_id,
+        &filter,
+        PREFETCH_PAGES,
+        None,
+    ) {
+        let response = match response {
+            Ok(response) => response,
+            Err(e) if is_interrupted(&e) => {
+                count.interrupted = Some(true);
+                break;
+            }
+            Err(e) => return Err(e),
+        };
+        let rows = response
+            .embedded
+            .and_then(|mut embedded| embedded.remove("associations"))
+            .unwrap_or_default();
+        let fetched = rows.len() as u64;
+        let kept = rows.values().filter(|row| refinement.keeps(row)).count() as u64;
+
+        count.pages += 1;
+        count.fetched += fetched;
+        count.count += kept;
+        if let Some(interrupt) = backend.interrupt() {
+            interrupt.progress().add_pages(1, fetched);
+        }
+    }
+    Ok(count)
+}
@@ -0,0 +1,98 @@
+use std::path::{Path, PathBuf};
+
+/// Free space on the filesystem holding `path`, with the filesystem's
+/// mount point (or volume) so paths on the same one can be grouped. A path
+/// that does not exist yet is looked up through its nearest existing
+/// ancestor. `None` when the platform cannot tell.
+pub fn free_space(path: &Path) -> Option<(String, u64)> {
+    let dir = existing_ancestor(path);
+    platform::free_space(&dir)
+}
+
+fn existing_ancestor(path: &Path) -> PathBuf {
+    // Relative paths end in "", the current directory.
+    path.ancestors()
+        .find(|dir| !dir.as_os_str().is_empty() && dir.is_dir())
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Decimal units, as disk sizes are usually quoted.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "kB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::path::Path;
+    use std::process::Command;
+
+    /// `statvfs` has a different layout on every Unix, so this asks the
+    /// POSIX `df` instead, in 1024-byte blocks.
+    pub fn free_space(dir: &Path) -> Option<(String, u64)> {
+        let output = Command::new("df").arg("-Pk").arg(dir).output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let text = String::from_utf8_lossy(&output.stdout);
+        // Filesystem, blocks, used, available, capacity, mount point.
+        let fields: Vec<&str> = text.lines().nth(1)?.split_whitespace().collect();
+        let available: u64 = fields.get(3)?.parse().ok()?;
+        let mount = fields.get(5..)?.join(" ");
+        Some((mount, available * 1024))
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::{Component, Path};
+
+    extern "system" {
+        fn GetDiskFreeSpaceExW(
+            directory: *const u16,
+            free_to_caller: *mut u64,
+            total: *mut u64,
+            total_free: *mut u64,
+        ) -> i32;
+    }
+
+    pub fn free_space(dir: &Path) -> Option<(String, u64)> {
+        let dir = dir.canonicalize().ok()?;
+        let volume = match dir.components().next()? {
+            Component::Prefix(prefix) => prefix.as_os_str().to_string_lossy().into_owned(),
+            _ => return None,
+        };
+        let wide: Vec<u16> = dir.as_os_str().encode_wide().chain(Some(0)).collect();
+        let mut available = 0u64;
+        let ok = unsafe {
+            GetDiskFreeSpaceExW(
+                wide.as_ptr(),
+                &mut available,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            )
+        };
+        (ok != 0).then_some((volume, available))
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod platform {
+    use std::path::Path;
+
+    pub fn free_space(_dir: &Path) -> Option<(String, u64)> {
+        None
+    }
+}
@@ -0,0 +1,879 @@
+use crate::atomic::AtomicFile;
+use crate::backend::{configure, session_config};
+use crate::checksum::{file_digests, file_sha256, HashingReader, HashingWriter};
+use crate::disk::{format_bytes, free_space};
+use crate::ftp::FTP_BASE_URL;
+use crate::http;
+use crate::interrupt::{is_interrupted, Interruptible};
+use crate::stats;
+use crate::throttle::Throttled;
+use crate::GwasClient;
+use anyhow::Result;
+use flate2::read::MultiGzDecoder;
+use rayon::prelude::*;
+use reqwest::header::{ACCEPT_ENCODING, CONTENT_LENGTH, RANGE};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Endpoints serving the same summary statistics tree. Plain HTTP skips
+/// the TLS handshake and is faster from some networks, but is only used
+/// when configured as the download mirror: downloads never move from
+/// HTTPS to it unasked.
+pub const DOWNLOAD_MIRRORS: [&str; 2] = [
+    FTP_BASE_URL,
+    "http://ftp.ebi.ac.uk/pub/databases/gwas/summary_statistics",
+];
+
+/// Suffix of the GWAS-SSF metadata file published next to each summary
+/// statistics file, holding its `data_file_md5sum`.
+const METADATA_SUFFIX: &str = "-meta.yaml";
+
+/// Bytes fetched from each mirror when measuring throughput.
+const PROBE_BYTES: u64 = 256 * 1024;
+
+/// What to do with files that already exist at their output path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownloadMode {
+    /// Always download, replacing existing files.
+    Overwrite,
+    /// Keep existing files whose size matches the expected size, or any
+    /// existing file when no size is known.
+    Skip,
+    /// Fetch only the missing tail of shorter files with a range request.
+    Resume,
+    /// Keep existing files whose size and checksum match what is expected,
+    /// re-downloading the rest.
+    Verify,
+}
+
+impl DownloadMode {
+    pub const NAMES: [&'static str; 4] = ["overwrite", "skip", "resume", "verify"];
+
+    pub fn parse(mode: &str) -> Result<Self> {
+        match mode {
+            "overwrite" => Ok(Self::Overwrite),
+            "skip" => Ok(Self::Skip),
+            "resume" => Ok(Self::Resume),
+            "verify" => Ok(Self::Verify),
+            _ => Err(anyhow::anyhow!(
+                "Unknown download mode {}; expected one of {}",
+                mode,
+                Self::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+/// Formats that downloads can be decompressed from as they arrive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    /// Needs the `zstd` feature.
+    Zstd,
+}
+
+impl Compression {
+    /// The format named by the extension of `path`, and `path` without it.
+    pub fn from_path(path: &str) -> Option<(Self, &str)> {
+        [
+            (".gz", Self::Gzip),
+            (".bgz", Self::Gzip),
+            (".zst", Self::Zstd),
+        ]
+        .into_iter()
+        .find_map(|(suffix, compression)| {
+            path.strip_suffix(suffix)
+                .filter(|stem| !stem.is_empty())
+                .map(|stem| (compression, stem))
+        })
+    }
+
+    fn decoder<'a>(self, reader: impl Read + 'a) -> io::Result<Box<dyn Read + 'a>> {
+        match self {
+            Self::Gzip => Ok(Box::new(MultiGzDecoder::new(reader))),
+            #[cfg(feature = "zstd")]
+            Self::Zstd => Ok(Box::new(zstd::stream::read::Decoder::new(reader)?)),
+            #[cfg(not(feature = "zstd"))]
+            Self::Zstd => Err(io::Error::new(
+                io::ErrorKind::Other,
+                "zstd decompression needs iani built with the zstd feature",
+            )),
+        }
+    }
+}
+
+/// Outcome of one download, returned to R and recorded in `manifest.json`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DownloadResult {
+    pub url: String,
+    pub path: String,
+    pub bytes: Option<u64>,
+    pub seconds: f64,
+    /// Hex SHA-256 of the file. Unknown for skipped files without a
+    /// recorded checksum.
+    pub sha256: Option<String>,
+    /// "ok", "skipped" (an existing file was kept), "failed" or
+    /// "cancelled" (interrupted by the user; partial files are kept for
+    /// resuming).
+    pub status: String,
+    /// Bytes already on disk when a download was resumed.
+    #[serde(default)]
+    pub resumed_from: Option<u64>,
+    /// Size and SHA-256 of the downloaded data of files decompressed as
+    /// they arrived; `bytes` and `sha256` describe the decompressed file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compressed_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compressed_sha256: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DownloadManifest {
+    /// Seconds since the Unix epoch.
+    pub created: u64,
+    pub files: Vec<DownloadResult>,
+}
+
+/// Result of checking a local file against the remote size and the
+/// checksums recorded in `manifest.json` or published by the server.
+#[derive(Debug, Serialize, Clone)]
+pub struct VerifyResult {
+    pub url: String,
+    pub path: String,
+    /// "ok", "missing", "truncated" (shorter than the remote file),
+    /// "corrupted" (longer, or a checksum differs), "unverified" (no
+    /// size or checksum to compare with) or "cancelled".
+    pub status: String,
+    pub bytes: Option<u64>,
+    pub expected_bytes: Option<u64>,
+    pub sha256: Option<String>,
+    pub expected_sha256: Option<String>,
+    pub md5: Option<String>,
+    pub expected_md5: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Space a batch of downloads needs on one filesystem, against what is
+/// free there.
+#[derive(Debug, Serialize, Clone)]
+pub struct SpaceCheck {
+    pub mount: String,
+    pub needed: u64,
+    pub available: u64,
+}
+
+enum Outcome {
+    Downloaded {
+        bytes: u64,
+        sha256: String,
+        resumed_from: Option<u64>,
+        /// Size and SHA-256 of the data before decompression.
+        compressed: Option<(u64, String)>,
+    },
+    Kept {
+        bytes: u64,
+        sha256: Option<String>,
+    },
+}
+
+impl GwasClient {
+    /// Writes `url` to `path`, or appends to the first `offset` bytes
+    /// already in `path` when the server honours a range request.
+    fn fetch_to_file(&self, url: &str, path: &str, offset: u64) -> Result<Outcome> {
+        // Files are kept as served, so checksums and resumed ranges match.
+        let mut request = self.client.get(url).header(ACCEPT_ENCODING, "identity");
+        if offset > 0 {
+            request = request.header(RANGE, format!("bytes={offset}-"));
+        }
+        let response = http::send(request)?;
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("HTTP {}", response.status()));
+        }
+        if let Some(parent) = Path::new(path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let resumed = offset > 0 && response.status() == StatusCode::PARTIAL_CONTENT;
+        let mut response = Throttled::new(
+            Interruptible::new(response, &self.interrupt),
+            self.throttle.as_deref(),
+            &self.interrupt,
+        );
+        let (bytes, sha256) = if resumed {
+            let file = fs::OpenOptions::new().append(true).open(path)?;
+            let mut writer =
+                HashingWriter::with_prefix(BufWriter::new(file), fs::File::open(path)?)?;
+            std::io::copy(&mut response, &mut writer)?;
+            writer.finish()?
+        } else {
+            let mut writer = HashingWriter::new(BufWriter::new(fs::File::create(path)?));
+            std::io::copy(&mut response, &mut writer)?;
+            writer.finish()?
+        };
+        Ok(Outcome::Downloaded {
+            bytes,
+            sha256,
+            resumed_from: Some(offset).filter(|_| resumed),
+            compressed: None,
+        })
+    }
+
+    /// Decompresses `url` into `path` as it arrives, so the compressed
+    /// file never touches the disk.
+    fn fetch_decompressed(
+        &self,
+        url: &str,
+        path: &str,
+        compression: Compression,
+    ) -> Result<Outcome> {
+        let response = http::send(self.client.get(url).header(ACCEPT_ENCODING, "identity"))?;
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("HTTP {}", response.status()));
+        }
+        if let Some(parent) = Path::new(path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut compressed = HashingReader::new(Throttled::new(
+            Interruptible::new(response, &self.interrupt),
+            self.throttle.as_deref(),
+            &self.interrupt,
+        ));
+        let mut writer = HashingWriter::new(BufWriter::new(fs::File::create(path)?));
+        io::copy(&mut compression.decoder(&mut compressed)?, &mut writer)
+            .map_err(|e| anyhow::anyhow!("Decompressing {}: {}", url, e))?;
+        let (bytes, sha256) = writer.finish()?;
+        Ok(Outcome::Downloaded {
+            bytes,
+            sha256,
+            resumed_from: None,
+            compressed: Some(compressed.finish()?),
+        })
+    }
+
+    /// Output path of a download to `path`: without its compression
+    /// extension when this client decompresses downloads.
+    pub fn output_path<'a>(&self, path: &'a str) -> &'a str {
+        match Compression::from_path(path) {
+            Some((_, stem)) if self.decompress => stem,
+            _ => path,
+        }
+    }
+
+    /// Size of a remote file from the `Content-Length` of a HEAD request.
+    fn remote_size(&self, url: &str) -> Option<u64> {
+        let response = http::send(self.client.head(url)).ok()?;
+        if !response.status().is_success() {
+            return None;
+        }
+        response
+            .headers()
+            .get(CONTENT_LENGTH)?
+            .to_str()
+            .ok()?
+            .parse()
+            .ok()
+    }
+
+    fn fetch_with_mode(
+        &self,
+        url: &str,
+        path: &str,
+        mode: DownloadMode,
+        recorded: Option<&DownloadResult>,
+    ) -> Result<Outcome> {
+        match Compression::from_path(path) {
+            Some((compression, stem)) if self.decompress => {
+                self.fetch_decompressed_with_mode(url, stem, compression, mode, recorded)
+            }
+            _ => self.fetch_plain_with_mode(url, path, mode, recorded),
+        }
+    }
+
+    /// Decompressed files cannot be compared with the remote size or
+    /// resumed, so existing ones are only kept when they match what
+    /// `recorded` says was written.
+    fn fetch_decompressed_with_mode(
+        &self,
+        url: &str,
+        path: &str,
+        compression: Compression,
+        mode: DownloadMode,
+        recorded: Option<&DownloadResult>,
+    ) -> Result<Outcome> {
+        let existing = match fs::metadata(path) {
+            Ok(metadata) if metadata.is_file() => metadata.len(),
+            _ => return self.fetch_decompressed(url, path, compression),
+        };
+        let recorded_size = recorded.and_then(|r| r.bytes);
+        let recorded_sha256 = recorded.and_then(|r| r.sha256.clone());
+        let keep = match mode {
+            DownloadMode::Overwrite => false,
+            DownloadMode::Skip => recorded_size.map_or(true, |size| size == existing),
+            DownloadMode::Resume => recorded_size == Some(existing),
+            DownloadMode::Verify => {
+                let (bytes, sha256) = file_sha256(path)?;
+                recorded_size == Some(bytes)
+                    && recorded_sha256.as_deref().map_or(true, |r| r == sha256)
+            }
+        };
+        if keep {
+            return Ok(Outcome::Kept {
+                bytes: existing,
+                sha256: recorded_sha256,
+            });
+        }
+        self.fetch_decompressed(url, path, compression)
+    }
+
+    fn fetch_plain_with_mode(
+        &self,
+        url: &str,
+        path: &str,
+        mode: DownloadMode,
+        recorded: Option<&DownloadResult>,
+    ) -> Result<Outcome> {
+        let existing = match fs::metadata(path) {
+            Ok(metadata) if metadata.is_file() => metadata.len(),
+            _ => return self.fetch_to_file(url, path, 0),
+        };
+        let recorded_size = recorded.and_then(|r| r.bytes);
+        let recorded_sha256 = recorded.and_then(|r| r.sha256.clone());
+
+        match mode {
+            DownloadMode::Overwrite => self.fetch_to_file(url, path, 0),
+            DownloadMode::Skip => {
+                let expected = recorded_size.or_else(|| self.remote_size(url));
+                if expected.map_or(true, |expected| expected == existing) {
+                    return Ok(Outcome::Kept {
+                        bytes: existing,
+                        sha256: recorded_sha256,
+                    });
+                }
+                self.fetch_to_file(url, path, 0)
+            }
+            DownloadMode::Resume => match self.remote_size(url).or(recorded_size) {
+                Some(total) if total == existing => Ok(Outcome::Kept {
+                    bytes: existing,
+                    sha256: recorded_sha256,
+                }),
+                Some(total) if existing < total => self.fetch_to_file(url, path, existing),
+                _ => self.fetch_to_file(url, path, 0),
+            },
+            DownloadMode::Verify => {
+                // Without a recorded checksum only the size can be checked.
+                let expected = recorded_size.or_else(|| self.remote_size(url));
+                let (bytes, sha256) = file_sha256(path)?;
+                let size_matches = expected == Some(bytes);
+                let sha256_matches = recorded_sha256.map_or(true, |recorded| recorded == sha256);
+                if size_matches && sha256_matches {
+                    return Ok(Outcome::Kept {
+                        bytes,
+                        sha256: Some(sha256),
+                    });
+                }
+                self.fetch_to_file(url, path, 0)
+            }
+        }
+    }
+
+    /// Downloads one file, checksumming it as it is written. Existing files
+    /// are handled according to `mode`, using the size and checksum
+    /// `recorded` in an earlier manifest when available. Failures are
+    /// reported in the result rather than returned as errors.
+    pub fn download_file(
+        &self,
+        url: &str,
+        path: &str,
+        mode: DownloadMode,
+        recorded: Option<&DownloadResult>,
+    ) -> DownloadResult {
+        let timer = Instant::now();
+        let outcome = match self.interrupt.check() {
+            Ok(()) => self.fetch_with_mode(url, path, mode, recorded),
+            Err(e) => Err(e.into()),
+        };
+        let mut result = DownloadResult {
+            url: url.to_string(),
+            path: self.output_path(path).to_string(),
+            bytes: None,
+            seconds: 0.0,
+            sha256: None,
+            status: "ok".to_string(),
+            resumed_from: None,
+            compressed_bytes: None,
+            compressed_sha256: None,
+            error: None,
+        };
+        match outcome {
+            Ok(Outcome::Downloaded {
+                bytes,
+                sha256,
+                resumed_from,
+                compressed,
+            }) => {
+                result.bytes = Some(bytes);
+                result.sha256 = Some(sha256);
+                result.resumed_from = resumed_from;
+                if let Some((bytes, sha256)) = compressed {
+                    result.compressed_bytes = Some(bytes);
+                    result.compressed_sha256 = Some(sha256);
+                }
+            }
+            Ok(Outcome::Kept { bytes, sha256 }) => {
+                result.bytes = Some(bytes);
+                result.sha256 = sha256;
+                result.status = "skipped".to_string();
+                if let Some(recorded) = recorded {
+                    result.compressed_bytes = recorded.compressed_bytes;
+                    result.compressed_sha256 = recorded.compressed_sha256.clone();
+                }
+            }
+            Err(e) => {
+                result.status = if is_interrupted(&e) {
+                    "cancelled"
+                } else {
+                    "failed"
+                }
+                .to_string();
+                result.error = Some(e.to_string());
+            }
+        }
+        result.seconds = timer.elapsed().as_secs_f64();
+        result
+    }
+
+    /// The GWAS-SSF metadata file published next to `url`, if there is one.
+    pub fn published_metadata(&self, url: &str) -> Option<String> {
+        let response = http::send(self.client.get(format!("{url}{METADATA_SUFFIX}"))).ok()?;
+        if !response.status().is_success() {
+            return None;
+        }
+        let body = self.read_body(response).ok()?;
+        Some(String::from_utf8_lossy(&body).into_owned())
+    }
+
+    /// The `data_file_md5sum` from the metadata file published next to
+    /// `url`, if there is one.
+    fn published_md5(&self, url: &str) -> Option<String> {
+        let metadata = self.published_metadata(url)?;
+        metadata_value(&metadata, "data_file_md5sum").map(|md5| md5.to_ascii_lowercase())
+    }
+
+    /// Checks a local file without downloading it: its size against the
+    /// remote `Content-Length` (or the size `recorded` in a manifest), and
+    /// its checksums against the recorded SHA-256 and the published MD5.
+    pub fn verify_file(
+        &self,
+        url: &str,
+        path: &str,
+        recorded: Option<&DownloadResult>,
+    ) -> VerifyResult {
+        let mut result = VerifyResult {
+            url: url.to_string(),
+            path: path.to_string(),
+            status: "missing".to_string(),
+            bytes: None,
+            expected_bytes: None,
+            sha256: None,
+            expected_sha256: recorded.and_then(|r| r.sha256.clone()),
+            md5: None,
+            expected_md5: None,
+            error: None,
+        };
+        if let Err(e) = self.interrupt.check() {
+            result.status = "cancelled".to_string();
+            result.error = Some(e.to_string());
+            return result;
+        }
+        // The remote size and published MD5 describe the compressed file,
+        // not one decompressed on download.
+        let decompressed =
+            Compression::from_path(url).is_some() && Compression::from_path(path).is_none();
+        result.expected_bytes = if decompressed {
+            recorded.and_then(|r| r.bytes)
+        } else {
+            self.remote_size(url)
+                .or_else(|| recorded.and_then(|r| r.bytes))
+        };
+        if !Path::new(path).is_file() {
+            return result;
+        }
+        let (bytes, sha256, md5) = match file_digests(path) {
+            Ok(digests) => digests,
+            Err(e) => {
+                result.status = "failed".to_string();
+                result.error = Some(e.to_string());
+                return result;
+            }
+        };
+        result.bytes = Some(bytes);
+        result.sha256 = Some(sha256);
+        result.md5 = Some(md5);
+        if !decompressed {
+            result.expected_md5 = self.published_md5(url);
+        }
+
+        let differs = |actual: &Option<String>, expected: &Option<String>| matches!((actual, expected), (Some(a), Some(e)) if a != e);
+        result.status = match result.expected_bytes {
+            Some(expected) if bytes < expected => "truncated",
+            Some(expected) if bytes > expected => "corrupted",
+            _ if differs(&result.sha256, &result.expected_sha256)
+                || differs(&result.md5, &result.expected_md5) =>
+            {
+                "corrupted"
+            }
+            None if result.expected_sha256.is_none() && result.expected_md5.is_none() => {
+                "unverified"
+            }
+            _ => "ok",
+        }
+        .to_string();
+        result
+    }
+
+    /// Verifies files on `max_concurrent` threads, using the checksums of
+    /// the manifest written next to them. Results are in the order of
+    /// `urls`.
+    pub fn verify_files(
+        &self,
+        urls: &[String],
+        paths: &[String],
+        max_concurrent: usize,
+    ) -> Result<Vec<VerifyResult>> {
+        let recorded = read_manifest(paths);
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(max_concurrent)
+            .build()?;
+        Ok(pool.install(|| {
+            urls.par_iter()
+                .zip(paths.par_iter())
+                .map(|(url, path)| {
+                    let recorded = recorded.get(path).filter(|r| same_file(&r.url, url));
+                    self.verify_file(url, path, recorded)
+                })
+                .collect()
+        }))
+    }
+
+    /// Throughput of a ranged read of the first `PROBE_BYTES` of `url`, in
+    /// bytes per second, including connection setup.
+    pub fn probe_throughput(&self, url: &str) -> Result<f64> {
+        let timer = Instant::now();
+        let response = http::send(
+            self.client
+                .get(url)
+                .header(RANGE, format!("bytes=0-{}", PROBE_BYTES - 1)),
+        )?;
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("HTTP {}", response.status()));
+        }
+        // Servers ignoring the range send the whole file; stop early.
+        let mut body = Vec::new();
+        stats::Counted(response.take(PROBE_BYTES)).read_to_end(&mut body)?;
+        Ok(body.len() as f64 / timer.elapsed().as_secs_f64().max(1e-6))
+    }
+
+    /// The mirror in `mirrors` serving `url` fastest, probing each in turn
+    /// so the measurements do not compete for bandwidth. `None` when `url`
+    /// is not under any mirror or every probe fails.
+    pub fn fastest_mirror(&self, url: &str, mirrors: &[String]) -> Option<String> {
+        self.fastest_for(mirror_relative(url, mirrors)?, mirrors)
+    }
+
+    /// The mirror in `mirrors` serving the file at `relative` fastest.
+    fn fastest_for(&self, relative: &str, mirrors: &[String]) -> Option<String> {
+        mirrors
+            .iter()
+            .filter_map(|mirror| {
+                let rate = self.probe_throughput(&format!("{mirror}{relative}")).ok()?;
+                Some((mirror, rate))
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(mirror, _)| mirror.clone())
+    }
+
+    /// Points URLs under a known mirror at the session's download mirror,
+    /// choosing the fastest HTTPS one on first use and remembering it in the
+    /// session configuration. Plain HTTP mirrors are only used when
+    /// configured. Other URLs are left alone.
+    pub fn use_download_mirror(&self, urls: Vec<String>) -> Vec<String> {
+        let configured = session_config().ok().and_then(|c| c.download_mirror);
+        let mut mirrors: Vec<String> = DOWNLOAD_MIRRORS.iter().map(|m| m.to_string()).collect();
+        mirrors.extend(configured.clone());
+        let mirror = match configured {
+            Some(mirror) => mirror,
+            None => {
+                let relative = match urls.iter().find_map(|url| mirror_relative(url, &mirrors)) {
+                    Some(relative) => relative,
+                    None => return urls,
+                };
+                let secure: Vec<String> = mirrors
+                    .iter()
+                    .filter(|mirror| mirror.starts_with("https://"))
+                    .cloned()
+                    .collect();
+                match secure.as_slice() {
+                    [only] => only.clone(),
+                    _ => match self.fastest_for(relative, &secure) {
+                        Some(mirror) => {
+                            let _ = configure(None, None, None, Some(mirror.clone()));
+                            mirror
+                        }
+                        None => return urls,
+                    },
+                }
+            }
+        };
+        urls.iter()
+            .map(|url| match mirror_relative(url, &mirrors) {
+                Some(relative) => format!("{mirror}{relative}"),
+                None => url.clone(),
+            })
+            .collect()
+    }
+
+    /// Downloads files on `max_concurrent` threads. Results are in the
+    /// order of `urls`.
+    pub fn download_files(
+        &self,
+        urls: &[String],
+        paths: &[String],
+        max_concurrent: usize,
+        mode: DownloadMode,
+    ) -> Result<Vec<DownloadResult>> {
+        self.download_files_with_progress(urls, paths, max_concurrent, mode, |_| {})
+    }
+
+    /// Like `download_files`, calling `progress` from the worker threads as
+    /// each file finishes.
+    pub fn download_files_with_progress(
+        &self,
+        urls: &[String],
+        paths: &[String],
+        max_concurrent: usize,
+        mode: DownloadMode,
+        progress: impl Fn(&DownloadResult) + Sync,
+    ) -> Result<Vec<DownloadResult>> {
+        self.check_disk_space(urls, paths, max_concurrent, mode)?;
+        self.download_files_unchecked(urls, paths, max_concurrent, mode, progress)
+    }
+
+    /// `download_files_with_progress` without the disk space check, for
+    /// callers that made it already.
+    pub fn download_files_unchecked(
+        &self,
+        urls: &[String],
+        paths: &[String],
+        max_concurrent: usize,
+        mode: DownloadMode,
+        progress: impl Fn(&DownloadResult) + Sync,
+    ) -> Result<Vec<DownloadResult>> {
+        let recorded = match mode {
+            DownloadMode::Overwrite => HashMap::new(),
+            _ => read_manifest(paths),
+        };
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(max_concurrent)
+            .build()?;
+        self.interrupt.progress().add_files_total(urls.len() as u64);
+        Ok(pool.install(|| {
+            urls.par_iter()
+                .zip(paths.par_iter())
+                .map(|(url, path)| {
+                    let recorded = recorded
+                        .get(self.output_path(path))
+                        .filter(|r| same_file(&r.url, url));
+                    let result = self.download_file(url, path, mode, recorded);
+                    self.interrupt.progress().file_done();
+                    progress(&result);
+                    result
+                })
+                .collect()
+        }))
+    }
+
+    /// Bytes still to be written for `url` at `path` under `mode`, from
+    /// the server's Content-Length. Decompressed files grow beyond it, so
+    /// for them this is a lower bound.
+    fn bytes_needed(&self, url: &str, path: &str, mode: DownloadMode) -> Option<u64> {
+        let total = self.remote_size(url)?;
+        let output = self.output_path(path);
+        let existing = match fs::metadata(output) {
+            Ok(metadata) if metadata.is_file() && mode != DownloadMode::Overwrite => metadata.len(),
+            _ => return Some(total),
+        };
+        Some(if output != path {
+            // Kept when it matches the manifest, which is the usual case.
+            0
+        } else if existing == total {
+            0
+        } else if mode == DownloadMode::Resume && existing < total {
+            total - existing
+        } else {
+            total
+        })
+    }
+
+    /// Sums the sizes of the files with HEAD requests, on `max_concurrent`
+    /// threads, and fails before anything is downloaded when they will not
+    /// fit on a filesystem they go to. Files of unknown size and
+    /// filesystems whose free space cannot be read are left out, so the
+    /// check never stops a download that might fit.
+    pub fn check_disk_space(
+        &self,
+        urls: &[String],
+        paths: &[String],
+        max_concurrent: usize,
+        mode: DownloadMode,
+    ) -> Result<Vec<SpaceCheck>> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(max_concurrent)
+            .build()?;
+        let needed: Vec<Option<u64>> = pool.install(|| {
+            urls.par_iter()
+                .zip(paths.par_iter())
+                .map(|(url, path)| {
+                    if self.interrupt.is_requested() {
+                        return None;
+                    }
+                    self.bytes_needed(url, path, mode)
+                })
+                .collect()
+        });
+
+        let mut filesystems: HashMap<PathBuf, Option<(String, u64)>> = HashMap::new();
+        let mut checks: Vec<SpaceCheck> = Vec::new();
+        for (path, needed) in paths.iter().zip(needed) {
+            let needed = match needed {
+                Some(needed) if needed > 0 => needed,
+                _ => continue,
+            };
+            let dir = Path::new(path)
+                .parent()
+                .unwrap_or_else(|| Path::new(""))
+                .to_path_buf();
+            let (mount, available) = match filesystems
+                .entry(dir)
+                .or_insert_with_key(|dir| free_space(dir))
+            {
+                Some(filesystem) => filesystem.clone(),
+                None => continue,
+            };
+            match checks.iter_mut().find(|check| check.mount == mount) {
+                Some(check) => check.needed += needed,
+                None => checks.push(SpaceCheck {
+                    mount,
+                    needed,
+                    available,
+                }),
+            }
+        }
+
+        if let Some(check) = checks.iter().find(|check| check.needed > check.available) {
+            return Err(anyhow::anyhow!(
+                "Not enough disk space: the downloads need {} on {}, which has {} free. \
+                 Free up space or choose another output directory",
+                format_bytes(check.needed),
+                check.mount,
+                format_bytes(check.available)
+            ));
+        }
+        Ok(checks)
+    }
+}
+
+/// Value of a top-level `key: value` entry of a metadata file, joining the
+/// indented lines of a `>` or `|` block.
+pub fn metadata_value(metadata: &str, key: &str) -> Option<String> {
+    let mut lines = metadata.lines();
+    let value = lines.find_map(|line| line.strip_prefix(key)?.strip_prefix(':'))?;
+    let value = value.trim();
+    let value = if value.starts_with('>') || value.starts_with('|') {
+        lines
+            .take_while(|line| line.starts_with(' ') || line.trim().is_empty())
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+    } else {
+        value.trim_matches(|c| c == '"' || c == '\'').to_string()
+    };
+    Some(value).filter(|value| !value.is_empty())
+}
+
+/// The part of `url` after the mirror prefix it starts with, beginning
+/// with `/`.
+fn mirror_relative<'a>(url: &'a str, mirrors: &[String]) -> Option<&'a str> {
+    mirrors
+        .iter()
+        .filter_map(|mirror| url.strip_prefix(mirror.trim_end_matches('/')))
+        .find(|relative| relative.starts_with('/'))
+}
+
+/// Whether two URLs name the same file, possibly on different mirrors.
+fn same_file(a: &str, b: &str) -> bool {
+    let mirrors: Vec<String> = DOWNLOAD_MIRRORS.iter().map(|m| m.to_string()).collect();
+    match (mirror_relative(a, &mirrors), mirror_relative(b, &mirrors)) {
+        (Some(a), Some(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Deepest directory containing every path, where `manifest.json` is
+/// written.
+pub fn manifest_dir(paths: &[String]) -> PathBuf {
+    let mut common: Option<PathBuf> = None;
+    for path in paths {
+        let parent = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+        common = Some(match common {
+            None => parent.to_path_buf(),
+            Some(dir) => dir
+                .components()
+                .zip(parent.components())
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a)
+                .collect(),
+        });
+    }
+    common.unwrap_or_default()
+}
+
+/// Results recorded by an earlier run for these paths, keyed by path.
+/// Failed or cancelled downloads and unreadable manifests are ignored.
+fn read_manifest(paths: &[String]) -> HashMap<String, DownloadResult> {
+    let path = manifest_dir(paths).join(MANIFEST_FILE);
+    let manifest: Option<DownloadManifest> = fs::read(path)
+        .ok()
+        .and_then(|data| serde_json::from_slice(&data).ok());
+    manifest
+        .map(|manifest| manifest.files)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|file| matches!(file.status.as_str(), "ok" | "skipped"))
+        .map(|file| (file.path.clone(), file))
+        .collect()
+}
+
+/// Writes the results to `manifest.json` next to the downloads and
+/// returns its path.
+pub fn write_manifest(results: &[DownloadResult]) -> Result<String> {
+    let paths: Vec<String> = results.iter().map(|r| r.path.clone()).collect();
+    let path = manifest_dir(&paths).join(MANIFEST_FILE);
+    let manifest = DownloadManifest {
+        created: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        files: results.to_vec(),
+    };
+    let mut file = AtomicFile::create(&path)?;
+    file.write_all(serde_json::to_string_pretty(&manifest)?.as_bytes())?;
+    file.commit()?;
+    Ok(path.to_string_lossy().into_owned())
+}
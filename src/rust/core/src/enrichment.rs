@@ -0,0 +1,246 @@
+use crate::ids::chromosome_number;
+use crate::sumstats::open_text;
+use crate::variant_index::splitmix64;
+use crate::Association;
+use anyhow::Result;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::BufRead;
+
+/// GRCh38 chromosome lengths, by numeric chromosome (X = 23, Y = 24,
+/// MT = 25). Hits are shifted around these circles.
+const GRCH38_LENGTHS: [(i32, i64); 25] = [
+    (1, 248_956_422),
+    (2, 242_193_529),
+    (3, 198_295_559),
+    (4, 190_214_555),
+    (5, 181_538_259),
+    (6, 170_805_979),
+    (7, 159_345_973),
+    (8, 145_138_636),
+    (9, 138_394_717),
+    (10, 133_797_422),
+    (11, 135_086_622),
+    (12, 133_275_309),
+    (13, 114_364_328),
+    (14, 107_043_718),
+    (15, 101_991_189),
+    (16, 90_338_345),
+    (17, 83_257_441),
+    (18, 80_373_285),
+    (19, 58_617_616),
+    (20, 64_444_167),
+    (21, 46_709_983),
+    (22, 50_818_468),
+    (23, 156_040_895),
+    (24, 57_227_415),
+    (25, 16_569),
+];
+
+/// Merged BED intervals, 1-based and inclusive, sorted by start within
+/// each numeric chromosome.
+#[derive(Debug, Default)]
+pub struct Track {
+    intervals: HashMap<i32, Vec<(i64, i64)>>,
+}
+
+impl Track {
+    /// Reads a BED file, plain or gzipped. Header, `track` and `browser`
+    /// lines are skipped, as are chromosomes other than 1-22, X, Y and MT.
+    pub fn open(path: &str) -> Result<Self> {
+        let mut intervals: HashMap<i32, Vec<(i64, i64)>> = HashMap::new();
+        for line in open_text(path)?.lines() {
+            let line = line?;
+            if line.starts_with('#')
+                || line.starts_with("track")
+                || line.starts_with("browser")
+                || line.trim().is_empty()
+            {
+                continue;
+            }
+            let mut fields = line.split('\t');
+            let (chromosome, start, end) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(chromosome), Some(start), Some(end)) => (chromosome, start, end),
+                _ => continue,
+            };
+            let (chromosome, start, end) = match (
+                chromosome_number(chromosome).map(i32::from),
+                start.trim().parse::<i64>(),
+                end.trim().parse::<i64>(),
+            ) {
+                (Some(chromosome), Ok(start), Ok(end)) if end > start => (chromosome, start, end),
+                _ => continue,
+            };
+            intervals
+                .entry(chromosome)
+                .or_default()
+                .push((start + 1, end));
+        }
+
+        for list in intervals.values_mut() {
+            list.sort_unstable();
+            let mut merged: Vec<(i64, i64)> = Vec::with_capacity(list.len());
+            for &(start, end) in list.iter() {
+                match merged.last_mut() {
+                    Some(last) if start <= last.1 + 1 => last.1 = last.1.max(end),
+                    _ => merged.push((start, end)),
+                }
+            }
+            *list = merged;
+        }
+        Ok(Self { intervals })
+    }
+
+    pub fn intervals(&self) -> usize {
+        self.intervals.values().map(Vec::len).sum()
+    }
+
+    pub fn covered_bp(&self) -> i64 {
+        self.intervals
+            .values()
+            .flatten()
+            .map(|(start, end)| end - start + 1)
+            .sum()
+    }
+
+    pub fn contains(&self, chromosome: i32, position: i64) -> bool {
+        let list = match self.intervals.get(&chromosome) {
+            Some(list) => list,
+            None => return false,
+        };
+        let after = list.partition_point(|&(start, _)| start <= position);
+        after > 0 && list[after - 1].1 >= position
+    }
+
+    fn max_end(&self, chromosome: i32) -> i64 {
+        self.intervals
+            .get(&chromosome)
+            .and_then(|list| list.last())
+            .map_or(0, |&(_, end)| end)
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Enrichment {
+    pub track: String,
+    /// Distinct hit positions tested.
+    pub hits: usize,
+    /// Hits without a chromosome or position.
+    pub skipped: usize,
+    pub intervals: usize,
+    pub covered_bp: i64,
+    /// Hits inside the track.
+    pub observed: usize,
+    /// Mean overlap of the shifted hits.
+    pub expected: f64,
+    pub sd: f64,
+    pub fold_enrichment: Option<f64>,
+    pub z_score: Option<f64>,
+    /// One-sided, (1 + shifts with at least the observed overlap) /
+    /// (1 + n_perm).
+    pub p_value: f64,
+    pub n_perm: usize,
+    pub seed: u64,
+}
+
+/// Tests whether `hits` fall inside `track` more often than expected.
+/// Each permutation rotates every chromosome's hits by its own random
+/// offset around the chromosome, so the background keeps the number of
+/// hits per chromosome and their spacing (and with it their LD
+/// clustering). Permutations run in parallel; each draws from its own
+/// stream of `seed`, so results do not depend on the thread count.
+pub fn enrichment(
+    hits: &[Association],
+    track: &Track,
+    track_name: &str,
+    n_perm: usize,
+    seed: u64,
+) -> Result<Enrichment> {
+    if n_perm == 0 {
+        return Err(anyhow::anyhow!("n_perm must be at least 1"));
+    }
+
+    let mut positions: BTreeMap<i32, Vec<i64>> = BTreeMap::new();
+    let mut seen = HashSet::new();
+    let mut skipped = 0;
+    for hit in hits {
+        match (hit.chromosome, hit.base_pair_location) {
+            (Some(chromosome), Some(position)) if position > 0 => {
+                if seen.insert((chromosome, position)) {
+                    positions.entry(chromosome).or_default().push(position);
+                }
+            }
+            _ => skipped += 1,
+        }
+    }
+    if seen.is_empty() {
+        return Err(anyhow::anyhow!("No hits with a chromosome and position"));
+    }
+
+    let lengths: HashMap<i32, i64> = GRCH38_LENGTHS.iter().copied().collect();
+    // Hits or intervals beyond GRCh38 (another build or a contig) widen
+    // the circle rather than fall off it.
+    let chromosomes: Vec<(i32, i64, &[i64])> = positions
+        .iter()
+        .map(|(chromosome, list)| {
+            let length = lengths
+                .get(chromosome)
+                .copied()
+                .unwrap_or(0)
+                .max(list.iter().copied().max().unwrap_or(0))
+                .max(track.max_end(*chromosome));
+            (*chromosome, length, list.as_slice())
+        })
+        .collect();
+
+    let overlap = |shifts: &dyn Fn(usize, i64) -> i64| -> usize {
+        chromosomes
+            .iter()
+            .enumerate()
+            .map(|(i, &(chromosome, length, list))| {
+                let offset = shifts(i, length);
+                list.iter()
+                    .filter(|&&position| {
+                        track.contains(chromosome, (position - 1 + offset) % length + 1)
+                    })
+                    .count()
+            })
+            .sum()
+    };
+
+    let observed = overlap(&|_, _| 0);
+    let null: Vec<usize> = (0..n_perm)
+        .into_par_iter()
+        .map(|perm| {
+            let stream = splitmix64(seed ^ (perm as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+            overlap(&|i, length| (splitmix64(stream.wrapping_add(i as u64)) % length as u64) as i64)
+        })
+        .collect();
+
+    let n = n_perm as f64;
+    let expected = null.iter().sum::<usize>() as f64 / n;
+    let variance = null
+        .iter()
+        .map(|&count| (count as f64 - expected).powi(2))
+        .sum::<f64>()
+        / (n - 1.0).max(1.0);
+    let sd = variance.sqrt();
+    let as_extreme = null.iter().filter(|&&count| count >= observed).count();
+
+    Ok(Enrichment {
+        track: track_name.to_string(),
+        hits: seen.len(),
+        skipped,
+        intervals: track.intervals(),
+        covered_bp: track.covered_bp(),
+        observed,
+        expected,
+        sd,
+        fold_enrichment: (expected > 0.0).then(|| observed as f64 / expected),
+        z_score: (sd > 0.0).then(|| (observed as f64 - expected) / sd),
+        p_value: (1 + as_extreme) as f64 / (1 + n_perm) as f64,
+        n_perm,
+        seed,
+    })
+}
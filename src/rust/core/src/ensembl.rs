@@ -0,0 +1,311 @@
+use crate::http::{self, error_text, read_json, session_client, session_max_response_bytes};
+use crate::interrupt::Interrupt;
+use crate::stats;
+use crate::Association;
+use anyhow::Result;
+use reqwest::blocking::{Client, Response};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+static GENE_CACHE: Mutex<Option<HashMap<String, Gene>>> = Mutex::new(None);
+static VEP_CACHE: Mutex<Option<HashMap<String, VepAnnotation>>> = Mutex::new(None);
+static LAST_REQUEST: Mutex<Option<Instant>> = Mutex::new(None);
+
+// Ensembl allows 15 requests per second per client.
+const MIN_REQUEST_INTERVAL: Duration = Duration::from_millis(70);
+const VEP_BATCH_SIZE: usize = 200;
+const MAX_RETRIES: usize = 5;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Gene {
+    pub id: String,
+    pub display_name: Option<String>,
+    pub seq_region_name: String,
+    pub start: i64,
+    pub end: i64,
+    pub strand: Option<i32>,
+    pub biotype: Option<String>,
+    pub assembly_name: Option<String>,
+}
+
+impl Gene {
+    /// The gene body plus `flank_kb` on each side, clamped at 0.
+    pub fn window(&self, flank_kb: f64) -> (i64, i64) {
+        let flank = (flank_kb * 1000.0).round() as i64;
+        ((self.start - flank).max(0), self.end + flank)
+    }
+
+    /// Base pairs from the gene body to `position`, 0 inside it.
+    pub fn distance(&self, position: i64) -> i64 {
+        (self.start - position).max(position - self.end).max(0)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TranscriptConsequence {
+    pub gene_symbol: Option<String>,
+    pub impact: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VepResult {
+    pub id: String,
+    pub most_severe_consequence: Option<String>,
+    #[serde(default)]
+    pub transcript_consequences: Vec<TranscriptConsequence>,
+}
+
+/// The subset of a VEP result merged onto associations.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct VepAnnotation {
+    pub most_severe_consequence: Option<String>,
+    pub gene_symbol: Vec<String>,
+    pub impact: Option<String>,
+}
+
+impl From<VepResult> for VepAnnotation {
+    fn from(result: VepResult) -> Self {
+        let mut gene_symbol: Vec<String> = result
+            .transcript_consequences
+            .iter()
+            .filter_map(|t| t.gene_symbol.clone())
+            .collect();
+        gene_symbol.sort();
+        gene_symbol.dedup();
+
+        let impact = result
+            .transcript_consequences
+            .iter()
+            .filter_map(|t| t.impact.clone())
+            .min_by_key(|impact| impact_rank(impact));
+
+        Self {
+            most_severe_consequence: result.most_severe_consequence,
+            gene_symbol,
+            impact,
+        }
+    }
+}
+
+fn impact_rank(impact: &str) -> usize {
+    match impact {
+        "HIGH" => 0,
+        "MODERATE" => 1,
+        "LOW" => 2,
+        _ => 3,
+    }
+}
+
+pub const VEP_FIELDS: [&str; 3] = ["most_severe_consequence", "gene_symbol", "impact"];
+
+#[derive(Debug, Clone)]
+pub struct EnsemblClient {
+    client: Client,
+    base_url: String,
+    interrupt: Interrupt,
+}
+
+impl Default for EnsemblClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EnsemblClient {
+    pub fn new() -> Self {
+        Self::with_base_url("https://rest.ensembl.org".to_string())
+    }
+
+    pub fn with_base_url(base_url: String) -> Self {
+        Self {
+            client: session_client(),
+            base_url,
+            interrupt: Interrupt::new(),
+        }
+    }
+
+    /// Ends rate-limit waits early when `interrupt` is requested, e.g. the
+    /// flag of the backend whose queries the lookups are part of.
+    pub fn with_interrupt(mut self, interrupt: Interrupt) -> Self {
+        self.interrupt = interrupt;
+        self
+    }
+
+    /// Resolves a human gene symbol to its GRCh38 coordinates. Lookups are
+    /// cached for the lifetime of the R session.
+    pub fn get_gene(&self, symbol: &str) -> Result<Gene> {
+        let key = symbol.to_uppercase();
+        if let Some(gene) = GENE_CACHE
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Gene cache poisoned"))?
+            .as_ref()
+            .and_then(|cache| cache.get(&key))
+        {
+            stats::cache_hits(1);
+            return Ok(gene.clone());
+        }
+        stats::cache_misses(1);
+
+        let url = format!("{}/lookup/symbol/homo_sapiens/{symbol}", self.base_url);
+        let response = self.send_with_retry(|| {
+            http::send(
+                self.client
+                    .get(&url)
+                    .header("Content-Type", "application/json"),
+            )
+        })?;
+        let gene: Gene = read_json(response, session_max_response_bytes())?;
+
+        GENE_CACHE
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Gene cache poisoned"))?
+            .get_or_insert_with(HashMap::new)
+            .insert(key, gene.clone());
+        Ok(gene)
+    }
+
+    /// Runs the VEP REST endpoint over variant IDs in batches, reusing
+    /// annotations already fetched in this session.
+    pub fn vep(&self, variant_ids: &[String]) -> Result<HashMap<String, VepAnnotation>> {
+        let mut annotations = HashMap::new();
+        let mut missing = Vec::new();
+        {
+            let cache = VEP_CACHE
+                .lock()
+                .map_err(|_| anyhow::anyhow!("VEP cache poisoned"))?;
+            for id in variant_ids {
+                match cache.as_ref().and_then(|cache| cache.get(id)) {
+                    Some(annotation) => {
+                        annotations.insert(id.clone(), annotation.clone());
+                    }
+                    None => missing.push(id.clone()),
+                }
+            }
+        }
+        stats::cache_hits((variant_ids.len() - missing.len()) as u64);
+        missing.sort();
+        missing.dedup();
+        stats::cache_misses(missing.len() as u64);
+
+        let url = format!("{}/vep/homo_sapiens/id", self.base_url);
+        for batch in missing.chunks(VEP_BATCH_SIZE) {
+            let body = serde_json::json!({ "ids": batch });
+            let response = self.send_with_retry(|| {
+                http::send(
+                    self.client
+                        .post(&url)
+                        .header("Content-Type", "application/json")
+                        .header("Accept", "application/json")
+                        .json(&body),
+                )
+            })?;
+            let results: Vec<VepResult> = read_json(response, session_max_response_bytes())?;
+
+            let mut cache = VEP_CACHE
+                .lock()
+                .map_err(|_| anyhow::anyhow!("VEP cache poisoned"))?;
+            let cache = cache.get_or_insert_with(HashMap::new);
+            for result in results {
+                let id = result.id.clone();
+                let annotation = VepAnnotation::from(result);
+                cache.insert(id.clone(), annotation.clone());
+                annotations.insert(id, annotation);
+            }
+        }
+
+        Ok(annotations)
+    }
+
+    /// Spaces requests to stay under the Ensembl rate limit and retries
+    /// after `Retry-After` when the server answers 429.
+    fn send_with_retry(&self, send: impl Fn() -> reqwest::Result<Response>) -> Result<Response> {
+        for attempt in 1..=MAX_RETRIES {
+            {
+                let mut last = LAST_REQUEST
+                    .lock()
+                    .map_err(|_| anyhow::anyhow!("Rate limiter poisoned"))?;
+                if let Some(elapsed) = last.map(|t| t.elapsed()) {
+                    if elapsed < MIN_REQUEST_INTERVAL {
+                        stats::rate_limit_sleep(MIN_REQUEST_INTERVAL - elapsed);
+                        self.interrupt.sleep(MIN_REQUEST_INTERVAL - elapsed)?;
+                    }
+                }
+                *last = Some(Instant::now());
+            }
+
+            let response = send()?;
+            if response.status() == StatusCode::TOO_MANY_REQUESTS {
+                let wait = response
+                    .headers()
+                    .get("Retry-After")
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse::<f64>().ok())
+                    .unwrap_or(1.0);
+                tracing::info!(attempt, "Ensembl rate limit exceeded; retrying in {wait} s");
+                stats::retry();
+                stats::rate_limit_sleep(Duration::from_secs_f64(wait));
+                self.interrupt.sleep(Duration::from_secs_f64(wait))?;
+                continue;
+            }
+            if !response.status().is_success() {
+                let status = response.status();
+                let text = error_text(response);
+                return Err(anyhow::anyhow!("Ensembl HTTP {}: {}", status, text));
+            }
+            return Ok(response);
+        }
+
+        Err(anyhow::anyhow!(
+            "Ensembl rate limit still exceeded after {} attempts",
+            MAX_RETRIES
+        ))
+    }
+}
+
+/// Merges the requested VEP fields onto each association by variant ID.
+/// Associations VEP returns nothing for get empty fields.
+pub fn annotate_with_vep(
+    ensembl: &EnsemblClient,
+    associations: Vec<Association>,
+    fields: &[String],
+) -> Result<Vec<serde_json::Value>> {
+    for field in fields {
+        if !VEP_FIELDS.contains(&field.as_str()) {
+            return Err(anyhow::anyhow!(
+                "Unknown VEP field {}; expected one of {}",
+                field,
+                VEP_FIELDS.join(", ")
+            ));
+        }
+    }
+
+    let ids: Vec<String> = associations
+        .iter()
+        .filter_map(|a| a.variant_id.clone())
+        .collect();
+    let annotations = ensembl.vep(&ids)?;
+
+    associations
+        .into_iter()
+        .map(|association| {
+            let annotation = association
+                .variant_id
+                .as_ref()
+                .and_then(|id| annotations.get(id))
+                .cloned()
+                .unwrap_or_default();
+            let annotation = serde_json::to_value(annotation)?;
+
+            let mut value = serde_json::to_value(association)?;
+            if let Some(object) = value.as_object_mut() {
+                for field in fields {
+                    object.insert(field.clone(), annotation[field.as_str()].clone());
+                }
+            }
+            Ok(value)
+        })
+        .collect()
+}
@@ -0,0 +1,191 @@
+use crate::backend::SumstatsBackend;
+use crate::ensembl::{EnsemblClient, Gene};
+use crate::opentargets::{OpenTargetsClient, StudyLocusEvidence};
+use crate::{Association, GwasFilter};
+use anyhow::Result;
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// Associations fetched from the gene region, before keeping the best
+/// `max_variants` variants.
+const REGION_PAGE_SIZE: i32 = 500;
+
+/// One variant's evidence linking a trait association to a gene.
+#[derive(Debug, Serialize, Clone)]
+pub struct GeneEvidence {
+    /// Position in the table, 1 for the strongest evidence.
+    pub rank: usize,
+    pub variant_id: String,
+    pub chromosome: Option<i32>,
+    pub base_pair_location: Option<i64>,
+    /// Base pairs from the gene body, 0 inside it.
+    pub distance: Option<i64>,
+    pub study_accession: Option<String>,
+    pub p_value: Option<f64>,
+    /// Best Open Targets locus-to-gene score of the gene over the study
+    /// loci the variant tags.
+    pub l2g_score: Option<f64>,
+    pub l2g_study: Option<String>,
+    /// Whether the gene has the top L2G score at that locus.
+    pub l2g_top_gene: bool,
+    /// Strongest GTEx eQTL colocalisation (posterior H4) of the gene.
+    pub eqtl_h4: Option<f64>,
+    pub eqtl_tissue: Option<String>,
+    pub eqtl_study: Option<String>,
+    /// Why Open Targets evidence is missing for this variant.
+    pub error: Option<String>,
+}
+
+/// Evidence for `symbol` from `trait_id` associations within `flank_kb` of
+/// the gene, Open Targets L2G scores and GTEx eQTL colocalisations.
+#[derive(Debug, Serialize, Clone)]
+pub struct GeneEvidenceReport {
+    pub gene: Gene,
+    pub trait_id: String,
+    pub associations: usize,
+    pub evidence: Vec<GeneEvidence>,
+}
+
+impl GeneEvidence {
+    fn new(association: &Association, variant_id: String, gene: &Gene) -> Self {
+        Self {
+            rank: 0,
+            variant_id,
+            chromosome: association.chromosome,
+            base_pair_location: association.base_pair_location,
+            distance: association
+                .base_pair_location
+                .map(|position| gene.distance(position)),
+            study_accession: association.study_accession.clone(),
+            p_value: association.p_value,
+            l2g_score: None,
+            l2g_study: None,
+            l2g_top_gene: false,
+            eqtl_h4: None,
+            eqtl_tissue: None,
+            eqtl_study: None,
+            error: None,
+        }
+    }
+
+    /// Keeps the gene's best L2G score and eQTL colocalisation over `loci`.
+    fn add_loci(&mut self, gene: &Gene, loci: &[StudyLocusEvidence]) {
+        for locus in loci {
+            for (i, score) in locus.l2g.iter().enumerate() {
+                let matches =
+                    score.gene_id == gene.id || is_gene_symbol(gene, score.gene_symbol.as_deref());
+                if matches && self.l2g_score.map_or(true, |best| score.score > best) {
+                    self.l2g_score = Some(score.score);
+                    self.l2g_study = Some(locus.study_id.clone());
+                    // Scores are sorted, highest first.
+                    self.l2g_top_gene = i == 0;
+                }
+            }
+            for coloc in &locus.colocalisations {
+                let matches = coloc.gene_id.as_deref() == Some(gene.id.as_str())
+                    || is_gene_symbol(gene, coloc.gene_symbol.as_deref());
+                let gtex = coloc.qtl_study.to_ascii_uppercase().starts_with("GTEX");
+                if matches && gtex && self.eqtl_h4.map_or(true, |best| coloc.h4 > best) {
+                    self.eqtl_h4 = Some(coloc.h4);
+                    self.eqtl_tissue = coloc.tissue.clone();
+                    self.eqtl_study = Some(coloc.qtl_study.clone());
+                }
+            }
+        }
+    }
+}
+
+fn is_gene_symbol(gene: &Gene, symbol: Option<&str>) -> bool {
+    match (gene.display_name.as_deref(), symbol) {
+        (Some(name), Some(symbol)) => name.eq_ignore_ascii_case(symbol),
+        _ => false,
+    }
+}
+
+/// Higher L2G scores first, then stronger eQTL colocalisation, then
+/// smaller p-values; missing values sort last.
+fn compare_evidence(a: &GeneEvidence, b: &GeneEvidence) -> Ordering {
+    let descending = |a: Option<f64>, b: Option<f64>| match (a, b) {
+        (Some(a), Some(b)) => b.total_cmp(&a),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    };
+    descending(a.l2g_score, b.l2g_score)
+        .then_with(|| descending(a.eqtl_h4, b.eqtl_h4))
+        .then_with(|| descending(b.p_value, a.p_value))
+}
+
+/// Builds the evidence table for a gene and trait. The `max_variants`
+/// most significant variants in the region are looked up in Open Targets
+/// Genetics; a failed lookup is reported on its row.
+#[allow(clippy::too_many_arguments)]
+pub fn gene_evidence(
+    backend: &dyn SumstatsBackend,
+    ensembl: &EnsemblClient,
+    opentargets: &OpenTargetsClient,
+    symbol: &str,
+    trait_id: &str,
+    flank_kb: f64,
+    p_value_max: f64,
+    max_variants: usize,
+) -> Result<GeneEvidenceReport> {
+    let gene = ensembl.get_gene(symbol)?;
+    let filter = GwasFilter {
+        trait_id: Some(trait_id.to_string()),
+        p_value_range: Some(("0.0".to_string(), p_value_max.to_string())),
+        size: Some(REGION_PAGE_SIZE),
+        ..Default::default()
+    };
+    let response = backend.gene_window_associations(&gene, flank_kb, &filter)?;
+    let associations: Vec<Association> = response
+        .embedded
+        .into_iter()
+        .flat_map(|embedded| embedded.into_values())
+        .flat_map(|associations| associations.into_values())
+        .collect();
+
+    // The most significant association of each variant.
+    let mut best: HashMap<String, &Association> = HashMap::new();
+    for association in &associations {
+        let variant_id = match &association.variant_id {
+            Some(variant_id) => variant_id,
+            None => continue,
+        };
+        let p_value = association.p_value.unwrap_or(1.0);
+        let entry = best.entry(variant_id.clone()).or_insert(association);
+        if p_value < entry.p_value.unwrap_or(1.0) {
+            *entry = association;
+        }
+    }
+    let mut evidence: Vec<GeneEvidence> = best
+        .into_iter()
+        .map(|(variant_id, association)| GeneEvidence::new(association, variant_id, &gene))
+        .collect();
+    evidence.sort_by(|a, b| {
+        a.p_value
+            .unwrap_or(1.0)
+            .total_cmp(&b.p_value.unwrap_or(1.0))
+            .then_with(|| a.variant_id.cmp(&b.variant_id))
+    });
+    evidence.truncate(max_variants);
+
+    for row in &mut evidence {
+        match opentargets.variant_evidence(&row.variant_id, None) {
+            Ok(loci) => row.add_loci(&gene, &loci),
+            Err(e) => row.error = Some(e.to_string()),
+        }
+    }
+    evidence.sort_by(compare_evidence);
+    for (i, row) in evidence.iter_mut().enumerate() {
+        row.rank = i + 1;
+    }
+
+    Ok(GeneEvidenceReport {
+        gene,
+        trait_id: trait_id.to_string(),
+        associations: associations.len(),
+        evidence,
+    })
+}
@@ -0,0 +1,365 @@
+use crate::backend::SumstatsBackend;
+use crate::interrupt::is_interrupted;
+use crate::liftover::{lift_associations, ChainMap};
+use crate::paging::{PagePrefetcher, PREFETCH_PAGES};
+use crate::refine::Refinement;
+use crate::{Association, GwasFilter};
+use anyhow::Result;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Formats `gwas_associations()` can write to an `output_file`, the
+/// default first.
+pub const FORMATS: [&str; 3] = ["ndjson", "csv", "tsv"];
+
+/// Columns of CSV and TSV exports, in the order of the API's fields.
+pub const COLUMNS: [&str; 15] = [
+    "variant_id",
+    "chromosome",
+    "base_pair_location",
+    "study_accession",
+    "trait",
+    "p_value",
+    "code",
+    "effect_allele",
+    "other_allele",
+    "effect_allele_frequency",
+    "odds_ratio",
+    "ci_lower",
+    "ci_upper",
+    "beta",
+    "se",
+];
+
+/// How missing values are written in CSV and TSV exports, as R reads them.
+const NA: &str = "NA";
+
+/// What an export wrote, returned to R instead of the rows.
+#[derive(Debug, Serialize)]
+pub struct ExportSummary {
+    pub output_file: String,
+    pub format: String,
+    pub pages: u64,
+    pub rows: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interrupted: Option<bool>,
+}
+
+/// Path of an export written to standard output, for shell pipelines.
+pub const STDOUT: &str = "-";
+
+/// An export file, gzip-compressed when its name ends in `.gz`, or
+/// standard output.
+enum Output {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    Stdout(BufWriter<io::Stdout>),
+}
+
+impl Output {
+    fn create(path: &str) -> Result<Self> {
+        if path == STDOUT {
+            return Ok(Output::Stdout(BufWriter::new(io::stdout())));
+        }
+        let path = Path::new(path);
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)?;
+        }
+        let file = BufWriter::new(File::create(path)?);
+        Ok(if path.extension().map_or(false, |ext| ext == "gz") {
+            Output::Gzip(GzEncoder::new(file, Compression::default()))
+        } else {
+            Output::Plain(file)
+        })
+    }
+
+    /// Writes what is buffered and, for gzip, the end of the stream.
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Output::Plain(mut file) => file.flush(),
+            Output::Gzip(encoder) => encoder.finish()?.flush(),
+            Output::Stdout(mut out) => out.flush(),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Plain(file) => file.write(buf),
+            Output::Gzip(encoder) => encoder.write(buf),
+            Output::Stdout(out) => out.write(buf),
+        }
+    }
+
+    /// Also ends a gzip block, so what was written so far can be
+    /// decompressed even if the stream is never finished.
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Plain(file) => file.flush(),
+            Output::Gzip(encoder) => encoder.flush(),
+            Output::Stdout(out) => out.flush(),
+        }
+    }
+}
+
+/// Writes association rows to a file a page at a time.
+trait RowSink {
+    /// Writes `rows` and flushes them, so they survive a crash of the
+    /// session.
+    fn write_page(&mut self, rows: &[Association]) -> Result<()>;
+
+    fn finish(self: Box<Self>) -> Result<()>;
+}
+
+/// One association per line as compact JSON, with only the refinement's
+/// fields when it projects onto some.
+struct Ndjson {
+    out: Output,
+    refinement: Refinement,
+}
+
+impl RowSink for Ndjson {
+    fn write_page(&mut self, rows: &[Association]) -> Result<()> {
+        for row in rows {
+            if self.refinement.columns.is_some() {
+                serde_json::to_writer(&mut self.out, &self.refinement.project_row(row)?)?;
+            } else {
+                serde_json::to_writer(&mut self.out, row)?;
+            }
+            self.out.write_all(b"\n")?;
+        }
+        self.out.flush()?;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<()> {
+        Ok(self.out.finish()?)
+    }
+}
+
+/// A flat table with a header of `COLUMNS`, or of the refinement's
+/// columns, missing values written as `NA` and traits joined with `;`. CSV quotes values as RFC 4180 does,
+/// and a literal "NA" to keep it apart from a missing value; TSV cannot
+/// quote, so tabs and line breaks inside values become spaces.
+struct Delimited {
+    out: Output,
+    delimiter: char,
+    /// Indices into `COLUMNS` of the columns written, in order.
+    columns: Vec<usize>,
+}
+
+impl Delimited {
+    fn create(mut out: Output, delimiter: char, refinement: &Refinement) -> Result<Self> {
+        let columns = refinement
+            .columns
+            .clone()
+            .unwrap_or_else(|| (0..COLUMNS.len()).collect());
+        let header: Vec<&str> = columns.iter().map(|&i| COLUMNS[i]).collect();
+        writeln!(out, "{}", header.join(&delimiter.to_string()))?;
+        Ok(Self {
+            out,
+            delimiter,
+            columns,
+        })
+    }
+
+    fn cell<'a>(&self, value: Option<Cow<'a, str>>) -> Cow<'a, str> {
+        let value = match value {
+            Some(value) => value,
+            None => return Cow::Borrowed(NA),
+        };
+        if self.delimiter == '\t' {
+            if value.contains(['\t', '\n', '\r']) {
+                return Cow::Owned(value.replace(['\t', '\n', '\r'], " "));
+            }
+            value
+        } else if value == NA || value.contains([self.delimiter, '"', '\n', '\r']) {
+            Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+        } else {
+            value
+        }
+    }
+}
+
+/// The values of `row` in `COLUMNS` order, `None` where missing. Numbers
+/// are written as JSON writes them, e.g. `1e-310`; NaN and infinities
+/// count as missing.
+fn row_values(row: &Association) -> [Option<Cow<'_, str>>; 15] {
+    fn text(value: &Option<String>) -> Option<Cow<'_, str>> {
+        value.as_deref().map(Cow::Borrowed)
+    }
+    let int = |value: Option<i64>| value.map(|value| Cow::Owned(value.to_string()));
+    let float = |value: Option<f64>| {
+        value
+            .and_then(serde_json::Number::from_f64)
+            .map(|number| Cow::Owned(number.to_string()))
+    };
+    [
+        text(&row.variant_id),
+        int(row.chromosome.map(i64::from)),
+        int(row.base_pair_location),
+        text(&row.study_accession),
+        row.trait_ids
+            .as_ref()
+            .filter(|traits| !traits.is_empty())
+            .map(|traits| Cow::Owned(traits.join(";"))),
+        float(row.p_value),
+        int(row.code.map(i64::from)),
+        text(&row.effect_allele),
+        text(&row.other_allele),
+        float(row.effect_allele_frequency),
+        float(row.odds_ratio),
+        float(row.ci_lower),
+        float(row.ci_upper),
+        float(row.beta),
+        float(row.se),
+    ]
+}
+
+impl RowSink for Delimited {
+    fn write_page(&mut self, rows: &[Association]) -> Result<()> {
+        let mut line = String::new();
+        for row in rows {
+            line.clear();
+            let mut values = row_values(row);
+            for (i, &column) in self.columns.iter().enumerate() {
+                if i > 0 {
+                    line.push(self.delimiter);
+                }
+                line.push_str(&self.cell(values[column].take()));
+            }
+            line.push('\n');
+            self.out.write_all(line.as_bytes())?;
+        }
+        self.out.flush()?;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<()> {
+        Ok(self.out.finish()?)
+    }
+}
+
+/// Fetches associations page by page and writes each page to `path` as
+/// soon as it arrives, so a pull too large to hold in memory, or cut
+/// short by an error or Ctrl-C, leaves every row fetched until then on
+/// disk. Pages are fetched in batches of up to `PREFETCH_PAGES` until a
+/// short page or, when `pages` is set, that many pages. Rows are converted
+/// with `chain` first if given, then filtered and projected by
+/// `refinement`. The file is replaced, not appended to, and gzip-compressed
+/// if its name ends in `.gz`; a `path` of [`STDOUT`] writes to standard
+/// output.
+#[allow(clippy::too_many_arguments)]
+pub fn export_associations(
+    backend: &dyn SumstatsBackend,
+    entity_type: Option<&str>,
+    entity_id: Option<&str>,
+    filter: &GwasFilter,
+    pages: Option<usize>,
+    chain: Option<&ChainMap>,
+    refinement: &Refinement,
+    format: &str,
+    path: &str,
+) -> Result<ExportSummary> {
+    let mut sink: Box<dyn RowSink> = match format {
+        "ndjson" => Box::new(Ndjson {
+            out: Output::create(path)?,
+            refinement: refinement.clone(),
+        }),
+        "csv" => Box::new(Delimited::create(Output::create(path)?, ',', refinement)?),
+        "tsv" => Box::new(Delimited::create(Output::create(path)?, '\t', refinement)?),
+        _ => {
+            return Err(anyhow::anyhow!(
+                "Unknown output format {}; expected one of {}",
+                format,
+                FORMATS.join(", ")
+            ))
+        }
+    };
+
+    let mut summary = ExportSummary {
+        output_file: path.to_string(),
+        format: format.to_string(),
+        pages: 0,
+        rows: 0,
+        interrupted: None,
+    };
+    let written = write_pages(
+        backend,
+        entity_type,
+        entity_id,
+        filter,
+        pages,
+        chain,
+        refinement,
+        sink.as_mut(),
+        &mut summary,
+    );
+    // A gzip file cut short by an error is still ended properly.
+    let finished = sink.finish();
+    written?;
+    finished?;
+    Ok(summary)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn write_pages(
+    backend: &dyn SumstatsBackend,
+    entity_type: Option<&str>,
+    entity_id: Option<&str>,
+    filter: &GwasFilter,
+    pages: Option<usize>,
+    chain: Option<&ChainMap>,
+    refinement: &Refinement,
+    sink: &mut dyn RowSink,
+    summary: &mut ExportSummary,
+) -> Result<()> {
+    let prefetcher = PagePrefetcher::new(
+        backend,
+        entity_type,
+        entity_id,
+        filter,
+        PREFETCH_PAGES,
+        pages,
+    );
+    for response in prefetcher {
+        if backend.interrupt().map_or(false, |i| i.is_requested()) {
+            summary.interrupted = Some(true);
+            break;
+        }
+        let response = match response {
+            Ok(response) => response,
+            Err(e) if is_interrupted(&e) => {
+                summary.interrupted = Some(true);
+                break;
+            }
+            Err(e) => return Err(e),
+        };
+        let mut rows: Vec<Association> = response
+            .embedded
+            .and_then(|mut embedded| embedded.remove("associations"))
+            .map(|rows| rows.into_values().collect())
+            .unwrap_or_default();
+        if let Some(chain) = chain {
+            lift_associations(rows.iter_mut(), chain);
+        }
+        refinement.filter_rows(&mut rows);
+        sink.write_page(&rows)?;
+
+        summary.pages += 1;
+        summary.rows += rows.len() as u64;
+        if let Some(interrupt) = backend.interrupt() {
+            interrupt.progress().add_pages(1, rows.len() as u64);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,303 @@
+use crate::http::{self, read_body, session_client, session_max_response_bytes};
+use anyhow::Result;
+use rayon::prelude::*;
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+
+pub const FTP_BASE_URL: &str = "https://ftp.ebi.ac.uk/pub/databases/gwas/summary_statistics";
+const CRAWL_THREADS: usize = 4;
+
+/// One link in an HTTP directory listing.
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub name: String,
+    pub is_dir: bool,
+    pub size: Option<u64>,
+    pub modified: Option<String>,
+}
+
+/// A file available for download, with a suggested path relative to an
+/// output directory that keeps studies and harmonised files apart.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ManifestEntry {
+    pub study_accession: String,
+    pub file_name: String,
+    pub harmonised: bool,
+    /// Size as reported by the listing; human-readable sizes (e.g. "12M")
+    /// are converted and so approximate.
+    pub size: Option<u64>,
+    pub modified: Option<String>,
+    pub url: String,
+    pub output_path: String,
+}
+
+/// How listing one study went, so that failed studies can be listed again
+/// on their own.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StudyListing {
+    pub study_accession: String,
+    /// Files listed for the study, after any `harmonised_only` filter.
+    pub files: usize,
+    /// "ok" or "failed".
+    pub status: String,
+    pub error: Option<String>,
+}
+
+/// The files of the crawled studies, and the outcome for each study.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FtpManifest {
+    pub files: Vec<ManifestEntry>,
+    pub studies: Vec<StudyListing>,
+}
+
+/// Crawls the HTTPS view of the GWAS Catalog FTP site, where studies are
+/// grouped in directories of 1000 accessions (`GCST000001-GCST001000/`).
+#[derive(Debug, Clone)]
+pub struct FtpCrawler {
+    client: Client,
+    base_url: String,
+}
+
+impl Default for FtpCrawler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FtpCrawler {
+    pub fn new() -> Self {
+        Self::with_base_url(FTP_BASE_URL.to_string())
+    }
+
+    pub fn with_base_url(base_url: String) -> Self {
+        Self {
+            client: session_client(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    pub fn list_dir(&self, url: &str) -> Result<Vec<DirEntry>> {
+        let response = http::send(self.client.get(url))?;
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("HTTP {} for {}", response.status(), url));
+        }
+        let body = read_body(response, session_max_response_bytes())?;
+        Ok(parse_listing(&String::from_utf8_lossy(&body)))
+    }
+
+    /// Study accessions in listing order, stopping after `limit` studies.
+    pub fn studies(&self, limit: Option<usize>) -> Result<Vec<String>> {
+        let full = |studies: &Vec<String>| limit.map_or(false, |limit| studies.len() >= limit);
+        let mut studies = Vec::new();
+        for range in self.list_dir(&format!("{}/", self.base_url))? {
+            if full(&studies) {
+                break;
+            }
+            if !(range.is_dir && range.name.starts_with("GCST")) {
+                continue;
+            }
+            let url = format!("{}/{}/", self.base_url, range.name);
+            for study in self.list_dir(&url)? {
+                if full(&studies) {
+                    break;
+                }
+                if study.is_dir && study.name.starts_with("GCST") {
+                    studies.push(study.name);
+                }
+            }
+        }
+        Ok(studies)
+    }
+
+    pub fn study_url(&self, accession: &str) -> Result<String> {
+        Ok(format!(
+            "{}/{}/{accession}",
+            self.base_url,
+            range_dir(accession)?
+        ))
+    }
+
+    /// Files of one study, including its `harmonised/` subfolder.
+    pub fn study_files(&self, accession: &str) -> Result<Vec<ManifestEntry>> {
+        let study_url = self.study_url(accession)?;
+        let mut files = Vec::new();
+        for entry in self.list_dir(&format!("{study_url}/"))? {
+            if entry.is_dir {
+                if entry.name == "harmonised" {
+                    for file in self.list_dir(&format!("{study_url}/harmonised/"))? {
+                        if !file.is_dir {
+                            files.push(manifest_entry(accession, &study_url, file, true));
+                        }
+                    }
+                }
+                continue;
+            }
+            files.push(manifest_entry(accession, &study_url, entry, false));
+        }
+        Ok(files)
+    }
+
+    /// Manifest of the given studies, or of the first `limit` studies on the
+    /// site when none are given. Studies are listed concurrently; a study
+    /// whose listing fails is reported as failed without files.
+    pub fn manifest(
+        &self,
+        studies: Option<Vec<String>>,
+        limit: Option<usize>,
+        harmonised_only: bool,
+    ) -> Result<FtpManifest> {
+        let studies = match studies {
+            Some(studies) => studies,
+            None => self.studies(limit)?,
+        };
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(CRAWL_THREADS)
+            .build()?;
+        let listings: Vec<Result<Vec<ManifestEntry>>> = pool.install(|| {
+            studies
+                .par_iter()
+                .map(|study| self.study_files(study))
+                .collect()
+        });
+
+        let mut manifest = FtpManifest {
+            files: Vec::new(),
+            studies: Vec::with_capacity(studies.len()),
+        };
+        for (study, files) in studies.into_iter().zip(listings) {
+            let listing = match files {
+                Ok(files) => {
+                    let n = manifest.files.len();
+                    manifest.files.extend(
+                        files
+                            .into_iter()
+                            .filter(|file| file.harmonised || !harmonised_only),
+                    );
+                    StudyListing {
+                        study_accession: study,
+                        files: manifest.files.len() - n,
+                        status: "ok".to_string(),
+                        error: None,
+                    }
+                }
+                Err(e) => StudyListing {
+                    study_accession: study,
+                    files: 0,
+                    status: "failed".to_string(),
+                    error: Some(e.to_string()),
+                },
+            };
+            manifest.studies.push(listing);
+        }
+        Ok(manifest)
+    }
+}
+
+fn manifest_entry(
+    accession: &str,
+    study_url: &str,
+    entry: DirEntry,
+    harmonised: bool,
+) -> ManifestEntry {
+    let relative = if harmonised {
+        format!("harmonised/{}", entry.name)
+    } else {
+        entry.name.clone()
+    };
+    ManifestEntry {
+        study_accession: accession.to_string(),
+        url: format!("{study_url}/{relative}"),
+        output_path: format!("{accession}/{relative}"),
+        file_name: entry.name,
+        harmonised,
+        size: entry.size,
+        modified: entry.modified,
+    }
+}
+
+/// Directory grouping an accession with its neighbours, e.g.
+/// `GCST90002001-GCST90003000` for GCST90002357.
+pub fn range_dir(accession: &str) -> Result<String> {
+    let digits = accession
+        .strip_prefix("GCST")
+        .filter(|d| !d.is_empty() && d.chars().all(|c| c.is_ascii_digit()))
+        .ok_or_else(|| anyhow::anyhow!("Invalid study accession: {}", accession))?;
+    let number: u64 = digits.parse()?;
+    let first = (number.max(1) - 1) / 1000 * 1000 + 1;
+    let width = digits.len();
+    Ok(format!("GCST{first:0width$}-GCST{:0width$}", first + 999))
+}
+
+/// Links of an Apache- or nginx-style HTML index page, skipping sort links
+/// and the parent directory. Size and date come from the text after each
+/// link on the same row.
+pub fn parse_listing(html: &str) -> Vec<DirEntry> {
+    let mut entries = Vec::new();
+    for line in html.lines() {
+        let mut rest = line;
+        while let Some(start) = rest.find("<a href=\"") {
+            rest = &rest[start + 9..];
+            let href = match rest.find('"') {
+                Some(end) => &rest[..end],
+                None => break,
+            };
+            let row_end = rest.find("<a href=\"").unwrap_or(rest.len());
+            let after_link = rest
+                .find("</a>")
+                .filter(|&i| i < row_end)
+                .map_or("", |i| &rest[i + 4..row_end]);
+
+            if href.starts_with(['?', '/']) || href.starts_with("..") || href.contains("://") {
+                continue;
+            }
+            let is_dir = href.ends_with('/');
+            let name = href.trim_end_matches('/').to_string();
+            let text = strip_tags(after_link);
+            let tokens: Vec<&str> = text.split_whitespace().collect();
+            entries.push(DirEntry {
+                name,
+                is_dir,
+                size: tokens.iter().rev().find_map(|t| parse_size(t)),
+                modified: match tokens.as_slice() {
+                    [date, time, ..] if time.contains(':') => Some(format!("{date} {time}")),
+                    _ => None,
+                },
+            });
+        }
+    }
+    entries
+}
+
+fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => {
+                in_tag = false;
+                text.push(' ');
+            }
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.replace("&nbsp;", " ")
+}
+
+/// Byte count from a listing size such as `5120`, `12M` or `1.5G`.
+fn parse_size(token: &str) -> Option<u64> {
+    let (number, multiplier) = match token.chars().last()? {
+        'K' | 'k' => (&token[..token.len() - 1], 1u64 << 10),
+        'M' => (&token[..token.len() - 1], 1 << 20),
+        'G' => (&token[..token.len() - 1], 1 << 30),
+        'T' => (&token[..token.len() - 1], 1 << 40),
+        _ => (token, 1),
+    };
+    if number.contains(':') || number.contains('-') {
+        return None;
+    }
+    let value: f64 = number.parse().ok()?;
+    Some((value * multiplier as f64).round() as u64)
+}
@@ -0,0 +1,176 @@
+use crate::ids::chromosome_number;
+use crate::sumstats::open_text;
+use crate::Association;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::BufRead;
+
+#[derive(Debug, Clone)]
+pub struct GeneRecord {
+    pub name: String,
+    pub start: i64,
+    pub end: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NearestGeneAnnotated {
+    #[serde(flatten)]
+    pub association: Association,
+    pub nearest_gene: Option<String>,
+    pub distance_bp: Option<i64>,
+}
+
+/// Protein-coding gene coordinates keyed by numeric chromosome (X = 23,
+/// Y = 24, MT = 25, as used by the API).
+#[derive(Debug, Default)]
+pub struct GeneTable {
+    genes: HashMap<i32, ChromosomeGenes>,
+}
+
+/// The genes of one chromosome sorted by start, so that the nearest to a
+/// position is found by binary search.
+#[derive(Debug, Default)]
+struct ChromosomeGenes {
+    genes: Vec<GeneRecord>,
+    /// For each gene, the index of the gene reaching furthest among it and
+    /// those starting before it.
+    furthest: Vec<usize>,
+}
+
+impl ChromosomeGenes {
+    fn new(mut genes: Vec<GeneRecord>) -> Self {
+        genes.sort_by_key(|gene| (gene.start, gene.end));
+        let mut furthest: Vec<usize> = Vec::with_capacity(genes.len());
+        for (i, gene) in genes.iter().enumerate() {
+            let reach = match furthest.last() {
+                Some(&previous) if genes[previous].end >= gene.end => previous,
+                _ => i,
+            };
+            furthest.push(reach);
+        }
+        Self { genes, furthest }
+    }
+}
+
+impl GeneTable {
+    /// Reads either a GTF (GENCODE or Ensembl, `gene` features only) or a
+    /// BED-like table `chrom start end name [biotype]`. Non-coding genes are
+    /// dropped whenever a biotype is available.
+    pub fn open(path: &str) -> Result<Self> {
+        let mut genes: HashMap<i32, Vec<GeneRecord>> = HashMap::new();
+
+        for line in open_text(path)?.lines() {
+            let line = line?;
+            if line.starts_with('#') || line.starts_with("track") || line.trim().is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+
+            let parsed = if fields.len() >= 9 && fields[2] == "gene" {
+                parse_gtf(&fields)
+            } else if fields.len() >= 4 && fields[1].parse::<i64>().is_ok() {
+                parse_bed(&fields)
+            } else {
+                None
+            };
+
+            if let Some((chromosome, gene)) = parsed {
+                genes.entry(chromosome).or_default().push(gene);
+            }
+        }
+
+        Ok(GeneTable {
+            genes: genes
+                .into_iter()
+                .map(|(chromosome, genes)| (chromosome, ChromosomeGenes::new(genes)))
+                .collect(),
+        })
+    }
+
+    /// Nearest gene to a 1-based position; distance is 0 inside a gene body.
+    /// Of two genes as near, the one upstream of the position is returned.
+    pub fn nearest(&self, chromosome: i32, position: i64) -> Option<(&str, i64)> {
+        let chromosome = self.genes.get(&chromosome)?;
+        // Genes starting at or before the position: the one reaching
+        // furthest is the nearest of them, or contains the position.
+        let after = chromosome
+            .genes
+            .partition_point(|gene| gene.start <= position);
+        let upstream = after.checked_sub(1).map(|i| {
+            let gene = &chromosome.genes[chromosome.furthest[i]];
+            (gene.name.as_str(), (position - gene.end).max(0))
+        });
+        let downstream = chromosome
+            .genes
+            .get(after)
+            .map(|gene| (gene.name.as_str(), gene.start - position));
+        match (upstream, downstream) {
+            (Some(up), Some(down)) if down.1 < up.1 => Some(down),
+            (Some(up), _) => Some(up),
+            (None, down) => down,
+        }
+    }
+
+    pub fn annotate(&self, associations: Vec<Association>) -> Vec<NearestGeneAnnotated> {
+        associations
+            .into_iter()
+            .map(|association| {
+                let nearest = match (association.chromosome, association.base_pair_location) {
+                    (Some(chromosome), Some(position)) => self.nearest(chromosome, position),
+                    _ => None,
+                };
+                NearestGeneAnnotated {
+                    nearest_gene: nearest.map(|(name, _)| name.to_string()),
+                    distance_bp: nearest.map(|(_, distance)| distance),
+                    association,
+                }
+            })
+            .collect()
+    }
+}
+
+fn gtf_attribute<'a>(attributes: &'a str, key: &str) -> Option<&'a str> {
+    attributes.split(';').find_map(|attribute| {
+        let (name, value) = attribute.trim().split_once(' ')?;
+        (name == key).then(|| value.trim_matches('"'))
+    })
+}
+
+fn parse_gtf(fields: &[&str]) -> Option<(i32, GeneRecord)> {
+    let attributes = fields[8];
+    let biotype = gtf_attribute(attributes, "gene_type")
+        .or_else(|| gtf_attribute(attributes, "gene_biotype"));
+    if biotype.map_or(false, |b| b != "protein_coding") {
+        return None;
+    }
+    let name =
+        gtf_attribute(attributes, "gene_name").or_else(|| gtf_attribute(attributes, "gene_id"))?;
+
+    Some((
+        chromosome_number(fields[0]).map(i32::from)?,
+        GeneRecord {
+            name: name.to_string(),
+            start: fields[3].parse().ok()?,
+            end: fields[4].parse().ok()?,
+        },
+    ))
+}
+
+fn parse_bed(fields: &[&str]) -> Option<(i32, GeneRecord)> {
+    // A non-numeric fifth column is a biotype rather than a BED score.
+    if fields.get(4).map_or(false, |biotype| {
+        biotype.parse::<f64>().is_err() && *biotype != "protein_coding"
+    }) {
+        return None;
+    }
+
+    Some((
+        chromosome_number(fields[0]).map(i32::from)?,
+        GeneRecord {
+            name: fields[3].to_string(),
+            start: fields[1].parse::<i64>().ok()? + 1,
+            end: fields[2].parse().ok()?,
+        },
+    ))
+}
@@ -0,0 +1,643 @@
+use crate::backend::session_config;
+use crate::disk::format_bytes;
+use crate::stats;
+use crate::transport::HttpResponse;
+use anyhow::Result;
+use reqwest::blocking::{Client, ClientBuilder, RequestBuilder, Response};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{NoProxy, Proxy, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{self, BufReader, Read};
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// Largest API response body read by default. Association pages of the
+/// largest size the API serves are a few megabytes.
+pub const DEFAULT_MAX_RESPONSE_BYTES: u64 = 256_000_000;
+
+/// Most of an error response's body quoted in the error.
+const ERROR_BODY_BYTES: u64 = 64_000;
+
+/// HTTP timeouts in seconds. `None` keeps reqwest's default (no connect or
+/// total limit, 30 seconds to read) and 0 removes the limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Timeouts {
+    /// Establishing a connection, including the TLS handshake.
+    pub connect_timeout: Option<f64>,
+    /// Waiting for a response, or for more of a response body.
+    pub read_timeout: Option<f64>,
+    /// A whole API request, from sending it to reading the last byte.
+    /// Downloads are exempt, as large files legitimately take long.
+    pub timeout: Option<f64>,
+}
+
+/// `None` for reqwest's default, `Some(None)` for no limit.
+fn duration(seconds: Option<f64>) -> Option<Option<Duration>> {
+    seconds.map(|seconds| (seconds > 0.0).then(|| Duration::from_secs_f64(seconds)))
+}
+
+/// Checks a timeout given in seconds, mapping infinity to 0 (no limit).
+pub fn validate_timeout(name: &str, seconds: f64) -> Result<f64> {
+    if seconds.is_nan() || seconds < 0.0 {
+        return Err(anyhow::anyhow!(
+            "{} must be a number of seconds, 0 or Inf, got {}",
+            name,
+            seconds
+        ));
+    }
+    Ok(if seconds.is_infinite() { 0.0 } else { seconds })
+}
+
+impl Timeouts {
+    /// The timeouts set with `gwas_configure()`.
+    pub fn session() -> Self {
+        session_config()
+            .map(|config| config.timeouts)
+            .unwrap_or_default()
+    }
+
+    /// Applies the total timeout to an API request. It also replaces the
+    /// read timeout for the request's waits, which it bounds anyway.
+    pub fn api(&self, request: RequestBuilder) -> RequestBuilder {
+        match duration(self.timeout) {
+            Some(Some(total)) => request.timeout(total),
+            _ => request,
+        }
+    }
+}
+
+/// Which proxy requests go through. With no URL, reqwest follows the
+/// `https_proxy`, `http_proxy`, `all_proxy` and `no_proxy` environment
+/// variables, in upper or lower case.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProxySettings {
+    /// A proxy URL, possibly with credentials, or "none" to connect
+    /// directly.
+    pub url: Option<String>,
+    /// Basic credentials for the proxy, replacing any in the URL.
+    pub credentials: Option<(String, String)>,
+}
+
+impl ProxySettings {
+    /// The proxy set with `gwas_configure()`.
+    pub fn session() -> Self {
+        Self {
+            url: session_config().ok().and_then(|config| config.proxy),
+            credentials: None,
+        }
+    }
+
+    fn apply(&self, builder: ClientBuilder) -> Result<ClientBuilder> {
+        let url = match self.url.as_deref() {
+            None => return Ok(builder),
+            Some("none") => return Ok(builder.no_proxy()),
+            Some(url) => url,
+        };
+        // An explicit proxy replaces the environment's, except that hosts
+        // listed in `no_proxy` are still reached directly.
+        let mut proxy = Proxy::all(url)?.no_proxy(NoProxy::from_env());
+        if let Some((username, password)) = &self.credentials {
+            proxy = proxy.basic_auth(username, password);
+        }
+        Ok(builder.proxy(proxy))
+    }
+}
+
+/// TLS implementation for HTTPS, among those compiled in with the `rustls`
+/// (default) and `native-tls` cargo features.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TlsBackend {
+    /// rustls when compiled in, otherwise the platform's TLS.
+    #[default]
+    Auto,
+    /// rustls, trusting the Mozilla root certificates.
+    Rustls,
+    /// The platform's TLS library, trusting its certificate store.
+    Native,
+}
+
+impl TlsBackend {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "auto" => Ok(Self::Auto),
+            "rustls" => Ok(Self::Rustls),
+            "native" => Ok(Self::Native),
+            _ => Err(anyhow::anyhow!(
+                "tls_backend must be \"auto\", \"rustls\" or \"native\", got {}",
+                name
+            )),
+        }
+    }
+
+    /// Fails unless this build has the backend.
+    pub fn check(self) -> Result<()> {
+        let (available, feature) = match self {
+            Self::Auto => (
+                cfg!(any(feature = "rustls", feature = "native-tls")),
+                "rustls",
+            ),
+            Self::Rustls => (cfg!(feature = "rustls"), "rustls"),
+            Self::Native => (cfg!(feature = "native-tls"), "native-tls"),
+        };
+        if available {
+            return Ok(());
+        }
+        Err(anyhow::anyhow!(
+            "This build of iani has no {} TLS backend; rebuild it with the {} cargo feature",
+            match self {
+                Self::Native => "native",
+                _ => "rustls",
+            },
+            feature
+        ))
+    }
+}
+
+/// How connections are kept open for reuse between requests. `None`
+/// keeps reqwest's default: every idle connection, for 90 seconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct PoolSettings {
+    /// Idle connections kept per host.
+    pub pool_max_idle_per_host: Option<usize>,
+    /// Seconds an idle connection is kept, 0 for no limit.
+    pub pool_idle_timeout: Option<f64>,
+    /// Whether HTTP/2 is offered to servers, which may then carry every
+    /// request over one connection; HTTP/1.1 otherwise.
+    #[serde(default)]
+    pub http2: bool,
+}
+
+impl PoolSettings {
+    /// The connection pool settings set with `gwas_configure()`.
+    pub fn session() -> Self {
+        session_config()
+            .map(|config| config.pool)
+            .unwrap_or_default()
+    }
+
+    fn apply(&self, mut builder: ClientBuilder) -> ClientBuilder {
+        if let Some(idle) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(idle);
+        }
+        if let Some(timeout) = duration(self.pool_idle_timeout) {
+            builder = builder.pool_idle_timeout(timeout);
+        }
+        if !self.http2 {
+            builder = builder.http1_only();
+        }
+        builder
+    }
+}
+
+/// How HTTPS connections are made and verified.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TlsSettings {
+    /// PEM file of CA certificates trusted besides the backend's own, such
+    /// as the CA of a proxy that intercepts TLS.
+    pub ca_file: Option<String>,
+    pub tls_backend: TlsBackend,
+}
+
+impl TlsSettings {
+    /// The TLS settings set with `gwas_configure()`.
+    pub fn session() -> Self {
+        session_config()
+            .map(|config| config.tls)
+            .unwrap_or_default()
+    }
+
+    fn apply(&self, builder: ClientBuilder) -> Result<ClientBuilder> {
+        self.tls_backend.check()?;
+        let builder = tls::select(builder, self.tls_backend);
+        match &self.ca_file {
+            Some(path) => tls::add_ca_file(builder, path),
+            None => Ok(builder),
+        }
+    }
+}
+
+/// Checks a CA file setting: "" for none (`None`), otherwise a PEM file
+/// holding at least one certificate.
+pub fn validate_ca_file(path: &str) -> Result<Option<String>> {
+    if path.is_empty() {
+        return Ok(None);
+    }
+    // Loading the certificates into a throwaway builder checks them.
+    let _ = tls::add_ca_file(Client::builder(), path)?;
+    Ok(Some(path.to_string()))
+}
+
+#[cfg(any(feature = "rustls", feature = "native-tls"))]
+mod tls {
+    use super::TlsBackend;
+    use anyhow::Result;
+    use reqwest::blocking::ClientBuilder;
+    use reqwest::Certificate;
+
+    pub fn select(builder: ClientBuilder, backend: TlsBackend) -> ClientBuilder {
+        match backend {
+            #[cfg(feature = "rustls")]
+            TlsBackend::Auto | TlsBackend::Rustls => builder.use_rustls_tls(),
+            #[cfg(feature = "native-tls")]
+            TlsBackend::Native => builder.use_native_tls(),
+            // Only backends that are compiled in get this far.
+            #[allow(unreachable_patterns)]
+            _ => builder,
+        }
+    }
+
+    /// Trusts the certificates of a PEM file, failing when it has none.
+    pub fn add_ca_file(mut builder: ClientBuilder, path: &str) -> Result<ClientBuilder> {
+        let pem = std::fs::read(path)
+            .map_err(|e| anyhow::anyhow!("Cannot read CA file {}: {}", path, e))?;
+        let certificates = Certificate::from_pem_bundle(&pem)
+            .map_err(|e| anyhow::anyhow!("Cannot parse CA file {}: {}", path, e))?;
+        if certificates.is_empty() {
+            return Err(anyhow::anyhow!(
+                "CA file {} holds no PEM certificates",
+                path
+            ));
+        }
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+        Ok(builder)
+    }
+}
+
+/// Without TLS only plain HTTP works, and there is nothing to trust.
+#[cfg(not(any(feature = "rustls", feature = "native-tls")))]
+mod tls {
+    use super::TlsBackend;
+    use anyhow::Result;
+    use reqwest::blocking::ClientBuilder;
+
+    pub fn select(builder: ClientBuilder, _backend: TlsBackend) -> ClientBuilder {
+        builder
+    }
+
+    pub fn add_ca_file(_builder: ClientBuilder, _path: &str) -> Result<ClientBuilder> {
+        Err(anyhow::anyhow!(
+            "This build of iani has no TLS backend to give a CA file to"
+        ))
+    }
+}
+
+/// Identifies the package to servers, as EBI asks of API clients.
+pub const DEFAULT_USER_AGENT: &str = concat!(
+    "iani/",
+    env!("CARGO_PKG_VERSION"),
+    " (+https://github.com/collide2954/iani)"
+);
+
+/// Headers sent with every request.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HeaderSettings {
+    /// Replaces [`DEFAULT_USER_AGENT`].
+    pub user_agent: Option<String>,
+    /// Contact, such as an email address, added to the User-Agent so a
+    /// service can reach whoever runs a heavy job.
+    pub contact: Option<String>,
+    /// Extra headers in order, shown by name only as values may be keys.
+    #[serde(serialize_with = "serialize_header_names")]
+    pub headers: Vec<(String, String)>,
+}
+
+fn serialize_header_names<S: serde::Serializer>(
+    headers: &[(String, String)],
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.collect_seq(headers.iter().map(|(name, _)| name))
+}
+
+impl HeaderSettings {
+    /// The headers set with `gwas_headers()`.
+    pub fn session() -> Self {
+        session_config()
+            .map(|config| config.headers)
+            .unwrap_or_default()
+    }
+
+    pub fn user_agent(&self) -> String {
+        let user_agent = self.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT);
+        match &self.contact {
+            Some(contact) => format!("{user_agent} ({contact})"),
+            None => user_agent.to_string(),
+        }
+    }
+
+    /// The extra headers as "Name: value" lines.
+    pub fn lines(&self) -> Vec<String> {
+        self.headers
+            .iter()
+            .map(|(name, value)| format!("{name}: {value}"))
+            .collect()
+    }
+
+    fn apply(&self, builder: ClientBuilder) -> Result<ClientBuilder> {
+        Ok(builder
+            .user_agent(self.user_agent())
+            .default_headers(header_map(&self.headers)?))
+    }
+}
+
+/// Splits a "Name: value" header line.
+pub fn parse_header(line: &str) -> Result<(String, String)> {
+    let (name, value) = line
+        .split_once(':')
+        .ok_or_else(|| anyhow::anyhow!("Header must be \"Name: value\", got {}", line))?;
+    let header = (name.trim().to_string(), value.trim().to_string());
+    header_map(std::slice::from_ref(&header))?;
+    Ok(header)
+}
+
+/// Checks header names and values; later values of a name add to, rather
+/// than replace, earlier ones.
+pub fn header_map(headers: &[(String, String)]) -> Result<HeaderMap> {
+    let mut map = HeaderMap::new();
+    for (name, value) in headers {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| anyhow::anyhow!("Invalid header name {:?}", name))?;
+        let value = HeaderValue::from_str(value)
+            .map_err(|_| anyhow::anyhow!("Invalid value for header {}", name))?;
+        map.append(name, value);
+    }
+    Ok(map)
+}
+
+/// Checks a proxy setting: "" for the environment's proxy (`None`),
+/// "none" for direct connections, otherwise an http(s) proxy URL.
+pub fn validate_proxy(proxy: &str) -> Result<Option<String>> {
+    match proxy {
+        "" => return Ok(None),
+        "none" => return Ok(Some(proxy.to_string())),
+        _ => {}
+    }
+    let url = Url::parse(proxy)
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https") && url.has_host())
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "proxy must be an http(s) URL such as http://proxy.example.org:3128, \"none\" or \"\", got {}",
+                redact_proxy(proxy)
+            )
+        })?;
+    Ok(Some(url.to_string()))
+}
+
+/// `proxy` with any password replaced, for showing the configuration.
+pub fn redact_proxy(proxy: &str) -> String {
+    match Url::parse(proxy) {
+        Ok(mut url) if url.password().is_some() => {
+            let _ = url.set_password(Some("***"));
+            url.to_string()
+        }
+        Ok(_) => proxy.to_string(),
+        // Unparseable, so there is no telling where a password would be.
+        Err(_) => "<invalid URL>".to_string(),
+    }
+}
+
+/// A client with the given timeouts, connection pool, proxy, TLS settings
+/// and headers.
+pub fn client(
+    timeouts: &Timeouts,
+    pool: &PoolSettings,
+    proxy: &ProxySettings,
+    tls: &TlsSettings,
+    headers: &HeaderSettings,
+) -> Result<Client> {
+    let mut builder = Client::builder();
+    if let Some(connect) = duration(timeouts.connect_timeout) {
+        builder = builder.connect_timeout(connect);
+    }
+    // The blocking client applies its timeout to each wait for the
+    // response and for body data, which makes it a read timeout.
+    if let Some(read) = duration(timeouts.read_timeout) {
+        builder = builder.timeout(read);
+    }
+    builder = pool.apply(builder);
+    builder = proxy.apply(builder)?;
+    builder = tls.apply(builder)?;
+    builder = headers.apply(builder)?;
+    Ok(builder.build()?)
+}
+
+/// A client with the session's connect and read timeouts, connection pool,
+/// proxy, TLS settings and headers, for the clients of other services.
+pub fn session_client() -> Client {
+    client(
+        &Timeouts::session(),
+        &PoolSettings::session(),
+        &ProxySettings::session(),
+        &TlsSettings::session(),
+        &HeaderSettings::session(),
+    )
+    .unwrap_or_else(|_| Client::new())
+}
+
+/// Error returned when a response body is larger than the client allows.
+#[derive(Debug, Clone)]
+pub struct ResponseTooLarge {
+    pub url: String,
+    /// The body's length when the server declared it, otherwise the bytes
+    /// read before giving up.
+    pub size: u64,
+    /// Whether `size` is the declared length rather than a lower bound.
+    pub declared: bool,
+    pub limit: u64,
+}
+
+impl fmt::Display for ResponseTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Response from {} is {}{}, over the limit of {}; raise max_response_bytes with gwas_configure() if this is expected",
+            self.url,
+            if self.declared { "" } else { "more than " },
+            format_bytes(self.size),
+            format_bytes(self.limit)
+        )
+    }
+}
+
+impl std::error::Error for ResponseTooLarge {}
+
+/// Error returned for an API response with an error status, quoting the
+/// start of its body.
+#[derive(Debug, Clone)]
+pub struct HttpStatus {
+    pub status: StatusCode,
+    pub text: String,
+}
+
+impl fmt::Display for HttpStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HTTP {}: {}", self.status, self.text)
+    }
+}
+
+impl std::error::Error for HttpStatus {}
+
+/// Whether `error` is an [`HttpStatus`] of 404: the server has no record
+/// of what was asked for, or no results past the last page.
+pub fn is_not_found(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<HttpStatus>()
+        .map_or(false, |e| e.status == StatusCode::NOT_FOUND)
+}
+
+/// Checks a response size limit given in bytes, mapping infinity to 0 (no
+/// limit).
+pub fn validate_max_response_bytes(bytes: f64) -> Result<u64> {
+    if bytes.is_nan() || bytes < 0.0 {
+        return Err(anyhow::anyhow!(
+            "max_response_bytes must be a number of bytes, 0 or Inf, got {}",
+            bytes
+        ));
+    }
+    Ok(if bytes.is_infinite() { 0 } else { bytes as u64 })
+}
+
+/// The response size limit set with `gwas_configure()`, 0 for none.
+pub fn session_max_response_bytes() -> u64 {
+    session_config().map_or(DEFAULT_MAX_RESPONSE_BYTES, |config| {
+        config.max_response_bytes
+    })
+}
+
+/// Reads a body while counting it, failing once it passes the limit.
+struct LimitedBody {
+    response: HttpResponse,
+    url: String,
+    limit: u64,
+    read: u64,
+    exceeded: bool,
+}
+
+impl LimitedBody {
+    /// Fails at once when the declared length is over `limit` (0 for no
+    /// limit), before reading any of the body.
+    fn new(response: HttpResponse, limit: u64) -> Result<Self> {
+        let url = response.url.clone();
+        if let Some(length) = response.content_length {
+            if limit > 0 && length > limit {
+                return Err(ResponseTooLarge {
+                    url,
+                    size: length,
+                    declared: true,
+                    limit,
+                }
+                .into());
+            }
+        }
+        Ok(Self {
+            response,
+            url,
+            limit,
+            read: 0,
+            exceeded: false,
+        })
+    }
+
+    /// The typed error when reading stopped at the limit, otherwise
+    /// `error` itself.
+    fn error(&self, error: anyhow::Error) -> anyhow::Error {
+        if self.exceeded {
+            ResponseTooLarge {
+                url: self.url.clone(),
+                size: self.read,
+                declared: false,
+                limit: self.limit,
+            }
+            .into()
+        } else {
+            error
+        }
+    }
+}
+
+impl Read for LimitedBody {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.response.body.read(buf)?;
+        self.read += n as u64;
+        stats::add_bytes(n as u64);
+        if self.limit > 0 && self.read > self.limit {
+            self.exceeded = true;
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "response body over the size limit",
+            ));
+        }
+        Ok(n)
+    }
+}
+
+/// Sends `request`, logging its method, URL, query parameters, status and
+/// time taken at debug level, or the error at warn level; see
+/// `gwas_set_verbose()`, and counts it for `gwas_stats()`. Every request
+/// the package makes goes through here.
+pub fn send(request: RequestBuilder) -> reqwest::Result<Response> {
+    let (client, request) = request.build_split();
+    let request = request?;
+    let method = request.method().clone();
+    let mut url = request.url().clone();
+    let params = url.query().unwrap_or_default().to_string();
+    url.set_query(None);
+    let started = Instant::now();
+    let result = client.execute(request);
+    let ms = started.elapsed().as_millis() as u64;
+    stats::request(result.is_err());
+    match &result {
+        Ok(response) => tracing::debug!(
+            params = params.as_str(),
+            status = response.status().as_u16(),
+            ms,
+            "{} {}",
+            method,
+            url
+        ),
+        Err(e) => tracing::warn!(
+            params = params.as_str(),
+            ms,
+            "{} {} failed: {}",
+            method,
+            url,
+            e
+        ),
+    }
+    result
+}
+
+/// Reads a whole response body of at most `limit` bytes (0 for no limit),
+/// failing with [`ResponseTooLarge`] otherwise.
+pub fn read_body(response: impl Into<HttpResponse>, limit: u64) -> Result<Vec<u8>> {
+    let mut body = LimitedBody::new(response.into(), limit)?;
+    let mut data = Vec::new();
+    match body.read_to_end(&mut data) {
+        Ok(_) => Ok(data),
+        Err(e) => Err(body.error(e.into())),
+    }
+}
+
+/// Deserializes a JSON response as it arrives, without holding the whole
+/// body, failing with [`ResponseTooLarge`] past `limit` bytes (0 for no
+/// limit).
+pub fn read_json<T: DeserializeOwned>(response: impl Into<HttpResponse>, limit: u64) -> Result<T> {
+    let mut body = LimitedBody::new(response.into(), limit)?;
+    let result = serde_json::from_reader(BufReader::new(&mut body));
+    result.map_err(|e| body.error(e.into()))
+}
+
+/// The start of an error response's body, for quoting in the error.
+pub fn error_text(response: impl Into<HttpResponse>) -> String {
+    let mut data = Vec::new();
+    let body = response.into().body;
+    match stats::Counted(body.take(ERROR_BODY_BYTES)).read_to_end(&mut data) {
+        Ok(_) => String::from_utf8_lossy(&data).into_owned(),
+        Err(_) => "Unable to read response body".to_string(),
+    }
+}
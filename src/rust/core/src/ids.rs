@@ -0,0 +1,95 @@
+//! Identifiers as users type them, normalized to the form the API expects
+//! before they become part of a request path.
+
+use anyhow::Result;
+
+/// Chromosome names accepted by `normalize_chromosome`, for error messages.
+const CHROMOSOME_FORMS: &str =
+    "1-22, X, Y or MT, with or without \"chr\" (e.g. \"chr1\", \"x\", \"23\", \"M\")";
+
+/// Variant ID forms accepted by `normalize_variant_id`, for error messages.
+const VARIANT_FORMS: &str = "an rs number (\"rs7412\" or \"7412\") or a position with alleles \
+     (\"19:44908822:C:T\", \"chr19:44908822:C:T\" or \"19_44908822_C_T\")";
+
+/// `name` without a case-insensitive `prefix`.
+fn strip_prefix_ignore_case<'a>(name: &'a str, prefix: &str) -> &'a str {
+    match name.get(..prefix.len()) {
+        Some(start) if start.eq_ignore_ascii_case(prefix) => &name[prefix.len()..],
+        _ => name,
+    }
+}
+
+/// The number of a chromosome, 1 to 25 with X, Y and MT as 23, 24 and 25,
+/// as the API codes them in request paths and the harmonised files in
+/// their chromosome column. Names may have a `chr` prefix and any case,
+/// and M stands for MT.
+pub fn chromosome_number(chromosome: &str) -> Option<u8> {
+    let name = strip_prefix_ignore_case(chromosome.trim(), "chr");
+    let number = match name.to_ascii_uppercase().as_str() {
+        "X" => 23,
+        "Y" => 24,
+        "M" | "MT" => 25,
+        digits if digits.bytes().all(|b| b.is_ascii_digit()) => digits.parse().ok()?,
+        _ => return None,
+    };
+    (1..=25).contains(&number).then_some(number)
+}
+
+/// A chromosome as the API expects it in a request path: its number.
+pub fn normalize_chromosome(chromosome: &str) -> Result<String> {
+    chromosome_number(chromosome)
+        .map(|number| number.to_string())
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "Chromosome \"{}\" is not recognised; use {}",
+                chromosome,
+                CHROMOSOME_FORMS
+            )
+        })
+}
+
+/// A variant ID as the API expects it: rs numbers as `rs` and digits, and
+/// positions with alleles as `chromosome_position_ref_alt`, the form of
+/// the harmonised files' `hm_variant_id`, which name chromosomes by
+/// letter ("X_100_AT_A") where request paths number them. The `rs` and
+/// `chr` prefixes are optional and case-insensitive, and the parts of a
+/// position may be separated by `:`, `_` or `-`.
+pub fn normalize_variant_id(variant_id: &str) -> Result<String> {
+    let id = variant_id.trim();
+    let digits = strip_prefix_ignore_case(id, "rs");
+    if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Ok(format!("rs{digits}"));
+    }
+
+    let parts: Vec<&str> = id.split([':', '_', '-']).collect();
+    if let [chromosome, position, reference, alternate] = parts.as_slice() {
+        let chromosome = strip_prefix_ignore_case(chromosome, "chr");
+        let is_allele = |allele: &str| {
+            !allele.is_empty()
+                && allele
+                    .bytes()
+                    .all(|b| matches!(b.to_ascii_uppercase(), b'A' | b'C' | b'G' | b'T'))
+        };
+        if !chromosome.is_empty()
+            && chromosome.bytes().all(|b| b.is_ascii_alphanumeric())
+            && !position.is_empty()
+            && position.bytes().all(|b| b.is_ascii_digit())
+            && is_allele(reference)
+            && is_allele(alternate)
+        {
+            return Ok(format!(
+                "{}_{}_{}_{}",
+                chromosome.to_ascii_uppercase(),
+                position,
+                reference.to_ascii_uppercase(),
+                alternate.to_ascii_uppercase()
+            ));
+        }
+    }
+
+    Err(anyhow::anyhow!(
+        "Variant ID \"{}\" is not recognised; use {}",
+        variant_id,
+        VARIANT_FORMS
+    ))
+}
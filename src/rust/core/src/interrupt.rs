@@ -0,0 +1,197 @@
+use crate::logging;
+use crate::progress::{self, Progress, ProgressSnapshot};
+use crate::stats;
+use std::fmt;
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the R thread checks for Ctrl-C while work runs elsewhere.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Least time between progress reports to R.
+const REPORT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Error returned by work stopped by a user interrupt.
+#[derive(Debug, Clone, Copy)]
+pub struct Interrupted;
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Interrupted by user")
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+/// Whether `error` is, or wraps an I/O error caused by, [`Interrupted`].
+pub fn is_interrupted(error: &anyhow::Error) -> bool {
+    error.is::<Interrupted>()
+        || error
+            .downcast_ref::<io::Error>()
+            .and_then(|e| e.get_ref())
+            .map_or(false, |inner| inner.is::<Interrupted>())
+}
+
+/// A flag shared by a client and its worker threads, set when the user
+/// interrupts. Workers check it between requests and reads, stop, and
+/// return what they have so far. It also carries the workers' progress
+/// back to the R thread.
+#[derive(Debug, Clone, Default)]
+pub struct Interrupt {
+    signal: Arc<Signal>,
+    progress: Progress,
+}
+
+#[derive(Debug, Default)]
+struct Signal {
+    requested: AtomicBool,
+    /// Held while setting `requested`, so sleepers cannot miss the wakeup.
+    lock: Mutex<()>,
+    wake: Condvar,
+}
+
+impl Interrupt {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the flag and wakes every thread in [`Interrupt::sleep`].
+    pub fn request(&self) {
+        let _guard = self.signal.lock.lock();
+        self.signal.requested.store(true, Ordering::Relaxed);
+        self.signal.wake.notify_all();
+    }
+
+    /// Clears the flag and the progress counters for a new operation.
+    pub fn reset(&self) {
+        self.signal.requested.store(false, Ordering::Relaxed);
+        self.progress.reset();
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.signal.requested.load(Ordering::Relaxed)
+    }
+
+    pub fn progress(&self) -> &Progress {
+        &self.progress
+    }
+
+    /// `Err(Interrupted)` once an interrupt has been requested.
+    pub fn check(&self) -> Result<(), Interrupted> {
+        if self.is_requested() {
+            Err(Interrupted)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Sleeps for `duration`, for backoff, rate limiting or polling, but
+    /// returns `Err(Interrupted)` as soon as an interrupt is requested,
+    /// from any thread.
+    pub fn sleep(&self, duration: Duration) -> Result<(), Interrupted> {
+        let guard = match self.signal.lock.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let waited = self
+            .signal
+            .wake
+            .wait_timeout_while(guard, duration, |_| !self.is_requested());
+        // A poisoned lock only means another sleeper panicked.
+        drop(waited);
+        self.check()
+    }
+}
+
+/// Reads from `inner` until an interrupt is requested, then fails with an
+/// I/O error wrapping [`Interrupted`]. Bytes read count as progress and
+/// towards the session's `gwas_stats()`.
+pub struct Interruptible<'a, R> {
+    inner: R,
+    interrupt: &'a Interrupt,
+}
+
+impl<'a, R: Read> Interruptible<'a, R> {
+    pub fn new(inner: R, interrupt: &'a Interrupt) -> Self {
+        Self { inner, interrupt }
+    }
+}
+
+impl<R: Read> Read for Interruptible<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Not ErrorKind::Interrupted, which io::copy retries.
+        self.interrupt
+            .check()
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        let n = self.inner.read(buf)?;
+        self.interrupt.progress.add_bytes(n as u64);
+        stats::add_bytes(n as u64);
+        Ok(n)
+    }
+}
+
+/// Runs `work` on another thread while this one, which must be the R main
+/// thread, polls R for Ctrl-C and requests `interrupt` when it sees one.
+/// The R interrupt is consumed, so the R function returns normally with
+/// whatever partial results `work` produces. With `gwas_progress(TRUE)`,
+/// the poll also passes changed progress to R every `REPORT_INTERVAL`, and
+/// with `gwas_set_verbose()` it prints what the worker threads logged.
+pub fn with_interrupts<T: Send>(interrupt: &Interrupt, work: impl FnOnce() -> T + Send) -> T {
+    interrupt.reset();
+    let mut reporting = progress::enabled();
+    let mut reported = ProgressSnapshot::default();
+    let mut last_report = Instant::now();
+    let (done, finished) = mpsc::channel();
+    thread::scope(|scope| {
+        let worker = scope.spawn(move || {
+            let result = work();
+            let _ = done.send(());
+            result
+        });
+        // A disconnected channel means the worker panicked; join re-raises it.
+        while let Err(mpsc::RecvTimeoutError::Timeout) = finished.recv_timeout(POLL_INTERVAL) {
+            if interrupt_pending() {
+                interrupt.request();
+            }
+            logging::flush();
+            let snapshot = interrupt.progress.snapshot();
+            if reporting && snapshot != reported && last_report.elapsed() >= REPORT_INTERVAL {
+                // A failing handler is not called again for this operation.
+                reporting = progress::report(&snapshot, false);
+                reported = snapshot;
+                last_report = Instant::now();
+            }
+        }
+        logging::flush();
+        // Only operations that reported get a final call to close their bar.
+        if reporting && reported != ProgressSnapshot::default() {
+            progress::report(&interrupt.progress.snapshot(), true);
+        }
+        worker
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+/// Asks the host whether the user interrupted, e.g. R's Ctrl-C; nothing is
+/// pending until one is installed with [`set_interrupt_check`].
+static INTERRUPT_CHECK: RwLock<Option<fn() -> bool>> = RwLock::new(None);
+
+/// Polls `check` for user interrupts from now on. The R package checks
+/// R's own interrupt flag without letting R's longjmp unwind through Rust
+/// frames.
+pub fn set_interrupt_check(check: fn() -> bool) {
+    if let Ok(mut current) = INTERRUPT_CHECK.write() {
+        *current = Some(check);
+    }
+}
+
+/// Whether the host has seen a user interrupt. Must be called on the
+/// thread the host's check expects, R's main thread for the R package.
+pub fn interrupt_pending() -> bool {
+    let check = INTERRUPT_CHECK.read().ok().and_then(|check| *check);
+    check.map_or(false, |check| check())
+}
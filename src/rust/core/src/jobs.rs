@@ -0,0 +1,187 @@
+use crate::download::{DownloadMode, DownloadResult};
+use crate::interrupt::interrupt_pending;
+use crate::GwasClient;
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How often a waiting `collect` checks whether the job has finished.
+const WAIT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Background jobs of this R session, by id. Finished jobs stay here until
+/// they are collected.
+static JOBS: Mutex<Option<HashMap<u64, Job>>> = Mutex::new(None);
+static NEXT_JOB: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Debug, Default)]
+struct Progress {
+    completed: AtomicUsize,
+    failed: AtomicUsize,
+    bytes: AtomicU64,
+    /// Milliseconds from start to finish, 0 while running.
+    elapsed_ms: AtomicU64,
+}
+
+struct Job {
+    files: usize,
+    started: Instant,
+    progress: Arc<Progress>,
+    handle: JoinHandle<Result<Vec<DownloadResult>>>,
+}
+
+/// Snapshot of a job, returned to R by `gwas_job_status()`.
+#[derive(Debug, Serialize, Clone)]
+pub struct JobStatus {
+    pub job: u64,
+    /// "running" or "finished"; collect a finished job for its results.
+    pub state: String,
+    pub files: usize,
+    /// Files done so far, including failed and skipped ones.
+    pub completed: usize,
+    pub failed: usize,
+    pub bytes: u64,
+    pub seconds: f64,
+}
+
+impl Job {
+    fn status(&self, id: u64) -> JobStatus {
+        let finished = self.handle.is_finished();
+        let elapsed_ms = self.progress.elapsed_ms.load(Ordering::Relaxed);
+        JobStatus {
+            job: id,
+            state: if finished { "finished" } else { "running" }.to_string(),
+            files: self.files,
+            completed: self.progress.completed.load(Ordering::Relaxed),
+            failed: self.progress.failed.load(Ordering::Relaxed),
+            bytes: self.progress.bytes.load(Ordering::Relaxed),
+            seconds: if finished {
+                elapsed_ms as f64 / 1000.0
+            } else {
+                self.started.elapsed().as_secs_f64()
+            },
+        }
+    }
+}
+
+fn with_jobs<T>(f: impl FnOnce(&mut HashMap<u64, Job>) -> T) -> Result<T> {
+    let mut jobs = JOBS
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Job registry poisoned"))?;
+    Ok(f(jobs.get_or_insert_with(HashMap::new)))
+}
+
+/// Starts downloading on a background thread through a persisted queue,
+/// writing `manifest.json` when the job finishes if `write_manifest`.
+/// Returns the new job's status once the files are known to fit on disk.
+pub fn spawn_download(
+    client: GwasClient,
+    urls: Vec<String>,
+    paths: Vec<String>,
+    max_concurrent: usize,
+    mode: DownloadMode,
+    write_manifest: bool,
+) -> Result<JobStatus> {
+    // Here rather than only in the job, so a batch that cannot fit fails
+    // the call instead of a collect much later.
+    client.check_disk_space(&urls, &paths, max_concurrent, mode)?;
+    let id = NEXT_JOB.fetch_add(1, Ordering::Relaxed);
+    let started = Instant::now();
+    let progress = Arc::new(Progress::default());
+    let worker_progress = progress.clone();
+    let files = urls.len();
+
+    let handle = thread::Builder::new()
+        .name(format!("iani-job-{id}"))
+        .spawn(move || {
+            let results = client.download_queued(
+                &urls,
+                &paths,
+                max_concurrent,
+                mode,
+                write_manifest,
+                |result| {
+                    worker_progress.completed.fetch_add(1, Ordering::Relaxed);
+                    if result.status == "failed" {
+                        worker_progress.failed.fetch_add(1, Ordering::Relaxed);
+                    }
+                    let bytes = result.bytes.unwrap_or(0);
+                    worker_progress.bytes.fetch_add(bytes, Ordering::Relaxed);
+                },
+            );
+            // At least 1 ms, as 0 means still running.
+            let elapsed_ms = (started.elapsed().as_millis() as u64).max(1);
+            worker_progress
+                .elapsed_ms
+                .store(elapsed_ms, Ordering::Relaxed);
+            results
+        })?;
+
+    let job = Job {
+        files,
+        started,
+        progress,
+        handle,
+    };
+    let status = job.status(id);
+    with_jobs(|jobs| jobs.insert(id, job))?;
+    Ok(status)
+}
+
+/// Status of job `id`, or of every job not yet collected when `id` is
+/// `None`, in start order.
+pub fn status(id: Option<u64>) -> Result<Vec<JobStatus>> {
+    with_jobs(|jobs| match id {
+        Some(id) => jobs
+            .get(&id)
+            .map(|job| vec![job.status(id)])
+            .ok_or_else(|| unknown_job(id)),
+        None => {
+            let mut statuses: Vec<JobStatus> =
+                jobs.iter().map(|(id, job)| job.status(*id)).collect();
+            statuses.sort_by_key(|status| status.job);
+            Ok(statuses)
+        }
+    })?
+}
+
+/// Results of job `id`, removing it from the registry. A running job is an
+/// error unless `wait`, which blocks until it finishes. Ctrl-C stops the
+/// wait, leaving the job running.
+pub fn collect(id: u64, wait: bool) -> Result<Vec<DownloadResult>> {
+    let job = with_jobs(|jobs| {
+        let finished = jobs
+            .get(&id)
+            .map(|job| job.handle.is_finished())
+            .ok_or_else(|| unknown_job(id))?;
+        if !finished && !wait {
+            return Err(anyhow::anyhow!(
+                "Job {} is still running; check gwas_job_status() or collect with wait = TRUE",
+                id
+            ));
+        }
+        Ok(jobs.remove(&id))
+    })??;
+    let job = job.ok_or_else(|| unknown_job(id))?;
+
+    while !job.handle.is_finished() {
+        thread::sleep(WAIT_INTERVAL);
+        if interrupt_pending() {
+            with_jobs(|jobs| jobs.insert(id, job))?;
+            return Err(anyhow::anyhow!(
+                "Interrupted while waiting; job {} is still running",
+                id
+            ));
+        }
+    }
+    job.handle
+        .join()
+        .map_err(|_| anyhow::anyhow!("Job {} panicked", id))?
+}
+
+fn unknown_job(id: u64) -> anyhow::Error {
+    anyhow::anyhow!("No job {}; a job can be collected only once", id)
+}
@@ -0,0 +1,216 @@
+use crate::ids;
+use crate::Association;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+
+const BED_MAGIC: [u8; 3] = [0x6c, 0x1b, 0x01];
+
+#[derive(Debug, Clone)]
+pub struct BimRecord {
+    pub chromosome: String,
+    pub variant_id: String,
+    pub position: i64,
+    pub allele1: String,
+    pub allele2: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LdAnnotated {
+    #[serde(flatten)]
+    pub association: Association,
+    pub r2: Option<f64>,
+}
+
+/// A PLINK 1 binary fileset (`.bed`/`.bim`/`.fam`) in SNP-major mode.
+/// Genotypes are read from the `.bed` on demand, one variant at a time.
+#[derive(Debug)]
+pub struct PlinkFileset {
+    bed_path: String,
+    pub variants: Vec<BimRecord>,
+    pub samples: Vec<(String, String)>,
+    by_id: HashMap<String, usize>,
+    by_position: HashMap<(String, i64), usize>,
+    pub n_samples: usize,
+}
+
+impl PlinkFileset {
+    pub fn open(prefix: &str) -> Result<Self> {
+        let bed_path = format!("{prefix}.bed");
+
+        let mut magic = [0u8; 3];
+        fs::File::open(&bed_path)?.read_exact(&mut magic)?;
+        if magic != BED_MAGIC {
+            return Err(anyhow::anyhow!(
+                "{} is not a SNP-major PLINK .bed file",
+                bed_path
+            ));
+        }
+
+        let fam = BufReader::new(fs::File::open(format!("{prefix}.fam"))?);
+        let mut samples = Vec::new();
+        for line in fam.lines() {
+            let line = line?;
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() >= 2 {
+                samples.push((fields[0].to_string(), fields[1].to_string()));
+            }
+        }
+        let n_samples = samples.len();
+
+        let bim = BufReader::new(fs::File::open(format!("{prefix}.bim"))?);
+        let mut variants = Vec::new();
+        for line in bim.lines() {
+            let line = line?;
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 6 {
+                continue;
+            }
+            variants.push(BimRecord {
+                chromosome: ids::normalize_chromosome(fields[0])
+                    .unwrap_or_else(|_| fields[0].to_string()),
+                variant_id: fields[1].to_string(),
+                position: fields[3].parse()?,
+                allele1: fields[4].to_uppercase(),
+                allele2: fields[5].to_uppercase(),
+            });
+        }
+
+        let by_id = variants
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (v.variant_id.clone(), i))
+            .collect();
+        let by_position = variants
+            .iter()
+            .enumerate()
+            .map(|(i, v)| ((v.chromosome.clone(), v.position), i))
+            .collect();
+
+        Ok(Self {
+            bed_path,
+            variants,
+            samples,
+            by_id,
+            by_position,
+            n_samples,
+        })
+    }
+
+    /// Finds an association's variant in the reference, by ID first and then
+    /// by chromosome and position.
+    pub fn locate(&self, association: &Association) -> Option<usize> {
+        self.find(
+            association.variant_id.as_deref(),
+            association.chromosome.map(|c| c.to_string()).as_deref(),
+            association.base_pair_location,
+        )
+    }
+
+    pub fn find(
+        &self,
+        variant_id: Option<&str>,
+        chromosome: Option<&str>,
+        position: Option<i64>,
+    ) -> Option<usize> {
+        if let Some(index) = variant_id.and_then(|id| self.by_id.get(id)) {
+            return Some(*index);
+        }
+        let chromosome = chromosome?;
+        let chromosome =
+            ids::normalize_chromosome(chromosome).unwrap_or_else(|_| chromosome.to_string());
+        self.by_position.get(&(chromosome, position?)).copied()
+    }
+
+    /// Allele-1 dosages (0, 1 or 2) for one variant; `None` marks a missing call.
+    pub fn dosages(&self, index: usize) -> Result<Vec<Option<u8>>> {
+        let bytes_per_variant = (self.n_samples + 3) / 4;
+        let mut buffer = vec![0u8; bytes_per_variant];
+        let mut bed = fs::File::open(&self.bed_path)?;
+        bed.seek(SeekFrom::Start(
+            (BED_MAGIC.len() + index * bytes_per_variant) as u64,
+        ))?;
+        bed.read_exact(&mut buffer)?;
+
+        Ok((0..self.n_samples)
+            .map(
+                |sample| match (buffer[sample / 4] >> ((sample % 4) * 2)) & 0b11 {
+                    0b00 => Some(2),
+                    0b10 => Some(1),
+                    0b11 => Some(0),
+                    _ => None,
+                },
+            )
+            .collect())
+    }
+
+    pub fn r2(&self, a: usize, b: usize) -> Result<Option<f64>> {
+        Ok(r2(&self.dosages(a)?, &self.dosages(b)?))
+    }
+}
+
+/// Squared Pearson correlation of dosages over samples called in both variants.
+pub fn r2(a: &[Option<u8>], b: &[Option<u8>]) -> Option<f64> {
+    let pairs: Vec<(f64, f64)> = a
+        .iter()
+        .zip(b)
+        .filter_map(|(x, y)| Some((f64::from((*x)?), f64::from((*y)?))))
+        .collect();
+    if pairs.len() < 2 {
+        return None;
+    }
+
+    let n = pairs.len() as f64;
+    let mean_x = pairs.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = pairs.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let (mut cov, mut var_x, mut var_y) = (0.0, 0.0, 0.0);
+    for (x, y) in &pairs {
+        cov += (x - mean_x) * (y - mean_y);
+        var_x += (x - mean_x).powi(2);
+        var_y += (y - mean_y).powi(2);
+    }
+    if var_x == 0.0 || var_y == 0.0 {
+        return None;
+    }
+    Some(cov * cov / (var_x * var_y))
+}
+
+/// Attaches r² with the lead variant to every association. Without an explicit
+/// lead, the association with the smallest p-value is used.
+pub fn annotate_r2(
+    associations: Vec<Association>,
+    reference: &PlinkFileset,
+    lead_variant: Option<&str>,
+) -> Result<Vec<LdAnnotated>> {
+    let lead = match lead_variant {
+        Some(id) => associations
+            .iter()
+            .find(|a| a.variant_id.as_deref() == Some(id)),
+        None => associations
+            .iter()
+            .filter(|a| a.p_value.is_some())
+            .min_by(|a, b| {
+                a.p_value
+                    .unwrap_or(1.0)
+                    .total_cmp(&b.p_value.unwrap_or(1.0))
+            }),
+    };
+
+    let lead_index = lead
+        .and_then(|a| reference.locate(a))
+        .ok_or_else(|| anyhow::anyhow!("Lead variant not found in the LD reference"))?;
+    let lead_dosages = reference.dosages(lead_index)?;
+
+    associations
+        .into_iter()
+        .map(|association| {
+            let r2 = match reference.locate(&association) {
+                Some(index) => r2(&lead_dosages, &reference.dosages(index)?),
+                None => None,
+            };
+            Ok(LdAnnotated { association, r2 })
+        })
+        .collect()
+}
@@ -0,0 +1,1144 @@
+//! Client for the GWAS Summary Statistics Database API and the services
+//! around it: typed models, paged queries, downloads and the local
+//! analyses built on them. The `iani` R package wraps it through the
+//! `iani-r` crate, but nothing here depends on R.
+
+use anyhow::Result;
+use reqwest::blocking::Client;
+use reqwest::header::HeaderMap;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use url::Url;
+
+pub mod arrow;
+pub mod atomic;
+pub mod backend;
+pub mod breaker;
+pub mod cassette;
+pub mod catalog;
+pub mod catalog_index;
+pub mod checksum;
+pub mod clump;
+pub mod conditional;
+pub mod count;
+pub mod disk;
+pub mod download;
+pub mod enrichment;
+pub mod ensembl;
+pub mod evidence;
+pub mod export;
+pub mod ftp;
+pub mod genes;
+pub mod http;
+pub mod ids;
+pub mod interrupt;
+pub mod jobs;
+pub mod ld;
+pub mod liftover;
+pub mod links;
+pub mod logging;
+pub mod mirror;
+pub mod munge;
+pub mod ols;
+pub mod opengwas;
+pub mod opentargets;
+pub mod ordered;
+pub mod paging;
+pub mod panels;
+pub mod pgs;
+pub mod progress;
+pub mod prs;
+pub mod queue;
+pub mod refine;
+pub mod remote_tabix;
+pub mod stats;
+pub mod study_filter;
+pub mod study_sync;
+pub mod summary;
+pub mod sumstats;
+pub mod tabix;
+pub mod terms;
+#[cfg(test)]
+mod tests;
+pub mod throttle;
+pub mod trait_labels;
+pub mod trait_match;
+pub mod trait_tree;
+pub mod transport;
+pub mod variant_index;
+pub mod zenodo;
+
+use backend::session_config;
+use breaker::{BreakerTransport, CircuitBreaker};
+use cassette::CassetteSettings;
+use conditional::ConditionalTransport;
+use http::{
+    error_text, header_map, read_body, read_json, session_max_response_bytes, HeaderSettings,
+    HttpStatus, PoolSettings, ProxySettings, Timeouts, TlsBackend, TlsSettings,
+};
+use ids::{normalize_chromosome, normalize_variant_id};
+use interrupt::Interrupt;
+use ordered::OrderedMap;
+use paging::{associations_endpoint, parse_page};
+use throttle::TokenBucket;
+use transport::{HttpResponse, HttpTransport, LimitedTransport, ReqwestTransport};
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct Association {
+    pub variant_id: Option<String>,
+    pub chromosome: Option<i32>,
+    pub base_pair_location: Option<i64>,
+    pub study_accession: Option<String>,
+    #[serde(rename = "trait")]
+    pub trait_ids: Option<Vec<String>>,
+    pub p_value: Option<f64>,
+    pub code: Option<i32>,
+    pub effect_allele: Option<String>,
+    pub other_allele: Option<String>,
+    pub effect_allele_frequency: Option<f64>,
+    pub odds_ratio: Option<f64>,
+    pub ci_lower: Option<f64>,
+    pub ci_upper: Option<f64>,
+    pub beta: Option<f64>,
+    pub se: Option<f64>,
+    #[serde(rename = "_links")]
+    pub links: Option<BTreeMap<String, serde_json::Value>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Link {
+    pub href: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HalResponse<T> {
+    #[serde(rename = "_embedded")]
+    pub embedded: Option<OrderedMap<T>>,
+    #[serde(rename = "_links")]
+    pub links: Option<BTreeMap<String, serde_json::Value>>,
+    /// Set when the user interrupted a paged query, which then holds only
+    /// the pages fetched before the interrupt.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interrupted: Option<bool>,
+    /// Set to false when the server had no record of what was asked for
+    /// (HTTP 404) and an empty result was asked for instead of an error.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub found: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Chromosome {
+    pub chromosome: String,
+    #[serde(rename = "_links")]
+    pub links: Option<BTreeMap<String, Link>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Study {
+    pub study_accession: String,
+    #[serde(rename = "_links")]
+    pub links: Option<BTreeMap<String, Link>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Trait {
+    #[serde(rename = "trait")]
+    pub trait_name: String,
+    #[serde(rename = "_links")]
+    pub links: Option<BTreeMap<String, Link>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SummaryStatsFile {
+    pub study_accession: String,
+    pub trait_id: Option<String>,
+    pub file_path: String,
+    pub file_size: Option<i64>,
+    pub file_type: Option<String>,
+    pub download_url: Option<String>,
+    #[serde(rename = "_links")]
+    pub links: Option<BTreeMap<String, Link>>,
+}
+
+/// Parses associations from either a HAL response returned by
+/// `gwas_associations()` or a plain JSON array of association records.
+/// Records are returned in document order.
+pub fn associations_from_json(json: &str) -> Result<Vec<Association>> {
+    // Parse straight into the target types: a `serde_json::Value` would
+    // reorder the row keys ("0", "1", "10", ...).
+    if json.trim_start().starts_with('[') {
+        return Ok(serde_json::from_str(json)?);
+    }
+
+    let response: HalResponse<OrderedMap<Association>> = serde_json::from_str(json)?;
+    Ok(response
+        .embedded
+        .into_iter()
+        .flat_map(|embedded| embedded.into_values())
+        .flat_map(|associations| associations.into_values())
+        .collect())
+}
+
+impl HalResponse<OrderedMap<Association>> {
+    /// No associations, marked as not found.
+    pub fn not_found() -> Self {
+        Self {
+            embedded: Some(OrderedMap::from([(
+                "associations".to_string(),
+                OrderedMap::new(),
+            )])),
+            links: None,
+            interrupted: None,
+            found: Some(false),
+        }
+    }
+
+    pub fn associations_mut(&mut self) -> impl Iterator<Item = &mut Association> {
+        self.embedded
+            .iter_mut()
+            .flat_map(|embedded| embedded.values_mut())
+            .flat_map(|associations| associations.values_mut())
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct GwasFilter {
+    pub p_value_range: Option<(String, String)>,
+    pub bp_location_range: Option<(i64, i64)>,
+    pub study: Option<String>,
+    pub trait_id: Option<String>,
+    pub reveal: Option<String>,
+    pub start: Option<i32>,
+    pub size: Option<i32>,
+}
+
+impl GwasFilter {
+    pub fn to_params(&self) -> BTreeMap<String, String> {
+        let mut params = BTreeMap::new();
+
+        if let Some((lower, upper)) = &self.p_value_range {
+            params.insert("p_lower".to_string(), lower.clone());
+            params.insert("p_upper".to_string(), upper.clone());
+        }
+
+        if let Some((lower, upper)) = &self.bp_location_range {
+            params.insert("bp_lower".to_string(), lower.to_string());
+            params.insert("bp_upper".to_string(), upper.to_string());
+        }
+
+        if let Some(study) = &self.study {
+            params.insert("study_accession".to_string(), study.clone());
+        }
+
+        if let Some(trait_id) = &self.trait_id {
+            params.insert("trait".to_string(), trait_id.clone());
+        }
+
+        if let Some(reveal) = &self.reveal {
+            params.insert("reveal".to_string(), reveal.clone());
+        }
+
+        if let Some(start) = self.start {
+            params.insert("start".to_string(), start.to_string());
+        }
+
+        if let Some(size) = self.size {
+            params.insert("size".to_string(), size.to_string());
+        }
+
+        params
+    }
+
+    /// Checks the filter before any request is sent, naming the offending
+    /// parameter as R passes it and how to fix it, rather than leaving the
+    /// server to answer with a bare 400 or silently no rows.
+    pub fn validate(&self) -> Result<()> {
+        if let Some((lower, upper)) = &self.p_value_range {
+            let parse = |name: &str, value: &str| {
+                value.trim().parse::<f64>().map_err(|_| {
+                    anyhow::anyhow!(
+                        "{} must be a number such as \"5e-8\", got \"{}\"",
+                        name,
+                        value
+                    )
+                })
+            };
+            let (min, max) = (parse("p_value_min", lower)?, parse("p_value_max", upper)?);
+            if !(0.0..=1.0).contains(&min) {
+                return Err(anyhow::anyhow!(
+                    "p_value_min must be between 0 and 1, got {}",
+                    lower
+                ));
+            }
+            if !(max > 0.0 && max <= 1.0) {
+                return Err(anyhow::anyhow!(
+                    "p_value_max must be above 0 and at most 1, got {}",
+                    upper
+                ));
+            }
+            if min > max {
+                return Err(anyhow::anyhow!(
+                    "p_value_min ({}) is above p_value_max ({}); swap them",
+                    lower,
+                    upper
+                ));
+            }
+        }
+
+        if let Some((lower, upper)) = self.bp_location_range {
+            if lower < 0 {
+                return Err(anyhow::anyhow!(
+                    "bp_min must not be negative, got {}",
+                    lower
+                ));
+            }
+            if lower > upper {
+                return Err(anyhow::anyhow!(
+                    "bp_min ({}) is above bp_max ({}); swap them",
+                    lower,
+                    upper
+                ));
+            }
+        }
+
+        if let Some(reveal) = self.reveal.as_deref() {
+            if !REVEAL_MODES.contains(&reveal) {
+                return Err(anyhow::anyhow!(
+                    "reveal must be \"{}\", got \"{}\"",
+                    REVEAL_MODES.join("\" or \""),
+                    reveal
+                ));
+            }
+        }
+
+        if let Some(start) = self.start.filter(|start| *start < 0) {
+            return Err(anyhow::anyhow!("start must not be negative, got {}", start));
+        }
+
+        if let Some(size) = self.size.filter(|size| !(1..=MAX_PAGE_SIZE).contains(size)) {
+            return Err(anyhow::anyhow!(
+                "size must be between 1 and {}, got {}; fetch more rows with pages or output_file",
+                MAX_PAGE_SIZE,
+                size
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Named p-value thresholds: genome-wide significance and the usual
+/// suggestive level.
+pub const SIGNIFICANCE_PRESETS: [(&str, &str); 2] =
+    [("genome_wide", "5e-8"), ("suggestive", "1e-5")];
+
+/// The p-value threshold of a significance preset, or a custom threshold
+/// given as a number above 0 and at most 1, as text for `p_upper`.
+pub fn significance_threshold(significance: &str) -> Result<String> {
+    let significance = significance.trim();
+    if let Some((_, threshold)) = SIGNIFICANCE_PRESETS
+        .iter()
+        .find(|(name, _)| *name == significance)
+    {
+        return Ok(threshold.to_string());
+    }
+    match significance.parse::<f64>() {
+        Ok(threshold) if threshold > 0.0 && threshold <= 1.0 => Ok(significance.to_string()),
+        _ => Err(anyhow::anyhow!(
+            "significance must be {} or a p-value threshold such as \"1e-6\", got \"{}\"",
+            SIGNIFICANCE_PRESETS
+                .iter()
+                .map(|(name, threshold)| format!("\"{name}\" ({threshold})"))
+                .collect::<Vec<_>>()
+                .join(", "),
+            significance
+        )),
+    }
+}
+
+/// Values of the API's `reveal` parameter.
+pub const REVEAL_MODES: [&str; 2] = ["raw", "all"];
+
+/// Most associations the API serves in one page.
+pub const MAX_PAGE_SIZE: i32 = 1000;
+
+/// Longest request URL sent to an API. Servers commonly reject longer URLs,
+/// often with a bare 414 or a dropped connection.
+pub const MAX_URL_LENGTH: usize = 8000;
+
+#[derive(Debug, Clone)]
+pub struct GwasClient {
+    /// Makes the downloads.
+    client: Client,
+    /// Sends API requests, through `client` unless the builder was given
+    /// another transport.
+    transport: Arc<dyn HttpTransport>,
+    base_url: String,
+    /// Largest API response body read, 0 for no limit.
+    max_response_bytes: u64,
+    /// Added to this client's API requests on top of its default headers;
+    /// see `GwasClient::with_headers`.
+    call_headers: HeaderMap,
+    /// Shared by every download made through this client and its clones.
+    throttle: Option<Arc<TokenBucket>>,
+    interrupt: Interrupt,
+    /// Whether downloads of `.gz`, `.bgz` and `.zst` files are
+    /// decompressed as they arrive.
+    decompress: bool,
+}
+
+/// Settings for a `GwasClient`, starting from the session's timeouts,
+/// response size limit, connection pool, proxy, TLS settings and headers.
+#[derive(Debug, Clone)]
+pub struct GwasClientBuilder {
+    base_url: String,
+    timeouts: Timeouts,
+    max_response_bytes: u64,
+    pool: PoolSettings,
+    proxy: ProxySettings,
+    tls: TlsSettings,
+    headers: HeaderSettings,
+    cassette: CassetteSettings,
+    /// Consecutive failures that open the circuit breaker, and for how
+    /// long; see the `breaker` module.
+    breaker: (u32, Duration),
+    transport: Option<Arc<dyn HttpTransport>>,
+}
+
+impl GwasClientBuilder {
+    pub fn base_url(mut self, base_url: String) -> Self {
+        self.base_url = base_url;
+        self
+    }
+
+    /// Limit on establishing a connection; `None` for no limit.
+    pub fn connect_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeouts.connect_timeout = Some(timeout.map_or(0.0, |t| t.as_secs_f64()));
+        self
+    }
+
+    /// Limit on waiting for a response or for more of its body; `None`
+    /// for no limit.
+    pub fn read_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeouts.read_timeout = Some(timeout.map_or(0.0, |t| t.as_secs_f64()));
+        self
+    }
+
+    /// Limit on a whole API request; downloads are exempt. `None` for no
+    /// limit.
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeouts.timeout = Some(timeout.map_or(0.0, |t| t.as_secs_f64()));
+        self
+    }
+
+    /// Largest API response body to read; bigger responses fail with
+    /// `http::ResponseTooLarge`. `None` for no limit.
+    pub fn max_response_bytes(mut self, bytes: Option<u64>) -> Self {
+        self.max_response_bytes = bytes.unwrap_or(0);
+        self
+    }
+
+    /// Idle connections kept open per host for later requests.
+    pub fn pool_max_idle_per_host(mut self, idle: usize) -> Self {
+        self.pool.pool_max_idle_per_host = Some(idle);
+        self
+    }
+
+    /// How long an idle connection is kept open; `None` for no limit.
+    pub fn pool_idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.pool.pool_idle_timeout = Some(timeout.map_or(0.0, |t| t.as_secs_f64()));
+        self
+    }
+
+    /// Offers HTTP/2 to servers, negotiated over TLS, instead of only
+    /// HTTP/1.1.
+    pub fn http2(mut self, enabled: bool) -> Self {
+        self.pool.http2 = enabled;
+        self
+    }
+
+    /// Sends requests through the proxy at `url`, which may carry
+    /// credentials, except to hosts listed in `no_proxy`. "none" connects
+    /// directly and `None` follows the proxy environment variables.
+    pub fn proxy(mut self, url: Option<String>) -> Self {
+        self.proxy.url = url;
+        self
+    }
+
+    /// Basic credentials for the proxy.
+    pub fn proxy_credentials(mut self, username: &str, password: &str) -> Self {
+        self.proxy.credentials = Some((username.to_string(), password.to_string()));
+        self
+    }
+
+    /// Trusts the CA certificates in a PEM file besides the TLS backend's
+    /// own, e.g. for a proxy that intercepts TLS. `None` for none.
+    pub fn ca_file(mut self, path: Option<String>) -> Self {
+        self.tls.ca_file = path;
+        self
+    }
+
+    /// TLS implementation; only those compiled in can be built.
+    pub fn tls_backend(mut self, backend: TlsBackend) -> Self {
+        self.tls.tls_backend = backend;
+        self
+    }
+
+    /// User-Agent sent instead of `http::DEFAULT_USER_AGENT`.
+    pub fn user_agent(mut self, user_agent: String) -> Self {
+        self.headers.user_agent = Some(user_agent);
+        self
+    }
+
+    /// Contact, such as an email address, added to the User-Agent.
+    pub fn contact(mut self, contact: String) -> Self {
+        self.headers.contact = Some(contact);
+        self
+    }
+
+    /// A header sent with every request, after the session's.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers
+            .headers
+            .push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Sends API requests through `transport`, e.g. a
+    /// `transport::MockTransport` in tests, instead of over the network.
+    /// Downloads still go over the network.
+    pub fn transport(mut self, transport: Arc<dyn HttpTransport>) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    /// Fails API requests at once for `cooldown` after `failures`
+    /// consecutive ones got no response or a server error, then lets one
+    /// through to probe. 0 failures turns the breaker off.
+    pub fn circuit_breaker(mut self, failures: u32, cooldown: Duration) -> Self {
+        self.breaker = (failures, cooldown);
+        self
+    }
+
+    /// Records API responses to, or replays them from, a cassette
+    /// directory; see the `cassette` module. Ignored with `transport`.
+    pub fn cassette(mut self, cassette: CassetteSettings) -> Self {
+        self.cassette = cassette;
+        self
+    }
+
+    pub fn build(self) -> Result<GwasClient> {
+        let client = http::client(
+            &self.timeouts,
+            &self.pool,
+            &self.proxy,
+            &self.tls,
+            &self.headers,
+        )?;
+        let transport = match self.transport {
+            Some(transport) => transport,
+            None => {
+                // Every client shares the session's limit on requests in
+                // flight, and its kept responses to revalidate; the breaker
+                // is the client's own.
+                let limited = LimitedTransport::new(
+                    Arc::new(ReqwestTransport::new(client.clone(), self.timeouts)),
+                    &throttle::API_REQUESTS,
+                );
+                let (failures, cooldown) = self.breaker;
+                let network = Arc::new(BreakerTransport::new(
+                    Arc::new(ConditionalTransport::new(Arc::new(limited))),
+                    CircuitBreaker::new(failures, cooldown),
+                ));
+                // Tokens and header values, which may be keys, stay out of
+                // recorded bodies.
+                let secrets = session_config()
+                    .ok()
+                    .and_then(|config| config.token)
+                    .into_iter()
+                    .chain(self.headers.headers.iter().map(|(_, value)| value.clone()))
+                    .collect();
+                self.cassette
+                    .transport(network, secrets, self.max_response_bytes)?
+            }
+        };
+        Ok(GwasClient {
+            client,
+            transport,
+            base_url: self.base_url,
+            max_response_bytes: self.max_response_bytes,
+            call_headers: HeaderMap::new(),
+            throttle: None,
+            interrupt: Interrupt::new(),
+            decompress: false,
+        })
+    }
+}
+
+impl GwasClient {
+    pub fn builder() -> GwasClientBuilder {
+        GwasClientBuilder {
+            base_url: "https://www.ebi.ac.uk/gwas/summary-statistics/api".to_string(),
+            timeouts: Timeouts::session(),
+            max_response_bytes: session_max_response_bytes(),
+            pool: PoolSettings::session(),
+            proxy: ProxySettings::session(),
+            tls: TlsSettings::session(),
+            headers: HeaderSettings::session(),
+            cassette: CassetteSettings::session(),
+            breaker: (
+                breaker::DEFAULT_BREAKER_FAILURES,
+                breaker::DEFAULT_BREAKER_COOLDOWN,
+            ),
+            transport: None,
+        }
+    }
+
+    pub fn new() -> Result<Self> {
+        Self::builder().build()
+    }
+
+    pub fn with_base_url(base_url: String) -> Result<Self> {
+        Self::builder().base_url(base_url).build()
+    }
+
+    /// Sends a GET request to the API through the client's transport.
+    fn api_get(&self, url: Url) -> Result<HttpResponse> {
+        self.transport.get(url, &self.call_headers)
+    }
+
+    /// A client sending `headers` with its API requests as well, for one
+    /// call or a few; a header already set is replaced. Clones share the
+    /// connection pool.
+    pub fn with_headers(mut self, headers: &[(String, String)]) -> Result<Self> {
+        self.call_headers.extend(header_map(headers)?);
+        Ok(self)
+    }
+
+    /// Reads an API response body within the size limit.
+    fn read_body(&self, response: impl Into<HttpResponse>) -> Result<Vec<u8>> {
+        read_body(response, self.max_response_bytes)
+    }
+
+    /// Deserializes an API response as it arrives, within the size limit.
+    fn read_json<T: DeserializeOwned>(&self, response: impl Into<HttpResponse>) -> Result<T> {
+        read_json(response, self.max_response_bytes)
+    }
+
+    /// Flag that stops this client's paged fetches and downloads; see
+    /// `interrupt::with_interrupts`.
+    pub fn interrupt(&self) -> &Interrupt {
+        &self.interrupt
+    }
+
+    /// Limits the combined download rate of this client, across threads,
+    /// to `bytes_per_second`. `None` removes the limit.
+    pub fn with_bandwidth_limit(mut self, bytes_per_second: Option<f64>) -> Self {
+        self.throttle = bytes_per_second.map(|rate| Arc::new(TokenBucket::new(rate)));
+        self
+    }
+
+    /// Decompresses downloads of compressed files into paths without the
+    /// compression extension; see `download::Compression`.
+    pub fn with_decompression(mut self, decompress: bool) -> Self {
+        self.decompress = decompress;
+        self
+    }
+
+    fn url_for(&self, endpoint: &str, params: &BTreeMap<String, String>) -> Result<Url> {
+        let mut url = Url::parse(&format!(
+            "{}/{}",
+            self.base_url,
+            endpoint.trim_start_matches('/')
+        ))?;
+        for (key, value) in params {
+            url.query_pairs_mut().append_pair(key, value);
+        }
+        Ok(url)
+    }
+
+    fn url_fits(&self, endpoint: &str, params: &BTreeMap<String, String>) -> bool {
+        self.url_for(endpoint, params)
+            .map_or(false, |url| url.as_str().len() <= MAX_URL_LENGTH)
+    }
+
+    /// Builds a request URL, refusing URLs longer than `MAX_URL_LENGTH`
+    /// rather than letting the server fail on them.
+    fn build_url(&self, endpoint: &str, params: &BTreeMap<String, String>) -> Result<Url> {
+        let url = self.url_for(endpoint, params)?;
+        let length = url.as_str().len();
+        if length > MAX_URL_LENGTH {
+            let (longest, value) = params
+                .iter()
+                .max_by_key(|(_, value)| value.len())
+                .map_or(("", 0), |(key, value)| (key.as_str(), value.len()));
+            return Err(anyhow::anyhow!(
+                "Request URL is {} characters, over the limit of {}; the longest parameter is {} ({} characters)",
+                length,
+                MAX_URL_LENGTH,
+                longest,
+                value
+            ));
+        }
+        Ok(url)
+    }
+
+    fn check_json_response(&self, response: HttpResponse) -> Result<HttpResponse> {
+        if !response.status.is_success() {
+            let status = response.status;
+            let text = error_text(response);
+            return Err(HttpStatus { status, text }.into());
+        }
+
+        if let Some(ct_str) = response.content_type() {
+            if !ct_str.contains("application/json") {
+                return Err(anyhow::anyhow!("Expected JSON response, got: {}", ct_str));
+            }
+        }
+
+        Ok(response)
+    }
+
+    pub fn get_associations(
+        &self,
+        params: BTreeMap<String, String>,
+    ) -> Result<HalResponse<OrderedMap<Association>>> {
+        let url = self.build_url("/associations", &params)?;
+        let response = self.api_get(url)?;
+        let response = self.check_json_response(response)?;
+        let data = parse_page(&self.read_body(response)?)?;
+        Ok(data)
+    }
+
+    pub fn get_variant_associations(
+        &self,
+        variant_id: &str,
+        params: BTreeMap<String, String>,
+    ) -> Result<HalResponse<OrderedMap<Association>>> {
+        let endpoint = format!("/associations/{variant_id}");
+        let url = self.build_url(&endpoint, &params)?;
+        let response = self.api_get(url)?;
+        let response = self.check_json_response(response)?;
+        let data = parse_page(&self.read_body(response)?)?;
+        Ok(data)
+    }
+
+    pub fn get_chromosomes(&self) -> Result<HalResponse<Vec<Chromosome>>> {
+        let url = self.build_url("/chromosomes", &BTreeMap::new())?;
+        let response = self.api_get(url)?;
+        let response = self.check_json_response(response)?;
+        let data: HalResponse<Vec<Chromosome>> = self.read_json(response)?;
+        Ok(data)
+    }
+
+    pub fn get_chromosome(&self, chromosome: &str) -> Result<Chromosome> {
+        let endpoint = format!("/chromosomes/{chromosome}");
+        let url = self.build_url(&endpoint, &BTreeMap::new())?;
+        let response = self.api_get(url)?;
+        let response = self.check_json_response(response)?;
+        let data: Chromosome = self.read_json(response)?;
+        Ok(data)
+    }
+
+    pub fn get_chromosome_associations(
+        &self,
+        chromosome: &str,
+        params: BTreeMap<String, String>,
+    ) -> Result<HalResponse<OrderedMap<Association>>> {
+        let endpoint = format!("/chromosomes/{chromosome}/associations");
+        let url = self.build_url(&endpoint, &params)?;
+        let response = self.api_get(url)?;
+        let response = self.check_json_response(response)?;
+        let data = parse_page(&self.read_body(response)?)?;
+        Ok(data)
+    }
+
+    pub fn get_chromosome_variant_associations(
+        &self,
+        chromosome: &str,
+        variant_id: &str,
+        params: BTreeMap<String, String>,
+    ) -> Result<HalResponse<OrderedMap<Association>>> {
+        let endpoint = format!("/chromosomes/{chromosome}/associations/{variant_id}");
+        let url = self.build_url(&endpoint, &params)?;
+        let response = self.api_get(url)?;
+        let response = self.check_json_response(response)?;
+        let data = parse_page(&self.read_body(response)?)?;
+        Ok(data)
+    }
+
+    pub fn get_studies(
+        &self,
+        params: BTreeMap<String, String>,
+    ) -> Result<HalResponse<Vec<Vec<Study>>>> {
+        let url = self.build_url("/studies", &params)?;
+        let response = self.api_get(url)?;
+        let response = self.check_json_response(response)?;
+        let data: HalResponse<Vec<Vec<Study>>> = self.read_json(response)?;
+        Ok(data)
+    }
+
+    pub fn get_study(&self, study_accession: &str) -> Result<Study> {
+        let endpoint = format!("/studies/{study_accession}");
+        let url = self.build_url(&endpoint, &BTreeMap::new())?;
+        let response = self.api_get(url)?;
+        let response = self.check_json_response(response)?;
+        let data: Study = self.read_json(response)?;
+        Ok(data)
+    }
+
+    pub fn get_study_associations(
+        &self,
+        study_accession: &str,
+        params: BTreeMap<String, String>,
+    ) -> Result<HalResponse<OrderedMap<Association>>> {
+        let endpoint = format!("/studies/{study_accession}/associations");
+        let url = self.build_url(&endpoint, &params)?;
+        let response = self.api_get(url)?;
+        let response = self.check_json_response(response)?;
+        let data = parse_page(&self.read_body(response)?)?;
+        Ok(data)
+    }
+
+    pub fn get_traits(&self, params: BTreeMap<String, String>) -> Result<HalResponse<Vec<Trait>>> {
+        let url = self.build_url("/traits", &params)?;
+        let response = self.api_get(url)?;
+        let response = self.check_json_response(response)?;
+        let data: HalResponse<Vec<Trait>> = self.read_json(response)?;
+        Ok(data)
+    }
+
+    pub fn get_trait(&self, trait_id: &str) -> Result<Trait> {
+        let endpoint = format!("/traits/{trait_id}");
+        let url = self.build_url(&endpoint, &BTreeMap::new())?;
+        let response = self.api_get(url)?;
+        let response = self.check_json_response(response)?;
+        let data: Trait = self.read_json(response)?;
+        Ok(data)
+    }
+
+    pub fn get_trait_associations(
+        &self,
+        trait_id: &str,
+        params: BTreeMap<String, String>,
+    ) -> Result<HalResponse<OrderedMap<Association>>> {
+        let endpoint = format!("/traits/{trait_id}/associations");
+        let url = self.build_url(&endpoint, &params)?;
+        let response = self.api_get(url)?;
+        let response = self.check_json_response(response)?;
+        let data = parse_page(&self.read_body(response)?)?;
+        Ok(data)
+    }
+
+    pub fn get_trait_studies(
+        &self,
+        trait_id: &str,
+        params: BTreeMap<String, String>,
+    ) -> Result<HalResponse<Vec<Study>>> {
+        let endpoint = format!("/traits/{trait_id}/studies");
+        let url = self.build_url(&endpoint, &params)?;
+        let response = self.api_get(url)?;
+        let response = self.check_json_response(response)?;
+        let data: HalResponse<Vec<Study>> = self.read_json(response)?;
+        Ok(data)
+    }
+
+    pub fn get_trait_study(&self, trait_id: &str, study_accession: &str) -> Result<Study> {
+        let endpoint = format!("/traits/{trait_id}/studies/{study_accession}");
+        let url = self.build_url(&endpoint, &BTreeMap::new())?;
+        let response = self.api_get(url)?;
+        let response = self.check_json_response(response)?;
+        let data: Study = self.read_json(response)?;
+        Ok(data)
+    }
+
+    pub fn get_trait_study_associations(
+        &self,
+        trait_id: &str,
+        study_accession: &str,
+        params: BTreeMap<String, String>,
+    ) -> Result<HalResponse<OrderedMap<Association>>> {
+        let endpoint = format!("/traits/{trait_id}/studies/{study_accession}/associations");
+        let url = self.build_url(&endpoint, &params)?;
+        let response = self.api_get(url)?;
+        let response = self.check_json_response(response)?;
+        let data = parse_page(&self.read_body(response)?)?;
+        Ok(data)
+    }
+
+    pub fn get_study_summary_stats_files(
+        &self,
+        study_accession: &str,
+    ) -> Result<HalResponse<Vec<SummaryStatsFile>>> {
+        let endpoint = format!("/studies/{study_accession}/summary-statistics");
+        let url = self.build_url(&endpoint, &BTreeMap::new())?;
+        let response = self.api_get(url)?;
+
+        let response = self.check_json_response(response)?;
+        let data: HalResponse<Vec<SummaryStatsFile>> = self.read_json(response)?;
+        Ok(data)
+    }
+
+    pub fn get_trait_summary_stats_files(
+        &self,
+        trait_id: &str,
+    ) -> Result<HalResponse<Vec<SummaryStatsFile>>> {
+        let endpoint = format!("/traits/{trait_id}/summary-statistics");
+        let url = self.build_url(&endpoint, &BTreeMap::new())?;
+        let response = self.api_get(url)?;
+
+        let response = self.check_json_response(response)?;
+        let data: HalResponse<Vec<SummaryStatsFile>> = self.read_json(response)?;
+        Ok(data)
+    }
+
+    pub fn get_trait_study_summary_stats_files(
+        &self,
+        trait_id: &str,
+        study_accession: &str,
+    ) -> Result<HalResponse<Vec<SummaryStatsFile>>> {
+        let endpoint = format!("/traits/{trait_id}/studies/{study_accession}/summary-statistics");
+        let url = self.build_url(&endpoint, &BTreeMap::new())?;
+        let response = self.api_get(url)?;
+
+        let response = self.check_json_response(response)?;
+        let data: HalResponse<Vec<SummaryStatsFile>> = self.read_json(response)?;
+        Ok(data)
+    }
+
+    /// Download URLs of a study's summary statistics files, taken from
+    /// `download_url` or the `download` link, each paired with a local path
+    /// under `<output_dir>/<study_accession>/` (or its `harmonised/` folder).
+    pub fn study_downloads(
+        &self,
+        study_accession: &str,
+        output_dir: &str,
+    ) -> Result<Vec<(String, String)>> {
+        let files = self.get_study_summary_stats_files(study_accession)?;
+        let mut downloads: Vec<(String, String)> = Vec::new();
+        for file in files
+            .embedded
+            .into_iter()
+            .flat_map(|embedded| embedded.into_values())
+            .flatten()
+        {
+            let url = file
+                .download_url
+                .clone()
+                .or_else(|| {
+                    file.links
+                        .as_ref()
+                        .and_then(|links| links.get("download"))
+                        .map(|link| link.href.clone())
+                })
+                .or_else(|| Some(file.file_path.clone()).filter(|path| path.starts_with("http")))
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "No download URL for {} in {}",
+                        file.file_path,
+                        study_accession
+                    )
+                })?;
+
+            let url_path = Url::parse(&url)
+                .map(|url| url.path().to_string())
+                .unwrap_or_default();
+            let name = url_path
+                .rsplit('/')
+                .next()
+                .filter(|name| !name.is_empty())
+                .unwrap_or_else(|| file.file_path.rsplit('/').next().unwrap_or(""));
+            // Keep harmonised files apart, as on the FTP site.
+            let mut path = Path::new(output_dir).join(study_accession);
+            if url_path.contains("/harmonised/") {
+                path.push("harmonised");
+            }
+            let path = path.join(name).to_string_lossy().into_owned();
+            if !downloads.iter().any(|(existing, _)| existing == &url) {
+                downloads.push((url, path));
+            }
+        }
+
+        if downloads.is_empty() {
+            return Err(anyhow::anyhow!(
+                "No summary statistics files listed for {}",
+                study_accession
+            ));
+        }
+        Ok(downloads)
+    }
+
+    pub fn get_entity(
+        &self,
+        entity_type: &str,
+        id: Option<&str>,
+        filter: &GwasFilter,
+    ) -> Result<String> {
+        filter.validate()?;
+        let params = filter.to_params();
+
+        match entity_type {
+            "chromosomes" => {
+                if let Some(chromosome_id) = id {
+                    match self.get_chromosome(&normalize_chromosome(chromosome_id)?) {
+                        Ok(data) => Ok(to_json(&data)?),
+                        Err(e) => Err(e),
+                    }
+                } else {
+                    match self.get_chromosomes() {
+                        Ok(data) => Ok(to_json(&data)?),
+                        Err(e) => Err(e),
+                    }
+                }
+            }
+            "studies" => {
+                if let Some(study_id) = id {
+                    match self.get_study(study_id) {
+                        Ok(data) => Ok(to_json(&data)?),
+                        Err(e) => Err(e),
+                    }
+                } else {
+                    match self.get_studies(params) {
+                        Ok(data) => Ok(to_json(&data)?),
+                        Err(e) => Err(e),
+                    }
+                }
+            }
+            "traits" => {
+                if let Some(trait_id) = id {
+                    match self.get_trait(trait_id) {
+                        Ok(data) => Ok(to_json(&data)?),
+                        Err(e) => Err(e),
+                    }
+                } else {
+                    match self.get_traits(params) {
+                        Ok(data) => Ok(to_json(&data)?),
+                        Err(e) => Err(e),
+                    }
+                }
+            }
+            _ => Err(anyhow::anyhow!("Invalid entity type: {}", entity_type)),
+        }
+    }
+
+    /// The URL `get_entity` would request.
+    pub fn entity_url(
+        &self,
+        entity_type: &str,
+        id: Option<&str>,
+        filter: &GwasFilter,
+    ) -> Result<Url> {
+        if !matches!(entity_type, "chromosomes" | "studies" | "traits") {
+            return Err(anyhow::anyhow!("Invalid entity type: {}", entity_type));
+        }
+        match id {
+            Some(id) if entity_type == "chromosomes" => self.build_url(
+                &format!("/chromosomes/{}", normalize_chromosome(id)?),
+                &BTreeMap::new(),
+            ),
+            Some(id) => self.build_url(&format!("/{entity_type}/{id}"), &BTreeMap::new()),
+            // Chromosomes are listed whole.
+            None if entity_type == "chromosomes" => {
+                self.build_url("/chromosomes", &BTreeMap::new())
+            }
+            None => self.build_url(&format!("/{entity_type}"), &filter.to_params()),
+        }
+    }
+
+    pub fn get_unified_associations(
+        &self,
+        entity_type: Option<&str>,
+        entity_id: Option<&str>,
+        filter: &GwasFilter,
+    ) -> Result<String> {
+        let data = self.fetch_unified_associations(entity_type, entity_id, filter)?;
+        Ok(to_json(&data)?)
+    }
+
+    pub fn fetch_unified_associations(
+        &self,
+        entity_type: Option<&str>,
+        entity_id: Option<&str>,
+        filter: &GwasFilter,
+    ) -> Result<HalResponse<OrderedMap<Association>>> {
+        filter.validate()?;
+        let params = filter.to_params();
+        if !self.url_fits(&associations_endpoint(entity_type, entity_id)?, &params) {
+            return self.fetch_split_associations(entity_type, entity_id, filter);
+        }
+
+        match (entity_type, entity_id) {
+            (None, None) => self.get_associations(params),
+            (Some("variant"), Some(variant_id)) => {
+                self.get_variant_associations(&normalize_variant_id(variant_id)?, params)
+            }
+            (Some("chromosome"), Some(chromosome_id)) => {
+                self.get_chromosome_associations(&normalize_chromosome(chromosome_id)?, params)
+            }
+            (Some("study"), Some(study_id)) => self.get_study_associations(study_id, params),
+            (Some("trait"), Some(trait_id)) => self.get_trait_associations(trait_id, params),
+            _ => Err(anyhow::anyhow!("Invalid entity type or missing ID")),
+        }
+    }
+
+    /// The URL of `endpoint`, a path under the base URL, with `params` as
+    /// its query string.
+    pub fn raw_url(&self, endpoint: &str, params: &BTreeMap<String, String>) -> Result<Url> {
+        if endpoint.contains("://") || endpoint.contains(['?', '#']) {
+            return Err(anyhow::anyhow!(
+                "Endpoint must be a path under the base URL, with parameters passed separately; got {}",
+                endpoint
+            ));
+        }
+        self.build_url(endpoint, params)
+    }
+
+    /// The JSON body of a GET request to any endpoint, unparsed, for
+    /// endpoints without a typed method yet.
+    pub fn get_raw(&self, endpoint: &str, params: &BTreeMap<String, String>) -> Result<String> {
+        self.get_body(self.raw_url(endpoint, params)?)
+    }
+
+    /// The JSON body of a GET request to `url`, unparsed.
+    fn get_body(&self, url: Url) -> Result<String> {
+        let response = self.api_get(url)?;
+        let response = self.check_json_response(response)?;
+        Ok(String::from_utf8(self.read_body(response)?)?)
+    }
+
+    pub fn list_files(
+        &self,
+        entity_type: &str,
+        entity_id: &str,
+        secondary_id: Option<&str>,
+    ) -> Result<String> {
+        let result = match (entity_type, secondary_id) {
+            ("study", None) => self.get_study_summary_stats_files(entity_id),
+            ("trait", None) => self.get_trait_summary_stats_files(entity_id),
+            ("trait", Some(study_id)) => {
+                self.get_trait_study_summary_stats_files(entity_id, study_id)
+            }
+            _ => return Err(anyhow::anyhow!("Invalid file entity type or parameters")),
+        };
+
+        match result {
+            Ok(data) => Ok(to_json(&data)?),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Serializes a result for R in the session's JSON layout, set with
+/// `gwas_configure(json = )`.
+pub fn to_json<T: Serialize + ?Sized>(value: &T) -> serde_json::Result<String> {
+    let compact = session_config().map_or(false, |config| config.json == "compact");
+    if compact {
+        serde_json::to_string(value)
+    } else {
+        serde_json::to_string_pretty(value)
+    }
+}
@@ -0,0 +1,145 @@
+use crate::ids;
+use crate::sumstats::open_text;
+use crate::Association;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::io::BufRead;
+
+#[derive(Debug, Clone)]
+struct Block {
+    target_start: i64,
+    target_end: i64,
+    query_name: String,
+    query_start: i64,
+    query_size: i64,
+    query_reverse: bool,
+}
+
+/// Coordinate map parsed from a UCSC chain file (plain or gzipped), e.g.
+/// `hg38ToHg19.over.chain.gz` to move GRCh38 positions onto GRCh37.
+#[derive(Debug, Default)]
+pub struct ChainMap {
+    blocks: HashMap<String, Vec<Block>>,
+    longest_block: HashMap<String, i64>,
+}
+
+impl ChainMap {
+    pub fn open(path: &str) -> Result<Self> {
+        let mut map = ChainMap::default();
+        let mut current: Option<(String, i64, String, i64, i64, bool)> = None;
+
+        for line in open_text(path)?.lines() {
+            let line = line?;
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.is_empty() {
+                continue;
+            }
+
+            if fields[0] == "chain" {
+                if fields.len() < 12 {
+                    return Err(anyhow::anyhow!("Malformed chain header: {}", line));
+                }
+                current = Some((
+                    normalize_chromosome(fields[2]),
+                    fields[5].parse()?,
+                    normalize_chromosome(fields[7]),
+                    fields[8].parse()?,
+                    fields[10].parse()?,
+                    fields[9] == "-",
+                ));
+                continue;
+            }
+
+            let (target_name, target_pos, query_name, query_size, query_pos, query_reverse) =
+                current
+                    .as_mut()
+                    .ok_or_else(|| anyhow::anyhow!("Alignment data before chain header"))?;
+            let size: i64 = fields[0].parse()?;
+
+            map.blocks
+                .entry(target_name.clone())
+                .or_default()
+                .push(Block {
+                    target_start: *target_pos,
+                    target_end: *target_pos + size,
+                    query_name: query_name.clone(),
+                    query_start: *query_pos,
+                    query_size: *query_size,
+                    query_reverse: *query_reverse,
+                });
+            let longest = map.longest_block.entry(target_name.clone()).or_insert(0);
+            *longest = (*longest).max(size);
+
+            *target_pos += size;
+            *query_pos += size;
+            if fields.len() >= 3 {
+                *target_pos += fields[1].parse::<i64>()?;
+                *query_pos += fields[2].parse::<i64>()?;
+            }
+        }
+
+        for blocks in map.blocks.values_mut() {
+            blocks.sort_by_key(|b| b.target_start);
+        }
+        Ok(map)
+    }
+
+    /// Maps a 1-based position to the query build, with the chromosome
+    /// numbered as by `ids::normalize_chromosome`; `None` when the position
+    /// falls in a gap.
+    pub fn lift(&self, chromosome: &str, position: i64) -> Option<(String, i64)> {
+        let chromosome = normalize_chromosome(chromosome);
+        let blocks = self.blocks.get(&chromosome)?;
+        let longest = self.longest_block.get(&chromosome).copied().unwrap_or(0);
+        let zero_based = position - 1;
+
+        let end = blocks.partition_point(|b| b.target_start <= zero_based);
+        blocks[..end]
+            .iter()
+            .rev()
+            .take_while(|b| b.target_start + longest > zero_based)
+            .find(|b| zero_based < b.target_end)
+            .map(|b| {
+                let offset = b.query_start + (zero_based - b.target_start);
+                let mapped = if b.query_reverse {
+                    b.query_size - 1 - offset
+                } else {
+                    offset
+                };
+                (b.query_name.clone(), mapped + 1)
+            })
+    }
+}
+
+/// A chain's sequence name as the API numbers chromosomes ("chrX" is
+/// "23"); other contigs, such as alternate haplotypes, keep their name.
+fn normalize_chromosome(chromosome: &str) -> String {
+    ids::normalize_chromosome(chromosome).unwrap_or_else(|_| chromosome.to_string())
+}
+
+/// Rewrites chromosome and position of each association in place. Rows
+/// that cannot be lifted (gaps, or alternate contigs) have both cleared,
+/// so that no row keeps a chromosome of the old build.
+pub fn lift_associations<'a>(
+    associations: impl Iterator<Item = &'a mut Association>,
+    chain: &ChainMap,
+) {
+    for association in associations {
+        let lifted = match (association.chromosome, association.base_pair_location) {
+            (Some(chromosome), Some(position)) => chain
+                .lift(&chromosome.to_string(), position)
+                .and_then(|(c, p)| Some((c.parse().ok()?, p))),
+            _ => continue,
+        };
+        match lifted {
+            Some((chromosome, position)) => {
+                association.chromosome = Some(chromosome);
+                association.base_pair_location = Some(position);
+            }
+            None => {
+                association.chromosome = None;
+                association.base_pair_location = None;
+            }
+        }
+    }
+}
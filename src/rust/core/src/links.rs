@@ -0,0 +1,119 @@
+use crate::ordered::OrderedMap;
+use crate::paging::parse_page;
+use crate::{
+    Association, Chromosome, GwasClient, HalResponse, Link, Study, SummaryStatsFile, Trait,
+};
+use anyhow::Result;
+use serde::Serialize;
+use url::Url;
+
+/// A response reached through a `_links` href, typed by the endpoint the
+/// link points at. Serializes as the response itself.
+#[derive(Debug, Serialize, Clone)]
+#[serde(untagged)]
+pub enum Linked {
+    Associations(HalResponse<OrderedMap<Association>>),
+    Chromosomes(HalResponse<Vec<Chromosome>>),
+    Chromosome(Chromosome),
+    Studies(HalResponse<Vec<Vec<Study>>>),
+    TraitStudies(HalResponse<Vec<Study>>),
+    Study(Study),
+    Traits(HalResponse<Vec<Trait>>),
+    Trait(Trait),
+    Files(HalResponse<Vec<SummaryStatsFile>>),
+    /// An endpoint without a typed method, as the API sent it.
+    Other(serde_json::Value),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Associations,
+    Chromosomes,
+    Chromosome,
+    Studies,
+    TraitStudies,
+    Study,
+    Traits,
+    Trait,
+    Files,
+    Other,
+}
+
+/// The response type of an endpoint, from its path under the base URL.
+fn kind(segments: &[&str]) -> Kind {
+    match segments {
+        ["associations"] | ["associations", _] => Kind::Associations,
+        ["chromosomes"] => Kind::Chromosomes,
+        ["chromosomes", _] => Kind::Chromosome,
+        ["chromosomes", _, "associations"] | ["chromosomes", _, "associations", _] => {
+            Kind::Associations
+        }
+        ["studies"] => Kind::Studies,
+        ["studies", _] | ["traits", _, "studies", _] => Kind::Study,
+        ["studies", _, "associations"]
+        | ["traits", _, "associations"]
+        | ["traits", _, "studies", _, "associations"] => Kind::Associations,
+        ["studies", _, "summary-statistics"]
+        | ["traits", _, "summary-statistics"]
+        | ["traits", _, "studies", _, "summary-statistics"] => Kind::Files,
+        ["traits"] => Kind::Traits,
+        ["traits", _] => Kind::Trait,
+        ["traits", _, "studies"] => Kind::TraitStudies,
+        _ => Kind::Other,
+    }
+}
+
+impl GwasClient {
+    /// The URL of `link`, without the `{?start,size}` template some links
+    /// carry, refused unless it lies under the configured base URL: the
+    /// client's headers and token are not for other hosts.
+    pub fn link_url(&self, link: &Link) -> Result<Url> {
+        let href = link.href.split('{').next().unwrap_or_default();
+        let url = Url::parse(href)
+            .map_err(|e| anyhow::anyhow!("Invalid link URL {}: {}", link.href, e))?;
+        let base = Url::parse(&self.base_url)?;
+        let base_path = base.path().trim_end_matches('/');
+        let under_base = url.scheme() == base.scheme()
+            && url.host_str() == base.host_str()
+            && url.port_or_known_default() == base.port_or_known_default()
+            && url
+                .path()
+                .strip_prefix(base_path)
+                .map_or(false, |rest| rest.is_empty() || rest.starts_with('/'));
+        if !under_base {
+            return Err(anyhow::anyhow!(
+                "Link {} is not under the API base URL {}",
+                href,
+                self.base_url
+            ));
+        }
+        Ok(url)
+    }
+
+    /// Requests the endpoint `link` points at, one of those in a response's
+    /// `_links`, and deserializes the response into the type its typed
+    /// method would return.
+    pub fn follow_link(&self, link: &Link) -> Result<Linked> {
+        let url = self.link_url(link)?;
+        let base = Url::parse(&self.base_url)?;
+        let path = &url.path()[base.path().trim_end_matches('/').len()..];
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let kind = kind(&segments);
+
+        let response = self.api_get(url)?;
+        let response = self.check_json_response(response)?;
+        let body = self.read_body(response)?;
+        Ok(match kind {
+            Kind::Associations => Linked::Associations(parse_page(&body)?),
+            Kind::Chromosomes => Linked::Chromosomes(serde_json::from_slice(&body)?),
+            Kind::Chromosome => Linked::Chromosome(serde_json::from_slice(&body)?),
+            Kind::Studies => Linked::Studies(serde_json::from_slice(&body)?),
+            Kind::TraitStudies => Linked::TraitStudies(serde_json::from_slice(&body)?),
+            Kind::Study => Linked::Study(serde_json::from_slice(&body)?),
+            Kind::Traits => Linked::Traits(serde_json::from_slice(&body)?),
+            Kind::Trait => Linked::Trait(serde_json::from_slice(&body)?),
+            Kind::Files => Linked::Files(serde_json::from_slice(&body)?),
+            Kind::Other => Linked::Other(serde_json::from_slice(&body)?),
+        })
+    }
+}
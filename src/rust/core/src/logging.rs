@@ -0,0 +1,207 @@
+use anyhow::Result;
+use std::fmt::{self, Write as _};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, Once, RwLock};
+use std::thread::{self, ThreadId};
+use tracing::field::{Field, Visit};
+use tracing::level_filters::LevelFilter;
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Metadata, Subscriber};
+
+/// Level names for `gwas_set_verbose()`, least verbose first.
+pub const LEVELS: [&str; 6] = ["off", "error", "warn", "info", "debug", "trace"];
+
+/// Lines held for the R thread at most; later ones are counted and dropped,
+/// e.g. while a background job logs with nobody polling.
+const MAX_PENDING: usize = 1000;
+
+/// Index into `LEVELS` of the most verbose level logged.
+static LEVEL: AtomicUsize = AtomicUsize::new(0);
+
+static INSTALL: Once = Once::new();
+
+/// The thread that set the level, R's main thread, which alone may print.
+static R_THREAD: Mutex<Option<ThreadId>> = Mutex::new(None);
+
+/// Lines logged on other threads, printed when the R thread next polls
+/// or logs.
+static PENDING: Mutex<Pending> = Mutex::new(Pending {
+    lines: Vec::new(),
+    dropped: 0,
+});
+
+struct Pending {
+    lines: Vec<String>,
+    dropped: usize,
+}
+
+/// Sets the most verbose level logged to the R console, installing the
+/// console subscriber on first use, and returns the previous level.
+/// Must be called on the R main thread.
+pub fn set_level(name: &str) -> Result<&'static str> {
+    let level = LEVELS.iter().position(|l| *l == name).ok_or_else(|| {
+        anyhow::anyhow!(
+            "Unknown log level {}; expected one of {}",
+            name,
+            LEVELS.join(", ")
+        )
+    })?;
+    INSTALL.call_once(|| {
+        // Fails only if the host installed a subscriber of its own first.
+        let _ = tracing::subscriber::set_global_default(Console);
+    });
+    if let Ok(mut r_thread) = R_THREAD.lock() {
+        *r_thread = Some(thread::current().id());
+    }
+    let previous = LEVEL.swap(level, Ordering::Relaxed);
+    // Callsites cache whether they are enabled; make them ask again.
+    tracing::callsite::rebuild_interest_cache();
+    Ok(LEVELS[previous])
+}
+
+/// Prints the lines other threads have logged. Must run on the R main
+/// thread; see `interrupt::with_interrupts`.
+pub fn flush() {
+    let (lines, dropped) = match PENDING.lock() {
+        Ok(mut pending) => (
+            std::mem::take(&mut pending.lines),
+            std::mem::take(&mut pending.dropped),
+        ),
+        Err(_) => return,
+    };
+    for line in &lines {
+        print(line);
+    }
+    if dropped > 0 {
+        print(&format!("iani  WARN {dropped} log lines dropped"));
+    }
+}
+
+fn rank(level: &Level) -> usize {
+    match *level {
+        Level::ERROR => 1,
+        Level::WARN => 2,
+        Level::INFO => 3,
+        Level::DEBUG => 4,
+        Level::TRACE => 5,
+    }
+}
+
+/// Writes the package's events to the R console as one line each: level,
+/// message, then the fields as `name=value`.
+struct Console;
+
+impl Subscriber for Console {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.target().starts_with("iani")
+            && rank(metadata.level()) <= LEVEL.load(Ordering::Relaxed)
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        Some(match LEVEL.load(Ordering::Relaxed) {
+            0 => LevelFilter::OFF,
+            1 => LevelFilter::ERROR,
+            2 => LevelFilter::WARN,
+            3 => LevelFilter::INFO,
+            4 => LevelFilter::DEBUG,
+            _ => LevelFilter::TRACE,
+        })
+    }
+
+    // Spans are not shown; events carry what they need in their fields.
+    fn new_span(&self, _span: &Attributes<'_>) -> Id {
+        Id::from_u64(1)
+    }
+
+    fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut line = Line::default();
+        event.record(&mut line);
+        emit(format!(
+            "iani {:>5} {}{}",
+            event.metadata().level(),
+            line.message,
+            line.fields
+        ));
+    }
+
+    fn enter(&self, _span: &Id) {}
+
+    fn exit(&self, _span: &Id) {}
+}
+
+#[derive(Default)]
+struct Line {
+    message: String,
+    fields: String,
+}
+
+impl Visit for Line {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record_debug(field, &format_args!("{value}"));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{value:?}");
+        } else {
+            let _ = write!(self.fields, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+/// Prints `line` on the R thread, after anything other threads logged
+/// before it, and holds it for the R thread otherwise.
+fn emit(line: String) {
+    let on_r_thread = R_THREAD
+        .lock()
+        .map_or(false, |r_thread| *r_thread == Some(thread::current().id()));
+    if on_r_thread {
+        flush();
+        print(&line);
+    } else if let Ok(mut pending) = PENDING.lock() {
+        if pending.lines.len() < MAX_PENDING {
+            pending.lines.push(line);
+        } else {
+            pending.dropped += 1;
+        }
+    }
+}
+
+/// Where lines are printed, standard error unless the host installs its
+/// own console with [`set_printer`].
+static PRINTER: RwLock<fn(&str)> = RwLock::new(default_print);
+
+/// Prints lines with `printer` from now on; the R package writes them to
+/// R's console (standard error, like `message()`).
+pub fn set_printer(printer: fn(&str)) {
+    if let Ok(mut current) = PRINTER.write() {
+        *current = printer;
+    }
+}
+
+fn print(line: &str) {
+    let printer = PRINTER
+        .read()
+        .map_or(default_print as fn(&str), |printer| *printer);
+    printer(line);
+}
+
+#[cfg(not(test))]
+fn default_print(line: &str) {
+    eprintln!("{line}");
+}
+
+/// Tests run without R, so lines are kept for them to inspect.
+#[cfg(test)]
+pub static PRINTED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+#[cfg(test)]
+fn default_print(line: &str) {
+    if let Ok(mut printed) = PRINTED.lock() {
+        printed.push(line.to_string());
+    }
+}
@@ -0,0 +1,276 @@
+use crate::backend::{Associations, SumstatsBackend};
+use crate::ids::chromosome_number;
+use crate::ordered::OrderedMap;
+use crate::sumstats::{SumstatsReader, SumstatsRecord};
+use crate::tabix::{TabixFile, MAX_POSITION};
+use crate::{to_json, Association, GwasFilter, HalResponse};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MirrorStudy {
+    pub study_accession: String,
+    pub path: String,
+}
+
+/// Backend answering queries from a directory of downloaded harmonised
+/// files (bgzipped with `.tbi` indexes), without network access. Region
+/// queries use the index; variant and study queries scan files.
+#[derive(Debug, Clone)]
+pub struct LocalMirror {
+    studies: BTreeMap<String, String>,
+}
+
+impl LocalMirror {
+    pub fn open(dir: &str) -> Result<Self> {
+        let mut studies = BTreeMap::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let name = match path.file_name().and_then(|n| n.to_str()) {
+                Some(name) => name,
+                None => continue,
+            };
+            if !(name.ends_with(".tsv.gz") && path.with_extension("gz.tbi").exists()) {
+                continue;
+            }
+            if let Some(accession) = study_accession(name) {
+                studies.insert(accession, path.to_string_lossy().into_owned());
+            }
+        }
+
+        if studies.is_empty() {
+            return Err(anyhow::anyhow!(
+                "No tabix-indexed harmonised files (GCST*.tsv.gz with .tbi) in {}",
+                dir
+            ));
+        }
+        Ok(Self { studies })
+    }
+
+    fn selected(&self, filter: &GwasFilter) -> Result<Vec<(&String, &String)>> {
+        match &filter.study {
+            Some(study) => self
+                .studies
+                .get_key_value(study)
+                .map(|entry| vec![entry])
+                .ok_or_else(|| anyhow::anyhow!("Study {} is not in the local mirror", study)),
+            None => Ok(self.studies.iter().collect()),
+        }
+    }
+}
+
+/// Study accession from a file name such as `GCST90002357.h.tsv.gz`.
+pub fn study_accession(file_name: &str) -> Option<String> {
+    let start = file_name.find("GCST")?;
+    let accession: String = file_name[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric())
+        .collect();
+    (accession.len() > 4).then_some(accession)
+}
+
+fn to_association(record: SumstatsRecord, study: &str) -> Association {
+    Association {
+        variant_id: record.variant_id,
+        chromosome: record
+            .chromosome
+            .as_deref()
+            .and_then(chromosome_number)
+            .map(i32::from),
+        base_pair_location: record.position,
+        study_accession: Some(study.to_string()),
+        trait_ids: None,
+        p_value: record.p_value,
+        code: None,
+        effect_allele: record.effect_allele,
+        other_allele: record.other_allele,
+        effect_allele_frequency: record.effect_allele_frequency,
+        odds_ratio: record.odds_ratio,
+        ci_lower: None,
+        ci_upper: None,
+        beta: record.beta,
+        se: record.standard_error,
+        links: None,
+    }
+}
+
+/// Applies the p-value and position filters, then pages with start/size,
+/// stopping as soon as the page is full.
+pub struct Page {
+    p_range: Option<(f64, f64)>,
+    bp_range: Option<(i64, i64)>,
+    skip: usize,
+    size: usize,
+    associations: OrderedMap<Association>,
+    matched: usize,
+}
+
+impl Page {
+    pub fn new(filter: &GwasFilter) -> Result<Self> {
+        let p_range = match &filter.p_value_range {
+            Some((lower, upper)) => Some((lower.parse()?, upper.parse()?)),
+            None => None,
+        };
+        Ok(Self {
+            p_range,
+            bp_range: filter.bp_location_range,
+            skip: filter.start.unwrap_or(0).max(0) as usize,
+            size: filter.size.unwrap_or(20).max(0) as usize,
+            associations: OrderedMap::new(),
+            matched: 0,
+        })
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.associations.len() >= self.size
+    }
+
+    fn offer(&mut self, record: SumstatsRecord, study: &str) {
+        if let Some((lower, upper)) = self.p_range {
+            if record.p_value.map_or(true, |p| p < lower || p > upper) {
+                return;
+            }
+        }
+        if let Some((lower, upper)) = self.bp_range {
+            if record.position.map_or(true, |p| p < lower || p > upper) {
+                return;
+            }
+        }
+        self.matched += 1;
+        if self.matched > self.skip && !self.is_full() {
+            self.associations.insert(
+                (self.matched - 1).to_string(),
+                to_association(record, study),
+            );
+        }
+    }
+
+    pub fn scan(
+        &mut self,
+        records: impl Iterator<Item = Result<SumstatsRecord>>,
+        study: &str,
+        keep: impl Fn(&SumstatsRecord) -> bool,
+    ) -> Result<()> {
+        for record in records {
+            if self.is_full() {
+                break;
+            }
+            let record = record?;
+            if keep(&record) {
+                self.offer(record, study);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Associations {
+        HalResponse {
+            embedded: Some(OrderedMap::from([(
+                "associations".to_string(),
+                self.associations,
+            )])),
+            links: None,
+            interrupted: None,
+            found: None,
+        }
+    }
+}
+
+impl SumstatsBackend for LocalMirror {
+    fn associations(
+        &self,
+        entity_type: Option<&str>,
+        entity_id: Option<&str>,
+        filter: &GwasFilter,
+    ) -> Result<Associations> {
+        if filter.trait_id.is_some() {
+            return Err(anyhow::anyhow!(
+                "The local mirror has no trait metadata; filter by study instead"
+            ));
+        }
+        let mut page = Page::new(filter)?;
+
+        match (entity_type, entity_id) {
+            (Some("chromosome"), Some(chromosome)) => {
+                let (start, end) = filter.bp_location_range.unwrap_or((1, MAX_POSITION));
+                for (study, path) in self.selected(filter)? {
+                    if page.is_full() {
+                        break;
+                    }
+                    let file = TabixFile::open(path)?;
+                    page.scan(file.query(chromosome, start, end)?, study, |_| true)?;
+                }
+            }
+            (Some("variant"), Some(variant_id)) => {
+                for (study, path) in self.selected(filter)? {
+                    if page.is_full() {
+                        break;
+                    }
+                    page.scan(SumstatsReader::open(path)?, study, |r| {
+                        r.variant_id.as_deref() == Some(variant_id)
+                    })?;
+                }
+            }
+            (Some("study"), Some(study_id)) => {
+                let path = self.studies.get(study_id).ok_or_else(|| {
+                    anyhow::anyhow!("Study {} is not in the local mirror", study_id)
+                })?;
+                page.scan(SumstatsReader::open(path)?, study_id, |_| true)?;
+            }
+            (None, None) => {
+                for (study, path) in self.selected(filter)? {
+                    if page.is_full() {
+                        break;
+                    }
+                    page.scan(SumstatsReader::open(path)?, study, |_| true)?;
+                }
+            }
+            (Some("trait"), _) => {
+                return Err(anyhow::anyhow!(
+                    "The local mirror does not support trait association queries"
+                ))
+            }
+            _ => return Err(anyhow::anyhow!("Invalid entity type or missing ID")),
+        }
+
+        Ok(page.finish())
+    }
+
+    fn entity(&self, entity_type: &str, id: Option<&str>, _filter: &GwasFilter) -> Result<String> {
+        match (entity_type, id) {
+            ("studies", None) => {
+                let studies: Vec<MirrorStudy> = self
+                    .studies
+                    .iter()
+                    .map(|(study, path)| MirrorStudy {
+                        study_accession: study.clone(),
+                        path: path.clone(),
+                    })
+                    .collect();
+                Ok(to_json(&studies)?)
+            }
+            ("studies", Some(study)) => {
+                let path = self
+                    .studies
+                    .get(study)
+                    .ok_or_else(|| anyhow::anyhow!("Study {} is not in the local mirror", study))?;
+                Ok(to_json(&MirrorStudy {
+                    study_accession: study.to_string(),
+                    path: path.clone(),
+                })?)
+            }
+            ("chromosomes", _) => {
+                let mut chromosomes = BTreeSet::new();
+                for path in self.studies.values() {
+                    chromosomes.extend(TabixFile::open(path)?.index.names);
+                }
+                Ok(to_json(&chromosomes)?)
+            }
+            _ => Err(anyhow::anyhow!(
+                "The local mirror supports the 'studies' and 'chromosomes' entity types"
+            )),
+        }
+    }
+}
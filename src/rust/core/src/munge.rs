@@ -0,0 +1,207 @@
+use crate::atomic::AtomicFile;
+use crate::summary::{Summarizer, SummaryCache};
+use crate::sumstats::{is_strand_ambiguous, SumstatsReader};
+use anyhow::Result;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io::{BufWriter, Write};
+
+#[derive(Debug, Clone)]
+pub struct MungeSettings {
+    pub n: Option<f64>,
+    pub info_min: f64,
+    pub maf_min: f64,
+    /// Directory where the input's column statistics are cached.
+    pub cache_dir: Option<String>,
+}
+
+impl Default for MungeSettings {
+    fn default() -> Self {
+        Self {
+            n: None,
+            info_min: 0.9,
+            maf_min: 0.01,
+            cache_dir: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct MungeReport {
+    pub output: String,
+    pub n_read: usize,
+    pub n_written: usize,
+    pub n_missing: usize,
+    pub n_p_out_of_bounds: usize,
+    pub n_low_info: usize,
+    pub n_low_maf: usize,
+    pub n_not_snp: usize,
+    pub n_strand_ambiguous: usize,
+    pub n_duplicate: usize,
+    /// Genomic inflation factor of the input, as in `gwas_summary()`.
+    pub lambda_gc: Option<f64>,
+}
+
+/// Writes an LDSC-compatible `<out>.sumstats.gz` (SNP, A1, A2, Z, N) from a
+/// summary statistics file, following the filters applied by `munge_sumstats.py`.
+/// The input is summarized in the same pass unless its summary is cached.
+pub fn munge(path: &str, out: &str, settings: &MungeSettings) -> Result<MungeReport> {
+    let output = format!("{out}.sumstats.gz");
+    let mut writer = GzEncoder::new(
+        BufWriter::new(AtomicFile::create(&output)?),
+        Compression::default(),
+    );
+    writeln!(writer, "SNP\tA1\tA2\tZ\tN")?;
+
+    let mut report = MungeReport {
+        output: output.clone(),
+        ..Default::default()
+    };
+    let mut seen = HashSet::new();
+    let cache = match &settings.cache_dir {
+        Some(dir) => Some(SummaryCache::open(dir, path)?),
+        None => None,
+    };
+    let cached = cache.as_ref().and_then(SummaryCache::get);
+    let mut summarizer = Summarizer::new();
+
+    for record in SumstatsReader::open(path)? {
+        let record = record?;
+        report.n_read += 1;
+        if cached.is_none() {
+            summarizer.add(&record);
+        }
+
+        let (snp, a1, a2) = match (
+            &record.variant_id,
+            &record.effect_allele,
+            &record.other_allele,
+        ) {
+            (Some(snp), Some(a1), Some(a2)) => (snp, a1, a2),
+            _ => {
+                report.n_missing += 1;
+                continue;
+            }
+        };
+        let n = match record.n.or(settings.n) {
+            Some(n) => n,
+            None => {
+                report.n_missing += 1;
+                continue;
+            }
+        };
+        let sign = match (record.beta, record.odds_ratio) {
+            (Some(beta), _) => beta.signum(),
+            (None, Some(or)) if or > 0.0 => (or - 1.0).signum(),
+            _ => {
+                report.n_missing += 1;
+                continue;
+            }
+        };
+
+        let z = match (record.p_value, record.beta, record.standard_error) {
+            (Some(p), _, _) if p <= 0.0 || p > 1.0 => {
+                report.n_p_out_of_bounds += 1;
+                continue;
+            }
+            (Some(p), _, _) => sign * -normal_quantile(p / 2.0),
+            (None, Some(beta), Some(se)) if se > 0.0 => beta / se,
+            _ => {
+                report.n_missing += 1;
+                continue;
+            }
+        };
+
+        if record.info.map_or(false, |info| info < settings.info_min) {
+            report.n_low_info += 1;
+            continue;
+        }
+        if record
+            .effect_allele_frequency
+            .map_or(false, |eaf| eaf.min(1.0 - eaf) < settings.maf_min)
+        {
+            report.n_low_maf += 1;
+            continue;
+        }
+        if !is_snp_allele(a1) || !is_snp_allele(a2) {
+            report.n_not_snp += 1;
+            continue;
+        }
+        if is_strand_ambiguous(a1, a2) {
+            report.n_strand_ambiguous += 1;
+            continue;
+        }
+        if !seen.insert(snp.clone()) {
+            report.n_duplicate += 1;
+            continue;
+        }
+
+        writeln!(writer, "{snp}\t{a1}\t{a2}\t{z:.3}\t{n}")?;
+        report.n_written += 1;
+    }
+
+    AtomicFile::commit_buffered(writer.finish()?)?;
+    let summary = match cached {
+        Some(summary) => summary,
+        None => {
+            let summary = summarizer.finish(path);
+            if let Some(cache) = &cache {
+                cache.put(&summary)?;
+            }
+            summary
+        }
+    };
+    report.lambda_gc = summary.lambda_gc;
+    Ok(report)
+}
+
+fn is_snp_allele(allele: &str) -> bool {
+    matches!(allele, "A" | "C" | "G" | "T")
+}
+
+/// Inverse of the standard normal CDF (Acklam's rational approximation),
+/// accurate to about 1e-9 over (0, 0.5].
+pub fn normal_quantile(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969683028665376e1,
+        2.209460984245205e2,
+        -2.759285104469687e2,
+        1.38357751867269e2,
+        -3.066479806614716e1,
+        2.506628277459239,
+    ];
+    const B: [f64; 5] = [
+        -5.447609879822406e1,
+        1.615858368580409e2,
+        -1.556989798598866e2,
+        6.680131188771972e1,
+        -1.328068155288572e1,
+    ];
+    const C: [f64; 6] = [
+        -7.784894002430293e-3,
+        -3.223964580411365e-1,
+        -2.400758277161838,
+        -2.549732539343734,
+        4.374664141464968,
+        2.938163982698783,
+    ];
+    const D: [f64; 4] = [
+        7.784695709041462e-3,
+        3.224671290700398e-1,
+        2.445134137142996,
+        3.754408661907416,
+    ];
+
+    if p < 0.02425 {
+        let q = (-2.0 * p.ln()).sqrt();
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    } else {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    }
+}
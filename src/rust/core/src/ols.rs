@@ -0,0 +1,114 @@
+//! Client for the EBI Ontology Lookup Service (OLS), which names the EFO
+//! terms that the summary statistics API identifies traits by.
+
+use crate::http::is_not_found;
+use crate::{GwasClient, HalResponse};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The ontology trait IDs are looked up in. EFO imports the MONDO, HP and
+/// Orphanet terms the GWAS Catalog also uses.
+const ONTOLOGY: &str = "efo";
+
+/// Terms asked for per page of descendants, the most OLS returns.
+const DESCENDANTS_PAGE_SIZE: usize = 500;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OlsTerm {
+    pub iri: String,
+    /// The ID as trait IDs are written, e.g. "EFO_0001360".
+    pub short_form: String,
+    pub label: Option<String>,
+}
+
+/// Page counts of an OLS listing.
+#[derive(Debug, Deserialize)]
+struct OlsPage {
+    #[serde(rename = "totalPages")]
+    total_pages: usize,
+}
+
+/// One page of an OLS term listing.
+#[derive(Debug, Deserialize)]
+struct OlsTerms {
+    #[serde(rename = "_embedded", default)]
+    embedded: Option<BTreeMap<String, Vec<OlsTerm>>>,
+    page: Option<OlsPage>,
+}
+
+/// Client for the OLS REST API. Requests go through the same URL building
+/// and response checks as `GwasClient`.
+#[derive(Debug, Clone)]
+pub struct OlsClient {
+    api: GwasClient,
+}
+
+impl OlsClient {
+    pub fn new() -> Result<Self> {
+        Self::with_base_url("https://www.ebi.ac.uk/ols4/api".to_string())
+    }
+
+    pub fn with_base_url(base_url: String) -> Result<Self> {
+        Ok(Self {
+            api: GwasClient::with_base_url(base_url)?,
+        })
+    }
+
+    /// The term with `short_form` (e.g. "EFO_0001360"), or `None` when the
+    /// ontology has none.
+    pub fn term(&self, short_form: &str) -> Result<Option<OlsTerm>> {
+        let params = BTreeMap::from([("short_form".to_string(), short_form.to_string())]);
+        let url = self
+            .api
+            .build_url(&format!("/ontologies/{ONTOLOGY}/terms"), &params)?;
+        let response = match self
+            .api
+            .api_get(url)
+            .and_then(|response| self.api.check_json_response(response))
+        {
+            Ok(response) => response,
+            Err(e) if is_not_found(&e) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let page: HalResponse<Vec<OlsTerm>> = self.api.read_json(response)?;
+        Ok(page
+            .embedded
+            .into_iter()
+            .flat_map(|embedded| embedded.into_values())
+            .flatten()
+            .find(|term| term.short_form == short_form))
+    }
+
+    /// Every term below `term` in the ontology, children and their
+    /// children in turn, as OLS lists them.
+    pub fn descendants(&self, term: &OlsTerm) -> Result<Vec<OlsTerm>> {
+        // OLS takes the IRI as a path segment encoded twice over.
+        let iri: String = url::form_urlencoded::byte_serialize(term.iri.as_bytes()).collect();
+        let iri: String = url::form_urlencoded::byte_serialize(iri.as_bytes()).collect();
+        let endpoint = format!("/ontologies/{ONTOLOGY}/terms/{iri}/descendants");
+        let mut terms = Vec::new();
+        let mut page = 0;
+        loop {
+            let params = BTreeMap::from([
+                ("page".to_string(), page.to_string()),
+                ("size".to_string(), DESCENDANTS_PAGE_SIZE.to_string()),
+            ]);
+            let url = self.api.build_url(&endpoint, &params)?;
+            let response = self.api.api_get(url)?;
+            let response = self.api.check_json_response(response)?;
+            let listing: OlsTerms = self.api.read_json(response)?;
+            terms.extend(
+                listing
+                    .embedded
+                    .into_iter()
+                    .flat_map(|embedded| embedded.into_values())
+                    .flatten(),
+            );
+            page += 1;
+            if listing.page.map_or(true, |pages| page >= pages.total_pages) {
+                return Ok(terms);
+            }
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use crate::backend::{Associations, SumstatsBackend};
+use crate::http::{self, error_text, read_json, session_client, session_max_response_bytes};
+use crate::ids::{chromosome_number, normalize_chromosome};
+use crate::interrupt::Interrupt;
+use crate::ordered::OrderedMap;
+use crate::{to_json, Association, GwasFilter, HalResponse};
+use anyhow::Result;
+use reqwest::blocking::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+
+/// One association as returned by the OpenGWAS `associations`, `tophits`
+/// and `phewas` endpoints.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OpenGwasAssociation {
+    pub id: Option<String>,
+    pub rsid: Option<String>,
+    pub chr: Option<String>,
+    pub position: Option<i64>,
+    pub ea: Option<String>,
+    pub nea: Option<String>,
+    pub eaf: Option<f64>,
+    pub beta: Option<f64>,
+    pub se: Option<f64>,
+    pub p: Option<f64>,
+}
+
+impl From<OpenGwasAssociation> for Association {
+    fn from(a: OpenGwasAssociation) -> Self {
+        Association {
+            variant_id: a.rsid,
+            chromosome: a.chr.as_deref().and_then(chromosome_number).map(i32::from),
+            base_pair_location: a.position,
+            study_accession: a.id,
+            trait_ids: None,
+            p_value: a.p,
+            code: None,
+            effect_allele: a.ea,
+            other_allele: a.nea,
+            effect_allele_frequency: a.eaf,
+            odds_ratio: None,
+            ci_lower: None,
+            ci_upper: None,
+            beta: a.beta,
+            se: a.se,
+            links: None,
+        }
+    }
+}
+
+/// The last query sent and its results.
+type LastQuery = Option<(String, Vec<OpenGwasAssociation>)>;
+
+/// Backend for the IEU OpenGWAS API. OpenGWAS has no endpoint that pages
+/// through every association of a study, so study queries return its top
+/// hits and region queries require a study. Each query returns all of its
+/// results at once; pages are cut from the last query's results rather
+/// than asked for again.
+#[derive(Debug, Clone)]
+pub struct OpenGwasClient {
+    client: Client,
+    base_url: String,
+    token: Option<String>,
+    interrupt: Interrupt,
+    last_query: Arc<Mutex<LastQuery>>,
+}
+
+impl OpenGwasClient {
+    pub fn new(token: Option<String>) -> Self {
+        Self::with_base_url(token, "https://gwas-api.mrcieu.ac.uk".to_string())
+    }
+
+    pub fn with_base_url(token: Option<String>, base_url: String) -> Self {
+        Self {
+            client: session_client(),
+            base_url,
+            token,
+            interrupt: Interrupt::new(),
+            last_query: Arc::default(),
+        }
+    }
+
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    fn send<T: serde::de::DeserializeOwned>(&self, request: RequestBuilder) -> Result<T> {
+        let response = http::send(self.authorize(request))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = error_text(response);
+            return Err(anyhow::anyhow!("OpenGWAS HTTP {}: {}", status, text));
+        }
+        read_json(response, session_max_response_bytes())
+    }
+
+    fn post(&self, endpoint: &str, body: serde_json::Value) -> Result<Vec<OpenGwasAssociation>> {
+        let query = format!("{endpoint} {body}");
+        // Held through the request, so that pages of one query requested
+        // together wait for its results instead of sending it again.
+        let mut last_query = self
+            .last_query
+            .lock()
+            .map_err(|_| anyhow::anyhow!("OpenGWAS query cache poisoned"))?;
+        if let Some((last, results)) = last_query.as_ref() {
+            if *last == query {
+                return Ok(results.clone());
+            }
+        }
+        let results: Vec<OpenGwasAssociation> = self.send(
+            self.client
+                .post(format!("{}/{endpoint}", self.base_url))
+                .json(&body),
+        )?;
+        *last_query = Some((query, results.clone()));
+        Ok(results)
+    }
+
+    /// Associations of variants (rsIDs or `chr:start-end` ranges) in studies.
+    pub fn associations(
+        &self,
+        variants: &[String],
+        studies: &[String],
+    ) -> Result<Vec<OpenGwasAssociation>> {
+        self.post(
+            "associations",
+            serde_json::json!({ "variant": variants, "id": studies, "proxies": 0 }),
+        )
+    }
+
+    /// Associations of a study at or below `p_value`, without clumping.
+    pub fn tophits(&self, studies: &[String], p_value: f64) -> Result<Vec<OpenGwasAssociation>> {
+        self.post(
+            "tophits",
+            serde_json::json!({ "id": studies, "pval": p_value, "clump": 0 }),
+        )
+    }
+
+    /// Associations of variants across every study, at or below `p_value`.
+    pub fn phewas(&self, variants: &[String], p_value: f64) -> Result<Vec<OpenGwasAssociation>> {
+        self.post(
+            "phewas",
+            serde_json::json!({ "variant": variants, "pval": p_value }),
+        )
+    }
+
+    pub fn gwasinfo(&self, id: Option<&str>) -> Result<serde_json::Value> {
+        let url = match id {
+            Some(id) => format!("{}/gwasinfo/{id}", self.base_url),
+            None => format!("{}/gwasinfo", self.base_url),
+        };
+        self.send(self.client.get(url))
+    }
+}
+
+fn p_bounds(filter: &GwasFilter) -> Result<(f64, f64)> {
+    match &filter.p_value_range {
+        Some((lower, upper)) => Ok((lower.parse()?, upper.parse()?)),
+        None => Ok((0.0, 1.0)),
+    }
+}
+
+impl SumstatsBackend for OpenGwasClient {
+    fn associations(
+        &self,
+        entity_type: Option<&str>,
+        entity_id: Option<&str>,
+        filter: &GwasFilter,
+    ) -> Result<Associations> {
+        let (p_lower, p_upper) = p_bounds(filter)?;
+        let studies: Vec<String> = filter
+            .study
+            .iter()
+            .flat_map(|list| list.split(','))
+            .map(str::trim)
+            .filter(|study| !study.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        let results = match (entity_type, entity_id) {
+            (Some("variant"), Some(variant_id)) if !studies.is_empty() => {
+                self.associations(&[variant_id.to_string()], &studies)?
+            }
+            (Some("variant"), Some(variant_id)) => {
+                self.phewas(&[variant_id.to_string()], p_upper)?
+            }
+            (Some("study"), Some(study_id)) => {
+                // Without a p-value filter, fall back to genome-wide significance.
+                let threshold = filter.p_value_range.as_ref().map_or(5e-8, |_| p_upper);
+                self.tophits(&[study_id.to_string()], threshold)?
+            }
+            (Some("chromosome"), Some(chromosome)) => {
+                if studies.is_empty() {
+                    return Err(anyhow::anyhow!(
+                        "OpenGWAS region queries need a study filter"
+                    ));
+                }
+                let (start, end) = filter.bp_location_range.ok_or_else(|| {
+                    anyhow::anyhow!("OpenGWAS chromosome queries need a bp_min/bp_max range")
+                })?;
+                let chromosome = normalize_chromosome(chromosome)?;
+                self.associations(&[format!("{chromosome}:{start}-{end}")], &studies)?
+            }
+            (Some("trait"), _) => {
+                return Err(anyhow::anyhow!(
+                    "OpenGWAS does not support trait association queries"
+                ))
+            }
+            _ => return Err(anyhow::anyhow!("Invalid entity type or missing ID")),
+        };
+
+        let start = filter.start.unwrap_or(0).max(0) as usize;
+        let size = filter.size.unwrap_or(20).max(0) as usize;
+        let associations: OrderedMap<Association> = results
+            .into_iter()
+            .filter(|a| a.p.map_or(true, |p| p >= p_lower && p <= p_upper))
+            .skip(start)
+            .take(size)
+            .enumerate()
+            .map(|(i, a)| ((start + i).to_string(), Association::from(a)))
+            .collect();
+
+        Ok(HalResponse {
+            embedded: Some(OrderedMap::from([(
+                "associations".to_string(),
+                associations,
+            )])),
+            links: None,
+            interrupted: None,
+            found: None,
+        })
+    }
+
+    fn entity(&self, entity_type: &str, id: Option<&str>, _filter: &GwasFilter) -> Result<String> {
+        match entity_type {
+            "studies" => Ok(to_json(&self.gwasinfo(id)?)?),
+            _ => Err(anyhow::anyhow!(
+                "OpenGWAS only supports the 'studies' entity type"
+            )),
+        }
+    }
+
+    fn interrupt(&self) -> Option<&Interrupt> {
+        Some(&self.interrupt)
+    }
+}
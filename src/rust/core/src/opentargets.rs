@@ -0,0 +1,305 @@
+use crate::http::{self, error_text, read_json, session_client, session_max_response_bytes};
+use anyhow::Result;
+use reqwest::blocking::Client;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+const STUDY_LOCI_QUERY: &str = r#"
+query StudyLoci($variantId: String!) {
+  indexVariantsAndStudiesForTagVariant(variantId: $variantId) {
+    associations {
+      indexVariant { id rsId }
+      study { studyId traitReported }
+      pval
+    }
+  }
+}"#;
+
+const STUDY_LOCUS_QUERY: &str = r#"
+query StudyLocus($studyId: String!, $variantId: String!) {
+  studyLocus2GeneTable(studyId: $studyId, variantId: $variantId) {
+    rows { gene { id symbol } yProbaModel }
+  }
+  qtlColocalisation(studyId: $studyId, variantId: $variantId) {
+    qtlStudyName
+    phenotypeId
+    gene { id symbol }
+    tissue { name }
+    h4
+    log2h4h3
+  }
+}"#;
+
+const SEARCH_QUERY: &str = r#"
+query Search($queryString: String!) {
+  search(queryString: $queryString) {
+    variants { id rsId }
+  }
+}"#;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct L2GScore {
+    pub gene_id: String,
+    pub gene_symbol: Option<String>,
+    pub score: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QtlColocalisation {
+    pub qtl_study: String,
+    pub phenotype_id: String,
+    pub gene_id: Option<String>,
+    pub gene_symbol: Option<String>,
+    pub tissue: Option<String>,
+    pub h4: f64,
+    pub log2_h4_h3: f64,
+}
+
+/// Locus-to-gene scores and colocalised QTLs for one study locus (a study
+/// and its lead variant).
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StudyLocusEvidence {
+    pub study_id: String,
+    pub trait_reported: Option<String>,
+    pub lead_variant: String,
+    pub lead_rsid: Option<String>,
+    pub p_value: Option<f64>,
+    pub l2g: Vec<L2GScore>,
+    pub colocalisations: Vec<QtlColocalisation>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GraphQlResponse<T> {
+    data: Option<T>,
+    errors: Option<Vec<GraphQlError>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GraphQlError {
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StudyLociData {
+    index_variants_and_studies_for_tag_variant: TagVariantAssociations,
+}
+
+#[derive(Debug, Deserialize)]
+struct TagVariantAssociations {
+    associations: Vec<TagVariantAssociation>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TagVariantAssociation {
+    index_variant: VariantRef,
+    study: StudyRef,
+    pval: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VariantRef {
+    id: String,
+    rs_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StudyRef {
+    study_id: String,
+    trait_reported: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StudyLocusData {
+    study_locus2_gene_table: L2GTable,
+    qtl_colocalisation: Vec<ColocRow>,
+}
+
+#[derive(Debug, Deserialize)]
+struct L2GTable {
+    rows: Vec<L2GRow>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct L2GRow {
+    gene: GeneRef,
+    y_proba_model: f64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ColocRow {
+    qtl_study_name: String,
+    phenotype_id: String,
+    gene: Option<GeneRef>,
+    tissue: Option<TissueRef>,
+    h4: f64,
+    log2h4h3: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeneRef {
+    id: String,
+    symbol: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TissueRef {
+    name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchData {
+    search: SearchResult,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchResult {
+    variants: Vec<VariantRef>,
+}
+
+#[derive(Debug, Clone)]
+pub struct OpenTargetsClient {
+    client: Client,
+    base_url: String,
+}
+
+impl Default for OpenTargetsClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OpenTargetsClient {
+    pub fn new() -> Self {
+        Self::with_base_url("https://api.genetics.opentargets.org/graphql".to_string())
+    }
+
+    pub fn with_base_url(base_url: String) -> Self {
+        Self {
+            client: session_client(),
+            base_url,
+        }
+    }
+
+    fn query<T: DeserializeOwned>(&self, query: &str, variables: serde_json::Value) -> Result<T> {
+        let response = http::send(
+            self.client
+                .post(&self.base_url)
+                .json(&serde_json::json!({ "query": query, "variables": variables })),
+        )?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = error_text(response);
+            return Err(anyhow::anyhow!("Open Targets HTTP {}: {}", status, text));
+        }
+
+        let body: GraphQlResponse<T> = read_json(response, session_max_response_bytes())?;
+        if let Some(errors) = body.errors.filter(|errors| !errors.is_empty()) {
+            let messages: Vec<String> = errors.into_iter().map(|e| e.message).collect();
+            return Err(anyhow::anyhow!("Open Targets: {}", messages.join("; ")));
+        }
+        body.data
+            .ok_or_else(|| anyhow::anyhow!("Open Targets returned no data"))
+    }
+
+    /// Open Targets Genetics identifies variants as `chrom_pos_ref_alt`
+    /// (GRCh38); rsIDs are resolved through its search endpoint.
+    pub fn resolve_variant(&self, variant: &str) -> Result<String> {
+        if !variant.to_lowercase().starts_with("rs") {
+            return Ok(variant.to_string());
+        }
+        let data: SearchData =
+            self.query(SEARCH_QUERY, serde_json::json!({ "queryString": variant }))?;
+        data.search
+            .variants
+            .into_iter()
+            .find(|v| {
+                v.rs_id
+                    .as_deref()
+                    .map_or(false, |rs_id| rs_id.eq_ignore_ascii_case(variant))
+            })
+            .map(|v| v.id)
+            .ok_or_else(|| {
+                anyhow::anyhow!("Variant {} not found in Open Targets Genetics", variant)
+            })
+    }
+
+    pub fn study_locus(
+        &self,
+        study_id: &str,
+        lead_variant: &str,
+    ) -> Result<(Vec<L2GScore>, Vec<QtlColocalisation>)> {
+        let data: StudyLocusData = self.query(
+            STUDY_LOCUS_QUERY,
+            serde_json::json!({ "studyId": study_id, "variantId": lead_variant }),
+        )?;
+
+        let mut l2g: Vec<L2GScore> = data
+            .study_locus2_gene_table
+            .rows
+            .into_iter()
+            .map(|row| L2GScore {
+                gene_id: row.gene.id,
+                gene_symbol: row.gene.symbol,
+                score: row.y_proba_model,
+            })
+            .collect();
+        l2g.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+        let colocalisations = data
+            .qtl_colocalisation
+            .into_iter()
+            .map(|row| QtlColocalisation {
+                qtl_study: row.qtl_study_name,
+                phenotype_id: row.phenotype_id,
+                gene_id: row.gene.as_ref().map(|g| g.id.clone()),
+                gene_symbol: row.gene.and_then(|g| g.symbol),
+                tissue: row.tissue.and_then(|t| t.name),
+                h4: row.h4,
+                log2_h4_h3: row.log2h4h3,
+            })
+            .collect();
+
+        Ok((l2g, colocalisations))
+    }
+
+    /// Collects L2G scores and colocalised QTLs for every study locus the
+    /// variant tags, or only for `study_id` when given.
+    pub fn variant_evidence(
+        &self,
+        variant: &str,
+        study_id: Option<&str>,
+    ) -> Result<Vec<StudyLocusEvidence>> {
+        let variant_id = self.resolve_variant(variant)?;
+        let data: StudyLociData = self.query(
+            STUDY_LOCI_QUERY,
+            serde_json::json!({ "variantId": variant_id }),
+        )?;
+
+        let mut evidence = Vec::new();
+        for association in data.index_variants_and_studies_for_tag_variant.associations {
+            if study_id.map_or(false, |id| id != association.study.study_id) {
+                continue;
+            }
+            let (l2g, colocalisations) =
+                self.study_locus(&association.study.study_id, &association.index_variant.id)?;
+            evidence.push(StudyLocusEvidence {
+                study_id: association.study.study_id,
+                trait_reported: association.study.trait_reported,
+                lead_variant: association.index_variant.id,
+                lead_rsid: association.index_variant.rs_id,
+                p_value: association.pval,
+                l2g,
+                colocalisations,
+            });
+        }
+
+        Ok(evidence)
+    }
+}
@@ -0,0 +1,573 @@
+use crate::backend::{Associations, SumstatsBackend};
+use crate::http::is_not_found;
+use crate::ids::{normalize_chromosome, normalize_variant_id};
+use crate::interrupt::is_interrupted;
+use crate::ordered::OrderedMap;
+use crate::{Association, GwasClient, GwasFilter, HalResponse, MAX_URL_LENGTH};
+use anyhow::Result;
+use rayon::prelude::*;
+use std::collections::VecDeque;
+use url::Url;
+
+pub const DEFAULT_PAGE_SIZE: i32 = 20;
+
+/// Pages `PagePrefetcher` requests at once. Requests still pass the
+/// client's rate limit one by one.
+pub const PREFETCH_PAGES: usize = 4;
+
+/// Path of the association endpoint for an entity, as used by
+/// `fetch_unified_associations`, with a variant ID or chromosome in the
+/// API's form.
+pub fn associations_endpoint(entity_type: Option<&str>, entity_id: Option<&str>) -> Result<String> {
+    match (entity_type, entity_id) {
+        (None, None) => Ok("/associations".to_string()),
+        (Some("variant"), Some(id)) => Ok(format!("/associations/{}", normalize_variant_id(id)?)),
+        (Some("chromosome"), Some(id)) => Ok(format!(
+            "/chromosomes/{}/associations",
+            normalize_chromosome(id)?
+        )),
+        (Some("study"), Some(id)) => Ok(format!("/studies/{id}/associations")),
+        (Some("trait"), Some(id)) => Ok(format!("/traits/{id}/associations")),
+        _ => Err(anyhow::anyhow!("Invalid entity type or missing ID")),
+    }
+}
+
+/// The filters for `pages` consecutive pages, starting at the filter's offset.
+fn page_filters(filter: &GwasFilter, pages: usize) -> Vec<GwasFilter> {
+    let start = filter.start.unwrap_or(0).max(0);
+    let size = filter.size.unwrap_or(DEFAULT_PAGE_SIZE).max(1);
+    (0..pages as i32)
+        .map(|page| GwasFilter {
+            start: Some(start + page * size),
+            size: Some(size),
+            ..filter.clone()
+        })
+        .collect()
+}
+
+/// Splits the longer of the comma-separated `trait_id` and `study` lists
+/// into consecutive chunks, one filter per chunk, packing each chunk with
+/// as many values as `fits` accepts.
+fn split_filter(
+    filter: &GwasFilter,
+    fits: impl Fn(&GwasFilter) -> bool,
+) -> Result<Vec<GwasFilter>> {
+    let lists = [
+        ("trait", filter.trait_id.as_deref()),
+        ("study_accession", filter.study.as_deref()),
+    ];
+    let (name, values) = lists
+        .into_iter()
+        .filter_map(|(name, list)| Some((name, list?.split(',').collect::<Vec<&str>>())))
+        .filter(|(_, values)| values.len() > 1)
+        .max_by_key(|(_, values)| values.iter().map(|value| value.len()).sum::<usize>())
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "Request URL would exceed {} characters and has no trait or study list to split; shorten the query",
+                MAX_URL_LENGTH
+            )
+        })?;
+
+    let with = |chunk: &[&str]| {
+        let list = Some(chunk.join(","));
+        match name {
+            "trait" => GwasFilter {
+                trait_id: list,
+                ..filter.clone()
+            },
+            _ => GwasFilter {
+                study: list,
+                ..filter.clone()
+            },
+        }
+    };
+    let too_long = || {
+        anyhow::anyhow!(
+            "Request URL would exceed {} characters even with one {} per request",
+            MAX_URL_LENGTH,
+            name
+        )
+    };
+
+    let mut filters = Vec::new();
+    let mut chunk: Vec<&str> = Vec::new();
+    for value in values {
+        chunk.push(value);
+        if fits(&with(&chunk)) {
+            continue;
+        }
+        chunk.pop();
+        if chunk.is_empty() {
+            return Err(too_long());
+        }
+        filters.push(with(&chunk));
+        chunk = vec![value];
+        if !fits(&with(&chunk)) {
+            return Err(too_long());
+        }
+    }
+    filters.push(with(&chunk));
+    Ok(filters)
+}
+
+/// Deserializes one page of associations. With the `simd-json` feature the
+/// page is parsed with simd-json first, falling back to serde_json if that
+/// fails (for example on CPUs without the required instructions).
+pub fn parse_page(body: &[u8]) -> Result<Associations> {
+    #[cfg(feature = "simd-json")]
+    {
+        // simd-json parses in place, so it needs its own copy of the body.
+        let mut buffer = body.to_vec();
+        if let Ok(page) = simd_json::serde::from_slice(&mut buffer) {
+            return Ok(sort_rows(page));
+        }
+    }
+    Ok(sort_rows(serde_json::from_slice(body)?))
+}
+
+/// Position of an association row from its key, the API's `"0"`, `"1"`,
+/// ...; other keys sort last.
+fn row_position(key: &str) -> usize {
+    key.parse().unwrap_or(usize::MAX)
+}
+
+/// Puts the rows of a page in position order, whatever order the server
+/// wrote its keys in, so `"10"` follows `"9"` rather than `"1"`.
+fn sort_rows(mut page: Associations) -> Associations {
+    if let Some(embedded) = &mut page.embedded {
+        for rows in embedded.values_mut() {
+            rows.sort_by_key(|key, _| row_position(key));
+        }
+    }
+    page
+}
+
+/// Deserializes page bodies on the rayon pool, preserving page order.
+pub fn parse_pages(bodies: &[Vec<u8>]) -> Result<Vec<Associations>> {
+    bodies.par_iter().map(|body| parse_page(body)).collect()
+}
+
+/// Concatenates pages into one response. Keys are renumbered from `start`
+/// in page order, and within a page in the order of the API's own keys.
+/// The merged map is sized up front and one buffer is reused for sorting
+/// every page.
+pub fn merge_pages(pages: Vec<Associations>, start: i32) -> Associations {
+    let pages: Vec<OrderedMap<Association>> = pages
+        .into_iter()
+        .map(|page| {
+            page.embedded
+                .and_then(|mut embedded| embedded.remove("associations"))
+                .unwrap_or_default()
+        })
+        .collect();
+    let total = pages.iter().map(OrderedMap::len).sum();
+    let page_size = pages.iter().map(OrderedMap::len).max().unwrap_or(0);
+
+    let mut merged = OrderedMap::with_capacity(total);
+    let mut buffer: Vec<(usize, Association)> = Vec::with_capacity(page_size);
+    let mut next = start.max(0) as usize;
+    for page in pages {
+        buffer.extend(
+            page.into_iter()
+                .map(|(key, association)| (row_position(&key), association)),
+        );
+        buffer.sort_unstable_by_key(|(index, _)| *index);
+        for (_, association) in buffer.drain(..) {
+            merged.insert(next.to_string(), association);
+            next += 1;
+        }
+    }
+
+    HalResponse {
+        embedded: Some(OrderedMap::from([("associations".to_string(), merged)])),
+        links: None,
+        interrupted: None,
+        found: None,
+    }
+}
+
+/// Walks the pages of an association query from the filter's start,
+/// requesting a batch of pages concurrently whenever the pages fetched so
+/// far run out, and handing them out in page order. The walk ends after a
+/// short page, at a page past the end of the results (HTTP 404), after
+/// `limit` pages, or with the first error, which is handed out in its
+/// page's place; an interrupt comes out as an
+/// [`Interrupted`](crate::interrupt::Interrupted) error.
+///
+/// The API reports no totals, so the end is only known once a page comes
+/// back short. The first batch is one page and each batch of full pages
+/// doubles the next, up to `ahead`: a result of one or two pages is
+/// fetched with no more requests than a sequential walk makes, and longer
+/// walks request fewer pages past the end than their last batch.
+pub struct PagePrefetcher<'a> {
+    backend: &'a dyn SumstatsBackend,
+    entity_type: Option<&'a str>,
+    entity_id: Option<&'a str>,
+    filter: GwasFilter,
+    size: i32,
+    ahead: usize,
+    /// Pages the next batch requests.
+    batch: usize,
+    limit: Option<usize>,
+    next_page: usize,
+    fetched: VecDeque<Result<Associations>>,
+    done: bool,
+}
+
+impl<'a> PagePrefetcher<'a> {
+    pub fn new(
+        backend: &'a dyn SumstatsBackend,
+        entity_type: Option<&'a str>,
+        entity_id: Option<&'a str>,
+        filter: &GwasFilter,
+        ahead: usize,
+        limit: Option<usize>,
+    ) -> Self {
+        Self {
+            backend,
+            entity_type,
+            entity_id,
+            filter: filter.clone(),
+            size: filter.size.unwrap_or(DEFAULT_PAGE_SIZE).max(1),
+            ahead: ahead.max(1),
+            batch: 1,
+            limit,
+            next_page: 0,
+            fetched: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    /// Requests the next batch of pages, or as many as `limit` leaves, and
+    /// queues them up to where the walk ends.
+    fn fetch_ahead(&mut self) {
+        let count = match self.limit {
+            Some(limit) => self.batch.min(limit.saturating_sub(self.next_page)),
+            None => self.batch,
+        };
+        if count == 0 {
+            self.done = true;
+            return;
+        }
+        if let Some(interrupt) = self.backend.interrupt() {
+            if let Err(e) = interrupt.check() {
+                self.fetched.push_back(Err(e.into()));
+                self.done = true;
+                return;
+            }
+        }
+
+        let first = self.next_page;
+        let start = self.filter.start.unwrap_or(0).max(0);
+        let filters: Vec<GwasFilter> = (first..first + count)
+            .map(|page| GwasFilter {
+                start: Some(start + page as i32 * self.size),
+                size: Some(self.size),
+                ..self.filter.clone()
+            })
+            .collect();
+        // One thread per page, as the requests wait on the network rather
+        // than the CPU.
+        let (backend, entity_type, entity_id) = (self.backend, self.entity_type, self.entity_id);
+        let pages: Vec<Result<Associations>> = std::thread::scope(|scope| {
+            let requests: Vec<_> = filters
+                .iter()
+                .map(|page| scope.spawn(move || backend.associations(entity_type, entity_id, page)))
+                .collect();
+            requests
+                .into_iter()
+                .map(|request| {
+                    request
+                        .join()
+                        .unwrap_or_else(|_| Err(anyhow::anyhow!("Page request panicked")))
+                })
+                .collect()
+        });
+        self.next_page += count;
+
+        for (page, result) in (first..).zip(pages) {
+            match result {
+                Ok(response) => {
+                    let rows = response
+                        .embedded
+                        .as_ref()
+                        .and_then(|embedded| embedded.get("associations"))
+                        .map_or(0, OrderedMap::len);
+                    self.fetched.push_back(Ok(response));
+                    if rows < self.size as usize {
+                        self.done = true;
+                        return;
+                    }
+                }
+                Err(e) if page > 0 && is_not_found(&e) => {
+                    self.done = true;
+                    return;
+                }
+                Err(e) => {
+                    self.fetched.push_back(Err(e));
+                    self.done = true;
+                    return;
+                }
+            }
+        }
+        if self.limit.map_or(false, |limit| self.next_page >= limit) {
+            self.done = true;
+        }
+        self.batch = (self.batch * 2).min(self.ahead);
+    }
+}
+
+impl Iterator for PagePrefetcher<'_> {
+    type Item = Result<Associations>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.fetched.is_empty() && !self.done {
+            self.fetch_ahead();
+        }
+        self.fetched.pop_front()
+    }
+}
+
+impl GwasClient {
+    fn fetch_page_body(&self, endpoint: &str, filter: &GwasFilter) -> Result<Vec<u8>> {
+        let url = self.build_url(endpoint, &filter.to_params())?;
+        let response = self.api_get(url)?;
+        let response = self.check_json_response(response)?;
+        self.read_body(response)
+    }
+
+    /// Runs a query whose URL would be too long as several requests, each
+    /// with part of its trait or study list, and merges their rows in
+    /// request order. Every request is paged by the filter's start and size.
+    /// A request answered with 404, none of whose entities have records,
+    /// adds no rows, and the query fails only when every request does. An
+    /// interrupt keeps the rows of the requests already made.
+    pub fn fetch_split_associations(
+        &self,
+        entity_type: Option<&str>,
+        entity_id: Option<&str>,
+        filter: &GwasFilter,
+    ) -> Result<Associations> {
+        let endpoint = associations_endpoint(entity_type, entity_id)?;
+        let fits = |filter: &GwasFilter| self.url_fits(&endpoint, &filter.to_params());
+        let mut pages = Vec::new();
+        let mut not_found = None;
+        let mut interrupted = false;
+        for chunk in split_filter(filter, fits)? {
+            if (!pages.is_empty() || not_found.is_some()) && self.interrupt.is_requested() {
+                interrupted = true;
+                break;
+            }
+            match self.fetch_unified_associations(entity_type, entity_id, &chunk) {
+                Ok(page) => pages.push(page),
+                Err(e) if is_not_found(&e) => not_found = Some(e),
+                Err(e) => return Err(e),
+            }
+        }
+        if pages.is_empty() && !interrupted {
+            if let Some(e) = not_found {
+                return Err(e);
+            }
+        }
+        let mut merged = merge_pages(pages, filter.start.unwrap_or(0));
+        merged.interrupted = Some(true).filter(|_| interrupted);
+        Ok(merged)
+    }
+
+    /// The URLs `fetch_unified_associations`, or `fetch_association_pages`
+    /// when `pages` is over 1, would request, including any split of a
+    /// long trait or study list. Every page is listed, as only the server
+    /// knows where the results end.
+    pub fn association_request_urls(
+        &self,
+        entity_type: Option<&str>,
+        entity_id: Option<&str>,
+        filter: &GwasFilter,
+        pages: usize,
+    ) -> Result<Vec<Url>> {
+        filter.validate()?;
+        let endpoint = associations_endpoint(entity_type, entity_id)?;
+        let fits = |filter: &GwasFilter| self.url_fits(&endpoint, &filter.to_params());
+        let filters = if pages > 1 {
+            page_filters(filter, pages)
+        } else if fits(filter) {
+            vec![filter.clone()]
+        } else {
+            split_filter(filter, fits)?
+        };
+        filters
+            .iter()
+            .map(|filter| self.build_url(&endpoint, &filter.to_params()))
+            .collect()
+    }
+
+    /// Fetches `pages` consecutive pages concurrently, then deserializes
+    /// them in parallel and merges them in page order. A page past the end
+    /// of the results (HTTP 404) ends the sequence, as does an interrupt,
+    /// which keeps the pages before the first one not fetched.
+    pub fn fetch_association_pages(
+        &self,
+        entity_type: Option<&str>,
+        entity_id: Option<&str>,
+        filter: &GwasFilter,
+        pages: usize,
+    ) -> Result<Associations> {
+        filter.validate()?;
+        let endpoint = associations_endpoint(entity_type, entity_id)?;
+        let responses: Vec<Result<Vec<u8>>> = page_filters(filter, pages)
+            .par_iter()
+            .map(|page| {
+                self.interrupt.check()?;
+                let body = self.fetch_page_body(&endpoint, page)?;
+                self.interrupt.progress().add_pages(1, 0);
+                Ok(body)
+            })
+            .collect();
+
+        let mut bodies = Vec::with_capacity(responses.len());
+        let mut interrupted = false;
+        for (page, response) in responses.into_iter().enumerate() {
+            match response {
+                Ok(body) => bodies.push(body),
+                Err(e) if page > 0 && is_not_found(&e) => break,
+                Err(e) if page > 0 && is_interrupted(&e) => {
+                    interrupted = true;
+                    break;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        let mut merged = merge_pages(parse_pages(&bodies)?, filter.start.unwrap_or(0));
+        let rows = merged
+            .embedded
+            .as_ref()
+            .and_then(|embedded| embedded.get("associations"))
+            .map_or(0, OrderedMap::len);
+        self.interrupt.progress().add_pages(0, rows as u64);
+        merged.interrupted = Some(true).filter(|_| interrupted);
+        Ok(merged)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::time::Instant;
+
+    fn page_body(page: usize, size: usize) -> Vec<u8> {
+        let associations: HashMap<String, serde_json::Value> = (0..size)
+            .map(|i| {
+                let position = (page * size + i) as i64 * 137 + 10_000;
+                (
+                    i.to_string(),
+                    serde_json::json!({
+                        "variant_id": format!("rs{position}"),
+                        "chromosome": 19,
+                        "base_pair_location": position,
+                        "study_accession": "GCST90002357",
+                        "trait": ["EFO_0004611"],
+                        "p_value": 1e-8 * (i + 1) as f64,
+                        "code": 10,
+                        "effect_allele": "A",
+                        "other_allele": "G",
+                        "effect_allele_frequency": 0.21,
+                        "odds_ratio": null,
+                        "ci_lower": null,
+                        "ci_upper": null,
+                        "beta": 0.013,
+                        "se": 0.002,
+                        "_links": {"self": {"href": format!("https://example.org/associations/rs{position}")}}
+                    }),
+                )
+            })
+            .collect();
+        serde_json::to_vec(&serde_json::json!({ "_embedded": { "associations": associations } }))
+            .unwrap()
+    }
+
+    #[test]
+    fn pages_parse_in_row_order() {
+        // Keys are written in hash order, not position order.
+        let page = parse_page(&page_body(0, 25)).unwrap();
+        let keys: Vec<&String> = page.embedded.as_ref().unwrap()["associations"]
+            .iter()
+            .map(|(key, _)| key)
+            .collect();
+        let expected: Vec<String> = (0..25).map(|i| i.to_string()).collect();
+        assert_eq!(keys, expected.iter().collect::<Vec<_>>());
+    }
+
+    /// simd-json must decode captured pages exactly as serde_json does.
+    #[cfg(feature = "simd-json")]
+    #[test]
+    fn simd_json_matches_serde_json() {
+        let fixtures = [
+            include_str!("../tests/fixtures/chromosome_associations.json"),
+            include_str!("../tests/fixtures/study_associations.json"),
+        ];
+        for fixture in fixtures {
+            let expected: Associations = serde_json::from_str(fixture).unwrap();
+            let mut buffer = fixture.as_bytes().to_vec();
+            let actual: Associations = simd_json::serde::from_slice(&mut buffer).unwrap();
+            assert_eq!(
+                serde_json::to_value(&actual).unwrap(),
+                serde_json::to_value(&expected).unwrap()
+            );
+        }
+    }
+
+    /// Sequential vs rayon deserialization of 64 pages of 1000 associations.
+    /// Run with `cargo test --release bench_page_deserialization -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_page_deserialization() {
+        let bodies: Vec<Vec<u8>> = (0..64).map(|page| page_body(page, 1000)).collect();
+        let megabytes = bodies.iter().map(Vec::len).sum::<usize>() as f64 / 1e6;
+
+        let timer = Instant::now();
+        let sequential: Vec<Associations> = bodies
+            .iter()
+            .map(|body| serde_json::from_slice(body).unwrap())
+            .collect();
+        let sequential_time = timer.elapsed();
+
+        let timer = Instant::now();
+        let parallel = parse_pages(&bodies).unwrap();
+        let parallel_time = timer.elapsed();
+
+        let merged = merge_pages(parallel, 0);
+        let associations = &merged.embedded.unwrap()["associations"];
+        assert_eq!(sequential.len(), 64);
+        assert_eq!(associations.len(), 64_000);
+        assert_eq!(
+            associations["63999"].base_pair_location,
+            Some(63_999 * 137 + 10_000)
+        );
+        println!(
+            "{megabytes:.1} MB: sequential {sequential_time:?}, parallel {parallel_time:?} ({:.1}x on {} threads)",
+            sequential_time.as_secs_f64() / parallel_time.as_secs_f64(),
+            rayon::current_num_threads()
+        );
+    }
+
+    /// Merge throughput for 1000 pages of 1000 associations.
+    /// Run with `cargo test --release bench_merge_pages -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_merge_pages() {
+        let page: Associations = serde_json::from_slice(&page_body(0, 1000)).unwrap();
+        let pages = vec![page; 1000];
+
+        let timer = Instant::now();
+        let merged = merge_pages(pages, 0);
+        let elapsed = timer.elapsed();
+
+        assert_eq!(merged.embedded.unwrap()["associations"].len(), 1_000_000);
+        println!(
+            "1M rows merged in {elapsed:?} ({:.1}M rows/s)",
+            1.0 / elapsed.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,219 @@
+use crate::backend::{missing_as_empty, SumstatsBackend};
+use crate::ensembl::EnsemblClient;
+use crate::interrupt::is_interrupted;
+use crate::{Association, GwasFilter};
+use anyhow::Result;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+/// Associations fetched per panel gene; only the lead is reported.
+const GENE_PAGE_SIZE: i32 = 500;
+
+/// One gene of a panel, with the file's free-text note (e.g. drugs).
+#[derive(Debug, Serialize, Clone)]
+pub struct PanelGene {
+    pub gene: String,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct GenePanel {
+    /// File name without its extension.
+    pub name: String,
+    pub genes: Vec<PanelGene>,
+}
+
+/// Scan result for one panel gene.
+#[derive(Debug, Serialize, Clone)]
+pub struct PanelHit {
+    pub gene: String,
+    pub note: Option<String>,
+    pub chromosome: Option<String>,
+    pub start: Option<i64>,
+    pub end: Option<i64>,
+    /// Associations at or below the p-value threshold in the gene window.
+    pub associations: usize,
+    pub lead_variant: Option<String>,
+    pub lead_p_value: Option<f64>,
+    pub lead_study: Option<String>,
+    /// Whether any association in the window reached the threshold.
+    pub significant: bool,
+    /// False when the server had no records for the window (HTTP 404).
+    pub found: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct PanelScan {
+    pub panel: String,
+    pub trait_id: String,
+    pub genes: usize,
+    pub significant_genes: usize,
+    /// Set when the scan was stopped by the user before every gene.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interrupted: Option<bool>,
+    /// Significant genes first, by lead p-value, then the rest in panel
+    /// order.
+    pub hits: Vec<PanelHit>,
+}
+
+/// Reads a panel file: tab-separated `gene` and optional note columns,
+/// with a header row. Lines starting with `#` are comments.
+pub fn read_panel(path: &str) -> Result<GenePanel> {
+    let text =
+        fs::read_to_string(path).map_err(|e| anyhow::anyhow!("Cannot read {}: {}", path, e))?;
+    let mut genes = Vec::new();
+    let mut lines = text
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'));
+    let header = lines
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Panel {} is empty", path))?;
+    if !header
+        .split('\t')
+        .next()
+        .map_or(false, |column| column.eq_ignore_ascii_case("gene"))
+    {
+        return Err(anyhow::anyhow!(
+            "Panel {} must start with a header whose first column is 'gene'",
+            path
+        ));
+    }
+    for line in lines {
+        let mut fields = line.split('\t');
+        let gene = fields.next().unwrap_or("").trim();
+        if gene.is_empty() {
+            continue;
+        }
+        let note = fields
+            .next()
+            .map(str::trim)
+            .filter(|note| !note.is_empty())
+            .map(str::to_string);
+        genes.push(PanelGene {
+            gene: gene.to_string(),
+            note,
+        });
+    }
+    let name = Path::new(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    Ok(GenePanel { name, genes })
+}
+
+fn scan_gene(
+    backend: &dyn SumstatsBackend,
+    ensembl: &EnsemblClient,
+    panel_gene: &PanelGene,
+    flank_kb: f64,
+    filter: &GwasFilter,
+    missing_ok: bool,
+) -> Result<PanelHit> {
+    let gene = ensembl.get_gene(&panel_gene.gene)?;
+    let mut response = backend.gene_window_associations(&gene, flank_kb, filter);
+    if missing_ok {
+        response = missing_as_empty(response);
+    }
+    let response = response?;
+    let found = response.found != Some(false);
+    let associations: Vec<Association> = response
+        .embedded
+        .into_iter()
+        .flat_map(|embedded| embedded.into_values())
+        .flat_map(|associations| associations.into_values())
+        .collect();
+    let lead = associations
+        .iter()
+        .filter(|a| a.p_value.is_some())
+        .min_by(|a, b| {
+            a.p_value
+                .unwrap_or(1.0)
+                .total_cmp(&b.p_value.unwrap_or(1.0))
+        });
+    Ok(PanelHit {
+        gene: panel_gene.gene.clone(),
+        note: panel_gene.note.clone(),
+        chromosome: Some(gene.seq_region_name.clone()),
+        start: Some(gene.start),
+        end: Some(gene.end),
+        associations: associations.len(),
+        lead_variant: lead.and_then(|a| a.variant_id.clone()),
+        lead_p_value: lead.and_then(|a| a.p_value),
+        lead_study: lead.and_then(|a| a.study_accession.clone()),
+        significant: !associations.is_empty(),
+        found,
+        error: None,
+    })
+}
+
+/// Looks for `trait_id` associations at or below `p_value_max` within
+/// `flank_kb` of each panel gene. Genes whose lookup fails are reported
+/// with an error, except with `missing_ok` a window the server has no
+/// records for, which is reported as not found; a user interrupt ends the
+/// scan with the genes so far.
+pub fn panel_scan(
+    backend: &dyn SumstatsBackend,
+    ensembl: &EnsemblClient,
+    panel: &GenePanel,
+    trait_id: &str,
+    flank_kb: f64,
+    p_value_max: f64,
+    missing_ok: bool,
+) -> Result<PanelScan> {
+    let filter = GwasFilter {
+        trait_id: Some(trait_id.to_string()),
+        p_value_range: Some(("0.0".to_string(), p_value_max.to_string())),
+        size: Some(GENE_PAGE_SIZE),
+        ..Default::default()
+    };
+
+    let mut hits = Vec::with_capacity(panel.genes.len());
+    let mut interrupted = None;
+    for panel_gene in &panel.genes {
+        if backend.interrupt().map_or(false, |i| i.is_requested()) {
+            interrupted = Some(true);
+            break;
+        }
+        match scan_gene(backend, ensembl, panel_gene, flank_kb, &filter, missing_ok) {
+            Ok(hit) => hits.push(hit),
+            Err(e) if is_interrupted(&e) => {
+                interrupted = Some(true);
+                break;
+            }
+            Err(e) => hits.push(PanelHit {
+                gene: panel_gene.gene.clone(),
+                note: panel_gene.note.clone(),
+                chromosome: None,
+                start: None,
+                end: None,
+                associations: 0,
+                lead_variant: None,
+                lead_p_value: None,
+                lead_study: None,
+                significant: false,
+                found: true,
+                error: Some(e.to_string()),
+            }),
+        }
+    }
+
+    // Stable, so genes without hits keep panel order.
+    hits.sort_by(|a, b| match (a.lead_p_value, b.lead_p_value) {
+        (Some(a), Some(b)) => a.total_cmp(&b),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    });
+
+    Ok(PanelScan {
+        panel: panel.name.clone(),
+        trait_id: trait_id.to_string(),
+        genes: panel.genes.len(),
+        significant_genes: hits.iter().filter(|hit| hit.significant).count(),
+        interrupted,
+        hits,
+    })
+}
@@ -0,0 +1,168 @@
+use crate::{to_json, GwasClient, GwasFilter};
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use url::Url;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PgsPage<T> {
+    pub count: Option<i64>,
+    pub next: Option<String>,
+    pub previous: Option<String>,
+    pub results: Vec<T>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EfoTrait {
+    pub id: String,
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PgsPublication {
+    pub title: Option<String>,
+    pub doi: Option<String>,
+    #[serde(rename = "PMID")]
+    pub pmid: Option<serde_json::Value>,
+    pub firstauthor: Option<String>,
+    pub date_publication: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PgsScore {
+    pub id: String,
+    pub name: Option<String>,
+    pub trait_reported: Option<String>,
+    #[serde(default)]
+    pub trait_efo: Vec<EfoTrait>,
+    pub variants_number: Option<i64>,
+    pub ftp_scoring_file: Option<String>,
+    pub publication: Option<PgsPublication>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PgsTrait {
+    pub id: String,
+    pub label: Option<String>,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub associated_pgs_ids: Vec<String>,
+}
+
+/// Client for the PGS Catalog REST API. Requests go through the same URL
+/// building, response checks and start/size paging as `GwasClient`.
+#[derive(Debug, Clone)]
+pub struct PgsClient {
+    api: GwasClient,
+}
+
+impl PgsClient {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            api: GwasClient::with_base_url("https://www.pgscatalog.org/rest".to_string())?,
+        })
+    }
+
+    /// A client sending its requests through `api`, whose base URL is the
+    /// PGS Catalog's `/rest` root.
+    pub fn with_client(api: GwasClient) -> Self {
+        Self { api }
+    }
+
+    fn url(
+        &self,
+        endpoint: &str,
+        mut params: BTreeMap<String, String>,
+        filter: &GwasFilter,
+    ) -> Result<Url> {
+        // The PGS Catalog pages with offset/limit rather than start/size.
+        if let Some(start) = filter.start {
+            params.insert("offset".to_string(), start.to_string());
+        }
+        if let Some(size) = filter.size {
+            params.insert("limit".to_string(), size.to_string());
+        }
+        self.api.build_url(endpoint, &params)
+    }
+
+    fn get<T: DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        params: BTreeMap<String, String>,
+        filter: &GwasFilter,
+    ) -> Result<T> {
+        let url = self.url(endpoint, params, filter)?;
+        let response = self.api.api_get(url)?;
+        let response = self.api.check_json_response(response)?;
+        self.api.read_json(response)
+    }
+
+    pub fn get_score(&self, pgs_id: &str) -> Result<PgsScore> {
+        self.get(
+            &format!("/score/{pgs_id}"),
+            BTreeMap::new(),
+            &GwasFilter::default(),
+        )
+    }
+
+    /// Scores developed for a trait (EFO ID), including its descendants.
+    pub fn search_scores(&self, trait_id: &str, filter: &GwasFilter) -> Result<PgsPage<PgsScore>> {
+        let params = BTreeMap::from([("trait_id".to_string(), trait_id.to_string())]);
+        self.get("/score/search", params, filter)
+    }
+
+    pub fn get_trait(&self, trait_id: &str) -> Result<PgsTrait> {
+        self.get(
+            &format!("/trait/{trait_id}"),
+            BTreeMap::new(),
+            &GwasFilter::default(),
+        )
+    }
+
+    pub fn search_traits(&self, term: &str, filter: &GwasFilter) -> Result<PgsPage<PgsTrait>> {
+        let params = BTreeMap::from([("term".to_string(), term.to_string())]);
+        self.get("/trait/search", params, filter)
+    }
+
+    /// The URL `get_entity` would request.
+    pub fn entity_url(&self, entity_type: &str, query: &str, filter: &GwasFilter) -> Result<Url> {
+        let single = GwasFilter::default();
+        match entity_type {
+            "score" => self.url(&format!("/score/{query}"), BTreeMap::new(), &single),
+            "scores" => self.url(
+                "/score/search",
+                BTreeMap::from([("trait_id".to_string(), query.to_string())]),
+                filter,
+            ),
+            "trait" => self.url(&format!("/trait/{query}"), BTreeMap::new(), &single),
+            "traits" => self.url(
+                "/trait/search",
+                BTreeMap::from([("term".to_string(), query.to_string())]),
+                filter,
+            ),
+            _ => Err(anyhow::anyhow!(
+                "Invalid PGS Catalog entity type: {}. Use 'score', 'scores', 'trait' or 'traits'",
+                entity_type
+            )),
+        }
+    }
+
+    pub fn get_entity(
+        &self,
+        entity_type: &str,
+        query: &str,
+        filter: &GwasFilter,
+    ) -> Result<String> {
+        match entity_type {
+            "score" => Ok(to_json(&self.get_score(query)?)?),
+            "scores" => Ok(to_json(&self.search_scores(query, filter)?)?),
+            "trait" => Ok(to_json(&self.get_trait(query)?)?),
+            "traits" => Ok(to_json(&self.search_traits(query, filter)?)?),
+            _ => Err(anyhow::anyhow!(
+                "Invalid PGS Catalog entity type: {}. Use 'score', 'scores', 'trait' or 'traits'",
+                entity_type
+            )),
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// Whether long operations report to R, set by `gwas_progress()`.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn set_enabled(enabled: bool) -> bool {
+    ENABLED.swap(enabled, Ordering::Relaxed)
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    pages: AtomicU64,
+    rows: AtomicU64,
+    bytes: AtomicU64,
+    files: AtomicU64,
+    files_total: AtomicU64,
+}
+
+/// Counters updated by worker threads and read by the R thread, which
+/// passes them to R while it waits; see `interrupt::with_interrupts`.
+#[derive(Debug, Clone, Default)]
+pub struct Progress(Arc<Counters>);
+
+/// What an operation has done so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProgressSnapshot {
+    /// Pages of a paged query fetched.
+    pub pages: u64,
+    /// Association rows parsed from those pages.
+    pub rows: u64,
+    /// Bytes downloaded, including resumed tails and compressed data.
+    pub bytes: u64,
+    /// Files finished, whatever their status, of `files_total`.
+    pub files: u64,
+    pub files_total: u64,
+}
+
+impl Progress {
+    pub fn reset(&self) {
+        for counter in [
+            &self.0.pages,
+            &self.0.rows,
+            &self.0.bytes,
+            &self.0.files,
+            &self.0.files_total,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+
+    pub fn add_pages(&self, pages: u64, rows: u64) {
+        self.0.pages.fetch_add(pages, Ordering::Relaxed);
+        self.0.rows.fetch_add(rows, Ordering::Relaxed);
+    }
+
+    pub fn add_bytes(&self, bytes: u64) {
+        self.0.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Adds to the files expected, so a run over several batches counts
+    /// all of them.
+    pub fn add_files_total(&self, files: u64) {
+        self.0.files_total.fetch_add(files, Ordering::Relaxed);
+    }
+
+    pub fn file_done(&self) {
+        self.0.files.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> ProgressSnapshot {
+        ProgressSnapshot {
+            pages: self.0.pages.load(Ordering::Relaxed),
+            rows: self.0.rows.load(Ordering::Relaxed),
+            bytes: self.0.bytes.load(Ordering::Relaxed),
+            files: self.0.files.load(Ordering::Relaxed),
+            files_total: self.0.files_total.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Takes a snapshot and whether the operation is done, and returns false
+/// when it failed.
+pub type Reporter = fn(&ProgressSnapshot, bool) -> bool;
+
+/// Receives progress from [`report`]; nothing does until the host
+/// installs a reporter with [`set_reporter`].
+static REPORTER: RwLock<Option<Reporter>> = RwLock::new(None);
+
+/// Passes progress to `reporter` from now on. The R package hands it to
+/// its R handler, which draws the progress bar.
+pub fn set_reporter(reporter: Reporter) {
+    if let Ok(mut current) = REPORTER.write() {
+        *current = Some(reporter);
+    }
+}
+
+/// Passes `snapshot` to the host's reporter, `done` on the last call of
+/// an operation. Runs on the thread in `interrupt::with_interrupts`, R's
+/// main thread for the R package. Returns false when the reporter failed,
+/// or there is none, so the operation stops reporting.
+pub fn report(snapshot: &ProgressSnapshot, done: bool) -> bool {
+    let reporter = REPORTER.read().ok().and_then(|reporter| *reporter);
+    reporter.map_or(false, |reporter| reporter(snapshot, done))
+}
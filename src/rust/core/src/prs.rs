@@ -0,0 +1,158 @@
+use crate::ld::PlinkFileset;
+use crate::sumstats::{complement, is_strand_ambiguous, SumstatsReader};
+use anyhow::Result;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SampleScore {
+    pub fid: String,
+    pub iid: String,
+    pub score: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PrsResult {
+    pub n_variants_read: usize,
+    pub n_variants_used: usize,
+    pub n_allele_flips: usize,
+    pub n_strand_flips: usize,
+    pub n_skipped_ambiguous: usize,
+    pub n_skipped_mismatch: usize,
+    pub scores: Vec<SampleScore>,
+}
+
+struct Weight {
+    index: usize,
+    weight: f64,
+    // Whether the effect allele is the reference's allele 1 (the counted allele).
+    counts_effect: bool,
+}
+
+/// Matches summary statistics to a PLINK genotype fileset and computes
+/// per-sample scores as the sum of effect-allele dosage times effect size.
+/// Missing calls are mean-imputed from the variant's observed dosages.
+pub fn compute_prs(
+    sumstats_path: &str,
+    genotypes: &PlinkFileset,
+    p_threshold: Option<f64>,
+) -> Result<PrsResult> {
+    let mut weights = Vec::new();
+    let mut result = PrsResult {
+        n_variants_read: 0,
+        n_variants_used: 0,
+        n_allele_flips: 0,
+        n_strand_flips: 0,
+        n_skipped_ambiguous: 0,
+        n_skipped_mismatch: 0,
+        scores: Vec::new(),
+    };
+
+    for record in SumstatsReader::open(sumstats_path)? {
+        let record = record?;
+        result.n_variants_read += 1;
+
+        if let (Some(threshold), Some(p)) = (p_threshold, record.p_value) {
+            if p > threshold {
+                continue;
+            }
+        }
+        let weight = match (record.beta, record.odds_ratio) {
+            (Some(beta), _) => beta,
+            (None, Some(or)) if or > 0.0 => or.ln(),
+            _ => continue,
+        };
+        let (effect, other) = match (&record.effect_allele, &record.other_allele) {
+            (Some(effect), Some(other)) => (effect.as_str(), other.as_str()),
+            _ => continue,
+        };
+        let index = match genotypes.find(
+            record.variant_id.as_deref(),
+            record.chromosome.as_deref(),
+            record.position,
+        ) {
+            Some(index) => index,
+            None => continue,
+        };
+
+        if is_strand_ambiguous(effect, other) {
+            result.n_skipped_ambiguous += 1;
+            continue;
+        }
+
+        let variant = &genotypes.variants[index];
+        let (a1, a2) = (variant.allele1.as_str(), variant.allele2.as_str());
+        let (flipped_effect, flipped_other) = (complement(effect), complement(other));
+        let counts_effect = if effect == a1 && other == a2 {
+            true
+        } else if effect == a2 && other == a1 {
+            result.n_allele_flips += 1;
+            false
+        } else if flipped_effect == a1 && flipped_other == a2 {
+            result.n_strand_flips += 1;
+            true
+        } else if flipped_effect == a2 && flipped_other == a1 {
+            result.n_strand_flips += 1;
+            result.n_allele_flips += 1;
+            false
+        } else {
+            result.n_skipped_mismatch += 1;
+            continue;
+        };
+
+        weights.push(Weight {
+            index,
+            weight,
+            counts_effect,
+        });
+    }
+
+    let n_samples = genotypes.n_samples;
+    let totals = weights
+        .par_iter()
+        .map(|w| {
+            let dosages = genotypes.dosages(w.index)?;
+            let called: Vec<f64> = dosages.iter().flatten().map(|&d| f64::from(d)).collect();
+            let mean = if called.is_empty() {
+                0.0
+            } else {
+                called.iter().sum::<f64>() / called.len() as f64
+            };
+
+            Ok::<_, anyhow::Error>(
+                dosages
+                    .iter()
+                    .map(|d| {
+                        let a1_dosage = d.map_or(mean, f64::from);
+                        let effect_dosage = if w.counts_effect {
+                            a1_dosage
+                        } else {
+                            2.0 - a1_dosage
+                        };
+                        effect_dosage * w.weight
+                    })
+                    .collect::<Vec<f64>>(),
+            )
+        })
+        .try_reduce(
+            || vec![0.0; n_samples],
+            |mut acc, partial| {
+                acc.iter_mut().zip(partial).for_each(|(a, p)| *a += p);
+                Ok(acc)
+            },
+        )?;
+
+    result.n_variants_used = weights.len();
+    result.scores = genotypes
+        .samples
+        .iter()
+        .zip(totals)
+        .map(|((fid, iid), score)| SampleScore {
+            fid: fid.clone(),
+            iid: iid.clone(),
+            score,
+        })
+        .collect();
+
+    Ok(result)
+}
@@ -0,0 +1,239 @@
+use crate::atomic::AtomicFile;
+use crate::download::{manifest_dir, write_manifest, DownloadMode, DownloadResult};
+use crate::GwasClient;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Download queue state, kept next to `manifest.json` until every file is
+/// done, so downloads cut short by a crash can be resumed.
+pub const QUEUE_FILE: &str = "queue.json";
+
+/// One file of a download queue.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QueueEntry {
+    pub url: String,
+    pub path: String,
+    /// "pending", "done" or "failed". Cancelled downloads stay pending.
+    pub state: String,
+    /// The last attempt's result, once there has been one.
+    pub result: Option<DownloadResult>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DownloadQueue {
+    /// Seconds since the Unix epoch.
+    pub created: u64,
+    /// Whether `manifest.json` is written once the queue has run.
+    pub write_manifest: bool,
+    /// Whether compressed files are decompressed as they arrive.
+    #[serde(default)]
+    pub decompress: bool,
+    pub entries: Vec<QueueEntry>,
+}
+
+impl DownloadQueue {
+    pub fn new(
+        urls: &[String],
+        paths: &[String],
+        write_manifest: bool,
+        decompress: bool,
+    ) -> Result<Self> {
+        Ok(Self {
+            created: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            write_manifest,
+            decompress,
+            entries: urls
+                .iter()
+                .zip(paths)
+                .map(|(url, path)| QueueEntry {
+                    url: url.clone(),
+                    path: path.clone(),
+                    state: "pending".to_string(),
+                    result: None,
+                })
+                .collect(),
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let data =
+            fs::read(path).map_err(|e| anyhow::anyhow!("Cannot read {}: {}", path.display(), e))?;
+        serde_json::from_slice(&data)
+            .map_err(|e| anyhow::anyhow!("Invalid download queue {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut file = AtomicFile::create(path)?;
+        file.write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+        file.commit()?;
+        Ok(())
+    }
+
+    /// Files not yet done, as URLs and paths.
+    pub fn unfinished(&self) -> (Vec<String>, Vec<String>) {
+        self.entries
+            .iter()
+            .filter(|entry| entry.state != "done")
+            .map(|entry| (entry.url.clone(), entry.path.clone()))
+            .unzip()
+    }
+
+    fn record(&mut self, index: usize, result: &DownloadResult) {
+        let entry = &mut self.entries[index];
+        entry.state = match result.status.as_str() {
+            "ok" | "skipped" => "done",
+            "failed" => "failed",
+            _ => "pending",
+        }
+        .to_string();
+        entry.result = Some(result.clone());
+    }
+}
+
+/// Queue files in `dir` or any directory below it, in path order.
+pub fn find_queues(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut queues = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                dirs.push(path);
+            } else if entry.file_name() == QUEUE_FILE {
+                queues.push(path);
+            }
+        }
+    }
+    queues.sort();
+    Ok(queues)
+}
+
+impl GwasClient {
+    /// Downloads files like `download_files_with_progress`, persisting
+    /// each file's state to `queue.json` as it finishes. The queue is
+    /// removed once every file is done; otherwise `resume_queue` picks up
+    /// the pending and failed files.
+    pub fn download_queued(
+        &self,
+        urls: &[String],
+        paths: &[String],
+        max_concurrent: usize,
+        mode: DownloadMode,
+        write_manifest: bool,
+        progress: impl Fn(&DownloadResult) + Sync,
+    ) -> Result<Vec<DownloadResult>> {
+        let queue_path = manifest_dir(paths).join(QUEUE_FILE);
+        let queue = DownloadQueue::new(urls, paths, write_manifest, self.decompress)?;
+        self.run_queue(
+            &queue_path,
+            queue,
+            urls,
+            paths,
+            max_concurrent,
+            mode,
+            progress,
+        )
+    }
+
+    /// Continues the queue saved at `queue_path`, resuming partial files.
+    pub fn resume_queue(
+        &self,
+        queue_path: &Path,
+        max_concurrent: usize,
+    ) -> Result<Vec<DownloadResult>> {
+        let queue = DownloadQueue::load(queue_path)?;
+        let (urls, paths) = queue.unfinished();
+        let client = self.clone().with_decompression(queue.decompress);
+        client.run_queue(
+            queue_path,
+            queue,
+            &urls,
+            &paths,
+            max_concurrent,
+            DownloadMode::Resume,
+            |_| {},
+        )
+    }
+
+    /// Resumes every queue in `dir` or below it, in path order, until the
+    /// user interrupts. Results of all queues are concatenated.
+    pub fn resume_queues(&self, dir: &Path, max_concurrent: usize) -> Result<Vec<DownloadResult>> {
+        let queues = find_queues(dir)?;
+        if queues.is_empty() {
+            return Err(anyhow::anyhow!(
+                "No {} in {} or below it",
+                QUEUE_FILE,
+                dir.display()
+            ));
+        }
+        let mut results = Vec::new();
+        for queue_path in queues {
+            if self.interrupt.is_requested() {
+                break;
+            }
+            results.extend(self.resume_queue(&queue_path, max_concurrent)?);
+        }
+        Ok(results)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn run_queue(
+        &self,
+        queue_path: &Path,
+        queue: DownloadQueue,
+        urls: &[String],
+        paths: &[String],
+        max_concurrent: usize,
+        mode: DownloadMode,
+        progress: impl Fn(&DownloadResult) + Sync,
+    ) -> Result<Vec<DownloadResult>> {
+        // Before saving, so a batch that cannot fit leaves no queue behind.
+        self.check_disk_space(urls, paths, max_concurrent, mode)?;
+        queue.save(queue_path)?;
+        // By URL, as results of decompressed files have a different path.
+        let index: HashMap<String, usize> = queue
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| (entry.url.clone(), i))
+            .collect();
+        let queue = Mutex::new(queue);
+
+        let results =
+            self.download_files_unchecked(urls, paths, max_concurrent, mode, |result| {
+                if let (Ok(mut queue), Some(&i)) = (queue.lock(), index.get(&result.url)) {
+                    queue.record(i, result);
+                    // A failed save only means the next resume repeats
+                    // this file.
+                    let _ = queue.save(queue_path);
+                }
+                progress(result);
+            })?;
+
+        let queue = queue
+            .into_inner()
+            .map_err(|_| anyhow::anyhow!("Download queue poisoned"))?;
+        // Files finished by earlier runs are recorded too.
+        let attempted: Vec<DownloadResult> = queue
+            .entries
+            .iter()
+            .filter_map(|entry| entry.result.clone())
+            .collect();
+        if queue.write_manifest && !attempted.is_empty() {
+            write_manifest(&attempted)?;
+        }
+        if queue.entries.iter().all(|entry| entry.state == "done") {
+            fs::remove_file(queue_path)?;
+        } else {
+            queue.save(queue_path)?;
+        }
+        Ok(results)
+    }
+}
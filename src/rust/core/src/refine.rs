@@ -0,0 +1,262 @@
+//! Refinements the API has no parameters for, applied on the client as
+//! pages of associations arrive: bounds on the effect allele frequency and
+//! standard error, the sign of beta, and a projection onto some of the
+//! fields, so only those are serialized and handed back. Merged pages can
+//! also be sorted, as the API orders rows only within a page.
+
+use crate::backend::Associations;
+use crate::export::COLUMNS;
+use crate::ordered::OrderedMap;
+use crate::{Association, HalResponse};
+use anyhow::Result;
+use serde_json::Value;
+use std::cmp::Ordering;
+
+/// Which way an effect points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BetaSign {
+    Positive,
+    Negative,
+}
+
+impl BetaSign {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "positive" => Ok(Self::Positive),
+            "negative" => Ok(Self::Negative),
+            _ => Err(anyhow::anyhow!(
+                "beta_sign must be \"positive\" or \"negative\", got \"{}\"",
+                name
+            )),
+        }
+    }
+}
+
+/// Association rows with the fields projected onto, keyed as the API
+/// keys its rows.
+pub type ProjectedAssociations = HalResponse<OrderedMap<OrderedMap<Value>>>;
+
+/// Client-side filters and projection of association rows. A row without
+/// the field a filter bounds is dropped by that filter.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Refinement {
+    /// Effect allele frequency range, inclusive.
+    pub eaf_range: Option<(f64, f64)>,
+    pub beta_sign: Option<BetaSign>,
+    /// Largest standard error, inclusive.
+    pub se_max: Option<f64>,
+    /// Indices into `COLUMNS` of the fields to keep, in the order asked
+    /// for; `None` keeps every field.
+    pub columns: Option<Vec<usize>>,
+}
+
+impl Refinement {
+    /// Checks the bounds and resolves column names, naming the offending
+    /// parameter as R passes it.
+    pub fn new(
+        eaf_range: Option<(f64, f64)>,
+        beta_sign: Option<&str>,
+        se_max: Option<f64>,
+        columns: Option<&[String]>,
+    ) -> Result<Self> {
+        if let Some((min, max)) = eaf_range {
+            if !(0.0..=1.0).contains(&min) || !(0.0..=1.0).contains(&max) || min > max {
+                return Err(anyhow::anyhow!(
+                    "eaf must be c(min, max) with 0 <= min <= max <= 1, got c({}, {})",
+                    min,
+                    max
+                ));
+            }
+        }
+        if let Some(se_max) = se_max.filter(|se_max| se_max.is_nan() || *se_max < 0.0) {
+            return Err(anyhow::anyhow!(
+                "se_max must not be negative, got {}",
+                se_max
+            ));
+        }
+        let columns = match columns {
+            Some(names) => {
+                let mut indices = Vec::with_capacity(names.len());
+                for name in names {
+                    let index = COLUMNS
+                        .iter()
+                        .position(|column| column == name)
+                        .ok_or_else(|| {
+                            anyhow::anyhow!(
+                                "Unknown column \"{}\"; expected some of {}",
+                                name,
+                                COLUMNS.join(", ")
+                            )
+                        })?;
+                    if !indices.contains(&index) {
+                        indices.push(index);
+                    }
+                }
+                if indices.is_empty() {
+                    return Err(anyhow::anyhow!("columns must name at least one column"));
+                }
+                Some(indices)
+            }
+            None => None,
+        };
+        Ok(Self {
+            eaf_range,
+            beta_sign: beta_sign.map(BetaSign::parse).transpose()?,
+            se_max,
+            columns,
+        })
+    }
+
+    /// Whether rows are filtered at all.
+    pub fn filters_rows(&self) -> bool {
+        self.eaf_range.is_some() || self.beta_sign.is_some() || self.se_max.is_some()
+    }
+
+    pub fn keeps(&self, row: &Association) -> bool {
+        let eaf = self.eaf_range.map_or(true, |(min, max)| {
+            row.effect_allele_frequency
+                .map_or(false, |eaf| (min..=max).contains(&eaf))
+        });
+        let beta = self.beta_sign.map_or(true, |sign| {
+            row.beta.map_or(false, |beta| match sign {
+                BetaSign::Positive => beta > 0.0,
+                BetaSign::Negative => beta < 0.0,
+            })
+        });
+        let se = self
+            .se_max
+            .map_or(true, |se_max| row.se.map_or(false, |se| se <= se_max));
+        eaf && beta && se
+    }
+
+    /// Drops the rows of `data` that do not pass, keeping the keys and
+    /// order of the others.
+    pub fn filter(&self, data: &mut Associations) {
+        if !self.filters_rows() {
+            return;
+        }
+        for rows in data
+            .embedded
+            .iter_mut()
+            .flat_map(|embedded| embedded.values_mut())
+        {
+            rows.retain(|_, row| self.keeps(row));
+        }
+    }
+
+    /// Drops the rows that do not pass from a page of rows.
+    pub fn filter_rows(&self, rows: &mut Vec<Association>) {
+        if self.filters_rows() {
+            rows.retain(|row| self.keeps(row));
+        }
+    }
+
+    /// The names of the kept fields, in order.
+    pub fn column_names(&self) -> Vec<&'static str> {
+        match &self.columns {
+            Some(indices) => indices.iter().map(|&i| COLUMNS[i]).collect(),
+            None => COLUMNS.to_vec(),
+        }
+    }
+
+    /// The kept fields of `row`, in order.
+    pub fn project_row(&self, row: &Association) -> Result<OrderedMap<Value>> {
+        let mut fields = match serde_json::to_value(row)? {
+            Value::Object(fields) => fields,
+            _ => unreachable!("associations serialize as objects"),
+        };
+        let names = self.column_names();
+        let mut projected = OrderedMap::with_capacity(names.len());
+        for name in names {
+            projected.insert(name.to_string(), fields.remove(name).unwrap_or(Value::Null));
+        }
+        Ok(projected)
+    }
+
+    /// `data` with only the kept fields of each row.
+    pub fn project(&self, data: Associations) -> Result<ProjectedAssociations> {
+        let embedded = match data.embedded {
+            Some(embedded) => {
+                let mut projected = OrderedMap::with_capacity(embedded.len());
+                for (name, rows) in embedded.iter() {
+                    let mut kept = OrderedMap::with_capacity(rows.len());
+                    for (key, row) in rows.iter() {
+                        kept.insert(key.clone(), self.project_row(row)?);
+                    }
+                    projected.insert(name.clone(), kept);
+                }
+                Some(projected)
+            }
+            None => None,
+        };
+        Ok(HalResponse {
+            embedded,
+            links: data.links,
+            interrupted: data.interrupted,
+            found: data.found,
+        })
+    }
+}
+
+/// An order of association rows. Rows missing the field sorted on come
+/// last, and ties keep the order the rows arrived in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    /// Most significant first.
+    PValue,
+    BpLocation,
+    /// By chromosome, then by position on it.
+    ChromosomeBp,
+}
+
+impl SortKey {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "p_value" => Ok(Self::PValue),
+            "bp_location" => Ok(Self::BpLocation),
+            "chromosome_bp" => Ok(Self::ChromosomeBp),
+            _ => Err(anyhow::anyhow!(
+                "sort_by must be \"p_value\", \"bp_location\" or \"chromosome_bp\", got \"{}\"",
+                name
+            )),
+        }
+    }
+
+    fn compare(self, a: &Association, b: &Association) -> Ordering {
+        let bp = || missing_last(a.base_pair_location, b.base_pair_location, |a, b| a.cmp(&b));
+        match self {
+            Self::PValue => missing_last(a.p_value, b.p_value, |a, b| a.total_cmp(&b)),
+            Self::BpLocation => bp(),
+            Self::ChromosomeBp => {
+                missing_last(a.chromosome, b.chromosome, |a, b| a.cmp(&b)).then_with(bp)
+            }
+        }
+    }
+}
+
+fn missing_last<T>(a: Option<T>, b: Option<T>, compare: impl Fn(T, T) -> Ordering) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => compare(a, b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+/// Sorts the rows of `data` by `key`, renumbering their keys from `start`
+/// in the new order as `merge_pages` numbers merged rows.
+pub fn sort_associations(data: &mut Associations, key: SortKey, start: usize) {
+    for rows in data
+        .embedded
+        .iter_mut()
+        .flat_map(|embedded| embedded.values_mut())
+    {
+        let mut sorted: Vec<Association> = std::mem::take(rows).into_values().collect();
+        sorted.sort_by(|a, b| key.compare(a, b));
+        *rows = sorted
+            .into_iter()
+            .enumerate()
+            .map(|(i, row)| ((start + i).to_string(), row))
+            .collect();
+    }
+}
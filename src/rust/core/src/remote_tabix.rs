@@ -0,0 +1,144 @@
+use crate::backend::Associations;
+use crate::http::{self, session_client};
+use crate::mirror::{study_accession, Page};
+use crate::stats;
+use crate::sumstats::ColumnMap;
+use crate::tabix::{TabixIndex, TabixQuery};
+use crate::GwasFilter;
+use anyhow::Result;
+use flate2::read::MultiGzDecoder;
+use reqwest::blocking::{Client, Response};
+use reqwest::header::RANGE;
+use reqwest::StatusCode;
+use std::io::{self, BufRead, BufReader, Read};
+
+// Upper bound on the compressed size of one BGZF block.
+const MAX_BLOCK_SIZE: u64 = 1 << 16;
+
+/// A bgzipped, tabix-indexed file served over HTTP(S), such as a harmonised
+/// file on the GWAS Catalog FTP. Only the index, the first block and the
+/// blocks overlapping a region are transferred.
+pub struct RemoteTabixFile {
+    client: Client,
+    pub url: String,
+    pub index: TabixIndex,
+    columns: ColumnMap,
+}
+
+impl RemoteTabixFile {
+    pub fn open(url: &str) -> Result<Self> {
+        let client = session_client();
+
+        let mut index_data = Vec::new();
+        let response = check_status(http::send(client.get(format!("{url}.tbi")))?)?;
+        MultiGzDecoder::new(stats::Counted(response)).read_to_end(&mut index_data)?;
+        let index = TabixIndex::parse(&index_data)?;
+
+        let blocks = get_blocks(&client, url, 0, MAX_BLOCK_SIZE - 1)?;
+        let header = BufReader::new(MultiGzDecoder::new(io::Cursor::new(blocks)))
+            .lines()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("{} is empty", url))??;
+
+        Ok(Self {
+            client,
+            url: url.to_string(),
+            index,
+            columns: ColumnMap::from_header(header.trim_start_matches('#')),
+        })
+    }
+
+    /// Records on `chromosome` between 1-based positions `start` and `end`
+    /// inclusive, fetched with one range request spanning the indexed
+    /// blocks that can overlap.
+    pub fn query(&self, chromosome: &str, start: i64, end: i64) -> Result<TabixQuery> {
+        let reader: Option<Box<dyn BufRead + Send>> =
+            match self.index.region_span(chromosome, start - 1, end) {
+                Some((first, last)) => {
+                    let block_start = first >> 16;
+                    let block_end = (last >> 16) + MAX_BLOCK_SIZE - 1;
+                    let blocks = get_blocks(&self.client, &self.url, block_start, block_end)?;
+                    let mut decoder = MultiGzDecoder::new(io::Cursor::new(blocks));
+                    io::copy(&mut (&mut decoder).take(first & 0xffff), &mut io::sink())?;
+                    Some(Box::new(BufReader::new(decoder)))
+                }
+                None => None,
+            };
+
+        Ok(TabixQuery::new(
+            reader,
+            &self.index,
+            self.columns.clone(),
+            chromosome,
+            start,
+            end,
+        ))
+    }
+
+    /// Associations in a region, with the p-value and paging filters
+    /// applied as by the association endpoints.
+    pub fn associations(
+        &self,
+        chromosome: &str,
+        start: i64,
+        end: i64,
+        filter: &GwasFilter,
+    ) -> Result<Associations> {
+        let study = self
+            .url
+            .rsplit('/')
+            .next()
+            .and_then(study_accession)
+            .unwrap_or_default();
+        let mut page = Page::new(filter)?;
+        page.scan(self.query(chromosome, start, end)?, &study, |_| true)?;
+        Ok(page.finish())
+    }
+}
+
+fn check_status(response: Response) -> Result<Response> {
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!(
+            "HTTP {} for {}",
+            response.status(),
+            response.url()
+        ));
+    }
+    Ok(response)
+}
+
+/// The complete BGZF blocks within the inclusive byte range `[start, end]`.
+/// A server that ignores the range sends the whole file with status 200,
+/// which is treated as an error.
+fn get_blocks(client: &Client, url: &str, start: u64, end: u64) -> Result<Vec<u8>> {
+    let response = http::send(
+        client
+            .get(url)
+            .header(RANGE, format!("bytes={start}-{end}")),
+    )?;
+    if response.status() == StatusCode::OK {
+        return Err(anyhow::anyhow!(
+            "{} does not support HTTP range requests",
+            url
+        ));
+    }
+    let mut data = check_status(response)?.bytes()?.to_vec();
+    stats::add_bytes(data.len() as u64);
+    data.truncate(complete_blocks_len(&data));
+    Ok(data)
+}
+
+/// Length of the prefix of `data` made of whole BGZF blocks, read from the
+/// BSIZE field each block header carries.
+fn complete_blocks_len(data: &[u8]) -> usize {
+    let mut position = 0;
+    while position + 18 <= data.len() && data[position..position + 2] == [0x1f, 0x8b] {
+        let block_size =
+            u16::from_le_bytes([data[position + 16], data[position + 17]]) as usize + 1;
+        if position + block_size > data.len() {
+            break;
+        }
+        position += block_size;
+    }
+    position
+}
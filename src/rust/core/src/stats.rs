@@ -0,0 +1,99 @@
+use serde::Serialize;
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Counters for the whole R session, shared by every client and thread
+/// and read by `gwas_stats()`.
+static REQUESTS: AtomicU64 = AtomicU64::new(0);
+static FAILED_REQUESTS: AtomicU64 = AtomicU64::new(0);
+static BYTES: AtomicU64 = AtomicU64::new(0);
+static CACHE_HITS: AtomicU64 = AtomicU64::new(0);
+static CACHE_MISSES: AtomicU64 = AtomicU64::new(0);
+static RETRIES: AtomicU64 = AtomicU64::new(0);
+static RATE_LIMIT_SLEEPS: AtomicU64 = AtomicU64::new(0);
+static RATE_LIMIT_MICROS: AtomicU64 = AtomicU64::new(0);
+
+/// API usage since the session started or the counters were last reset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct SessionStats {
+    /// HTTP requests sent, including those that failed.
+    pub requests: u64,
+    /// Requests that got no response (connection errors and timeouts),
+    /// not those answered with an error status.
+    pub failed_requests: u64,
+    /// Response body bytes read, from API responses and downloads alike.
+    pub bytes_downloaded: u64,
+    /// Lookups answered from a cache (Ensembl genes and VEP annotations,
+    /// `gwas_summary()` statistics, API responses the server confirmed
+    /// unchanged) and those that were not.
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+    /// Requests repeated after the server asked to slow down.
+    pub retries: u64,
+    /// Waits to respect a service's rate limit or the download bandwidth
+    /// limit, and their total length.
+    pub rate_limit_sleeps: u64,
+    pub rate_limit_sleep_seconds: f64,
+}
+
+pub fn request(failed: bool) {
+    REQUESTS.fetch_add(1, Ordering::Relaxed);
+    if failed {
+        FAILED_REQUESTS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+pub fn add_bytes(bytes: u64) {
+    BYTES.fetch_add(bytes, Ordering::Relaxed);
+}
+
+pub fn cache_hits(hits: u64) {
+    CACHE_HITS.fetch_add(hits, Ordering::Relaxed);
+}
+
+pub fn cache_misses(misses: u64) {
+    CACHE_MISSES.fetch_add(misses, Ordering::Relaxed);
+}
+
+pub fn retry() {
+    RETRIES.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn rate_limit_sleep(wait: Duration) {
+    RATE_LIMIT_SLEEPS.fetch_add(1, Ordering::Relaxed);
+    RATE_LIMIT_MICROS.fetch_add(wait.as_micros() as u64, Ordering::Relaxed);
+}
+
+pub fn snapshot() -> SessionStats {
+    read(|counter| counter.load(Ordering::Relaxed))
+}
+
+/// Zeroes the counters and returns their values before.
+pub fn reset() -> SessionStats {
+    read(|counter| counter.swap(0, Ordering::Relaxed))
+}
+
+fn read(value: impl Fn(&AtomicU64) -> u64) -> SessionStats {
+    SessionStats {
+        requests: value(&REQUESTS),
+        failed_requests: value(&FAILED_REQUESTS),
+        bytes_downloaded: value(&BYTES),
+        cache_hits: value(&CACHE_HITS),
+        cache_misses: value(&CACHE_MISSES),
+        retries: value(&RETRIES),
+        rate_limit_sleeps: value(&RATE_LIMIT_SLEEPS),
+        rate_limit_sleep_seconds: value(&RATE_LIMIT_MICROS) as f64 / 1e6,
+    }
+}
+
+/// Counts the bytes read from `inner` as downloaded.
+pub struct Counted<R>(pub R);
+
+impl<R: Read> Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.0.read(buf)?;
+        add_bytes(n as u64);
+        Ok(n)
+    }
+}
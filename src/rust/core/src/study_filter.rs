@@ -0,0 +1,123 @@
+//! Finding the studies of a trait that meet criteria only the GWAS Catalog
+//! records: discovery sample size, ancestry and publication year. The
+//! summary statistics API lists a trait's studies; each is then looked up
+//! in the Catalog and kept if its metadata meets every criterion.
+
+use crate::catalog::{CatalogClient, StudyInfo};
+use crate::trait_tree::subtree_studies;
+use crate::{GwasClient, Study};
+use anyhow::Result;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Catalog lookups in flight at once.
+const LOOKUP_THREADS: usize = 4;
+
+/// What a study must meet to be kept; criteria left unset keep every
+/// study.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct StudyCriteria {
+    /// Smallest discovery sample size.
+    pub min_n: Option<i64>,
+    /// Ancestral groups, at least one of which a discovery ancestry must
+    /// contain, ignoring case: "European" or "Asian" (which takes in "East
+    /// Asian" and "South Asian").
+    pub ancestry: Vec<String>,
+    /// Earliest publication year.
+    pub since: Option<i32>,
+}
+
+impl StudyCriteria {
+    pub fn new(min_n: Option<f64>, ancestry: Vec<String>, since: Option<i32>) -> Result<Self> {
+        let min_n = match min_n {
+            Some(n) if n.is_nan() || n < 0.0 => {
+                return Err(anyhow::anyhow!(
+                    "min_n must be a sample size of 0 or more, not {}",
+                    n
+                ))
+            }
+            Some(n) => Some(n.ceil() as i64),
+            None => None,
+        };
+        let ancestry = ancestry
+            .into_iter()
+            .map(|group| group.trim().to_lowercase())
+            .filter(|group| !group.is_empty())
+            .collect();
+        Ok(Self {
+            min_n,
+            ancestry,
+            since,
+        })
+    }
+
+    /// Whether `info` meets every criterion. A study missing the metadata
+    /// a criterion needs does not meet it.
+    pub fn matches(&self, info: &StudyInfo) -> bool {
+        let sample_size = self.min_n.map_or(true, |min_n| {
+            info.discovery_sample_size.unwrap_or(0) >= min_n
+        });
+        let ancestry = self.ancestry.is_empty()
+            || info.discovery_ancestry.iter().any(|group| {
+                let group = group.to_lowercase();
+                self.ancestry.iter().any(|wanted| group.contains(wanted))
+            });
+        let year = self.since.map_or(true, |since| {
+            publication_year(info).map_or(false, |year| year >= since)
+        });
+        sample_size && ancestry && year
+    }
+}
+
+/// The year of a study's publication date, e.g. "2020-05-12".
+fn publication_year(info: &StudyInfo) -> Option<i32> {
+    info.publication_date.as_deref()?.get(..4)?.parse().ok()
+}
+
+/// A study that met the criteria, with the traits it was listed under.
+#[derive(Debug, Serialize, Clone)]
+pub struct FoundStudy {
+    pub trait_ids: Vec<String>,
+    #[serde(flatten)]
+    pub info: StudyInfo,
+}
+
+/// The studies of `trait_ids` that meet `criteria`, by accession. Studies
+/// the Catalog has no record of are left out.
+pub fn find_studies(
+    client: &GwasClient,
+    catalog: &CatalogClient,
+    trait_ids: &[String],
+    criteria: &StudyCriteria,
+) -> Result<Vec<FoundStudy>> {
+    let mut listed: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for study in subtree_studies(client, trait_ids)? {
+        let traits = listed.entry(study.study_accession).or_default();
+        if !traits.contains(&study.trait_id) {
+            traits.push(study.trait_id);
+        }
+    }
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(LOOKUP_THREADS)
+        .build()?;
+    let found: Vec<Option<FoundStudy>> = pool.install(|| {
+        listed
+            .into_par_iter()
+            .map(|(study_accession, trait_ids)| {
+                client.interrupt().check()?;
+                let study = Study {
+                    study_accession,
+                    links: None,
+                };
+                let info = catalog.find_study_info(study)?;
+                client.interrupt().progress().add_pages(1, 0);
+                Ok(info
+                    .filter(|info| criteria.matches(info))
+                    .map(|info| FoundStudy { trait_ids, info }))
+            })
+            .collect::<Result<_>>()
+    })?;
+    Ok(found.into_iter().flatten().collect())
+}
@@ -0,0 +1,160 @@
+//! A local copy of the API's study catalog, kept up to date by syncing.
+//! The first sync walks the whole `/studies` listing and records each
+//! study's traits; later syncs walk the listing again, which conditional
+//! requests make cheap when it has not changed, but look up only the
+//! studies that are new, and drop those no longer listed.
+
+use crate::atomic::AtomicFile;
+use crate::catalog_index::all_pages;
+use crate::http::is_not_found;
+use crate::{GwasClient, Link, Study};
+use anyhow::Result;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Lookups of new studies in flight at once.
+const SYNC_THREADS: usize = 4;
+
+/// One study of a synced catalog.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SyncedStudy {
+    /// EFO IDs of the traits the study's links point to.
+    pub trait_ids: Vec<String>,
+    /// Seconds since the Unix epoch of the sync that first listed it.
+    pub added_at: u64,
+}
+
+/// The catalog as stored at `db_path`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StudyCatalog {
+    pub base_url: String,
+    /// Seconds since the Unix epoch when the last sync finished.
+    pub synced_at: u64,
+    pub studies: BTreeMap<String, SyncedStudy>,
+}
+
+impl StudyCatalog {
+    /// The catalog stored at `db_path`, if any.
+    pub fn load(db_path: &str) -> Result<Option<Self>> {
+        if !Path::new(db_path).exists() {
+            return Ok(None);
+        }
+        let data = std::fs::read(db_path)?;
+        serde_json::from_slice(&data)
+            .map(Some)
+            .map_err(|e| anyhow::anyhow!("Cannot read study catalog {}: {}", db_path, e))
+    }
+}
+
+/// What a sync changed.
+#[derive(Debug, Serialize)]
+pub struct SyncReport {
+    pub db_path: String,
+    pub synced_at: u64,
+    /// When the catalog was synced before, if it was.
+    pub previous_sync: Option<u64>,
+    pub studies: usize,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+/// The trait IDs a study's links point to, the last segment of each
+/// `trait` link.
+fn linked_traits(links: Option<&BTreeMap<String, Link>>) -> Vec<String> {
+    links
+        .into_iter()
+        .flat_map(|links| links.iter())
+        .filter(|(name, _)| name.as_str() == "trait")
+        .filter_map(|(_, link)| link.href.trim_end_matches('/').rsplit('/').next())
+        .filter(|id| !id.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Syncs the study catalog stored at `db_path` with the API, creating it
+/// on the first call. A new study's traits come from its links in the
+/// listing, or from the study itself when the listing has none. The file
+/// is replaced only once the sync has finished, so an interrupted or
+/// failed sync leaves the previous catalog in place.
+pub fn sync_studies(client: &GwasClient, db_path: &str) -> Result<SyncReport> {
+    let previous =
+        StudyCatalog::load(db_path)?.filter(|catalog| catalog.base_url == client.base_url);
+    let previous_sync = previous.as_ref().map(|catalog| catalog.synced_at);
+    let mut known = previous.map(|catalog| catalog.studies).unwrap_or_default();
+
+    let listed: Vec<Study> = all_pages(client, |params| client.get_studies(params))?
+        .into_iter()
+        .flatten()
+        .collect();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+    let mut new: BTreeMap<String, Study> = BTreeMap::new();
+    let mut still_listed = BTreeSet::new();
+    for study in listed {
+        if known.contains_key(&study.study_accession) {
+            still_listed.insert(study.study_accession);
+        } else {
+            new.insert(study.study_accession.clone(), study);
+        }
+    }
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(SYNC_THREADS)
+        .build()?;
+    let enriched: Vec<(String, SyncedStudy)> = pool.install(|| {
+        new.par_iter()
+            .map(|(accession, study)| {
+                let mut trait_ids = linked_traits(study.links.as_ref());
+                if trait_ids.is_empty() {
+                    client.interrupt().check()?;
+                    trait_ids = match client.get_study(accession) {
+                        Ok(study) => linked_traits(study.links.as_ref()),
+                        Err(e) if is_not_found(&e) => Vec::new(),
+                        Err(e) => return Err(e),
+                    };
+                }
+                trait_ids.sort();
+                trait_ids.dedup();
+                Ok((
+                    accession.clone(),
+                    SyncedStudy {
+                        trait_ids,
+                        added_at: now,
+                    },
+                ))
+            })
+            .collect::<Result<_>>()
+    })?;
+
+    let removed: Vec<String> = known
+        .keys()
+        .filter(|accession| !still_listed.contains(*accession))
+        .cloned()
+        .collect();
+    known.retain(|accession, _| still_listed.contains(accession));
+    let added: Vec<String> = enriched
+        .iter()
+        .map(|(accession, _)| accession.clone())
+        .collect();
+    known.extend(enriched);
+
+    let catalog = StudyCatalog {
+        base_url: client.base_url.clone(),
+        synced_at: now,
+        studies: known,
+    };
+    let mut file = AtomicFile::create(db_path)?;
+    file.write_all(serde_json::to_string_pretty(&catalog)?.as_bytes())?;
+    file.commit()?;
+    Ok(SyncReport {
+        db_path: db_path.to_string(),
+        synced_at: now,
+        previous_sync,
+        studies: catalog.studies.len(),
+        added,
+        removed,
+    })
+}
@@ -0,0 +1,392 @@
+use crate::atomic::AtomicFile;
+use crate::munge::normal_quantile;
+use crate::stats;
+use crate::sumstats::{SumstatsReader, SumstatsRecord};
+use crate::variant_index::fnv1a;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+const SKETCH_K: usize = 200;
+pub const SUMMARY_QUANTILES: [f64; 7] = [0.01, 0.05, 0.25, 0.5, 0.75, 0.95, 0.99];
+
+/// KLL quantile sketch. Memory stays at roughly `3k` values regardless of
+/// input size; rank error is about 1.7% at k = 200.
+#[derive(Debug, Clone)]
+pub struct KllSketch {
+    k: usize,
+    compactors: Vec<Vec<f64>>,
+    rng: u64,
+}
+
+impl KllSketch {
+    pub fn new(k: usize) -> Self {
+        Self {
+            k,
+            compactors: vec![Vec::new()],
+            rng: 0x2545_f491_4f6c_dd1d,
+        }
+    }
+
+    fn capacity(&self, level: usize) -> usize {
+        let depth = self.compactors.len() - level - 1;
+        ((self.k as f64) * (2.0f64 / 3.0).powi(depth as i32))
+            .ceil()
+            .max(2.0) as usize
+    }
+
+    fn size(&self) -> usize {
+        self.compactors.iter().map(Vec::len).sum()
+    }
+
+    fn max_size(&self) -> usize {
+        (0..self.compactors.len()).map(|h| self.capacity(h)).sum()
+    }
+
+    // Deterministic xorshift so summaries are reproducible run to run.
+    fn coin(&mut self) -> usize {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng & 1) as usize
+    }
+
+    pub fn insert(&mut self, value: f64) {
+        if value.is_nan() {
+            return;
+        }
+        self.compactors[0].push(value);
+        if self.size() >= self.max_size() {
+            self.compress();
+        }
+    }
+
+    fn compress(&mut self) {
+        for level in 0..self.compactors.len() {
+            if self.compactors[level].len() < self.capacity(level) {
+                continue;
+            }
+            if level + 1 == self.compactors.len() {
+                self.compactors.push(Vec::new());
+            }
+
+            let mut items = std::mem::take(&mut self.compactors[level]);
+            items.sort_by(f64::total_cmp);
+            // An odd item out stays behind at this level.
+            if items.len() % 2 == 1 {
+                let last = items.pop().unwrap_or_default();
+                self.compactors[level].push(last);
+            }
+            let offset = self.coin();
+            let promoted: Vec<f64> = items.into_iter().skip(offset).step_by(2).collect();
+            self.compactors[level + 1].extend(promoted);
+            break;
+        }
+    }
+
+    /// Approximate value at quantile `q` in [0, 1]; `None` when empty.
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        let mut weighted: Vec<(f64, u64)> = self
+            .compactors
+            .iter()
+            .enumerate()
+            .flat_map(|(level, items)| items.iter().map(move |v| (*v, 1u64 << level)))
+            .collect();
+        if weighted.is_empty() {
+            return None;
+        }
+        weighted.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let total: u64 = weighted.iter().map(|(_, w)| w).sum();
+        let target = (q.clamp(0.0, 1.0) * total as f64).ceil().max(1.0) as u64;
+        let mut cumulative = 0;
+        for (value, weight) in &weighted {
+            cumulative += weight;
+            if cumulative >= target {
+                return Some(*value);
+            }
+        }
+        weighted.last().map(|(v, _)| *v)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QuantileValue {
+    pub q: f64,
+    pub value: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ColumnSummary {
+    pub column: String,
+    pub n: u64,
+    pub n_missing: u64,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub mean: Option<f64>,
+    pub quantiles: Vec<QuantileValue>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SumstatsSummary {
+    pub path: String,
+    pub n_rows: u64,
+    /// Genomic inflation factor from the median p-value.
+    pub lambda_gc: Option<f64>,
+    pub columns: Vec<ColumnSummary>,
+}
+
+struct ColumnAccumulator {
+    column: &'static str,
+    get: fn(&SumstatsRecord) -> Option<f64>,
+    n: u64,
+    n_missing: u64,
+    min: f64,
+    max: f64,
+    sum: f64,
+    sketch: KllSketch,
+}
+
+impl ColumnAccumulator {
+    fn new(column: &'static str, get: fn(&SumstatsRecord) -> Option<f64>) -> Self {
+        Self {
+            column,
+            get,
+            n: 0,
+            n_missing: 0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            sum: 0.0,
+            sketch: KllSketch::new(SKETCH_K),
+        }
+    }
+
+    fn add(&mut self, record: &SumstatsRecord) {
+        match (self.get)(record).filter(|v| v.is_finite()) {
+            Some(value) => {
+                self.n += 1;
+                self.min = self.min.min(value);
+                self.max = self.max.max(value);
+                self.sum += value;
+                self.sketch.insert(value);
+            }
+            None => self.n_missing += 1,
+        }
+    }
+
+    fn finish(self) -> ColumnSummary {
+        let present = self.n > 0;
+        ColumnSummary {
+            column: self.column.to_string(),
+            n: self.n,
+            n_missing: self.n_missing,
+            min: present.then_some(self.min),
+            max: present.then_some(self.max),
+            mean: present.then(|| self.sum / self.n as f64),
+            quantiles: SUMMARY_QUANTILES
+                .iter()
+                .filter_map(|q| {
+                    self.sketch
+                        .quantile(*q)
+                        .map(|value| QuantileValue { q: *q, value })
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The column statistics of a summary statistics file, gathered record by
+/// record, so that a pass over the file made for another purpose can
+/// summarize it too.
+pub struct Summarizer {
+    n_rows: u64,
+    columns: Vec<ColumnAccumulator>,
+}
+
+impl Default for Summarizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Summarizer {
+    pub fn new() -> Self {
+        Self {
+            n_rows: 0,
+            columns: vec![
+                ColumnAccumulator::new("p_value", |r| r.p_value),
+                ColumnAccumulator::new("beta", |r| r.beta),
+                ColumnAccumulator::new("odds_ratio", |r| r.odds_ratio),
+                ColumnAccumulator::new("standard_error", |r| r.standard_error),
+                ColumnAccumulator::new("effect_allele_frequency", |r| r.effect_allele_frequency),
+                ColumnAccumulator::new("info", |r| r.info),
+                ColumnAccumulator::new("n", |r| r.n),
+            ],
+        }
+    }
+
+    pub fn add(&mut self, record: &SumstatsRecord) {
+        self.n_rows += 1;
+        for column in self.columns.iter_mut() {
+            column.add(record);
+        }
+    }
+
+    /// The summary of the records added, as the summary of `path`.
+    pub fn finish(self, path: &str) -> SumstatsSummary {
+        let columns: Vec<ColumnSummary> = self.columns.into_iter().map(|c| c.finish()).collect();
+        let lambda_gc = columns
+            .iter()
+            .find(|c| c.column == "p_value")
+            .and_then(|c| c.quantiles.iter().find(|q| q.q == 0.5))
+            .filter(|median| median.value > 0.0 && median.value <= 1.0)
+            .map(|median| normal_quantile(median.value / 2.0).powi(2) / 0.454_936_423_119_572_8);
+
+        SumstatsSummary {
+            path: path.to_string(),
+            n_rows: self.n_rows,
+            lambda_gc,
+            columns,
+        }
+    }
+}
+
+/// Distribution summary of the numeric columns of a summary statistics file,
+/// computed in a single streaming pass with bounded memory.
+pub fn summarize(path: &str) -> Result<SumstatsSummary> {
+    let mut summarizer = Summarizer::new();
+    for record in SumstatsReader::open(path)? {
+        summarizer.add(&record?);
+    }
+    Ok(summarizer.finish(path))
+}
+
+/// What a file's cached summary was computed from: its size, modification
+/// time and a hash of its first and last `STAMP_SAMPLE_BYTES`. Any change to
+/// one invalidates it, so a file rewritten within the clock's resolution is
+/// not mistaken for the one summarized. Sampling keeps stamping a large file
+/// as cheap as stamping a small one; a rewrite that keeps the size, the
+/// modification time and both ends is not noticed.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+struct FileStamp {
+    size: u64,
+    modified_secs: u64,
+    modified_nanos: u32,
+    content_hash: u64,
+}
+
+const STAMP_SAMPLE_BYTES: u64 = 64 * 1024;
+
+impl FileStamp {
+    fn of(path: &str) -> Result<Self> {
+        let metadata = fs::metadata(path)?;
+        let modified = metadata.modified()?.duration_since(UNIX_EPOCH)?;
+        let size = metadata.len();
+        let mut file = fs::File::open(path)?;
+        let mut sample = Vec::new();
+        (&mut file)
+            .take(STAMP_SAMPLE_BYTES)
+            .read_to_end(&mut sample)?;
+        // The tail, less whatever the head already covered.
+        let tail_start = size
+            .saturating_sub(STAMP_SAMPLE_BYTES)
+            .max(STAMP_SAMPLE_BYTES);
+        if tail_start < size {
+            file.seek(SeekFrom::Start(tail_start))?;
+            file.take(STAMP_SAMPLE_BYTES).read_to_end(&mut sample)?;
+        }
+        Ok(Self {
+            size,
+            modified_secs: modified.as_secs(),
+            modified_nanos: modified.subsec_nanos(),
+            content_hash: fnv1a(&sample, 0xcbf2_9ce4_8422_2325),
+        })
+    }
+}
+
+/// Cached summary together with the stamp of the file it was computed from.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct CachedSummary {
+    #[serde(flatten)]
+    stamp: FileStamp,
+    summary: SumstatsSummary,
+}
+
+pub fn stats_cache_path(cache_dir: &str, path: &str) -> Result<PathBuf> {
+    let canonical = fs::canonicalize(path)?;
+    let key = fnv1a(
+        canonical.to_string_lossy().as_bytes(),
+        0xcbf2_9ce4_8422_2325,
+    );
+    Ok(Path::new(cache_dir)
+        .join("stats")
+        .join(format!("{key:016x}.json")))
+}
+
+/// The cached column statistics of one file in a cache directory, shared by
+/// every entry point that summarizes local files.
+pub struct SummaryCache {
+    cache_path: PathBuf,
+    stamp: FileStamp,
+}
+
+impl SummaryCache {
+    /// Stamps `path` as it is now; open it before reading the file, so that
+    /// a summary stored later is not credited to a newer version.
+    pub fn open(cache_dir: &str, path: &str) -> Result<Self> {
+        Ok(Self {
+            cache_path: stats_cache_path(cache_dir, path)?,
+            stamp: FileStamp::of(path)?,
+        })
+    }
+
+    /// The cached summary, if the file is unchanged since it was stored.
+    pub fn get(&self) -> Option<SumstatsSummary> {
+        let cached = fs::read_to_string(&self.cache_path)
+            .ok()
+            .and_then(|data| serde_json::from_str::<CachedSummary>(&data).ok())
+            .filter(|cached| cached.stamp == self.stamp);
+        match cached {
+            Some(cached) => {
+                stats::cache_hits(1);
+                Some(cached.summary)
+            }
+            None => {
+                stats::cache_misses(1);
+                None
+            }
+        }
+    }
+
+    pub fn put(&self, summary: &SumstatsSummary) -> Result<()> {
+        let cached = CachedSummary {
+            stamp: self.stamp.clone(),
+            summary: summary.clone(),
+        };
+        // A cache that cannot be written only costs a rescan next time.
+        let data = serde_json::to_string(&cached)?;
+        let _ = AtomicFile::create(&self.cache_path).and_then(|mut file| {
+            file.write_all(data.as_bytes())?;
+            file.commit()
+        });
+        Ok(())
+    }
+}
+
+/// Like `summarize`, but reuses column statistics cached in `cache_dir` while
+/// the file is unchanged.
+pub fn summarize_cached(path: &str, cache_dir: Option<&str>) -> Result<SumstatsSummary> {
+    let cache = match cache_dir {
+        Some(dir) => SummaryCache::open(dir, path)?,
+        None => return summarize(path),
+    };
+    if let Some(summary) = cache.get() {
+        return Ok(summary);
+    }
+    let summary = summarize(path)?;
+    cache.put(&summary)?;
+    Ok(summary)
+}
@@ -0,0 +1,225 @@
+use anyhow::Result;
+use flate2::read::MultiGzDecoder;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Read};
+
+/// One row of a summary statistics file, with harmonised columns preferred
+/// over their raw counterparts when both are present.
+#[derive(Debug, Clone, Default)]
+pub struct SumstatsRecord {
+    pub variant_id: Option<String>,
+    pub chromosome: Option<String>,
+    pub position: Option<i64>,
+    pub effect_allele: Option<String>,
+    pub other_allele: Option<String>,
+    pub beta: Option<f64>,
+    pub odds_ratio: Option<f64>,
+    pub standard_error: Option<f64>,
+    pub p_value: Option<f64>,
+    pub effect_allele_frequency: Option<f64>,
+    pub info: Option<f64>,
+    pub n: Option<f64>,
+}
+
+const COLUMN_ALIASES: &[(&str, &[&str])] = &[
+    (
+        "variant_id",
+        &["hm_rsid", "rsid", "variant_id", "snp", "markername"],
+    ),
+    (
+        "chromosome",
+        &[
+            "hm_chrom",
+            "hm_chr",
+            "chromosome",
+            "chr",
+            "chrom",
+            "chr_name",
+        ],
+    ),
+    (
+        "position",
+        &[
+            "hm_pos",
+            "base_pair_location",
+            "bp",
+            "pos",
+            "position",
+            "chr_position",
+        ],
+    ),
+    (
+        "effect_allele",
+        &["hm_effect_allele", "effect_allele", "a1", "alt"],
+    ),
+    (
+        "other_allele",
+        &["hm_other_allele", "other_allele", "a2", "ref"],
+    ),
+    ("beta", &["hm_beta", "beta", "b", "effect_weight"]),
+    ("odds_ratio", &["hm_odds_ratio", "odds_ratio", "or"]),
+    ("standard_error", &["standard_error", "se"]),
+    ("p_value", &["p_value", "pval", "p"]),
+    (
+        "effect_allele_frequency",
+        &[
+            "hm_effect_allele_frequency",
+            "effect_allele_frequency",
+            "eaf",
+            "frq",
+        ],
+    ),
+    ("info", &["info", "imputation_info"]),
+    ("n", &["n", "n_total", "sample_size"]),
+];
+
+/// Opens a plain or gzip/bgzip-compressed text file.
+pub fn open_text(path: &str) -> Result<Box<dyn BufRead + Send>> {
+    let mut file = fs::File::open(path)?;
+    let mut magic = [0u8; 2];
+    let is_gzip = file.read(&mut magic)? == 2 && magic == [0x1f, 0x8b];
+    let file = fs::File::open(path)?;
+
+    if is_gzip {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(file))))
+    } else {
+        Ok(Box::new(BufReader::new(file)))
+    }
+}
+
+/// Positions of the canonical columns in a header line.
+#[derive(Debug, Clone, Default)]
+pub struct ColumnMap {
+    columns: HashMap<&'static str, usize>,
+}
+
+impl ColumnMap {
+    pub fn from_header(header_line: &str) -> Self {
+        let lower: Vec<String> = split_fields(header_line)
+            .into_iter()
+            .map(|h| h.to_lowercase())
+            .collect();
+        let mut columns = HashMap::new();
+        for (canonical, aliases) in COLUMN_ALIASES {
+            if let Some(index) = aliases
+                .iter()
+                .find_map(|alias| lower.iter().position(|h| h == alias))
+            {
+                columns.insert(*canonical, index);
+            }
+        }
+
+        Self { columns }
+    }
+
+    /// 0-based index of a canonical column, if the header has it.
+    pub fn column(&self, name: &str) -> Option<usize> {
+        self.columns.get(name).copied()
+    }
+
+    pub fn parse(&self, fields: &[&str]) -> SumstatsRecord {
+        let text = |name: &str| {
+            self.columns
+                .get(name)
+                .and_then(|&i| fields.get(i))
+                .filter(|v| !is_missing(v))
+                .map(|v| v.to_string())
+        };
+        let number = |name: &str| text(name).and_then(|v| v.parse::<f64>().ok());
+
+        SumstatsRecord {
+            variant_id: text("variant_id"),
+            chromosome: text("chromosome"),
+            position: text("position").and_then(|v| v.parse().ok()),
+            effect_allele: text("effect_allele").map(|a| a.to_uppercase()),
+            other_allele: text("other_allele").map(|a| a.to_uppercase()),
+            beta: number("beta"),
+            odds_ratio: number("odds_ratio"),
+            standard_error: number("standard_error"),
+            p_value: number("p_value"),
+            effect_allele_frequency: number("effect_allele_frequency"),
+            info: number("info"),
+            n: number("n"),
+        }
+    }
+}
+
+/// Streams records from a tab- or whitespace-delimited summary statistics file.
+pub struct SumstatsReader {
+    lines: std::io::Lines<Box<dyn BufRead + Send>>,
+    columns: ColumnMap,
+}
+
+impl SumstatsReader {
+    /// Opens `path`, skipping `##` and `#key=value` metadata lines before
+    /// the header, as PGS Catalog scoring files and VCF-style files have.
+    pub fn open(path: &str) -> Result<Self> {
+        let mut lines = open_text(path)?.lines();
+        let header_line = loop {
+            let line = lines
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("{} is empty", path))??;
+            if !is_metadata_line(&line) {
+                break line;
+            }
+        };
+
+        Ok(Self {
+            lines,
+            columns: ColumnMap::from_header(header_line.trim_start_matches('#')),
+        })
+    }
+}
+
+fn is_metadata_line(line: &str) -> bool {
+    line.starts_with("##") || (line.starts_with('#') && line.contains('='))
+}
+
+impl Iterator for SumstatsReader {
+    type Item = Result<SumstatsRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e.into())),
+            };
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            return Some(Ok(self.columns.parse(&split_fields(&line))));
+        }
+    }
+}
+
+pub fn split_fields(line: &str) -> Vec<&str> {
+    if line.contains('\t') {
+        line.split('\t').collect()
+    } else {
+        line.split_whitespace().collect()
+    }
+}
+
+fn is_missing(value: &str) -> bool {
+    matches!(value, "" | "NA" | "na" | "NaN" | "nan" | "." | "#NA")
+}
+
+pub fn complement(allele: &str) -> String {
+    allele
+        .chars()
+        .rev()
+        .map(|base| match base {
+            'A' => 'T',
+            'T' => 'A',
+            'C' => 'G',
+            'G' => 'C',
+            other => other,
+        })
+        .collect()
+}
+
+/// A/T and C/G SNPs cannot be strand-aligned from the alleles alone.
+pub fn is_strand_ambiguous(a: &str, b: &str) -> bool {
+    a.len() == 1 && b.len() == 1 && complement(a) == b
+}
@@ -0,0 +1,577 @@
+use crate::atomic::AtomicFile;
+use crate::ids::chromosome_number;
+use crate::sumstats::{open_text, split_fields, ColumnMap, SumstatsRecord};
+use anyhow::Result;
+use flate2::read::MultiGzDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, Crc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+
+const TABIX_MAGIC: &[u8; 4] = b"TBI\x01";
+const LINEAR_SHIFT: u32 = 14;
+// Uncompressed bytes per BGZF block, as written by bgzip.
+const BGZF_BLOCK_DATA: usize = 0xff00;
+const BGZF_EOF: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 0x06, 0, 0x42, 0x43, 0x02, 0, 0x1b, 0, 0x03, 0, 0,
+    0, 0, 0, 0, 0, 0, 0,
+];
+// Largest coordinate the tabix binning scheme can address.
+pub const MAX_POSITION: i64 = 1 << 29;
+
+#[derive(Debug, Default, Clone)]
+struct ReferenceIndex {
+    bins: HashMap<u32, Vec<(u64, u64)>>,
+    linear: Vec<u64>,
+}
+
+/// A parsed `.tbi` index. Offsets are BGZF virtual offsets: the compressed
+/// block start in the upper 48 bits, the position within the block below.
+#[derive(Debug, Clone)]
+pub struct TabixIndex {
+    pub names: Vec<String>,
+    /// 1-based sequence and start columns of the indexed file.
+    pub col_seq: usize,
+    pub col_beg: usize,
+    pub meta: char,
+    references: Vec<ReferenceIndex>,
+}
+
+struct Cursor<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        let end = self.position + n;
+        if end > self.data.len() {
+            return Err(anyhow::anyhow!("Tabix index is truncated"));
+        }
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(i32::from_le_bytes(bytes))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+}
+
+impl TabixIndex {
+    pub fn open(path: &str) -> Result<Self> {
+        let mut data = Vec::new();
+        MultiGzDecoder::new(fs::File::open(path)?).read_to_end(&mut data)?;
+        Self::parse(&data)
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let mut cursor = Cursor { data, position: 0 };
+        if cursor.take(4)? != TABIX_MAGIC {
+            return Err(anyhow::anyhow!("Not a tabix index"));
+        }
+        let n_references = cursor.i32()?.max(0) as usize;
+        let _format = cursor.i32()?;
+        let col_seq = cursor.i32()?.max(1) as usize;
+        let col_beg = cursor.i32()?.max(1) as usize;
+        let _col_end = cursor.i32()?;
+        let meta = char::from(cursor.i32()? as u8);
+        let _skip = cursor.i32()?;
+        let names_length = cursor.i32()?.max(0) as usize;
+        let names = cursor
+            .take(names_length)?
+            .split(|byte| *byte == 0)
+            .filter(|name| !name.is_empty())
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+            .collect();
+
+        let mut references = Vec::with_capacity(n_references);
+        for _ in 0..n_references {
+            let mut reference = ReferenceIndex::default();
+            for _ in 0..cursor.i32()?.max(0) {
+                let bin = cursor.u32()?;
+                let n_chunks = cursor.i32()?.max(0);
+                let chunks = (0..n_chunks)
+                    .map(|_| Ok((cursor.u64()?, cursor.u64()?)))
+                    .collect::<Result<Vec<_>>>()?;
+                reference.bins.insert(bin, chunks);
+            }
+            reference.linear = (0..cursor.i32()?.max(0))
+                .map(|_| cursor.u64())
+                .collect::<Result<Vec<_>>>()?;
+            references.push(reference);
+        }
+
+        Ok(Self {
+            names,
+            col_seq,
+            col_beg,
+            meta,
+            references,
+        })
+    }
+
+    /// Index of the sequence matching `chromosome`, tolerating `chr`
+    /// prefixes and numeric X/Y/MT codes on either side.
+    pub fn reference_id(&self, chromosome: &str) -> Option<usize> {
+        self.names
+            .iter()
+            .position(|name| name == chromosome)
+            .or_else(|| {
+                let code = chromosome_number(chromosome)?;
+                self.names
+                    .iter()
+                    .position(|name| chromosome_number(name) == Some(code))
+            })
+    }
+
+    /// Virtual offsets bounding the records that may overlap the 0-based
+    /// half-open region `[start, end)`: the smallest chunk start and the
+    /// largest chunk end.
+    pub fn region_span(&self, chromosome: &str, start: i64, end: i64) -> Option<(u64, u64)> {
+        let reference = &self.references[self.reference_id(chromosome)?];
+        let start = start.max(0) as u64;
+        let min_offset = reference
+            .linear
+            .get((start >> LINEAR_SHIFT) as usize)
+            .copied()
+            .unwrap_or(0);
+
+        region_bins(start, end.clamp(1, MAX_POSITION) as u64)
+            .iter()
+            .filter_map(|bin| reference.bins.get(bin))
+            .flatten()
+            .filter(|(_, chunk_end)| *chunk_end > min_offset)
+            .fold(None, |span, (chunk_start, chunk_end)| {
+                let chunk_start = (*chunk_start).max(min_offset);
+                Some(match span {
+                    Some((first, last)) => (chunk_start.min(first), (*chunk_end).max(last)),
+                    None => (chunk_start, *chunk_end),
+                })
+            })
+    }
+}
+
+/// Bins that may hold records overlapping `[start, end)` (tabix/SAM spec).
+fn region_bins(start: u64, end: u64) -> Vec<u32> {
+    let end = end - 1;
+    let mut bins = vec![0];
+    for (offset, shift) in [(1, 26), (9, 23), (73, 20), (585, 17), (4681, 14)] {
+        bins.extend((offset + (start >> shift))..=(offset + (end >> shift)));
+    }
+    bins.into_iter().map(|bin| bin as u32).collect()
+}
+
+/// Opens a BGZF file positioned at a virtual offset.
+pub fn open_at(path: &str, virtual_offset: u64) -> Result<Box<dyn BufRead + Send>> {
+    let mut file = fs::File::open(path)?;
+    file.seek(SeekFrom::Start(virtual_offset >> 16))?;
+    let mut decoder = MultiGzDecoder::new(BufReader::new(file));
+    io::copy(
+        &mut (&mut decoder).take(virtual_offset & 0xffff),
+        &mut io::sink(),
+    )?;
+    Ok(Box::new(BufReader::new(decoder)))
+}
+
+/// A bgzipped summary statistics file with its `.tbi` index.
+#[derive(Debug, Clone)]
+pub struct TabixFile {
+    pub path: String,
+    pub index: TabixIndex,
+    columns: ColumnMap,
+}
+
+impl TabixFile {
+    pub fn open(path: &str) -> Result<Self> {
+        let index = TabixIndex::open(&format!("{path}.tbi"))?;
+        let header = open_text(path)?
+            .lines()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("{} is empty", path))??;
+        Ok(Self {
+            path: path.to_string(),
+            index,
+            columns: ColumnMap::from_header(header.trim_start_matches('#')),
+        })
+    }
+
+    /// Records on `chromosome` between 1-based positions `start` and `end`
+    /// inclusive, read from the first indexed block that can overlap.
+    pub fn query(&self, chromosome: &str, start: i64, end: i64) -> Result<TabixQuery> {
+        let reader = match self.index.region_span(chromosome, start - 1, end) {
+            Some((first, _)) => Some(open_at(&self.path, first)?),
+            None => None,
+        };
+        Ok(TabixQuery::new(
+            reader,
+            &self.index,
+            self.columns.clone(),
+            chromosome,
+            start,
+            end,
+        ))
+    }
+}
+
+/// Iterator over the records of a region, stopping at the first record
+/// past its end (files are sorted by sequence and position).
+pub struct TabixQuery {
+    lines: Option<io::Lines<Box<dyn BufRead + Send>>>,
+    columns: ColumnMap,
+    col_seq: usize,
+    col_beg: usize,
+    meta: char,
+    chromosome: String,
+    start: i64,
+    end: i64,
+}
+
+impl TabixQuery {
+    /// A query over `reader`, which must be positioned at the start of the
+    /// region's first record or earlier; `None` yields no records.
+    pub fn new(
+        reader: Option<Box<dyn BufRead + Send>>,
+        index: &TabixIndex,
+        columns: ColumnMap,
+        chromosome: &str,
+        start: i64,
+        end: i64,
+    ) -> Self {
+        Self {
+            lines: reader.map(BufRead::lines),
+            columns,
+            col_seq: index.col_seq - 1,
+            col_beg: index.col_beg - 1,
+            meta: index.meta,
+            chromosome: chromosome.to_string(),
+            start,
+            end,
+        }
+    }
+}
+
+impl Iterator for TabixQuery {
+    type Item = Result<SumstatsRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.as_mut()?.next()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e.into())),
+            };
+            if line.trim().is_empty() || line.starts_with(self.meta) {
+                continue;
+            }
+
+            let fields = split_fields(&line);
+            let position = fields.get(self.col_beg).and_then(|p| p.parse::<i64>().ok());
+            let on_chromosome = fields
+                .get(self.col_seq)
+                .map_or(false, |c| same_chromosome(c, &self.chromosome));
+            match position {
+                Some(p) if on_chromosome && p <= self.end => {
+                    if p >= self.start {
+                        return Some(Ok(self.columns.parse(&fields)));
+                    }
+                }
+                Some(_) => {
+                    self.lines = None;
+                    return None;
+                }
+                None => continue,
+            }
+        }
+    }
+}
+
+fn same_chromosome(chromosome: &str, target: &str) -> bool {
+    chromosome == target
+        || chromosome_number(chromosome)
+            .map_or(false, |code| chromosome_number(target) == Some(code))
+}
+
+/// Writes BGZF: gzip members of at most 64 KiB, each recording its own
+/// compressed size, so readers can seek to any block.
+pub struct BgzfWriter<W: Write> {
+    inner: W,
+    buffer: Vec<u8>,
+    compressed_offset: u64,
+}
+
+impl<W: Write> BgzfWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            buffer: Vec::with_capacity(BGZF_BLOCK_DATA),
+            compressed_offset: 0,
+        }
+    }
+
+    /// Virtual offset of the next byte written.
+    pub fn virtual_offset(&self) -> u64 {
+        (self.compressed_offset << 16) | self.buffer.len() as u64
+    }
+
+    pub fn write_all(&mut self, mut data: &[u8]) -> Result<()> {
+        while !data.is_empty() {
+            let n = data.len().min(BGZF_BLOCK_DATA - self.buffer.len());
+            self.buffer.extend_from_slice(&data[..n]);
+            data = &data[n..];
+            if self.buffer.len() == BGZF_BLOCK_DATA {
+                self.flush_block()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn flush_block(&mut self) -> Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&self.buffer)?;
+        let compressed = encoder.finish()?;
+        let mut crc = Crc::new();
+        crc.update(&self.buffer);
+
+        let block_size = compressed.len() + 26;
+        let mut header = [
+            0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 0x06, 0, 0x42, 0x43, 0x02, 0, 0, 0,
+        ];
+        header[16..18].copy_from_slice(&((block_size - 1) as u16).to_le_bytes());
+        self.inner.write_all(&header)?;
+        self.inner.write_all(&compressed)?;
+        self.inner.write_all(&crc.sum().to_le_bytes())?;
+        self.inner
+            .write_all(&(self.buffer.len() as u32).to_le_bytes())?;
+
+        self.compressed_offset += block_size as u64;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    /// Flushes the last block and appends the BGZF end-of-file marker.
+    pub fn finish(mut self) -> Result<W> {
+        self.flush_block()?;
+        self.inner.write_all(&BGZF_EOF)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+/// Bin of the 0-based half-open interval `[start, end)` (tabix/SAM spec).
+fn region_to_bin(start: u64, end: u64) -> u32 {
+    let end = end - 1;
+    for (offset, shift) in [(4681, 14), (585, 17), (73, 20), (9, 23), (1, 26)] {
+        if start >> shift == end >> shift {
+            return (offset + (start >> shift)) as u32;
+        }
+    }
+    0
+}
+
+impl ReferenceIndex {
+    fn add(&mut self, start: u64, end: u64, chunk_start: u64, chunk_end: u64) {
+        let chunks = self.bins.entry(region_to_bin(start, end)).or_default();
+        match chunks.last_mut() {
+            Some(last) if last.1 == chunk_start => last.1 = chunk_end,
+            _ => chunks.push((chunk_start, chunk_end)),
+        }
+
+        let last_window = ((end - 1) >> LINEAR_SHIFT) as usize;
+        if self.linear.len() <= last_window {
+            self.linear.resize(last_window + 1, u64::MAX);
+        }
+        for window in (start >> LINEAR_SHIFT) as usize..=last_window {
+            self.linear[window] = self.linear[window].min(chunk_start);
+        }
+    }
+
+    /// Windows without records point at the closest earlier window.
+    fn fill_linear(&mut self) {
+        let mut previous = 0;
+        for offset in &mut self.linear {
+            if *offset == u64::MAX {
+                *offset = previous;
+            }
+            previous = *offset;
+        }
+    }
+}
+
+impl TabixIndex {
+    /// Writes the index to a partial file for the caller to commit.
+    fn write(&self, path: &str) -> Result<AtomicFile> {
+        let mut data = Vec::new();
+        data.extend_from_slice(TABIX_MAGIC);
+        let names: Vec<u8> = self
+            .names
+            .iter()
+            .flat_map(|name| name.bytes().chain([0]))
+            .collect();
+        for value in [
+            self.references.len() as i32,
+            0,
+            self.col_seq as i32,
+            self.col_beg as i32,
+            self.col_beg as i32,
+            self.meta as i32,
+            1,
+            names.len() as i32,
+        ] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&names);
+
+        for reference in &self.references {
+            let mut bins: Vec<_> = reference.bins.iter().collect();
+            bins.sort_by_key(|(bin, _)| **bin);
+            data.extend_from_slice(&(bins.len() as i32).to_le_bytes());
+            for (bin, chunks) in bins {
+                data.extend_from_slice(&bin.to_le_bytes());
+                data.extend_from_slice(&(chunks.len() as i32).to_le_bytes());
+                for (chunk_start, chunk_end) in chunks {
+                    data.extend_from_slice(&chunk_start.to_le_bytes());
+                    data.extend_from_slice(&chunk_end.to_le_bytes());
+                }
+            }
+            data.extend_from_slice(&(reference.linear.len() as i32).to_le_bytes());
+            for offset in &reference.linear {
+                data.extend_from_slice(&offset.to_le_bytes());
+            }
+        }
+
+        let mut writer = BgzfWriter::new(BufWriter::new(AtomicFile::create(path)?));
+        writer.write_all(&data)?;
+        Ok(AtomicFile::from_buffered(writer.finish()?)?)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IndexReport {
+    pub path: String,
+    pub index: String,
+    pub n_records: usize,
+    pub chromosomes: Vec<String>,
+}
+
+/// Compresses a summary statistics file sorted by chromosome and position
+/// to BGZF at `output` and writes its tabix index to `<output>.tbi`. The
+/// header line is kept as the first line; `output` may be the input path.
+pub fn build_index(path: &str, output: &str) -> Result<IndexReport> {
+    let mut lines = open_text(path)?.lines();
+    let header = lines
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("{} is empty", path))??;
+    let columns = ColumnMap::from_header(header.trim_start_matches('#'));
+    let (col_seq, col_beg) = match (columns.column("chromosome"), columns.column("position")) {
+        (Some(col_seq), Some(col_beg)) => (col_seq, col_beg),
+        _ => {
+            return Err(anyhow::anyhow!(
+                "{} has no chromosome and position columns",
+                path
+            ))
+        }
+    };
+
+    let mut writer = BgzfWriter::new(BufWriter::new(AtomicFile::create(output)?));
+    writer.write_all(header.as_bytes())?;
+    writer.write_all(b"\n")?;
+
+    let mut names: Vec<String> = Vec::new();
+    let mut references: Vec<ReferenceIndex> = Vec::new();
+    let mut seen = HashSet::new();
+    let mut last_position = 0;
+    let mut n_records = 0;
+
+    for (line_number, line) in lines.enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields = split_fields(&line);
+        let (chromosome, position) = match (
+            fields.get(col_seq),
+            fields.get(col_beg).and_then(|p| p.parse::<u64>().ok()),
+        ) {
+            (Some(chromosome), Some(position)) if position > 0 => (*chromosome, position),
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Line {} has no valid chromosome and position",
+                    line_number + 2
+                ))
+            }
+        };
+
+        if names.last().map(String::as_str) != Some(chromosome) {
+            if !seen.insert(chromosome.to_string()) {
+                return Err(anyhow::anyhow!(
+                    "{} is not sorted by chromosome: {} appears in more than one block",
+                    path,
+                    chromosome
+                ));
+            }
+            names.push(chromosome.to_string());
+            references.push(ReferenceIndex::default());
+            last_position = 0;
+        }
+        if position < last_position {
+            return Err(anyhow::anyhow!(
+                "{} is not sorted by position on chromosome {}",
+                path,
+                chromosome
+            ));
+        }
+        last_position = position;
+
+        let chunk_start = writer.virtual_offset();
+        writer.write_all(line.as_bytes())?;
+        writer.write_all(b"\n")?;
+        let chunk_end = writer.virtual_offset();
+        if let Some(reference) = references.last_mut() {
+            reference.add(position - 1, position, chunk_start, chunk_end);
+        }
+        n_records += 1;
+    }
+    let data = AtomicFile::from_buffered(writer.finish()?)?;
+
+    for reference in &mut references {
+        reference.fill_linear();
+    }
+    let index = TabixIndex {
+        names: names.clone(),
+        col_seq: col_seq + 1,
+        col_beg: col_beg + 1,
+        meta: '#',
+        references,
+    };
+    let index_path = format!("{output}.tbi");
+    // Only replace the file and its index once both are complete.
+    let index_file = index.write(&index_path)?;
+    data.commit()?;
+    index_file.commit()?;
+
+    Ok(IndexReport {
+        path: output.to_string(),
+        index: index_path,
+        n_records,
+        chromosomes: names,
+    })
+}
@@ -0,0 +1,152 @@
+use crate::download::{manifest_dir, metadata_value};
+use crate::GwasClient;
+use anyhow::Result;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::fs;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Append-only record of accepted usage terms, kept next to `manifest.json`.
+pub const AUDIT_FILE: &str = "audit.jsonl";
+
+/// Usage terms published for a file in its GWAS-SSF metadata.
+#[derive(Debug, Serialize, Clone)]
+pub struct FileTerms {
+    pub url: String,
+    pub path: String,
+    pub license: Option<String>,
+    pub terms_of_use: Option<String>,
+    /// Whether downloading needs `accept_terms = TRUE`: the file has terms
+    /// of use, or a license that is not an open one.
+    pub restricted: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct AuditEntry<'a> {
+    /// Seconds since the Unix epoch.
+    time: u64,
+    event: &'static str,
+    url: &'a str,
+    path: &'a str,
+    license: Option<&'a str>,
+    terms_of_use: Option<&'a str>,
+}
+
+/// CC0 and CC-BY licenses without NonCommercial or NoDerivatives clauses.
+fn is_open_license(license: &str) -> bool {
+    let normalized: String = license
+        .to_ascii_uppercase()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect();
+    normalized.starts_with("CC0")
+        || normalized == "PUBLICDOMAIN"
+        || (normalized.starts_with("CCBY")
+            && !normalized.contains("NC")
+            && !normalized.contains("ND"))
+}
+
+impl GwasClient {
+    /// Reads the license and terms of use published for a file. Files
+    /// without metadata have no known terms and are not restricted.
+    pub fn file_terms(&self, url: &str, path: &str) -> FileTerms {
+        let metadata = self.published_metadata(url).unwrap_or_default();
+        let license = metadata_value(&metadata, "license")
+            .or_else(|| metadata_value(&metadata, "data_license"));
+        let terms_of_use = metadata_value(&metadata, "terms_of_use");
+        let restricted = terms_of_use.is_some()
+            || license
+                .as_deref()
+                .map_or(false, |license| !is_open_license(license));
+        FileTerms {
+            url: url.to_string(),
+            path: path.to_string(),
+            license,
+            terms_of_use,
+            restricted,
+        }
+    }
+
+    /// Terms of each file on `max_concurrent` threads, in the order of `urls`.
+    pub fn files_terms(
+        &self,
+        urls: &[String],
+        paths: &[String],
+        max_concurrent: usize,
+    ) -> Result<Vec<FileTerms>> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(max_concurrent)
+            .build()?;
+        Ok(pool.install(|| {
+            urls.par_iter()
+                .zip(paths.par_iter())
+                .map(|(url, path)| self.file_terms(url, path))
+                .collect()
+        }))
+    }
+}
+
+/// Appends one `terms_accepted` line per file to `audit.jsonl` in the
+/// directory holding the files, and returns its path.
+pub fn record_acceptance(terms: &[&FileTerms]) -> Result<String> {
+    let paths: Vec<String> = terms.iter().map(|t| t.path.clone()).collect();
+    let dir = manifest_dir(&paths);
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(AUDIT_FILE);
+    let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+    let mut lines = String::new();
+    for file in terms {
+        let entry = AuditEntry {
+            time,
+            event: "terms_accepted",
+            url: &file.url,
+            path: &file.path,
+            license: file.license.as_deref(),
+            terms_of_use: file.terms_of_use.as_deref(),
+        };
+        lines.push_str(&serde_json::to_string(&entry)?);
+        lines.push('\n');
+    }
+    // One write, so concurrent sessions do not interleave partial lines.
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)?
+        .write_all(lines.as_bytes())?;
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// Checks that restricted files may be downloaded: fails listing their
+/// terms unless `accept_terms`, and otherwise records the acceptance.
+pub fn require_acceptance(terms: &[FileTerms], accept_terms: bool) -> Result<()> {
+    let restricted: Vec<&FileTerms> = terms.iter().filter(|t| t.restricted).collect();
+    if restricted.is_empty() {
+        return Ok(());
+    }
+    if !accept_terms {
+        return Err(anyhow::anyhow!("{}", terms_not_accepted(&restricted)));
+    }
+    record_acceptance(&restricted)?;
+    Ok(())
+}
+
+/// Error for downloads that include restricted files without
+/// `accept_terms = TRUE`, listing each file's terms.
+pub fn terms_not_accepted(restricted: &[&FileTerms]) -> String {
+    let mut message = format!(
+        "{} file(s) have usage restrictions. Review them with gwas_files(\"terms\", ...) and pass accept_terms = TRUE to download:",
+        restricted.len()
+    );
+    for file in restricted {
+        message.push_str(&format!("\n- {}", file.url));
+        if let Some(license) = &file.license {
+            message.push_str(&format!("\n  license: {license}"));
+        }
+        if let Some(terms) = &file.terms_of_use {
+            message.push_str(&format!("\n  terms of use: {terms}"));
+        }
+    }
+    message
+}
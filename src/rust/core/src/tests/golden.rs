@@ -0,0 +1,113 @@
+//! Golden-file tests for the file writers. Each test writes a small fixture
+//! through an exporter and compares the result byte for byte with a file in
+//! `tests/fixtures/golden/`. Gzip and BGZF outputs are compared after
+//! decompression, so a change of deflate implementation is not a regression.
+//!
+//! After an intended format change, regenerate the golden files with
+//! `IANI_UPDATE_GOLDEN=1 cargo test golden` and review the diff.
+
+use crate::munge::{munge, MungeSettings};
+use crate::sumstats::open_text;
+use crate::tabix::build_index;
+use flate2::read::MultiGzDecoder;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+const UPDATE_VAR: &str = "IANI_UPDATE_GOLDEN";
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/golden")
+}
+
+fn input() -> String {
+    golden_dir()
+        .join("sumstats.tsv")
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("iani-golden-{name}-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn gunzip(path: &Path) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    MultiGzDecoder::new(fs::File::open(path).unwrap())
+        .read_to_end(&mut bytes)
+        .unwrap();
+    bytes
+}
+
+/// Compares `actual` with the golden file `name`, or rewrites the golden
+/// file when `IANI_UPDATE_GOLDEN` is set.
+fn assert_golden(name: &str, actual: &[u8]) {
+    let path = golden_dir().join(name);
+    if std::env::var_os(UPDATE_VAR).is_some() {
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = fs::read(&path).unwrap_or_else(|e| {
+        panic!(
+            "Cannot read golden file {}: {e}; run with {UPDATE_VAR}=1 to create it",
+            path.display()
+        )
+    });
+    if expected == actual {
+        return;
+    }
+
+    let offset = expected
+        .iter()
+        .zip(actual)
+        .position(|(a, b)| a != b)
+        .unwrap_or_else(|| expected.len().min(actual.len()));
+    let line = expected[..offset].iter().filter(|&&b| b == b'\n').count() + 1;
+    let line_of = |bytes: &[u8]| {
+        String::from_utf8_lossy(bytes)
+            .lines()
+            .nth(line - 1)
+            .unwrap_or("<end of file>")
+            .to_string()
+    };
+    panic!(
+        "{name} differs from the golden file at byte {offset} (line {line}):\n  \
+         expected: {}\n  actual:   {}\n\
+         If the change is intended, rerun with {UPDATE_VAR}=1 and review the diff.",
+        line_of(&expected),
+        line_of(actual)
+    );
+}
+
+#[test]
+fn ldsc_sumstats_match_golden() {
+    let dir = scratch_dir("ldsc");
+    let out = dir.join("trait").to_string_lossy().into_owned();
+    let settings = MungeSettings {
+        n: Some(50_000.0),
+        ..Default::default()
+    };
+    let report = munge(&input(), &out, &settings).unwrap();
+
+    assert_golden("ldsc.sumstats", &gunzip(Path::new(&report.output)));
+    let mut report = serde_json::to_value(&report).unwrap();
+    report["output"] = "trait.sumstats.gz".into();
+    let report = serde_json::to_string_pretty(&report).unwrap() + "\n";
+    assert_golden("ldsc_report.json", report.as_bytes());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn tabix_index_matches_golden() {
+    let dir = scratch_dir("tabix");
+    let output = dir.join("sumstats.tsv.gz").to_string_lossy().into_owned();
+    let report = build_index(&input(), &output).unwrap();
+
+    let mut text = Vec::new();
+    open_text(&output).unwrap().read_to_end(&mut text).unwrap();
+    assert_eq!(text, fs::read(input()).unwrap());
+    assert_golden("sumstats.tsv.gz.tbi.raw", &gunzip(Path::new(&report.index)));
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,268 @@
+//! Tests of every typed `GwasClient` method through a `MockTransport`,
+//! with no server or network at all.
+
+use crate::ordered::OrderedMap;
+use crate::pgs::PgsClient;
+use crate::transport::{MockResponse, MockTransport};
+use crate::{Association, GwasClient, GwasFilter, HalResponse};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
+fn mock_client(transport: &Arc<MockTransport>) -> GwasClient {
+    GwasClient::builder()
+        .base_url("http://mock/api".to_string())
+        .transport(transport.clone())
+        .build()
+        .unwrap()
+}
+
+fn fixture(name: &str) -> MockResponse {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    MockResponse::fixture(path.join(name)).unwrap()
+}
+
+fn json(body: &str) -> Vec<MockResponse> {
+    vec![MockResponse::json(body)]
+}
+
+fn page(start: usize) -> BTreeMap<String, String> {
+    BTreeMap::from([
+        ("start".to_string(), start.to_string()),
+        ("size".to_string(), "20".to_string()),
+    ])
+}
+
+const STUDY: &str = r#"{"study_accession": "GCST000123"}"#;
+const TRAIT: &str = r#"{"trait": "EFO_0001360"}"#;
+const FILES: &str = r#"{
+  "_embedded": {
+    "summary_statistics": [
+      {"study_accession": "GCST000123", "file_path": "GCST000123.tsv.gz"}
+    ]
+  }
+}"#;
+
+#[test]
+fn every_typed_method_parses_its_recorded_response() {
+    let transport = Arc::new(MockTransport::new());
+    transport
+        .route_path(
+            "/api/associations",
+            vec![fixture("study_associations.json")],
+        )
+        .route_path(
+            "/api/associations/rs7412",
+            vec![fixture("study_associations.json")],
+        )
+        .route(
+            "/api/chromosomes",
+            json(r#"{"_embedded": {"chromosomes": [{"chromosome": "1"}, {"chromosome": "2"}]}}"#),
+        )
+        .route("/api/chromosomes/1", json(r#"{"chromosome": "1"}"#))
+        .route_path(
+            "/api/chromosomes/1/associations",
+            vec![fixture("chromosome_associations.json")],
+        )
+        .route_path(
+            "/api/chromosomes/1/associations/rs7412",
+            vec![fixture("chromosome_associations.json")],
+        )
+        .route_path(
+            "/api/studies",
+            json(&format!(r#"{{"_embedded": {{"studies": [[{STUDY}]]}}}}"#)),
+        )
+        .route("/api/studies/GCST000123", json(STUDY))
+        .route_path(
+            "/api/studies/GCST000123/associations",
+            vec![fixture("study_associations.json")],
+        )
+        .route_path(
+            "/api/traits",
+            json(&format!(r#"{{"_embedded": {{"trait": [{TRAIT}]}}}}"#)),
+        )
+        .route("/api/traits/EFO_0001360", json(TRAIT))
+        .route_path(
+            "/api/traits/EFO_0001360/associations",
+            vec![fixture("study_associations.json")],
+        )
+        .route_path(
+            "/api/traits/EFO_0001360/studies",
+            json(&format!(r#"{{"_embedded": {{"studies": [{STUDY}]}}}}"#)),
+        )
+        .route("/api/traits/EFO_0001360/studies/GCST000123", json(STUDY))
+        .route_path(
+            "/api/traits/EFO_0001360/studies/GCST000123/associations",
+            vec![fixture("study_associations.json")],
+        )
+        .route("/api/studies/GCST000123/summary-statistics", json(FILES))
+        .route("/api/traits/EFO_0001360/summary-statistics", json(FILES))
+        .route(
+            "/api/traits/EFO_0001360/studies/GCST000123/summary-statistics",
+            json(FILES),
+        );
+    let client = mock_client(&transport);
+
+    let rows = |mut data: HalResponse<OrderedMap<Association>>| data.associations_mut().count();
+    assert_eq!(rows(client.get_associations(page(0)).unwrap()), 100);
+    assert_eq!(
+        rows(client.get_variant_associations("rs7412", page(0)).unwrap()),
+        100
+    );
+    assert!(rows(client.get_chromosome_associations("1", page(0)).unwrap()) > 0);
+    assert!(
+        rows(
+            client
+                .get_chromosome_variant_associations("1", "rs7412", page(0))
+                .unwrap()
+        ) > 0
+    );
+    assert_eq!(
+        rows(
+            client
+                .get_study_associations("GCST000123", page(0))
+                .unwrap()
+        ),
+        100
+    );
+    assert_eq!(
+        rows(
+            client
+                .get_trait_associations("EFO_0001360", page(0))
+                .unwrap()
+        ),
+        100
+    );
+    assert_eq!(
+        rows(
+            client
+                .get_trait_study_associations("EFO_0001360", "GCST000123", page(0))
+                .unwrap()
+        ),
+        100
+    );
+
+    let chromosomes = client.get_chromosomes().unwrap().embedded.unwrap();
+    assert_eq!(chromosomes["chromosomes"].len(), 2);
+    assert_eq!(client.get_chromosome("1").unwrap().chromosome, "1");
+    let studies = client.get_studies(page(0)).unwrap().embedded.unwrap();
+    assert_eq!(studies["studies"][0][0].study_accession, "GCST000123");
+    assert_eq!(
+        client.get_study("GCST000123").unwrap().study_accession,
+        "GCST000123"
+    );
+    let traits = client.get_traits(page(0)).unwrap().embedded.unwrap();
+    assert_eq!(traits["trait"][0].trait_name, "EFO_0001360");
+    assert_eq!(
+        client.get_trait("EFO_0001360").unwrap().trait_name,
+        "EFO_0001360"
+    );
+    let trait_studies = client
+        .get_trait_studies("EFO_0001360", page(0))
+        .unwrap()
+        .embedded
+        .unwrap();
+    assert_eq!(trait_studies["studies"].len(), 1);
+    assert_eq!(
+        client
+            .get_trait_study("EFO_0001360", "GCST000123")
+            .unwrap()
+            .study_accession,
+        "GCST000123"
+    );
+    for files in [
+        client.get_study_summary_stats_files("GCST000123"),
+        client.get_trait_summary_stats_files("EFO_0001360"),
+        client.get_trait_study_summary_stats_files("EFO_0001360", "GCST000123"),
+    ] {
+        let files = files.unwrap().embedded.unwrap();
+        assert_eq!(
+            files["summary_statistics"][0].file_path,
+            "GCST000123.tsv.gz"
+        );
+    }
+
+    // Every request was answered by its own route, with the query intact.
+    let requests = transport.requests();
+    assert_eq!(requests.len(), 18);
+    assert_eq!(requests[0], "/api/associations?size=20&start=0");
+    assert!(requests.iter().all(|request| request.starts_with("/api/")));
+}
+
+#[test]
+fn mock_errors_surface_like_server_errors() {
+    let transport = Arc::new(MockTransport::new());
+    transport
+        .route(
+            "/api/studies/GCST1",
+            vec![
+                MockResponse::status(503, r#"{"message": "Maintenance"}"#),
+                MockResponse::json(r#"{"study_accession": "GCST1"}"#),
+            ],
+        )
+        .route(
+            "/api/traits/EFO_1",
+            vec![MockResponse::json("<html></html>").with_content_type("text/html")],
+        );
+    let client = mock_client(&transport);
+
+    let error = client.get_study("GCST1").unwrap_err().to_string();
+    assert!(
+        error.contains("503") && error.contains("Maintenance"),
+        "{error}"
+    );
+    // Responses are served in turn and the last one repeats.
+    assert_eq!(client.get_study("GCST1").unwrap().study_accession, "GCST1");
+    assert_eq!(client.get_study("GCST1").unwrap().study_accession, "GCST1");
+
+    let error = client.get_trait("EFO_1").unwrap_err().to_string();
+    assert!(
+        error.contains("Expected JSON response, got: text/html"),
+        "{error}"
+    );
+    let error = client.get_chromosome("X").unwrap_err().to_string();
+    assert!(
+        error.contains("404") && error.contains("No route"),
+        "{error}"
+    );
+}
+
+#[test]
+fn pgs_scores_parse_their_recorded_metadata() {
+    let transport = Arc::new(MockTransport::new());
+    transport
+        .route("/rest/score/PGS000001", vec![fixture("pgs_score.json")])
+        .route_path("/rest/score/search", vec![fixture("pgs_score_search.json")]);
+    let pgs = PgsClient::with_client(
+        GwasClient::builder()
+            .base_url("http://mock/rest".to_string())
+            .transport(transport.clone())
+            .build()
+            .unwrap(),
+    );
+
+    let score = pgs.get_score("PGS000001").unwrap();
+    assert_eq!(score.name.as_deref(), Some("PRS77_BC"));
+    assert_eq!(score.variants_number, Some(77));
+    assert_eq!(score.trait_efo[0].id, "EFO_0000305");
+    let publication = score.publication.unwrap();
+    assert_eq!(publication.doi.as_deref(), Some("10.1093/jnci/djv036"));
+    assert_eq!(publication.pmid, Some(serde_json::json!(25855707)));
+
+    let filter = GwasFilter {
+        start: Some(0),
+        size: Some(2),
+        ..Default::default()
+    };
+    let page = pgs.search_scores("EFO_0000305", &filter).unwrap();
+    assert_eq!((page.count, page.results.len()), (Some(3), 2));
+    assert!(page.next.is_some());
+    assert!(page.results[1].publication.is_none());
+    assert_eq!(
+        transport.requests(),
+        vec![
+            "/rest/score/PGS000001?",
+            "/rest/score/search?limit=2&offset=0&trait_id=EFO_0000305",
+        ]
+    );
+}
@@ -0,0 +1,239 @@
+//! A minimal HTTP/1.1 server for exercising the clients against canned
+//! responses without network access.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct StubResponse {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: Vec<u8>,
+    pub delay: Duration,
+}
+
+impl StubResponse {
+    pub fn json(body: &str) -> Self {
+        Self {
+            status: 200,
+            content_type: "application/json",
+            headers: Vec::new(),
+            body: body.as_bytes().to_vec(),
+            delay: Duration::ZERO,
+        }
+    }
+
+    /// A binary body, e.g. a compressed file.
+    pub fn bytes(body: &[u8]) -> Self {
+        Self {
+            content_type: "application/octet-stream",
+            body: body.to_vec(),
+            ..Self::json("")
+        }
+    }
+
+    pub fn status(status: u16, body: &str) -> Self {
+        Self {
+            status,
+            ..Self::json(body)
+        }
+    }
+
+    pub fn with_header(mut self, name: &'static str, value: &str) -> Self {
+        self.headers.push((name, value.to_string()));
+        self
+    }
+
+    /// Waits before answering, to simulate a slow server.
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    pub fn with_content_type(mut self, content_type: &'static str) -> Self {
+        self.content_type = content_type;
+        self
+    }
+}
+
+/// Routes are keyed by method, path and query parameters, so matching does
+/// not depend on the order in which a client appends parameters. Each route
+/// answers with its responses in turn and repeats the last one.
+type Routes = HashMap<String, VecDeque<StubResponse>>;
+
+/// Route key, headers and body of each request received.
+type Requests = Vec<(String, Vec<(String, String)>, Vec<u8>)>;
+
+pub struct StubServer {
+    pub base_url: String,
+    routes: Arc<Mutex<Routes>>,
+    requests: Arc<Mutex<Requests>>,
+}
+
+fn route_key(method: &str, target: &str) -> String {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let params: BTreeMap<String, String> = url::form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .collect();
+    let query: Vec<String> = params.iter().map(|(k, v)| format!("{k}={v}")).collect();
+    format!("{method} {path}?{}", query.join("&"))
+}
+
+impl StubServer {
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind stub server");
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let routes: Arc<Mutex<Routes>> = Arc::default();
+        let requests: Arc<Mutex<Requests>> = Arc::default();
+
+        let (thread_routes, thread_requests) = (routes.clone(), requests.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let (routes, requests) = (thread_routes.clone(), thread_requests.clone());
+                thread::spawn(move || handle(stream, &routes, &requests));
+            }
+        });
+
+        Self {
+            base_url,
+            routes,
+            requests,
+        }
+    }
+
+    /// Serves `responses` in turn for `target` (a path with optional query).
+    pub fn route(&self, method: &str, target: &str, responses: Vec<StubResponse>) -> &Self {
+        self.routes
+            .lock()
+            .unwrap()
+            .insert(route_key(method, target), responses.into());
+        self
+    }
+
+    /// Serves `responses` for `path` whatever the query, unless a route
+    /// for the exact query exists.
+    pub fn route_path(&self, method: &str, path: &str, responses: Vec<StubResponse>) -> &Self {
+        self.routes
+            .lock()
+            .unwrap()
+            .insert(format!("{method} {path}?*"), responses.into());
+        self
+    }
+
+    /// Route keys of the requests received so far.
+    pub fn requests(&self) -> Vec<String> {
+        let requests = self.requests.lock().unwrap();
+        requests.iter().map(|(key, _, _)| key.clone()).collect()
+    }
+
+    /// Bodies of the requests received so far, as text.
+    pub fn bodies(&self) -> Vec<String> {
+        let requests = self.requests.lock().unwrap();
+        requests
+            .iter()
+            .map(|(_, _, body)| String::from_utf8_lossy(body).into_owned())
+            .collect()
+    }
+
+    /// Values of header `name` in the requests received so far, `None`
+    /// for requests without it.
+    pub fn header_values(&self, name: &str) -> Vec<Option<String>> {
+        let requests = self.requests.lock().unwrap();
+        requests
+            .iter()
+            .map(|(_, headers, _)| {
+                headers
+                    .iter()
+                    .find(|(header, _)| header.eq_ignore_ascii_case(name))
+                    .map(|(_, value)| value.clone())
+            })
+            .collect()
+    }
+}
+
+fn handle(stream: TcpStream, routes: &Mutex<Routes>, requests: &Mutex<Requests>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() {
+        return;
+    }
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target.to_string()),
+        _ => return,
+    };
+
+    let mut content_length = 0;
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+            headers.push((name.to_string(), value.trim().to_string()));
+        }
+    }
+    let mut body = vec![0; content_length];
+    let _ = reader.read_exact(&mut body);
+
+    let key = route_key(&method, &target);
+    requests.lock().unwrap().push((key.clone(), headers, body));
+    let response = {
+        let mut routes = routes.lock().unwrap();
+        let path = target.split('?').next().unwrap_or("");
+        let any_query = format!("{method} {path}?*");
+        let key = if routes.contains_key(&key) {
+            key
+        } else {
+            any_query
+        };
+        match routes.get_mut(&key) {
+            Some(queue) if queue.len() > 1 => queue.pop_front().unwrap(),
+            Some(queue) => queue.front().cloned().unwrap(),
+            None => StubResponse::status(404, &format!("{{\"message\": \"No route for {key}\"}}")),
+        }
+    };
+
+    thread::sleep(response.delay);
+    let mut head = format!(
+        "HTTP/1.1 {} Stub\r\nContent-Type: {}\r\nConnection: close\r\n",
+        response.status, response.content_type
+    );
+    // A Content-Length header overrides the body's, e.g. for HEAD answers
+    // about files too large to serve. A Transfer-Encoding header sends the
+    // body as one chunk, with no length up front.
+    let has_header = |header: &str| {
+        response
+            .headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case(header))
+    };
+    let chunked = has_header("transfer-encoding");
+    if !has_header("content-length") && !chunked {
+        head.push_str(&format!("Content-Length: {}\r\n", response.body.len()));
+    }
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str("\r\n");
+
+    let mut stream = stream;
+    let _ = stream.write_all(head.as_bytes());
+    if chunked {
+        let _ = write!(stream, "{:x}\r\n", response.body.len());
+        let _ = stream.write_all(&response.body);
+        let _ = stream.write_all(b"\r\n0\r\n\r\n");
+    } else {
+        let _ = stream.write_all(&response.body);
+    }
+    let _ = stream.flush();
+}
@@ -0,0 +1,183 @@
+use crate::interrupt::{Interrupt, Interrupted};
+use crate::stats;
+use std::io::{self, Read};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Smallest burst allowed, so slow limits still read in useful chunks.
+const MIN_BURST: f64 = 16.0 * 1024.0;
+
+/// A token bucket limiting the combined rate of every reader that shares
+/// it, such as the parallel downloads of one `gwas_files()` call. Readers
+/// take tokens after each read and sleep off any deficit, so the long-run
+/// rate stays at the limit however many threads are reading.
+#[derive(Debug)]
+pub struct TokenBucket {
+    bytes_per_second: f64,
+    burst: f64,
+    state: Mutex<BucketState>,
+}
+
+#[derive(Debug)]
+struct BucketState {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    /// A bucket refilling at `bytes_per_second` and holding a quarter of a
+    /// second's worth of tokens.
+    pub fn new(bytes_per_second: f64) -> Self {
+        let burst = (bytes_per_second / 4.0).max(MIN_BURST);
+        Self {
+            bytes_per_second,
+            burst,
+            state: Mutex::new(BucketState {
+                tokens: burst,
+                updated: Instant::now(),
+            }),
+        }
+    }
+
+    /// Largest read worth making at once.
+    pub fn burst(&self) -> usize {
+        self.burst as usize
+    }
+
+    /// Takes `bytes` tokens, blocking until the bucket has refilled enough
+    /// to cover them or `interrupt` is requested.
+    pub fn take(&self, bytes: usize, interrupt: &Interrupt) -> Result<(), Interrupted> {
+        let wait = {
+            let mut state = match self.state.lock() {
+                Ok(state) => state,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            let now = Instant::now();
+            let refill = now.duration_since(state.updated).as_secs_f64() * self.bytes_per_second;
+            state.tokens = (state.tokens + refill).min(self.burst) - bytes as f64;
+            state.updated = now;
+            if state.tokens < 0.0 {
+                Duration::from_secs_f64(-state.tokens / self.bytes_per_second)
+            } else {
+                Duration::ZERO
+            }
+        };
+        if wait.is_zero() {
+            return Ok(());
+        }
+        stats::rate_limit_sleep(wait);
+        interrupt.sleep(wait)
+    }
+}
+
+/// Reads from `inner` no faster than `bucket` allows, or unthrottled
+/// without a bucket. Waiting for tokens ends early on an interrupt.
+pub struct Throttled<'a, R> {
+    inner: R,
+    bucket: Option<&'a TokenBucket>,
+    interrupt: &'a Interrupt,
+}
+
+impl<'a, R: Read> Throttled<'a, R> {
+    pub fn new(inner: R, bucket: Option<&'a TokenBucket>, interrupt: &'a Interrupt) -> Self {
+        Self {
+            inner,
+            bucket,
+            interrupt,
+        }
+    }
+}
+
+impl<R: Read> Read for Throttled<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bucket = match self.bucket {
+            Some(bucket) => bucket,
+            None => return self.inner.read(buf),
+        };
+        let len = buf.len().min(bucket.burst());
+        let n = self.inner.read(&mut buf[..len])?;
+        bucket
+            .take(n, self.interrupt)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        Ok(n)
+    }
+}
+
+/// API requests in flight at once, across the session, unless configured
+/// otherwise with `gwas_configure(max_requests =)`.
+pub const DEFAULT_MAX_REQUESTS: usize = 8;
+
+/// The session's limit on API requests in flight, shared by every client
+/// and thread: batch lookups, concurrent pages and prefetching alike.
+pub static API_REQUESTS: RequestLimit = RequestLimit::new(DEFAULT_MAX_REQUESTS);
+
+/// A counting semaphore bounding the requests in flight at once among
+/// everything that shares it. A request holds a [`RequestPermit`] until
+/// its response has been read or dropped.
+#[derive(Debug)]
+pub struct RequestLimit {
+    state: Mutex<LimitState>,
+    freed: Condvar,
+}
+
+#[derive(Debug)]
+struct LimitState {
+    limit: usize,
+    in_flight: usize,
+}
+
+impl RequestLimit {
+    pub const fn new(limit: usize) -> Self {
+        Self {
+            state: Mutex::new(LimitState {
+                limit,
+                in_flight: 0,
+            }),
+            freed: Condvar::new(),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, LimitState> {
+        match self.state.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.state().limit
+    }
+
+    /// Changes the limit; requests already in flight keep their permits.
+    pub fn set_limit(&self, limit: usize) {
+        self.state().limit = limit.max(1);
+        self.freed.notify_all();
+    }
+
+    /// Blocks until fewer requests than the limit are in flight, then
+    /// counts one more until the permit is dropped.
+    pub fn acquire(&self) -> RequestPermit<'_> {
+        let mut state = self.state();
+        while state.in_flight >= state.limit {
+            state = match self.freed.wait(state) {
+                Ok(state) => state,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+        }
+        state.in_flight += 1;
+        RequestPermit { limit: self }
+    }
+}
+
+/// A place among the requests in flight, given back when dropped.
+#[derive(Debug)]
+pub struct RequestPermit<'a> {
+    limit: &'a RequestLimit,
+}
+
+impl Drop for RequestPermit<'_> {
+    fn drop(&mut self) {
+        self.limit.state().in_flight -= 1;
+        self.limit.freed.notify_one();
+    }
+}
@@ -0,0 +1,139 @@
+//! A table of every trait the summary statistics API lists, with its
+//! label from OLS when asked for, written as `trait_id,label` CSV. Other
+//! functions read it back to show labels and to match trait names.
+
+use crate::atomic::AtomicFile;
+use crate::catalog_index::{all_pages, csv_field};
+use crate::ols::OlsClient;
+use crate::GwasClient;
+use anyhow::Result;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::io::{BufWriter, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Label lookups in flight at once.
+const LABEL_THREADS: usize = 4;
+
+/// One row of the table.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TraitLabel {
+    pub trait_id: String,
+    /// `None` when labels were not resolved or OLS has no such term.
+    pub label: Option<String>,
+}
+
+/// What `dump_traits` wrote.
+#[derive(Debug, Serialize)]
+pub struct TraitDump {
+    pub output: String,
+    /// Seconds since the Unix epoch.
+    pub built_at: u64,
+    pub traits: usize,
+    pub labelled: usize,
+}
+
+/// Writes every trait the API lists to `output`, sorted by ID, with the
+/// labels `ols` gives them when it is set. The file is replaced only once
+/// every trait has been listed and looked up.
+pub fn dump_traits(
+    client: &GwasClient,
+    ols: Option<&OlsClient>,
+    output: &str,
+) -> Result<TraitDump> {
+    let mut trait_ids: Vec<String> = all_pages(client, |params| client.get_traits(params))?
+        .into_iter()
+        .map(|t| t.trait_name)
+        .collect();
+    trait_ids.sort();
+    trait_ids.dedup();
+
+    let rows: Vec<TraitLabel> = match ols {
+        Some(ols) => {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(LABEL_THREADS)
+                .build()?;
+            pool.install(|| {
+                trait_ids
+                    .into_par_iter()
+                    .map(|trait_id| {
+                        client.interrupt().check()?;
+                        let label = ols.term(&trait_id)?.and_then(|term| term.label);
+                        client.interrupt().progress().add_pages(1, 0);
+                        Ok(TraitLabel { trait_id, label })
+                    })
+                    .collect::<Result<_>>()
+            })?
+        }
+        None => trait_ids
+            .into_iter()
+            .map(|trait_id| TraitLabel {
+                trait_id,
+                label: None,
+            })
+            .collect(),
+    };
+
+    let mut writer = BufWriter::new(AtomicFile::create(output)?);
+    writeln!(writer, "trait_id,label")?;
+    for row in &rows {
+        // Labels are single lines; a stray line break would split the row.
+        let label = row
+            .label
+            .as_deref()
+            .unwrap_or_default()
+            .replace(['\r', '\n'], " ");
+        writeln!(writer, "{},{}", csv_field(&row.trait_id), csv_field(&label))?;
+    }
+    AtomicFile::commit_buffered(writer)?;
+    Ok(TraitDump {
+        output: output.to_string(),
+        built_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        traits: rows.len(),
+        labelled: rows.iter().filter(|row| row.label.is_some()).count(),
+    })
+}
+
+/// The fields of one CSV line, unquoting quoted fields (RFC 4180).
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', _) => quoted = !quoted,
+            (',', false) => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Reads a table written by `dump_traits`.
+pub fn load_trait_labels(path: &str) -> Result<Vec<TraitLabel>> {
+    let data = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Cannot read trait table {}: {}", path, e))?;
+    let mut lines = data.lines();
+    if lines.next() != Some("trait_id,label") {
+        return Err(anyhow::anyhow!(
+            "{} is not a trait table; write one with gwas_all_traits()",
+            path
+        ));
+    }
+    Ok(lines
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let mut fields = csv_fields(line).into_iter();
+            TraitLabel {
+                trait_id: fields.next().unwrap_or_default(),
+                label: fields.next().filter(|label| !label.is_empty()),
+            }
+        })
+        .collect())
+}
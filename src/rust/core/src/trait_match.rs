@@ -0,0 +1,103 @@
+//! Matching a trait name, typos and all, against a trait table written by
+//! `trait_labels::dump_traits`. Names are compared word by word with the
+//! normalized Damerau-Levenshtein similarity, so "diabetees" still finds
+//! "type 2 diabetes mellitus".
+
+use crate::trait_labels::TraitLabel;
+use serde::Serialize;
+use strsim::normalized_damerau_levenshtein;
+
+/// Candidates returned by default.
+pub const DEFAULT_MATCHES: usize = 10;
+
+/// Lowest score returned by default: one typo in a word of five letters.
+pub const DEFAULT_MIN_SCORE: f64 = 0.7;
+
+/// Score of a query found whole in a label, word for word.
+const WORDS_SCORE: f64 = 0.95;
+
+/// Score of a query found in a label inside a longer word.
+const SUBSTRING_SCORE: f64 = 0.9;
+
+/// One candidate trait for a query.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct TraitMatch {
+    pub trait_id: String,
+    pub label: Option<String>,
+    /// From 0 to 1, where 1 is the same name or ID.
+    pub score: f64,
+}
+
+/// Lower-case words of `text`, split at anything but letters and digits.
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// How well `query` names a trait labelled `text`: 1 for the same words,
+/// [`WORDS_SCORE`] or [`SUBSTRING_SCORE`] when the query appears in it,
+/// and otherwise the similarity of the run of words in `text` closest to
+/// the query, scaled below a substring match.
+fn similarity(query: &[String], text: &str) -> f64 {
+    let text = words(text);
+    if query.is_empty() || text.is_empty() {
+        return 0.0;
+    }
+    if text == query {
+        return 1.0;
+    }
+    if text.windows(query.len()).any(|window| window == query) {
+        return WORDS_SCORE;
+    }
+    let (query, joined) = (query.join(" "), text.join(" "));
+    if joined.contains(&query) {
+        return SUBSTRING_SCORE;
+    }
+    let width = query.split(' ').count().min(text.len());
+    let closest = text
+        .windows(width)
+        .map(|window| normalized_damerau_levenshtein(&query, &window.join(" ")))
+        .fold(0.0, f64::max);
+    normalized_damerau_levenshtein(&query, &joined).max(closest * SUBSTRING_SCORE)
+}
+
+/// The traits of `table` whose label or ID best match `query`, at most
+/// `limit` of them scoring `min_score` or more, best first and then by ID.
+pub fn match_traits(
+    table: &[TraitLabel],
+    query: &str,
+    limit: usize,
+    min_score: f64,
+) -> Vec<TraitMatch> {
+    let query_words = words(query);
+    let query_id = query.trim().replace(':', "_");
+    let mut matches: Vec<TraitMatch> = table
+        .iter()
+        .filter_map(|row| {
+            let id_score: f64 = if row.trait_id.eq_ignore_ascii_case(&query_id) {
+                1.0
+            } else {
+                0.0
+            };
+            let label_score = row
+                .label
+                .as_deref()
+                .map_or(0.0, |label| similarity(&query_words, label));
+            let score = id_score.max(label_score);
+            (score >= min_score && score > 0.0).then(|| TraitMatch {
+                trait_id: row.trait_id.clone(),
+                label: row.label.clone(),
+                score,
+            })
+        })
+        .collect();
+    matches.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.trait_id.cmp(&b.trait_id))
+    });
+    matches.truncate(limit);
+    matches
+}
@@ -0,0 +1,126 @@
+//! Trait queries that take in the more specific traits below a trait in
+//! EFO: "diabetes mellitus" with its type 1, type 2 and gestational forms.
+//! The subtree comes from OLS, and each of its traits is queried in turn;
+//! results are tagged with the trait they were found under.
+
+use crate::backend::Associations;
+use crate::catalog_index::all_pages;
+use crate::http::is_not_found;
+use crate::interrupt::Interrupt;
+use crate::ols::OlsClient;
+use crate::paging::merge_pages;
+use crate::GwasClient;
+use anyhow::Result;
+use rayon::prelude::*;
+use serde::Serialize;
+
+/// Traits of a subtree queried at once.
+const SUBTREE_THREADS: usize = 4;
+
+/// `trait_id` followed by the IDs of every EFO term below it, sorted. A
+/// trait OLS does not know has no subtree but itself.
+pub fn trait_subtree(ols: &OlsClient, trait_id: &str) -> Result<Vec<String>> {
+    let term = match ols.term(trait_id)? {
+        Some(term) => term,
+        None => return Ok(vec![trait_id.to_string()]),
+    };
+    let mut descendants: Vec<String> = ols
+        .descendants(&term)?
+        .into_iter()
+        .map(|term| term.short_form)
+        .filter(|id| id != trait_id)
+        .collect();
+    descendants.sort();
+    descendants.dedup();
+    descendants.insert(0, trait_id.to_string());
+    Ok(descendants)
+}
+
+/// The associations `fetch` returns for each of `trait_ids`, merged in
+/// their order. A trait without records adds none, but when none of them
+/// has any, the first trait's error is returned, so that callers handle a
+/// missing subtree as they would a missing trait. Rows that do not name
+/// their trait are tagged with the one they were fetched for.
+pub fn subtree_associations(
+    trait_ids: &[String],
+    interrupt: Option<&Interrupt>,
+    fetch: impl Fn(&str) -> Result<Associations> + Sync,
+) -> Result<Associations> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(SUBTREE_THREADS)
+        .build()?;
+    let results: Vec<Result<Associations>> = pool.install(|| {
+        trait_ids
+            .par_iter()
+            .map(|trait_id| {
+                if let Some(interrupt) = interrupt {
+                    interrupt.check()?;
+                }
+                let mut associations = fetch(trait_id)?;
+                for association in associations.associations_mut() {
+                    if association.trait_ids.as_ref().map_or(true, Vec::is_empty) {
+                        association.trait_ids = Some(vec![trait_id.clone()]);
+                    }
+                }
+                Ok(associations)
+            })
+            .collect()
+    });
+
+    let mut found = Vec::with_capacity(results.len());
+    let mut first_missing = None;
+    let mut interrupted = false;
+    for result in results {
+        match result {
+            Ok(associations) => {
+                interrupted |= associations.interrupted == Some(true);
+                found.push(associations);
+            }
+            Err(e) if is_not_found(&e) => {
+                first_missing.get_or_insert(e);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    if found.is_empty() {
+        if let Some(e) = first_missing {
+            return Err(e);
+        }
+    }
+    let mut merged = merge_pages(found, 0);
+    merged.interrupted = Some(true).filter(|_| interrupted);
+    Ok(merged)
+}
+
+/// A study of one trait of a subtree.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct TraitStudy {
+    pub trait_id: String,
+    pub study_accession: String,
+}
+
+/// Every study of each of `trait_ids`, in their order, tagged with the
+/// trait it is listed under. A study of several of the traits is listed
+/// once for each.
+pub fn subtree_studies(client: &GwasClient, trait_ids: &[String]) -> Result<Vec<TraitStudy>> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(SUBTREE_THREADS)
+        .build()?;
+    let studies: Vec<Vec<TraitStudy>> = pool.install(|| {
+        trait_ids
+            .par_iter()
+            .map(|trait_id| {
+                let studies =
+                    all_pages(client, |params| client.get_trait_studies(trait_id, params))?;
+                Ok(studies
+                    .into_iter()
+                    .map(|study| TraitStudy {
+                        trait_id: trait_id.clone(),
+                        study_accession: study.study_accession,
+                    })
+                    .collect())
+            })
+            .collect::<Result<_>>()
+    })?;
+    Ok(studies.into_iter().flatten().collect())
+}
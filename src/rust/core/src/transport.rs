@@ -0,0 +1,266 @@
+//! The network layer under `GwasClient`'s API requests. The client sends
+//! them through an [`HttpTransport`]: [`ReqwestTransport`] by default, or
+//! a [`MockTransport`] serving canned or recorded responses, so that every
+//! typed method can be tested without network access.
+
+use crate::http::{self, Timeouts};
+use crate::throttle::{RequestLimit, RequestPermit};
+use anyhow::Result;
+use reqwest::blocking::{Client, Response};
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+use reqwest::StatusCode;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::io::{Cursor, Read};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use url::Url;
+
+/// A response as a transport hands it back: the status and headers, and
+/// the body to read as it arrives.
+pub struct HttpResponse {
+    pub status: StatusCode,
+    /// The URL that answered, after any redirects.
+    pub url: String,
+    pub headers: HeaderMap,
+    /// The body's length when the server declared it.
+    pub content_length: Option<u64>,
+    pub body: Box<dyn Read + Send>,
+}
+
+impl fmt::Debug for HttpResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HttpResponse")
+            .field("status", &self.status)
+            .field("url", &self.url)
+            .field("headers", &self.headers)
+            .field("content_length", &self.content_length)
+            .finish_non_exhaustive()
+    }
+}
+
+impl HttpResponse {
+    /// The Content-Type header, if it is readable text.
+    pub fn content_type(&self) -> Option<&str> {
+        self.headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+    }
+}
+
+impl From<Response> for HttpResponse {
+    fn from(response: Response) -> Self {
+        Self {
+            status: response.status(),
+            url: response.url().to_string(),
+            headers: response.headers().clone(),
+            content_length: response.content_length(),
+            body: Box::new(response),
+        }
+    }
+}
+
+/// Sends a `GwasClient`'s API requests.
+pub trait HttpTransport: fmt::Debug + Send + Sync {
+    /// Sends a GET request for `url` with `headers` on top of the
+    /// transport's own. Fails only when no response arrives; error
+    /// statuses are the caller's to check.
+    fn get(&self, url: Url, headers: &HeaderMap) -> Result<HttpResponse>;
+}
+
+/// Requests over the network through reqwest, each limited by the total
+/// timeout and logged and counted by `http::send`.
+#[derive(Debug, Clone)]
+pub struct ReqwestTransport {
+    client: Client,
+    timeouts: Timeouts,
+}
+
+impl ReqwestTransport {
+    pub fn new(client: Client, timeouts: Timeouts) -> Self {
+        Self { client, timeouts }
+    }
+}
+
+impl HttpTransport for ReqwestTransport {
+    fn get(&self, url: Url, headers: &HeaderMap) -> Result<HttpResponse> {
+        let request = self.client.get(url).headers(headers.clone());
+        Ok(http::send(self.timeouts.api(request))?.into())
+    }
+}
+
+/// Requests through `inner`, each holding a place under `limit` from
+/// sending until its body has been read or dropped.
+#[derive(Debug)]
+pub struct LimitedTransport {
+    inner: Arc<dyn HttpTransport>,
+    limit: &'static RequestLimit,
+}
+
+impl LimitedTransport {
+    pub fn new(inner: Arc<dyn HttpTransport>, limit: &'static RequestLimit) -> Self {
+        Self { inner, limit }
+    }
+}
+
+/// A response body holding its request's place under the limit.
+struct PermitBody {
+    body: Box<dyn Read + Send>,
+    _permit: RequestPermit<'static>,
+}
+
+impl Read for PermitBody {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.body.read(buf)
+    }
+}
+
+impl HttpTransport for LimitedTransport {
+    fn get(&self, url: Url, headers: &HeaderMap) -> Result<HttpResponse> {
+        let permit = self.limit.acquire();
+        let mut response = self.inner.get(url, headers)?;
+        response.body = Box::new(PermitBody {
+            body: response.body,
+            _permit: permit,
+        });
+        Ok(response)
+    }
+}
+
+/// A canned answer of a [`MockTransport`].
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    pub status: u16,
+    pub content_type: String,
+    pub body: Vec<u8>,
+}
+
+impl MockResponse {
+    pub fn json(body: &str) -> Self {
+        Self {
+            status: 200,
+            content_type: "application/json".to_string(),
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    pub fn status(status: u16, body: &str) -> Self {
+        Self {
+            status,
+            ..Self::json(body)
+        }
+    }
+
+    /// A JSON response whose body is a recorded file, e.g. a page saved
+    /// from the API.
+    pub fn fixture(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let body = std::fs::read(path)
+            .map_err(|e| anyhow::anyhow!("Cannot read fixture {}: {}", path.display(), e))?;
+        Ok(Self {
+            body,
+            ..Self::json("")
+        })
+    }
+
+    pub fn with_content_type(mut self, content_type: &str) -> Self {
+        self.content_type = content_type.to_string();
+        self
+    }
+}
+
+/// Answers requests from routes keyed by path and query parameters, so
+/// matching does not depend on the order in which the client appends
+/// parameters. Each route answers with its responses in turn and repeats
+/// the last one; requests without a route get a 404. Paths include the
+/// client's base URL path, so a client with base URL `http://mock` asks
+/// for the API's own paths.
+#[derive(Debug, Default)]
+pub struct MockTransport {
+    routes: Mutex<HashMap<String, VecDeque<MockResponse>>>,
+    requests: Mutex<Vec<String>>,
+}
+
+/// "path?a=1&b=2" with the parameters sorted, for a path with optional
+/// query.
+fn route_key(target: &str) -> String {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let params: BTreeMap<String, String> = url::form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .collect();
+    let query: Vec<String> = params.iter().map(|(k, v)| format!("{k}={v}")).collect();
+    format!("{path}?{}", query.join("&"))
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serves `responses` in turn for `target` (a path with optional query).
+    pub fn route(&self, target: &str, responses: Vec<MockResponse>) -> &Self {
+        if let Ok(mut routes) = self.routes.lock() {
+            routes.insert(route_key(target), responses.into());
+        }
+        self
+    }
+
+    /// Serves `responses` for `path` whatever the query, unless a route
+    /// for the exact query exists.
+    pub fn route_path(&self, path: &str, responses: Vec<MockResponse>) -> &Self {
+        if let Ok(mut routes) = self.routes.lock() {
+            routes.insert(format!("{path}?*"), responses.into());
+        }
+        self
+    }
+
+    /// Route keys of the requests received so far, in order.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests
+            .lock()
+            .map(|requests| requests.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl HttpTransport for MockTransport {
+    fn get(&self, url: Url, _headers: &HeaderMap) -> Result<HttpResponse> {
+        let target = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        let key = route_key(&target);
+        if let Ok(mut requests) = self.requests.lock() {
+            requests.push(key.clone());
+        }
+        let response = {
+            let mut routes = self
+                .routes
+                .lock()
+                .map_err(|_| anyhow::anyhow!("Mock routes poisoned"))?;
+            let key = if routes.contains_key(&key) {
+                key
+            } else {
+                format!("{}?*", url.path())
+            };
+            match routes.get_mut(&key) {
+                Some(queue) if queue.len() > 1 => queue.pop_front(),
+                Some(queue) => queue.front().cloned(),
+                None => None,
+            }
+            .unwrap_or_else(|| {
+                MockResponse::status(404, &format!("{{\"message\": \"No route for {key}\"}}"))
+            })
+        };
+
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_str(&response.content_type)?);
+        Ok(HttpResponse {
+            status: StatusCode::from_u16(response.status)?,
+            url: url.to_string(),
+            headers,
+            content_length: Some(response.body.len() as u64),
+            body: Box::new(Cursor::new(response.body)),
+        })
+    }
+}
@@ -0,0 +1,291 @@
+use crate::atomic::AtomicFile;
+use crate::sumstats::SumstatsReader;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+const BLOOM_MAGIC: &[u8; 8] = b"IANIBLM1";
+const MINHASH_MAGIC: &[u8; 8] = b"IANIMNH1";
+const FALSE_POSITIVE_RATE: f64 = 0.01;
+// 256 hashes give Jaccard estimates within about +/-0.06 (two standard errors).
+const MINHASH_SIZE: usize = 256;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MembershipResult {
+    pub variant_id: String,
+    pub present: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StudySimilarity {
+    pub study_a: String,
+    pub study_b: String,
+    pub jaccard: f64,
+}
+
+/// Bloom filter over variant IDs. Hashing is FNV-1a based so filters written
+/// to disk stay valid across Rust and package versions.
+#[derive(Debug, Clone)]
+pub struct BloomFilter {
+    bits: Vec<u8>,
+    n_bits: u64,
+    n_hashes: u32,
+}
+
+impl BloomFilter {
+    pub fn with_capacity(n_items: usize) -> Self {
+        let n = n_items.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let n_bits = ((-n * FALSE_POSITIVE_RATE.ln()) / (ln2 * ln2))
+            .ceil()
+            .max(64.0) as u64;
+        let n_hashes = ((n_bits as f64 / n) * ln2).round().clamp(1.0, 16.0) as u32;
+
+        Self {
+            bits: vec![0; ((n_bits + 7) / 8) as usize],
+            n_bits,
+            n_hashes,
+        }
+    }
+
+    fn positions(&self, item: &str) -> impl Iterator<Item = u64> {
+        let h1 = fnv1a(item.as_bytes(), 0xcbf2_9ce4_8422_2325);
+        let h2 = fnv1a(item.as_bytes(), 0x8422_2325_cbf2_9ce4) | 1;
+        let n_bits = self.n_bits;
+        (0..u64::from(self.n_hashes)).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % n_bits)
+    }
+
+    pub fn insert(&mut self, item: &str) {
+        for position in self.positions(item).collect::<Vec<_>>() {
+            self.bits[(position / 8) as usize] |= 1 << (position % 8);
+        }
+    }
+
+    pub fn contains(&self, item: &str) -> bool {
+        self.positions(item)
+            .all(|position| self.bits[(position / 8) as usize] & (1 << (position % 8)) != 0)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let mut file = BufWriter::new(AtomicFile::create(path)?);
+        file.write_all(BLOOM_MAGIC)?;
+        file.write_all(&self.n_bits.to_le_bytes())?;
+        file.write_all(&self.n_hashes.to_le_bytes())?;
+        file.write_all(&self.bits)?;
+        AtomicFile::commit_buffered(file)?;
+        Ok(())
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let mut data = Vec::new();
+        fs::File::open(path)?.read_to_end(&mut data)?;
+        if data.len() < 20 || &data[..8] != BLOOM_MAGIC {
+            return Err(anyhow::anyhow!(
+                "{} is not a variant filter",
+                path.display()
+            ));
+        }
+
+        let mut n_bits = [0u8; 8];
+        n_bits.copy_from_slice(&data[8..16]);
+        let mut n_hashes = [0u8; 4];
+        n_hashes.copy_from_slice(&data[16..20]);
+
+        let filter = Self {
+            bits: data[20..].to_vec(),
+            n_bits: u64::from_le_bytes(n_bits),
+            n_hashes: u32::from_le_bytes(n_hashes),
+        };
+        // Lookups take positions modulo n_bits, so an empty filter or one
+        // without hashes is corrupt rather than merely empty.
+        if filter.n_bits == 0 || filter.n_hashes == 0 {
+            return Err(anyhow::anyhow!("{} has a corrupt header", path.display()));
+        }
+        let n_bytes = filter.n_bits.checked_add(7).map(|bits| bits / 8);
+        if n_bytes != Some(filter.bits.len() as u64) {
+            return Err(anyhow::anyhow!("{} is truncated", path.display()));
+        }
+        Ok(filter)
+    }
+}
+
+/// MinHash sketch of a study's variant set: the minimum of each of
+/// `MINHASH_SIZE` seeded hashes over all variant IDs.
+#[derive(Debug, Clone)]
+pub struct MinHashSketch {
+    mins: Vec<u64>,
+}
+
+impl Default for MinHashSketch {
+    fn default() -> Self {
+        Self {
+            mins: vec![u64::MAX; MINHASH_SIZE],
+        }
+    }
+}
+
+impl MinHashSketch {
+    pub fn insert(&mut self, item: &str) {
+        let base = fnv1a(item.as_bytes(), 0xcbf2_9ce4_8422_2325);
+        for (i, min) in self.mins.iter_mut().enumerate() {
+            let hash = splitmix64(base ^ (i as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+            if hash < *min {
+                *min = hash;
+            }
+        }
+    }
+
+    /// Estimated Jaccard index between the two variant sets.
+    pub fn jaccard(&self, other: &MinHashSketch) -> f64 {
+        let matching = self
+            .mins
+            .iter()
+            .zip(&other.mins)
+            .filter(|(a, b)| a == b && **a != u64::MAX)
+            .count();
+        matching as f64 / self.mins.len() as f64
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let mut file = BufWriter::new(AtomicFile::create(path)?);
+        file.write_all(MINHASH_MAGIC)?;
+        file.write_all(&(self.mins.len() as u32).to_le_bytes())?;
+        for min in &self.mins {
+            file.write_all(&min.to_le_bytes())?;
+        }
+        AtomicFile::commit_buffered(file)?;
+        Ok(())
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let mut data = Vec::new();
+        fs::File::open(path)?.read_to_end(&mut data)?;
+        if data.len() < 12 || &data[..8] != MINHASH_MAGIC {
+            return Err(anyhow::anyhow!(
+                "{} is not a MinHash sketch",
+                path.display()
+            ));
+        }
+
+        let mut size = [0u8; 4];
+        size.copy_from_slice(&data[8..12]);
+        let size = u32::from_le_bytes(size) as usize;
+        if size != MINHASH_SIZE || data.len() != 12 + size * 8 {
+            return Err(anyhow::anyhow!("{} is truncated", path.display()));
+        }
+
+        let mins = data[12..]
+            .chunks_exact(8)
+            .map(|chunk| {
+                let mut bytes = [0u8; 8];
+                bytes.copy_from_slice(chunk);
+                u64::from_le_bytes(bytes)
+            })
+            .collect();
+        Ok(Self { mins })
+    }
+}
+
+pub fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+pub fn fnv1a(bytes: &[u8], offset_basis: u64) -> u64 {
+    bytes.iter().fold(offset_basis, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+pub fn filter_path(cache_dir: &str, study: &str) -> PathBuf {
+    Path::new(cache_dir)
+        .join("variants")
+        .join(format!("{study}.bloom"))
+}
+
+pub fn sketch_path(cache_dir: &str, study: &str) -> PathBuf {
+    Path::new(cache_dir)
+        .join("variants")
+        .join(format!("{study}.minhash"))
+}
+
+/// Builds the variant filter and MinHash sketch for a study from a local
+/// summary statistics file. The file is read twice so the filter can be
+/// sized exactly.
+pub fn build_variant_filter(cache_dir: &str, study: &str, path: &str) -> Result<usize> {
+    let mut n_variants = 0;
+    for record in SumstatsReader::open(path)? {
+        if record?.variant_id.is_some() {
+            n_variants += 1;
+        }
+    }
+
+    let mut filter = BloomFilter::with_capacity(n_variants);
+    let mut sketch = MinHashSketch::default();
+    for record in SumstatsReader::open(path)? {
+        if let Some(id) = record?.variant_id {
+            filter.insert(&id);
+            sketch.insert(&id);
+        }
+    }
+
+    filter.write(&filter_path(cache_dir, study))?;
+    sketch.write(&sketch_path(cache_dir, study))?;
+    Ok(n_variants)
+}
+
+pub fn has_variants(
+    cache_dir: &str,
+    study: &str,
+    variant_ids: &[String],
+) -> Result<Vec<MembershipResult>> {
+    let path = filter_path(cache_dir, study);
+    if !path.exists() {
+        return Err(anyhow::anyhow!(
+            "No variant filter for {}; build one with gwas_build_variant_filter()",
+            study
+        ));
+    }
+
+    let filter = BloomFilter::read(&path)?;
+    Ok(variant_ids
+        .iter()
+        .map(|id| MembershipResult {
+            variant_id: id.clone(),
+            present: filter.contains(id),
+        })
+        .collect())
+}
+
+/// Pairwise Jaccard estimates between the variant sets of indexed studies.
+pub fn study_similarity(cache_dir: &str, studies: &[String]) -> Result<Vec<StudySimilarity>> {
+    let sketches = studies
+        .iter()
+        .map(|study| {
+            let path = sketch_path(cache_dir, study);
+            if !path.exists() {
+                return Err(anyhow::anyhow!(
+                    "No variant sketch for {}; build one with gwas_build_variant_filter()",
+                    study
+                ));
+            }
+            MinHashSketch::read(&path)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut similarities = Vec::new();
+    for i in 0..studies.len() {
+        for j in (i + 1)..studies.len() {
+            similarities.push(StudySimilarity {
+                study_a: studies[i].clone(),
+                study_b: studies[j].clone(),
+                jaccard: sketches[i].jaccard(&sketches[j]),
+            });
+        }
+    }
+    Ok(similarities)
+}
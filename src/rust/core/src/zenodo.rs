@@ -0,0 +1,146 @@
+use crate::http::{self, error_text, read_json, session_client, session_max_response_bytes};
+use anyhow::Result;
+use reqwest::blocking::{Client, Response};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use url::Url;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Creator {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DepositMetadata {
+    pub title: String,
+    pub upload_type: String,
+    pub description: String,
+    pub creators: Vec<Creator>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Deposition {
+    pub id: i64,
+    pub doi: Option<String>,
+    pub links: Option<serde_json::Map<String, serde_json::Value>>,
+    pub state: Option<String>,
+    pub submitted: Option<bool>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ZenodoClient {
+    client: Client,
+    base_url: String,
+    token: String,
+}
+
+impl ZenodoClient {
+    pub fn new(token: String, sandbox: bool) -> Self {
+        let base_url = if sandbox {
+            "https://sandbox.zenodo.org/api"
+        } else {
+            "https://zenodo.org/api"
+        };
+        Self::with_base_url(token, base_url.to_string())
+    }
+
+    pub fn with_base_url(token: String, base_url: String) -> Self {
+        Self {
+            client: session_client(),
+            base_url,
+            token,
+        }
+    }
+
+    fn check_response(&self, response: Response) -> Result<Response> {
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = error_text(response);
+            return Err(anyhow::anyhow!("Zenodo HTTP {}: {}", status, text));
+        }
+        Ok(response)
+    }
+
+    fn create_deposition(&self) -> Result<Deposition> {
+        let response = http::send(
+            self.client
+                .post(format!("{}/deposit/depositions", self.base_url))
+                .bearer_auth(&self.token)
+                .json(&serde_json::json!({})),
+        )?;
+        read_json(self.check_response(response)?, session_max_response_bytes())
+    }
+
+    fn upload_file(&self, deposition: &Deposition, path: &str) -> Result<()> {
+        let bucket = deposition
+            .links
+            .as_ref()
+            .and_then(|links| links.get("bucket"))
+            .and_then(|bucket| bucket.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Deposition {} has no bucket link", deposition.id))?;
+        let file_name = Path::new(path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| anyhow::anyhow!("Invalid file path: {}", path))?;
+        // The file name becomes one encoded path segment, so names with
+        // spaces, `#` or `?` upload under their own name.
+        let mut url = Url::parse(bucket)?;
+        url.path_segments_mut()
+            .map_err(|_| anyhow::anyhow!("Invalid bucket link: {}", bucket))?
+            .pop_if_empty()
+            .push(file_name);
+
+        let response = http::send(
+            self.client
+                .put(url)
+                .bearer_auth(&self.token)
+                .body(fs::File::open(path)?),
+        )?;
+        self.check_response(response)?;
+        Ok(())
+    }
+
+    fn set_metadata(&self, id: i64, metadata: &DepositMetadata) -> Result<Deposition> {
+        let response = http::send(
+            self.client
+                .put(format!("{}/deposit/depositions/{id}", self.base_url))
+                .bearer_auth(&self.token)
+                .json(&serde_json::json!({ "metadata": metadata })),
+        )?;
+        read_json(self.check_response(response)?, session_max_response_bytes())
+    }
+
+    fn publish(&self, id: i64) -> Result<Deposition> {
+        let response = http::send(
+            self.client
+                .post(format!(
+                    "{}/deposit/depositions/{id}/actions/publish",
+                    self.base_url
+                ))
+                .bearer_auth(&self.token),
+        )?;
+        read_json(self.check_response(response)?, session_max_response_bytes())
+    }
+
+    /// Creates a deposition, uploads every file into its bucket, attaches the
+    /// metadata and optionally publishes it. The DOI is only minted on publish.
+    pub fn deposit(
+        &self,
+        files: &[String],
+        metadata: &DepositMetadata,
+        publish: bool,
+    ) -> Result<Deposition> {
+        let deposition = self.create_deposition()?;
+        for path in files {
+            self.upload_file(&deposition, path)?;
+        }
+        let deposition = self.set_metadata(deposition.id, metadata)?;
+
+        if publish {
+            self.publish(deposition.id)
+        } else {
+            Ok(deposition)
+        }
+    }
+}
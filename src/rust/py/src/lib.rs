@@ -0,0 +1,373 @@
+//! The PyO3 bindings of the `iani` Python module: the same client as the
+//! R package, with `iani-core` doing the paging, downloads and munging.
+//! Results come back as Python dicts and lists, and failures raise.
+
+use anyhow::Result;
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+
+use iani_core::backend::{
+    configure as configure_session, configure_max_response_bytes, configure_proxy,
+    configure_timeouts, configure_tls, current_backend,
+};
+use iani_core::download::DownloadMode;
+use iani_core::export::{self, export_associations};
+use iani_core::interrupt::{set_interrupt_check, with_interrupts};
+use iani_core::munge::{munge as munge_file, MungeSettings};
+use iani_core::refine::Refinement;
+use iani_core::terms::require_acceptance;
+use iani_core::{logging, stats as session_stats, to_json, GwasClient, GwasFilter};
+
+/// Parses the JSON `iani-core` returns into Python objects.
+fn loads(py: Python<'_>, json: &str) -> PyResult<PyObject> {
+    Ok(py.import("json")?.call_method1("loads", (json,))?.unbind())
+}
+
+/// Serializes `value` and parses it into Python objects.
+fn to_python<T: serde::Serialize + ?Sized>(py: Python<'_>, value: &T) -> PyResult<PyObject> {
+    let json = to_json(value).map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+    loads(py, &json)
+}
+
+/// Raises `RuntimeError` with `context` before the error's message.
+fn error(context: &str) -> impl FnOnce(anyhow::Error) -> PyErr + '_ {
+    move |e| PyRuntimeError::new_err(format!("{context}: {e}"))
+}
+
+/// Checks for Ctrl-C by running Python's signal handlers. The default
+/// SIGINT handler raises `KeyboardInterrupt`, which is consumed here so
+/// that paged queries and downloads return what they have so far.
+fn interrupt_pending() -> bool {
+    Python::with_gil(|py| py.check_signals().is_err())
+}
+
+/// The filter of an associations query, with the p-value range open at
+/// whichever end is not given.
+#[allow(clippy::too_many_arguments)]
+fn association_filter(
+    p_value_min: Option<f64>,
+    p_value_max: Option<f64>,
+    bp_min: Option<i64>,
+    bp_max: Option<i64>,
+    study: Option<String>,
+    trait_id: Option<String>,
+    reveal: Option<String>,
+    start: Option<i32>,
+    size: Option<i32>,
+) -> Result<GwasFilter> {
+    let p_value_range = match (p_value_min, p_value_max) {
+        (None, None) => None,
+        (min, max) => Some((
+            min.unwrap_or(0.0).to_string(),
+            max.unwrap_or(1.0).to_string(),
+        )),
+    };
+    let bp_location_range = match (bp_min, bp_max) {
+        (Some(min), Some(max)) => Some((min, max)),
+        (None, None) => None,
+        _ => return Err(anyhow::anyhow!("bp_min and bp_max go together")),
+    };
+    Ok(GwasFilter {
+        p_value_range,
+        bp_location_range,
+        study,
+        trait_id,
+        reveal,
+        start,
+        size,
+    })
+}
+
+/// Configures the session, like `gwas_configure()` in R, and returns the
+/// configuration.
+#[pyfunction]
+#[pyo3(signature = (
+    backend=None, token=None, mirror_dir=None, download_mirror=None, *,
+    connect_timeout=None, read_timeout=None, timeout=None, max_response_bytes=None,
+    proxy=None, ca_file=None, tls_backend=None,
+))]
+#[allow(clippy::too_many_arguments)]
+fn configure(
+    py: Python<'_>,
+    backend: Option<String>,
+    token: Option<String>,
+    mirror_dir: Option<String>,
+    download_mirror: Option<String>,
+    connect_timeout: Option<f64>,
+    read_timeout: Option<f64>,
+    timeout: Option<f64>,
+    max_response_bytes: Option<f64>,
+    proxy: Option<String>,
+    ca_file: Option<String>,
+    tls_backend: Option<String>,
+) -> PyResult<PyObject> {
+    let config = configure_session(backend, token, mirror_dir, download_mirror)
+        .and_then(|_| configure_timeouts(connect_timeout, read_timeout, timeout))
+        .and_then(|_| configure_max_response_bytes(max_response_bytes))
+        .and_then(|_| configure_proxy(proxy))
+        .and_then(|_| configure_tls(ca_file, tls_backend))
+        .map_err(error("Error configuring session"))?;
+    to_python(py, &config)
+}
+
+/// Logs requests to standard error up to `level`: "off", "error", "warn",
+/// "info", "debug" or "trace". Returns the previous level.
+#[pyfunction]
+fn set_verbose(level: &str) -> PyResult<&'static str> {
+    logging::flush();
+    logging::set_level(level).map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
+/// The session's request, byte, cache and rate-limit counters, zeroed
+/// after reading with `reset=True`.
+#[pyfunction]
+#[pyo3(signature = (reset=false))]
+fn stats(py: Python<'_>, reset: bool) -> PyResult<PyObject> {
+    let stats = if reset {
+        session_stats::reset()
+    } else {
+        session_stats::snapshot()
+    };
+    to_python(py, &stats)
+}
+
+/// Chromosomes, studies or traits, or one of them by `id`.
+#[pyfunction]
+#[pyo3(signature = (entity_type, id=None, start=None, size=None))]
+fn get(
+    py: Python<'_>,
+    entity_type: &str,
+    id: Option<String>,
+    start: Option<i32>,
+    size: Option<i32>,
+) -> PyResult<PyObject> {
+    let filter = GwasFilter {
+        start,
+        size,
+        ..Default::default()
+    };
+    let data = py
+        .allow_threads(|| current_backend()?.entity(entity_type, id.as_deref(), &filter))
+        .map_err(error(&format!("Error fetching {entity_type}")))?;
+    loads(py, &data)
+}
+
+/// Associations of a variant, chromosome, study or trait, or of all of
+/// them when `entity_type` is omitted. `pages` consecutive pages of `size`
+/// are fetched concurrently and merged. With `output_file`, rows are
+/// written to it page by page as NDJSON, CSV or TSV (gzipped when the name
+/// ends in .gz), every page unless `pages` is set, and a summary is
+/// returned. Ctrl-C keeps the pages already fetched.
+#[pyfunction]
+#[pyo3(signature = (
+    entity_type=None, entity_id=None, *, p_value_min=None, p_value_max=None,
+    bp_min=None, bp_max=None, study=None, trait_id=None, reveal=None, start=None,
+    size=None, pages=None, output_file=None, format=None,
+))]
+#[allow(clippy::too_many_arguments)]
+fn associations(
+    py: Python<'_>,
+    entity_type: Option<String>,
+    entity_id: Option<String>,
+    p_value_min: Option<f64>,
+    p_value_max: Option<f64>,
+    bp_min: Option<i64>,
+    bp_max: Option<i64>,
+    study: Option<String>,
+    trait_id: Option<String>,
+    reveal: Option<String>,
+    start: Option<i32>,
+    size: Option<i32>,
+    pages: Option<usize>,
+    output_file: Option<String>,
+    format: Option<String>,
+) -> PyResult<PyObject> {
+    let filter = association_filter(
+        p_value_min,
+        p_value_max,
+        bp_min,
+        bp_max,
+        study,
+        trait_id,
+        reveal,
+        start,
+        size,
+    )
+    .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    if format.is_some() && output_file.is_none() {
+        return Err(PyValueError::new_err(
+            "format applies to output_file, which is not set",
+        ));
+    }
+    let backend = current_backend().map_err(error("Error creating client"))?;
+    let entity_type = entity_type.as_deref();
+    let entity_id = entity_id.as_deref();
+
+    if let Some(output_file) = output_file {
+        let format = format.unwrap_or_else(|| export::FORMATS[0].to_string());
+        let export = || {
+            export_associations(
+                backend.as_ref(),
+                entity_type,
+                entity_id,
+                &filter,
+                pages.filter(|pages| *pages > 0),
+                None,
+                &Refinement::default(),
+                &format,
+                &output_file,
+            )
+        };
+        let summary = py
+            .allow_threads(|| match backend.interrupt() {
+                Some(interrupt) => with_interrupts(interrupt, export),
+                None => export(),
+            })
+            .map_err(error("Error exporting associations"))?;
+        return to_python(py, &summary);
+    }
+
+    let fetch = || match pages {
+        Some(pages) if pages > 1 => {
+            backend.association_pages(entity_type, entity_id, &filter, pages)
+        }
+        _ => backend.associations(entity_type, entity_id, &filter),
+    };
+    let data = py
+        .allow_threads(|| match backend.interrupt() {
+            Some(interrupt) => with_interrupts(interrupt, fetch),
+            None => fetch(),
+        })
+        .map_err(error("Error fetching associations"))?;
+    to_python(py, &data)
+}
+
+/// Summary statistics files of a study or trait, or of a trait's study
+/// given as `secondary_id`.
+#[pyfunction]
+#[pyo3(signature = (entity_type, entity_id, secondary_id=None))]
+fn list_files(
+    py: Python<'_>,
+    entity_type: &str,
+    entity_id: &str,
+    secondary_id: Option<&str>,
+) -> PyResult<PyObject> {
+    let data = py
+        .allow_threads(|| GwasClient::new()?.list_files(entity_type, entity_id, secondary_id))
+        .map_err(error("Error listing files"))?;
+    loads(py, &data)
+}
+
+/// Downloads the summary statistics files of a study under
+/// `<output_dir>/<study>/` and returns one result per file. `mode` is
+/// "overwrite", "skip", "resume" or "verify". Files with usage terms need
+/// `accept_terms=True`. Ctrl-C stops the remaining files; finished ones
+/// are still reported.
+#[pyfunction]
+#[pyo3(signature = (
+    study, output_dir=".", *, mode="overwrite", max_concurrent=4,
+    max_bytes_per_second=None, decompress=false, write_manifest=false, accept_terms=false,
+))]
+#[allow(clippy::too_many_arguments)]
+fn download_study(
+    py: Python<'_>,
+    study: &str,
+    output_dir: &str,
+    mode: &str,
+    max_concurrent: usize,
+    max_bytes_per_second: Option<f64>,
+    decompress: bool,
+    write_manifest: bool,
+    accept_terms: bool,
+) -> PyResult<PyObject> {
+    if let Some(rate) = max_bytes_per_second.filter(|rate| rate.is_nan() || *rate <= 0.0) {
+        return Err(PyValueError::new_err(format!(
+            "max_bytes_per_second must be positive, got {rate}"
+        )));
+    }
+    let mode = DownloadMode::parse(mode).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    let client = GwasClient::new()
+        .map_err(error("Error creating client"))?
+        .with_bandwidth_limit(max_bytes_per_second)
+        .with_decompression(decompress);
+
+    let results = py.allow_threads(|| {
+        let (urls, paths): (Vec<String>, Vec<String>) = client
+            .study_downloads(study, output_dir)
+            .map_err(error(&format!("Error resolving files for {study}")))?
+            .into_iter()
+            .unzip();
+        let urls = client.use_download_mirror(urls);
+        let terms = client
+            .files_terms(&urls, &paths, max_concurrent)
+            .map_err(error("Error creating thread pool"))?;
+        require_acceptance(&terms, accept_terms)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        with_interrupts(client.interrupt(), || {
+            client.download_queued(&urls, &paths, max_concurrent, mode, write_manifest, |_| {})
+        })
+        .map_err(error("Error downloading files"))
+    })?;
+    to_python(py, &results)
+}
+
+/// LDSC-style munging of a summary statistics file into
+/// `<out>.sumstats.gz`; returns the munge report. The input's column
+/// statistics are cached in `cache_dir` when it is set.
+#[pyfunction]
+#[pyo3(signature = (path, out, n=None, info_min=None, maf_min=None, cache_dir=None))]
+fn munge(
+    py: Python<'_>,
+    path: &str,
+    out: &str,
+    n: Option<f64>,
+    info_min: Option<f64>,
+    maf_min: Option<f64>,
+    cache_dir: Option<String>,
+) -> PyResult<PyObject> {
+    let defaults = MungeSettings::default();
+    let settings = MungeSettings {
+        n,
+        info_min: info_min.unwrap_or(defaults.info_min),
+        maf_min: maf_min.unwrap_or(defaults.maf_min),
+        cache_dir,
+    };
+    let report = py
+        .allow_threads(|| munge_file(path, out, &settings))
+        .map_err(error(&format!("Error munging {path}")))?;
+    to_python(py, &report)
+}
+
+/// The `iani` module. Importing it makes Ctrl-C interrupt long
+/// operations, as in R.
+#[pymodule]
+fn iani(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    set_interrupt_check(interrupt_pending);
+    m.add_function(wrap_pyfunction!(configure, m)?)?;
+    m.add_function(wrap_pyfunction!(set_verbose, m)?)?;
+    m.add_function(wrap_pyfunction!(stats, m)?)?;
+    m.add_function(wrap_pyfunction!(get, m)?)?;
+    m.add_function(wrap_pyfunction!(associations, m)?)?;
+    m.add_function(wrap_pyfunction!(list_files, m)?)?;
+    m.add_function(wrap_pyfunction!(download_study, m)?)?;
+    m.add_function(wrap_pyfunction!(munge, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_open_the_missing_p_value_bound() {
+        let filter =
+            association_filter(None, Some(5e-8), None, None, None, None, None, None, None).unwrap();
+        assert_eq!(
+            filter.p_value_range,
+            Some(("0".to_string(), "0.00000005".to_string()))
+        );
+        assert!(
+            association_filter(None, None, Some(1), None, None, None, None, None, None).is_err()
+        );
+    }
+}
@@ -0,0 +1,70 @@
+//! R's side of the hooks `iani-core` leaves to its host: the console for
+//! log lines, Ctrl-C and the progress handler. Installed once when R loads
+//! the package, from `entrypoint.c`.
+
+use iani_core::interrupt::set_interrupt_check;
+use iani_core::logging::set_printer;
+use iani_core::progress::{set_reporter, ProgressSnapshot};
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int, c_void};
+
+extern "C" {
+    static R_GlobalEnv: *mut c_void;
+    fn REprintf(format: *const c_char, ...);
+    fn R_CheckUserInterrupt();
+    fn R_ParseEvalString(text: *const c_char, env: *mut c_void) -> *mut c_void;
+    fn R_ToplevelExec(fun: extern "C" fn(*mut c_void), data: *mut c_void) -> c_int;
+}
+
+/// Writes `line` to R's console (standard error, like `message()`).
+fn print(line: &str) {
+    if let Ok(line) = CString::new(line) {
+        unsafe { REprintf(b"%s\n\0".as_ptr() as *const c_char, line.as_ptr()) }
+    }
+}
+
+/// Checks for a pending R user interrupt without letting R's longjmp
+/// unwind through Rust frames.
+fn interrupt_pending() -> bool {
+    extern "C" fn check(_: *mut c_void) {
+        unsafe { R_CheckUserInterrupt() }
+    }
+
+    // R_ToplevelExec returns FALSE when `check` was left by a jump.
+    unsafe { R_ToplevelExec(check, std::ptr::null_mut()) == 0 }
+}
+
+/// Passes `snapshot` to the package's R handler. Returns false when the
+/// handler failed; R has printed its error by then.
+fn report(snapshot: &ProgressSnapshot, done: bool) -> bool {
+    extern "C" fn eval(call: *mut c_void) {
+        unsafe {
+            R_ParseEvalString(call as *const c_char, R_GlobalEnv);
+        }
+    }
+
+    let call = format!(
+        "iani:::.gwas_progress_report({}, {}, {}, {}, {}, {})",
+        snapshot.pages,
+        snapshot.rows,
+        snapshot.bytes,
+        snapshot.files,
+        snapshot.files_total,
+        if done { "TRUE" } else { "FALSE" }
+    );
+    let call = match CString::new(call) {
+        Ok(call) => call,
+        Err(_) => return false,
+    };
+    // R_ToplevelExec keeps an R error in the handler from jumping over
+    // Rust frames.
+    unsafe { R_ToplevelExec(eval, call.as_ptr() as *mut c_void) != 0 }
+}
+
+/// Called by `R_init_iani()` before the routines are registered.
+#[no_mangle]
+pub extern "C" fn iani_install_hooks() {
+    set_printer(print);
+    set_interrupt_check(interrupt_pending);
+    set_reporter(report);
+}
@@ -1,518 +1,410 @@
+//! The extendr bindings of the `iani` R package: each exported function
+//! takes R's arguments, calls into `iani-core` and returns JSON for the R
+//! wrappers in `R/wrapper.R` to parse.
+
 use anyhow::Result;
 use extendr_api::prelude::*;
-use reqwest::blocking::Client;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::fs;
+use std::collections::BTreeMap;
 use std::path::Path;
-use url::Url;
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Association {
-    pub variant_id: Option<String>,
-    pub chromosome: Option<i32>,
-    pub base_pair_location: Option<i64>,
-    pub study_accession: Option<String>,
-    #[serde(rename = "trait")]
-    pub trait_ids: Option<Vec<String>>,
-    pub p_value: Option<f64>,
-    pub code: Option<i32>,
-    pub effect_allele: Option<String>,
-    pub other_allele: Option<String>,
-    pub effect_allele_frequency: Option<f64>,
-    pub odds_ratio: Option<f64>,
-    pub ci_lower: Option<f64>,
-    pub ci_upper: Option<f64>,
-    pub beta: Option<f64>,
-    pub se: Option<f64>,
-    #[serde(rename = "_links")]
-    pub links: Option<HashMap<String, serde_json::Value>>,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Link {
-    pub href: String,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct HalResponse<T> {
-    #[serde(rename = "_embedded")]
-    pub embedded: Option<HashMap<String, T>>,
-    #[serde(rename = "_links")]
-    pub links: Option<HashMap<String, serde_json::Value>>,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Chromosome {
-    pub chromosome: String,
-    #[serde(rename = "_links")]
-    pub links: Option<HashMap<String, Link>>,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Study {
-    pub study_accession: String,
-    #[serde(rename = "_links")]
-    pub links: Option<HashMap<String, Link>>,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Trait {
-    #[serde(rename = "trait")]
-    pub trait_name: String,
-    #[serde(rename = "_links")]
-    pub links: Option<HashMap<String, Link>>,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct SummaryStatsFile {
-    pub study_accession: String,
-    pub trait_id: Option<String>,
-    pub file_path: String,
-    pub file_size: Option<i64>,
-    pub file_type: Option<String>,
-    pub download_url: Option<String>,
-    #[serde(rename = "_links")]
-    pub links: Option<HashMap<String, Link>>,
-}
-
-#[derive(Debug, Default)]
-pub struct GwasFilter {
-    pub p_value_range: Option<(String, String)>,
-    pub bp_location_range: Option<(i64, i64)>,
-    pub study: Option<String>,
-    pub trait_id: Option<String>,
-    pub reveal: Option<String>,
-    pub start: Option<i32>,
-    pub size: Option<i32>,
-}
-
-impl GwasFilter {
-    pub fn to_params(&self) -> HashMap<String, String> {
-        let mut params = HashMap::new();
-
-        if let Some((lower, upper)) = &self.p_value_range {
-            params.insert("p_lower".to_string(), lower.clone());
-            params.insert("p_upper".to_string(), upper.clone());
-        }
-
-        if let Some((lower, upper)) = &self.bp_location_range {
-            params.insert("bp_lower".to_string(), lower.to_string());
-            params.insert("bp_upper".to_string(), upper.to_string());
-        }
 
-        if let Some(study) = &self.study {
-            params.insert("study_accession".to_string(), study.clone());
-        }
-
-        if let Some(trait_id) = &self.trait_id {
-            params.insert("trait".to_string(), trait_id.clone());
-        }
+use iani_core::backend::{
+    configure, configure_cassette, configure_headers, configure_json, configure_max_requests,
+    configure_max_response_bytes, configure_pool, configure_proxy, configure_timeouts,
+    configure_tls, current_backend, missing_as_empty, VariantInStudy,
+};
+use iani_core::catalog::CatalogClient;
+use iani_core::catalog_index::build_catalog_index;
+use iani_core::clump::{clump, ClumpSettings};
+use iani_core::count::count_associations;
+use iani_core::download::{self, DownloadMode};
+use iani_core::ensembl::{annotate_with_vep, EnsemblClient, VEP_FIELDS};
+use iani_core::export::{self, export_associations};
+use iani_core::ftp::FtpCrawler;
+use iani_core::genes::GeneTable;
+use iani_core::http::{parse_header, HeaderSettings};
+use iani_core::interrupt::with_interrupts;
+use iani_core::ld::{annotate_r2, PlinkFileset};
+use iani_core::liftover::{lift_associations, ChainMap};
+use iani_core::munge::{munge, MungeSettings};
+use iani_core::ols::OlsClient;
+use iani_core::opentargets::OpenTargetsClient;
+use iani_core::pgs::PgsClient;
+use iani_core::prs::compute_prs;
+use iani_core::refine::{sort_associations, Refinement, SortKey};
+use iani_core::remote_tabix::RemoteTabixFile;
+use iani_core::study_filter::{find_studies, StudyCriteria};
+use iani_core::study_sync::sync_studies;
+use iani_core::summary::summarize_cached;
+use iani_core::tabix::build_index;
+use iani_core::terms::require_acceptance;
+use iani_core::trait_labels::{dump_traits, load_trait_labels};
+use iani_core::trait_match::{match_traits, DEFAULT_MATCHES, DEFAULT_MIN_SCORE};
+use iani_core::trait_tree::{subtree_associations, subtree_studies, trait_subtree};
+use iani_core::variant_index::{build_variant_filter, filter_path, has_variants, study_similarity};
+use iani_core::zenodo::{Creator, DepositMetadata, ZenodoClient};
+use iani_core::{arrow, enrichment, evidence, jobs, logging, panels, progress, stats};
+use iani_core::{
+    associations_from_json, significance_threshold, to_json, Association, GwasClient, GwasFilter,
+    Link,
+};
+
+mod hooks;
+
+/// Configure the package for this R session
+/// @param backend Optional summary statistics backend ("ebi", "opengwas" or "local")
+/// @param token Optional API token for backends that require one
+/// @param mirror_dir Optional directory of tabix-indexed harmonised files for the local backend
+/// @param download_mirror Optional URL prefix for summary statistics downloads, or "auto" to measure the mirrors again
+/// @param connect_timeout Optional seconds allowed to establish a connection (0 for no limit)
+/// @param read_timeout Optional seconds allowed to wait for a response or more of its body (0 for no limit)
+/// @param timeout Optional seconds allowed for a whole API request (0 for no limit)
+/// @param max_response_bytes Optional size limit on an API response body in bytes (0 for no limit)
+/// @param proxy Optional proxy URL, "none" to connect directly, or "" for the proxy environment variables
+/// @param ca_file Optional PEM file of extra CA certificates to trust, or "" for none
+/// @param tls_backend Optional TLS implementation ("auto", "rustls" or "native")
+/// @param json Optional layout of returned JSON ("pretty" or "compact")
+/// @param max_requests Optional limit on API requests in flight at once
+/// @param pool_max_idle_per_host Optional number of idle connections kept open per host
+/// @param pool_idle_timeout Optional seconds an idle connection is kept open (0 for no limit)
+/// @param http2 Optional flag to offer HTTP/2 to servers instead of only HTTP/1.1
+/// @export
+#[extendr]
+#[allow(clippy::too_many_arguments)]
+fn gwas_configure(
+    backend: Option<String>,
+    token: Option<String>,
+    mirror_dir: Option<String>,
+    download_mirror: Option<String>,
+    connect_timeout: Option<f64>,
+    read_timeout: Option<f64>,
+    timeout: Option<f64>,
+    max_response_bytes: Option<f64>,
+    proxy: Option<String>,
+    ca_file: Option<String>,
+    tls_backend: Option<String>,
+    json: Option<String>,
+    max_requests: Option<f64>,
+    pool_max_idle_per_host: Option<f64>,
+    pool_idle_timeout: Option<f64>,
+    http2: Option<bool>,
+) -> String {
+    let configured = configure(backend, token, mirror_dir, download_mirror)
+        .and_then(|_| configure_timeouts(connect_timeout, read_timeout, timeout))
+        .and_then(|_| configure_max_response_bytes(max_response_bytes))
+        .and_then(|_| configure_proxy(proxy))
+        .and_then(|_| configure_tls(ca_file, tls_backend))
+        .and_then(|_| configure_json(json))
+        .and_then(|_| configure_max_requests(max_requests))
+        .and_then(|_| configure_pool(pool_max_idle_per_host, pool_idle_timeout, http2));
+    match configured {
+        Ok(config) => match to_json(&config) {
+            Ok(data) => data,
+            Err(e) => format!("Error serializing configuration: {e}"),
+        },
+        Err(e) => format!("Error configuring session: {e}"),
+    }
+}
 
-        if let Some(reveal) = &self.reveal {
-            params.insert("reveal".to_string(), reveal.clone());
-        }
+/// Report the progress of long operations to R
+/// @param enable Whether paged queries and downloads report their progress
+/// @export
+#[extendr]
+fn gwas_progress(enable: bool) -> String {
+    let previous = progress::set_enabled(enable);
+    serde_json::json!({ "enabled": enable, "previous": previous }).to_string()
+}
 
-        if let Some(start) = self.start {
-            params.insert("start".to_string(), start.to_string());
-        }
+/// Count the requests, bytes, cache use and rate-limit waits of the session
+/// @param reset Whether to zero the counters after reading them
+/// @export
+#[extendr]
+fn gwas_stats(reset: bool) -> String {
+    let stats = if reset {
+        stats::reset()
+    } else {
+        stats::snapshot()
+    };
+    match serde_json::to_string(&stats) {
+        Ok(data) => data,
+        Err(e) => format!("Error serializing statistics: {e}"),
+    }
+}
 
-        if let Some(size) = self.size {
-            params.insert("size".to_string(), size.to_string());
-        }
+/// Log requests to the R console
+/// @param level Most verbose level shown: "off", "error", "warn", "info", "debug" or "trace"
+/// @export
+#[extendr]
+fn gwas_set_verbose(level: &str) -> String {
+    logging::flush();
+    match logging::set_level(level) {
+        Ok(previous) => serde_json::json!({ "level": level, "previous": previous }).to_string(),
+        Err(e) => format!("Error: {e}"),
+    }
+}
 
-        params
+/// Set the User-Agent and extra headers sent with every request
+/// @param headers Optional "Name: value" lines replacing the extra headers
+/// @param user_agent Optional User-Agent, or "" for the default
+/// @param contact Optional contact added to the User-Agent, or "" for none
+/// @export
+#[extendr]
+fn gwas_headers(
+    headers: Option<Vec<String>>,
+    user_agent: Option<String>,
+    contact: Option<String>,
+) -> String {
+    let headers = match headers
+        .map(|lines| lines.iter().map(|line| parse_header(line)).collect())
+        .transpose()
+    {
+        Ok(headers) => headers,
+        Err(e) => return format!("Error: {e}"),
+    };
+    match configure_headers(headers, user_agent, contact) {
+        Ok(previous) => serde_json::json!({
+            "previous": {
+                "headers": previous.lines(),
+                "user_agent": previous.user_agent,
+                "contact": previous.contact,
+            },
+            "user_agent": HeaderSettings::session().user_agent(),
+        })
+        .to_string(),
+        Err(e) => format!("Error: {e}"),
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct GwasClient {
-    client: Client,
-    base_url: String,
+/// Record API responses to, or replay them from, a cassette directory
+/// @param mode Optional mode: "off", "record" or "replay"
+/// @param dir Optional cassette directory
+/// @export
+#[extendr]
+fn gwas_cassette(mode: Option<String>, dir: Option<String>) -> String {
+    match configure_cassette(mode, dir) {
+        Ok(previous) => match to_json(&previous) {
+            Ok(data) => data,
+            Err(e) => format!("Error serializing cassette settings: {e}"),
+        },
+        Err(e) => format!("Error: {e}"),
+    }
 }
 
-impl GwasClient {
-    pub fn new() -> Result<Self> {
-        Ok(Self {
-            client: Client::new(),
-            base_url: "https://www.ebi.ac.uk/gwas/summary-statistics/api".to_string(),
-        })
+/// Unified get function for entities (chromosomes, studies, traits)
+/// @param entity_type Type of entity: "chromosomes", "studies", or "traits"
+/// @param id Optional entity ID for specific entity
+/// @param start Offset number (default: 0)
+/// @param size Number of items returned (default: 20)
+/// @param dry_run Whether to return the request URL instead of sending it
+/// @param raw Whether to return the response body as the server sent it
+/// @export
+#[extendr]
+fn gwas_get(
+    entity_type: String,
+    id: Option<String>,
+    start: Option<i32>,
+    size: Option<i32>,
+    dry_run: bool,
+    raw: bool,
+) -> String {
+    let backend = match current_backend() {
+        Ok(b) => b,
+        Err(e) => return format!("Error creating client: {e}"),
+    };
+
+    let filter = GwasFilter {
+        start,
+        size,
+        ..Default::default()
+    };
+
+    if dry_run {
+        return dry_run_json(backend.entity_urls(&entity_type, id.as_deref(), &filter));
     }
 
-    pub fn with_base_url(base_url: String) -> Result<Self> {
-        Ok(Self {
-            client: Client::new(),
-            base_url,
-        })
+    let result = if raw {
+        backend.entity_body(&entity_type, id.as_deref(), &filter)
+    } else {
+        backend.entity(&entity_type, id.as_deref(), &filter)
+    };
+    match result {
+        Ok(data) => data,
+        Err(e) => format!("Error fetching {entity_type}: {e}"),
     }
+}
 
-    fn build_url(&self, endpoint: &str, params: &HashMap<String, String>) -> Result<Url> {
-        let mut url = Url::parse(&format!(
-            "{}/{}",
-            self.base_url,
-            endpoint.trim_start_matches('/')
-        ))?;
-        for (key, value) in params {
-            url.query_pairs_mut().append_pair(key, value);
-        }
-        Ok(url)
-    }
-
-    fn check_json_response(
-        &self,
-        response: reqwest::blocking::Response,
-    ) -> Result<reqwest::blocking::Response> {
-        if !response.status().is_success() {
-            let status = response.status();
-            let text = response
-                .text()
-                .unwrap_or_else(|_| "Unable to read response body".to_string());
-            return Err(anyhow::anyhow!("HTTP {}: {}", status, text));
+/// The p-value range of a query, its upper bound set by p_value_max or
+/// by a significance preset but not both; the error is returned to R.
+fn p_value_bounds(
+    p_value_min: Option<String>,
+    p_value_max: Option<String>,
+    significance: Option<String>,
+) -> std::result::Result<Option<(String, String)>, String> {
+    let p_value_max = match (significance, p_value_max) {
+        (Some(_), Some(_)) => return Err(
+            "Error: significance and p_value_max both set the p-value threshold; pass one of them"
+                .to_string(),
+        ),
+        (Some(significance), None) => {
+            Some(significance_threshold(&significance).map_err(|e| format!("Error: {e}"))?)
         }
+        (None, p_value_max) => p_value_max,
+    };
 
-        if let Some(content_type) = response.headers().get("content-type") {
-            if let Ok(ct_str) = content_type.to_str() {
-                if !ct_str.contains("application/json") {
-                    return Err(anyhow::anyhow!("Expected JSON response, got: {}", ct_str));
-                }
-            }
-        }
+    Ok(match (p_value_min, p_value_max) {
+        (Some(min), Some(max)) => Some((min, max)),
+        (Some(min), None) => Some((min, "1.0".to_string())),
+        (None, Some(max)) => Some(("0.0".to_string(), max)),
+        (None, None) => None,
+    })
+}
 
-        Ok(response)
-    }
-
-    pub fn get_associations(
-        &self,
-        params: HashMap<String, String>,
-    ) -> Result<HalResponse<HashMap<String, Association>>> {
-        let url = self.build_url("/associations", &params)?;
-        let response = self.client.get(url).send()?;
-        let response = self.check_json_response(response)?;
-        let data: HalResponse<HashMap<String, Association>> = response.json()?;
-        Ok(data)
-    }
-
-    pub fn get_variant_associations(
-        &self,
-        variant_id: &str,
-        params: HashMap<String, String>,
-    ) -> Result<HalResponse<HashMap<String, Association>>> {
-        let endpoint = format!("/associations/{variant_id}");
-        let url = self.build_url(&endpoint, &params)?;
-        let response = self.client.get(url).send()?;
-        let response = self.check_json_response(response)?;
-        let data: HalResponse<HashMap<String, Association>> = response.json()?;
-        Ok(data)
-    }
-
-    pub fn get_chromosomes(&self) -> Result<HalResponse<Vec<Chromosome>>> {
-        let url = self.build_url("/chromosomes", &HashMap::new())?;
-        let response = self.client.get(url).send()?;
-        let response = self.check_json_response(response)?;
-        let data: HalResponse<Vec<Chromosome>> = response.json()?;
-        Ok(data)
-    }
-
-    pub fn get_chromosome(&self, chromosome: &str) -> Result<Chromosome> {
-        let endpoint = format!("/chromosomes/{chromosome}");
-        let url = self.build_url(&endpoint, &HashMap::new())?;
-        let response = self.client.get(url).send()?;
-        let response = self.check_json_response(response)?;
-        let data: Chromosome = response.json()?;
-        Ok(data)
-    }
-
-    pub fn get_chromosome_associations(
-        &self,
-        chromosome: &str,
-        params: HashMap<String, String>,
-    ) -> Result<HalResponse<HashMap<String, Association>>> {
-        let endpoint = format!("/chromosomes/{chromosome}/associations");
-        let url = self.build_url(&endpoint, &params)?;
-        let response = self.client.get(url).send()?;
-        let response = self.check_json_response(response)?;
-        let data: HalResponse<HashMap<String, Association>> = response.json()?;
-        Ok(data)
-    }
-
-    pub fn get_chromosome_variant_associations(
-        &self,
-        chromosome: &str,
-        variant_id: &str,
-        params: HashMap<String, String>,
-    ) -> Result<HalResponse<HashMap<String, Association>>> {
-        let endpoint = format!("/chromosomes/{chromosome}/associations/{variant_id}");
-        let url = self.build_url(&endpoint, &params)?;
-        let response = self.client.get(url).send()?;
-        let response = self.check_json_response(response)?;
-        let data: HalResponse<HashMap<String, Association>> = response.json()?;
-        Ok(data)
-    }
-
-    pub fn get_studies(
-        &self,
-        params: HashMap<String, String>,
-    ) -> Result<HalResponse<Vec<Vec<Study>>>> {
-        let url = self.build_url("/studies", &params)?;
-        let response = self.client.get(url).send()?;
-        let response = self.check_json_response(response)?;
-        let data: HalResponse<Vec<Vec<Study>>> = response.json()?;
-        Ok(data)
-    }
-
-    pub fn get_study(&self, study_accession: &str) -> Result<Study> {
-        let endpoint = format!("/studies/{study_accession}");
-        let url = self.build_url(&endpoint, &HashMap::new())?;
-        let response = self.client.get(url).send()?;
-        let response = self.check_json_response(response)?;
-        let data: Study = response.json()?;
-        Ok(data)
-    }
-
-    pub fn get_study_associations(
-        &self,
-        study_accession: &str,
-        params: HashMap<String, String>,
-    ) -> Result<HalResponse<HashMap<String, Association>>> {
-        let endpoint = format!("/studies/{study_accession}/associations");
-        let url = self.build_url(&endpoint, &params)?;
-        let response = self.client.get(url).send()?;
-        let response = self.check_json_response(response)?;
-        let data: HalResponse<HashMap<String, Association>> = response.json()?;
-        Ok(data)
-    }
-
-    pub fn get_traits(&self, params: HashMap<String, String>) -> Result<HalResponse<Vec<Trait>>> {
-        let url = self.build_url("/traits", &params)?;
-        let response = self.client.get(url).send()?;
-        let response = self.check_json_response(response)?;
-        let data: HalResponse<Vec<Trait>> = response.json()?;
-        Ok(data)
-    }
-
-    pub fn get_trait(&self, trait_id: &str) -> Result<Trait> {
-        let endpoint = format!("/traits/{trait_id}");
-        let url = self.build_url(&endpoint, &HashMap::new())?;
-        let response = self.client.get(url).send()?;
-        let response = self.check_json_response(response)?;
-        let data: Trait = response.json()?;
-        Ok(data)
-    }
-
-    pub fn get_trait_associations(
-        &self,
-        trait_id: &str,
-        params: HashMap<String, String>,
-    ) -> Result<HalResponse<HashMap<String, Association>>> {
-        let endpoint = format!("/traits/{trait_id}/associations");
-        let url = self.build_url(&endpoint, &params)?;
-        let response = self.client.get(url).send()?;
-        let response = self.check_json_response(response)?;
-        let data: HalResponse<HashMap<String, Association>> = response.json()?;
-        Ok(data)
-    }
-
-    pub fn get_trait_studies(
-        &self,
-        trait_id: &str,
-        params: HashMap<String, String>,
-    ) -> Result<HalResponse<Vec<Study>>> {
-        let endpoint = format!("/traits/{trait_id}/studies");
-        let url = self.build_url(&endpoint, &params)?;
-        let response = self.client.get(url).send()?;
-        let response = self.check_json_response(response)?;
-        let data: HalResponse<Vec<Study>> = response.json()?;
-        Ok(data)
-    }
-
-    pub fn get_trait_study(&self, trait_id: &str, study_accession: &str) -> Result<Study> {
-        let endpoint = format!("/traits/{trait_id}/studies/{study_accession}");
-        let url = self.build_url(&endpoint, &HashMap::new())?;
-        let response = self.client.get(url).send()?;
-        let response = self.check_json_response(response)?;
-        let data: Study = response.json()?;
-        Ok(data)
-    }
-
-    pub fn get_trait_study_associations(
-        &self,
-        trait_id: &str,
-        study_accession: &str,
-        params: HashMap<String, String>,
-    ) -> Result<HalResponse<HashMap<String, Association>>> {
-        let endpoint = format!("/traits/{trait_id}/studies/{study_accession}/associations");
-        let url = self.build_url(&endpoint, &params)?;
-        let response = self.client.get(url).send()?;
-        let response = self.check_json_response(response)?;
-        let data: HalResponse<HashMap<String, Association>> = response.json()?;
-        Ok(data)
-    }
-
-    pub fn get_study_summary_stats_files(
-        &self,
-        study_accession: &str,
-    ) -> Result<HalResponse<Vec<SummaryStatsFile>>> {
-        let endpoint = format!("/studies/{study_accession}/summary-statistics");
-        let url = self.build_url(&endpoint, &HashMap::new())?;
-        let response = self.client.get(url).send()?;
-
-        let response = self.check_json_response(response)?;
-        let data: HalResponse<Vec<SummaryStatsFile>> = response.json()?;
-        Ok(data)
-    }
-
-    pub fn get_trait_summary_stats_files(
-        &self,
-        trait_id: &str,
-    ) -> Result<HalResponse<Vec<SummaryStatsFile>>> {
-        let endpoint = format!("/traits/{trait_id}/summary-statistics");
-        let url = self.build_url(&endpoint, &HashMap::new())?;
-        let response = self.client.get(url).send()?;
-
-        let response = self.check_json_response(response)?;
-        let data: HalResponse<Vec<SummaryStatsFile>> = response.json()?;
-        Ok(data)
-    }
-
-    pub fn get_trait_study_summary_stats_files(
-        &self,
-        trait_id: &str,
-        study_accession: &str,
-    ) -> Result<HalResponse<Vec<SummaryStatsFile>>> {
-        let endpoint = format!("/traits/{trait_id}/studies/{study_accession}/summary-statistics");
-        let url = self.build_url(&endpoint, &HashMap::new())?;
-        let response = self.client.get(url).send()?;
-
-        let response = self.check_json_response(response)?;
-        let data: HalResponse<Vec<SummaryStatsFile>> = response.json()?;
-        Ok(data)
-    }
-
-    pub fn download_summary_stats_file(&self, file_url: &str, output_path: &str) -> Result<String> {
-        let mut response = self.client.get(file_url).send()?;
-        if let Some(parent) = Path::new(output_path).parent() {
-            fs::create_dir_all(parent)?;
-        }
-        let mut file = fs::File::create(output_path)?;
-        std::io::copy(&mut response, &mut file)?;
-        Ok(output_path.to_string())
-    }
-
-    pub fn get_entity(
-        &self,
-        entity_type: &str,
-        id: Option<&str>,
-        filter: &GwasFilter,
-    ) -> Result<String> {
-        let params = filter.to_params();
-
-        match entity_type {
-            "chromosomes" => {
-                if let Some(chromosome_id) = id {
-                    match self.get_chromosome(chromosome_id) {
-                        Ok(data) => Ok(serde_json::to_string_pretty(&data)?),
-                        Err(e) => Err(e),
-                    }
-                } else {
-                    match self.get_chromosomes() {
-                        Ok(data) => Ok(serde_json::to_string_pretty(&data)?),
-                        Err(e) => Err(e),
-                    }
-                }
-            }
-            "studies" => {
-                if let Some(study_id) = id {
-                    match self.get_study(study_id) {
-                        Ok(data) => Ok(serde_json::to_string_pretty(&data)?),
-                        Err(e) => Err(e),
-                    }
-                } else {
-                    match self.get_studies(params) {
-                        Ok(data) => Ok(serde_json::to_string_pretty(&data)?),
-                        Err(e) => Err(e),
-                    }
-                }
-            }
-            "traits" => {
-                if let Some(trait_id) = id {
-                    match self.get_trait(trait_id) {
-                        Ok(data) => Ok(serde_json::to_string_pretty(&data)?),
-                        Err(e) => Err(e),
-                    }
-                } else {
-                    match self.get_traits(params) {
-                        Ok(data) => Ok(serde_json::to_string_pretty(&data)?),
-                        Err(e) => Err(e),
-                    }
-                }
-            }
-            _ => Err(anyhow::anyhow!("Invalid entity type: {}", entity_type)),
+/// What a `dry_run` call returns: the request URLs, unsent.
+fn dry_run_json(urls: Result<Vec<String>>) -> String {
+    match urls {
+        Ok(urls) => match to_json(&serde_json::json!({ "dry_run": true, "urls": urls })) {
+            Ok(data) => data,
+            Err(e) => format!("Error serializing request URLs: {e}"),
+        },
+        Err(e) => format!("Error building request URLs: {e}"),
+    }
+}
+
+/// GET any API endpoint and return its JSON unchanged
+/// @param endpoint Path under the API base URL, e.g. "/traits/EFO_0001645"
+/// @param param_names Query parameter names
+/// @param param_values Query parameter values, matching `param_names`
+/// @param dry_run Whether to return the request URL instead of sending it
+/// @export
+#[extendr]
+fn gwas_raw(
+    endpoint: String,
+    param_names: Vec<String>,
+    param_values: Vec<String>,
+    dry_run: bool,
+) -> String {
+    if param_names.len() != param_values.len() {
+        return "Error: every parameter needs a name and a value".to_string();
+    }
+    let mut params = BTreeMap::new();
+    for (name, value) in param_names.into_iter().zip(param_values) {
+        if params.contains_key(&name) {
+            return format!("Error: parameter {name} is given twice");
         }
+        params.insert(name, value);
     }
+    let client = match GwasClient::new() {
+        Ok(c) => c,
+        Err(e) => return format!("Error creating client: {e}"),
+    };
 
-    pub fn get_unified_associations(
-        &self,
-        entity_type: Option<&str>,
-        entity_id: Option<&str>,
-        filter: &GwasFilter,
-    ) -> Result<String> {
-        let params = filter.to_params();
+    if dry_run {
+        let url = client.raw_url(&endpoint, &params);
+        return dry_run_json(url.map(|url| vec![url.into()]));
+    }
 
-        let result = match (entity_type, entity_id) {
-            (None, None) => self.get_associations(params),
-            (Some("variant"), Some(variant_id)) => {
-                self.get_variant_associations(variant_id, params)
-            }
-            (Some("chromosome"), Some(chromosome_id)) => {
-                self.get_chromosome_associations(chromosome_id, params)
-            }
-            (Some("study"), Some(study_id)) => self.get_study_associations(study_id, params),
-            (Some("trait"), Some(trait_id)) => self.get_trait_associations(trait_id, params),
-            _ => return Err(anyhow::anyhow!("Invalid entity type or missing ID")),
-        };
+    match client.get_raw(&endpoint, &params) {
+        Ok(data) => data,
+        Err(e) => format!("Error fetching {endpoint}: {e}"),
+    }
+}
 
-        match result {
-            Ok(data) => Ok(serde_json::to_string_pretty(&data)?),
-            Err(e) => Err(e),
-        }
+/// Follow a link from the `_links` of an API response
+/// @param url Link URL, which must lie under the API base URL
+/// @export
+#[extendr]
+fn gwas_follow(url: String) -> String {
+    let client = match GwasClient::new() {
+        Ok(c) => c,
+        Err(e) => return format!("Error creating client: {e}"),
+    };
+
+    match client.follow_link(&Link { href: url }) {
+        Ok(data) => match to_json(&data) {
+            Ok(json) => json,
+            Err(e) => format!("Error serializing response: {e}"),
+        },
+        Err(e) => format!("Error following link: {e}"),
     }
+}
 
-    pub fn list_files(
-        &self,
-        entity_type: &str,
-        entity_id: &str,
-        secondary_id: Option<&str>,
-    ) -> Result<String> {
-        let result = match (entity_type, secondary_id) {
-            ("study", None) => self.get_study_summary_stats_files(entity_id),
-            ("trait", None) => self.get_trait_summary_stats_files(entity_id),
-            ("trait", Some(study_id)) => {
-                self.get_trait_study_summary_stats_files(entity_id, study_id)
-            }
-            _ => return Err(anyhow::anyhow!("Invalid file entity type or parameters")),
-        };
+/// Get a study with its GWAS Catalog metadata
+/// @param accession Study accession
+/// @export
+#[extendr]
+fn gwas_study_info(accession: String) -> String {
+    let client = match GwasClient::new() {
+        Ok(c) => c,
+        Err(e) => return format!("Error creating client: {e}"),
+    };
 
-        match result {
-            Ok(data) => Ok(serde_json::to_string_pretty(&data)?),
-            Err(e) => Err(e),
-        }
+    let info = client
+        .get_study(&accession)
+        .and_then(|study| CatalogClient::new().study_info(study));
+    match info {
+        Ok(info) => match to_json(&info) {
+            Ok(data) => data,
+            Err(e) => format!("Error serializing study info: {e}"),
+        },
+        Err(e) => format!("Error fetching study info for {accession}: {e}"),
     }
 }
 
-/// Unified get function for entities (chromosomes, studies, traits)
-/// @param entity_type Type of entity: "chromosomes", "studies", or "traits"
-/// @param id Optional entity ID for specific entity
+/// Find the studies of a trait by GWAS Catalog metadata
+/// @param trait_id EFO trait ID
+/// @param min_n Optional smallest discovery sample size
+/// @param ancestry Optional ancestral groups, one of which a discovery ancestry must contain
+/// @param since Optional earliest publication year
+/// @param include_child_traits Whether to add the studies of every trait below it in EFO
+/// @export
+#[extendr]
+fn gwas_find_studies(
+    trait_id: String,
+    min_n: Option<f64>,
+    ancestry: Option<Vec<String>>,
+    since: Option<i32>,
+    include_child_traits: bool,
+) -> String {
+    let criteria = match StudyCriteria::new(min_n, ancestry.unwrap_or_default(), since) {
+        Ok(criteria) => criteria,
+        Err(e) => return format!("Error: {e}"),
+    };
+    let client = match GwasClient::new() {
+        Ok(client) => client,
+        Err(e) => return format!("Error creating client: {e}"),
+    };
+    let studies = with_interrupts(client.interrupt(), || {
+        let trait_ids = if include_child_traits {
+            trait_subtree(&OlsClient::new()?, &trait_id)?
+        } else {
+            vec![trait_id.clone()]
+        };
+        find_studies(&client, &CatalogClient::new(), &trait_ids, &criteria)
+    });
+    match studies {
+        Ok(studies) => match to_json(&studies) {
+            Ok(data) => data,
+            Err(e) => format!("Error serializing studies: {e}"),
+        },
+        Err(e) => format!("Error finding studies of {trait_id}: {e}"),
+    }
+}
+
+/// Query the PGS Catalog for polygenic scores and traits
+/// @param entity_type "score", "scores", "trait" or "traits"
+/// @param query PGS ID, EFO trait ID or trait search term
 /// @param start Offset number (default: 0)
-/// @param size Number of items returned (default: 20)
+/// @param size Number of items returned (default: 50)
+/// @param dry_run Whether to return the request URL instead of sending it
 /// @export
 #[extendr]
-fn gwas_get(
+fn gwas_pgs(
     entity_type: String,
-    id: Option<String>,
+    query: String,
     start: Option<i32>,
     size: Option<i32>,
+    dry_run: bool,
 ) -> String {
-    let client = match GwasClient::new() {
+    let client = match PgsClient::new() {
         Ok(c) => c,
         Err(e) => return format!("Error creating client: {e}"),
     };
@@ -523,9 +415,14 @@ fn gwas_get(
         ..Default::default()
     };
 
-    match client.get_entity(&entity_type, id.as_deref(), &filter) {
+    if dry_run {
+        let url = client.entity_url(&entity_type, &query, &filter);
+        return dry_run_json(url.map(|url| vec![url.into()]));
+    }
+
+    match client.get_entity(&entity_type, &query, &filter) {
         Ok(data) => data,
-        Err(e) => format!("Error fetching {entity_type}: {e}"),
+        Err(e) => format!("Error fetching PGS Catalog {entity_type}: {e}"),
     }
 }
 
@@ -536,11 +433,31 @@ fn gwas_get(
 /// @param p_value_max Optional maximum p-value threshold
 /// @param bp_min Optional minimum base pair location
 /// @param bp_max Optional maximum base pair location
-/// @param study Optional study accession filter
-/// @param trait_id Optional trait ID filter
+/// @param study Optional study accession filter, or a comma-separated list of accessions
+/// @param trait_id Optional trait ID filter, or a comma-separated list of trait IDs. Lists
+///   too long for one request URL are split across several requests and merged, each
+///   paged by start and size.
 /// @param reveal Optional reveal mode ("raw" or "all")
 /// @param start Offset number (default: 0)
 /// @param size Number of items returned (default: 20)
+/// @param build Optional genome build for returned positions ("GRCh38" or "GRCh37")
+/// @param chain_file Optional UCSC chain file used to convert to `build`
+/// @param pages Optional number of consecutive pages of `size` to fetch concurrently and merge; Ctrl-C keeps the pages already fetched and marks the result "interrupted": true
+/// @param dry_run Whether to return the request URLs instead of sending them
+/// @param raw Whether to return the response body as the server sent it
+/// @param output_file Optional file to write the rows to page by page, fetching every page unless pages is set
+/// @param format Optional format of output_file ("ndjson", "csv" or "tsv"); names ending in .gz are gzip-compressed
+/// @param arrow_schema Optional address of an ArrowSchema to move the rows' schema into
+/// @param arrow_array Optional address of an ArrowArray to move the rows into as a record batch
+/// @param missing_ok Whether a 404 for an entity without records is an empty result marked "found": false rather than an error
+/// @param significance Optional p-value threshold in place of p_value_max: "genome_wide" (5e-8), "suggestive" (1e-5) or a number
+/// @param eaf_min Optional minimum effect allele frequency, applied to the rows on the client
+/// @param eaf_max Optional maximum effect allele frequency, applied to the rows on the client
+/// @param beta_sign Optional sign of beta to keep ("positive" or "negative"), applied on the client
+/// @param se_max Optional maximum standard error, applied on the client
+/// @param columns Optional fields to return, in order; the others are left out of the result
+/// @param sort_by Optional order of the merged rows ("p_value", "bp_location" or "chromosome_bp")
+/// @param include_child_traits Whether a trait query also queries every trait below it in EFO
 /// @export
 #[allow(clippy::too_many_arguments)]
 #[extendr]
@@ -556,17 +473,45 @@ fn gwas_associations(
     reveal: Option<String>,
     start: Option<i32>,
     size: Option<i32>,
+    build: Option<String>,
+    chain_file: Option<String>,
+    pages: Option<i32>,
+    dry_run: bool,
+    raw: bool,
+    output_file: Option<String>,
+    format: Option<String>,
+    arrow_schema: Option<f64>,
+    arrow_array: Option<f64>,
+    missing_ok: bool,
+    significance: Option<String>,
+    eaf_min: Option<f64>,
+    eaf_max: Option<f64>,
+    beta_sign: Option<String>,
+    se_max: Option<f64>,
+    columns: Option<Vec<String>>,
+    sort_by: Option<String>,
+    include_child_traits: bool,
 ) -> String {
-    let client = match GwasClient::new() {
+    let backend = match current_backend() {
         Ok(c) => c,
         Err(e) => return format!("Error creating client: {e}"),
     };
 
-    let p_value_range = match (p_value_min, p_value_max) {
-        (Some(min), Some(max)) => Some((min, max)),
-        (Some(min), None) => Some((min, "1.0".to_string())),
-        (None, Some(max)) => Some(("0.0".to_string(), max)),
-        (None, None) => None,
+    let chain = match (build.as_deref(), chain_file) {
+        (None, _) | (Some("GRCh38"), _) => None,
+        (Some("GRCh37"), Some(path)) => match ChainMap::open(&path) {
+            Ok(chain) => Some(chain),
+            Err(e) => return format!("Error reading chain file: {e}"),
+        },
+        (Some("GRCh37"), None) => {
+            return "Error: chain_file is required to convert positions to GRCh37".to_string()
+        }
+        (Some(other), _) => return format!("Error: unsupported build {other}"),
+    };
+
+    let p_value_range = match p_value_bounds(p_value_min, p_value_max, significance) {
+        Ok(range) => range,
+        Err(e) => return e,
     };
 
     let bp_location_range = match (bp_min, bp_max) {
@@ -584,97 +529,1327 @@ fn gwas_associations(
         size,
     };
 
-    match client.get_unified_associations(entity_type.as_deref(), entity_id.as_deref(), &filter) {
+    let eaf_range = match (eaf_min, eaf_max) {
+        (None, None) => None,
+        (min, max) => Some((min.unwrap_or(0.0), max.unwrap_or(1.0))),
+    };
+    let refinement =
+        match Refinement::new(eaf_range, beta_sign.as_deref(), se_max, columns.as_deref()) {
+            Ok(refinement) => refinement,
+            Err(e) => return format!("Error: {e}"),
+        };
+    let sort_by = match sort_by.as_deref().map(SortKey::parse).transpose() {
+        Ok(sort_by) => sort_by,
+        Err(e) => return format!("Error: {e}"),
+    };
+
+    let subtree = match (include_child_traits, entity_type.as_deref(), entity_id.as_deref()) {
+        (false, _, _) => None,
+        (true, Some("trait"), Some(trait_id)) => {
+            if dry_run || raw || output_file.is_some() {
+                return "Error: include_child_traits queries each trait of the subtree in turn, so it cannot be combined with dry_run, raw or output_file".to_string();
+            }
+            let subtree = OlsClient::new().and_then(|ols| trait_subtree(&ols, trait_id));
+            match subtree {
+                Ok(subtree) => Some(subtree),
+                Err(e) => return format!("Error looking up child traits: {e}"),
+            }
+        }
+        (true, _, _) => {
+            return "Error: include_child_traits applies to trait queries, with entity_type \"trait\" and an entity_id".to_string()
+        }
+    };
+
+    if dry_run {
+        return dry_run_json(backend.association_urls(
+            entity_type.as_deref(),
+            entity_id.as_deref(),
+            &filter,
+            pages.unwrap_or(1).max(1) as usize,
+        ));
+    }
+
+    if arrow_array.is_some() && (raw || output_file.is_some()) {
+        return "Error: arrow cannot be combined with raw or output_file".to_string();
+    }
+
+    if raw {
+        if build.is_some() || pages.map_or(false, |pages| pages > 1) || output_file.is_some() {
+            return "Error: raw returns one response unchanged, so it cannot be combined with build, pages or output_file".to_string();
+        }
+        if refinement != Refinement::default() || sort_by.is_some() {
+            return "Error: raw returns one response unchanged, so it cannot be combined with eaf, beta_sign, se_max, columns or sort_by".to_string();
+        }
+        return match backend.association_body(entity_type.as_deref(), entity_id.as_deref(), &filter)
+        {
+            Ok(data) => data,
+            Err(e) => format!("Error fetching associations: {e}"),
+        };
+    }
+
+    if output_file.is_some() && sort_by.is_some() {
+        return "Error: output_file is written page by page, so it cannot be sorted with sort_by"
+            .to_string();
+    }
+
+    if let Some(output_file) = output_file {
+        let format = format.unwrap_or_else(|| export::FORMATS[0].to_string());
+        let export = || {
+            export_associations(
+                backend.as_ref(),
+                entity_type.as_deref(),
+                entity_id.as_deref(),
+                &filter,
+                pages.filter(|pages| *pages > 0).map(|pages| pages as usize),
+                chain.as_ref(),
+                &refinement,
+                &format,
+                &output_file,
+            )
+        };
+        let result = match backend.interrupt() {
+            Some(interrupt) => with_interrupts(interrupt, export),
+            None => export(),
+        };
+        return match result {
+            Ok(summary) => match to_json(&summary) {
+                Ok(data) => data,
+                Err(e) => format!("Error serializing export summary: {e}"),
+            },
+            Err(e) => format!("Error exporting associations: {e}"),
+        };
+    } else if format.is_some() {
+        return "Error: format applies to output_file, which is not set".to_string();
+    }
+
+    let fetch_entity = |entity_id: Option<&str>| match pages {
+        Some(pages) if pages > 1 => {
+            backend.association_pages(entity_type.as_deref(), entity_id, &filter, pages as usize)
+        }
+        _ => backend.associations(entity_type.as_deref(), entity_id, &filter),
+    };
+    let fetch = || match &subtree {
+        Some(subtree) => subtree_associations(subtree, backend.interrupt(), |trait_id| {
+            fetch_entity(Some(trait_id))
+        }),
+        None => fetch_entity(entity_id.as_deref()),
+    };
+    // Ctrl-C ends a paged query early with the pages fetched so far.
+    let result = match backend.interrupt() {
+        Some(interrupt) => with_interrupts(interrupt, fetch),
+        None => fetch(),
+    };
+    let result = if missing_ok {
+        missing_as_empty(result)
+    } else {
+        result
+    };
+    let mut data = match result {
+        Ok(data) => data,
+        Err(e) => return format!("Error fetching associations: {e}"),
+    };
+
+    if let Some(chain) = &chain {
+        lift_associations(data.associations_mut(), chain);
+    }
+    refinement.filter(&mut data);
+    if let Some(sort_by) = sort_by {
+        sort_associations(
+            &mut data,
+            sort_by,
+            filter.start.unwrap_or(0).max(0) as usize,
+        );
+    }
+
+    if let (Some(schema), Some(array)) = (arrow_schema, arrow_array) {
+        let rows: Vec<Association> = data.associations_mut().map(std::mem::take).collect();
+        let exported = arrow::address(schema)
+            .and_then(|schema| Ok((schema, arrow::address(array)?)))
+            // Safety: the R wrapper passes structs nanoarrow just allocated.
+            .and_then(|(schema, array)| unsafe {
+                arrow::export_rows(&rows, refinement.columns.as_deref(), schema, array)
+            });
+        return match exported {
+            Ok(()) => serde_json::json!({ "rows": rows.len(), "interrupted": data.interrupted })
+                .to_string(),
+            Err(e) => format!("Error exporting associations to Arrow: {e}"),
+        };
+    }
+
+    let json = if refinement.columns.is_some() {
+        refinement
+            .project(data)
+            .and_then(|data| Ok(to_json(&data)?))
+    } else {
+        to_json(&data).map_err(anyhow::Error::from)
+    };
+    match json {
         Ok(data) => data,
         Err(e) => format!("Error fetching associations: {e}"),
     }
 }
 
-/// Unified file operations (list and download)
-/// @param operation Operation type: "list" or "download"
-/// @param entity_type Entity type: "study" or "trait"
-/// @param entity_id Primary entity ID
-/// @param secondary_id Optional secondary ID (for trait-study combinations)
-/// @param file_urls Optional vector of file URLs (for download)
-/// @param output_paths Optional vector of output paths (for download)
-/// @param max_concurrent Optional max concurrent downloads (default: 4)
+/// Convert association positions between genome builds
+/// @param associations JSON returned by gwas_associations() or a JSON array of associations
+/// @param chain_file UCSC chain file (plain or gzipped), e.g. hg38ToHg19.over.chain.gz
 /// @export
-#[allow(clippy::too_many_arguments)]
 #[extendr]
-fn gwas_files(
-    operation: String,
-    entity_type: String,
-    entity_id: String,
-    secondary_id: Option<String>,
-    file_urls: Option<Vec<String>>,
-    output_paths: Option<Vec<String>>,
-    max_concurrent: Option<usize>,
-) -> String {
-    let client = match GwasClient::new() {
-        Ok(c) => c,
-        Err(e) => return format!("Error creating client: {e}"),
+fn gwas_liftover(associations: String, chain_file: String) -> String {
+    let chain = match ChainMap::open(&chain_file) {
+        Ok(chain) => chain,
+        Err(e) => return format!("Error reading chain file: {e}"),
     };
 
-    match operation.as_str() {
-        "list" => match client.list_files(&entity_type, &entity_id, secondary_id.as_deref()) {
-            Ok(data) => data,
-            Err(e) => format!("Error listing files: {e}"),
-        },
-        "download" => {
-            match (file_urls, output_paths) {
-                (Some(urls), Some(paths)) => {
-                    if urls.len() != paths.len() {
-                        return "Error: file_urls and output_paths must have the same length."
-                            .to_string();
-                    }
+    let mut associations = match associations_from_json(&associations) {
+        Ok(a) => a,
+        Err(e) => return format!("Error parsing associations: {e}"),
+    };
+    lift_associations(associations.iter_mut(), &chain);
 
-                    let max_concurrent = max_concurrent.unwrap_or(4);
-
-                    use rayon::prelude::*;
-                    use rayon::ThreadPoolBuilder;
-
-                    // Build a custom thread pool with the desired number of threads
-                    let pool = match ThreadPoolBuilder::new().num_threads(max_concurrent).build() {
-                        Ok(p) => p,
-                        Err(e) => return format!("Error creating thread pool: {e}"),
-                    };
-
-                    let results = pool.install(|| {
-                        urls.par_iter()
-                            .zip(paths.par_iter())
-                            .map(|(url, path)| {
-                                match client.download_summary_stats_file(url, path) {
-                                    Ok(p) => Ok(format!("Downloaded: {p}")),
-                                    Err(e) => Err(format!("Failed to download {url}: {e}")),
-                                }
-                            })
-                            .collect::<Vec<_>>()
-                    });
-
-                    // Format results
-                    let mut success_count = 0;
-                    let mut error_messages = Vec::new();
-
-                    for result in results {
-                        match result {
-                            Ok(_) => success_count += 1,
-                            Err(err) => error_messages.push(err),
-                        }
-                    }
+    match to_json(&associations) {
+        Ok(data) => data,
+        Err(e) => format!("Error serializing associations: {e}"),
+    }
+}
 
-                    format!(
-                        "Downloaded {} of {} files successfully.\n{}",
-                        success_count,
-                        urls.len(),
-                        error_messages.join("\n")
-                    )
-                }
-                _ => {
-                    "Error: file_urls and output_paths required for download operation".to_string()
-                }
-            }
-        }
-        _ => format!("Invalid operation: {operation}. Use 'list' or 'download'"),
+/// Count the associations matching a filter without returning them
+/// @param entity_type Optional entity type: "variant", "chromosome", "study", "trait"
+/// @param entity_id Optional entity ID
+/// @param p_value_min Optional minimum p-value threshold
+/// @param p_value_max Optional maximum p-value threshold
+/// @param bp_min Optional minimum base pair location
+/// @param bp_max Optional maximum base pair location
+/// @param study Optional study accession filter, or a comma-separated list of accessions
+/// @param trait_id Optional trait ID filter, or a comma-separated list of trait IDs
+/// @param reveal Optional reveal mode ("raw" or "all")
+/// @param start Offset to count from (default: 0)
+/// @param significance Optional p-value threshold in place of p_value_max: "genome_wide" (5e-8), "suggestive" (1e-5) or a number
+/// @param eaf_min Optional minimum effect allele frequency of the rows counted
+/// @param eaf_max Optional maximum effect allele frequency of the rows counted
+/// @param beta_sign Optional sign of beta of the rows counted ("positive" or "negative")
+/// @param se_max Optional maximum standard error of the rows counted
+/// @export
+#[allow(clippy::too_many_arguments)]
+#[extendr]
+fn gwas_count(
+    entity_type: Option<String>,
+    entity_id: Option<String>,
+    p_value_min: Option<String>,
+    p_value_max: Option<String>,
+    bp_min: Option<i64>,
+    bp_max: Option<i64>,
+    study: Option<String>,
+    trait_id: Option<String>,
+    reveal: Option<String>,
+    start: Option<i32>,
+    significance: Option<String>,
+    eaf_min: Option<f64>,
+    eaf_max: Option<f64>,
+    beta_sign: Option<String>,
+    se_max: Option<f64>,
+) -> String {
+    let backend = match current_backend() {
+        Ok(b) => b,
+        Err(e) => return format!("Error creating client: {e}"),
+    };
+    let p_value_range = match p_value_bounds(p_value_min, p_value_max, significance) {
+        Ok(range) => range,
+        Err(e) => return e,
+    };
+    let filter = GwasFilter {
+        p_value_range,
+        bp_location_range: bp_min.zip(bp_max),
+        study,
+        trait_id,
+        reveal,
+        start,
+        size: None,
+    };
+    let eaf_range = match (eaf_min, eaf_max) {
+        (None, None) => None,
+        (min, max) => Some((min.unwrap_or(0.0), max.unwrap_or(1.0))),
+    };
+    let refinement = match Refinement::new(eaf_range, beta_sign.as_deref(), se_max, None) {
+        Ok(refinement) => refinement,
+        Err(e) => return format!("Error: {e}"),
+    };
+
+    let count = || {
+        count_associations(
+            backend.as_ref(),
+            entity_type.as_deref(),
+            entity_id.as_deref(),
+            &filter,
+            &refinement,
+        )
+    };
+    // Ctrl-C returns the count so far, marked "interrupted": true.
+    let result = match backend.interrupt() {
+        Some(interrupt) => with_interrupts(interrupt, count),
+        None => count(),
+    };
+    match result {
+        Ok(count) => match to_json(&count) {
+            Ok(data) => data,
+            Err(e) => format!("Error serializing count: {e}"),
+        },
+        Err(e) => format!("Error counting associations: {e}"),
+    }
+}
+
+/// Associations in a window around a lead variant
+/// @param variant_id Lead variant ID (e.g. "rs7412")
+/// @param window_kb Window size in kilobases on either side of the lead variant
+/// @param study Optional study accession used for both the lookup and the region query
+/// @param reveal Optional reveal mode ("raw" or "all")
+/// @param start Offset number (default: 0)
+/// @param size Number of items returned (default: 20)
+/// @export
+#[extendr]
+fn gwas_locus(
+    variant_id: String,
+    window_kb: f64,
+    study: Option<String>,
+    reveal: Option<String>,
+    start: Option<i32>,
+    size: Option<i32>,
+) -> String {
+    let backend = match current_backend() {
+        Ok(b) => b,
+        Err(e) => return format!("Error creating client: {e}"),
+    };
+
+    let filter = GwasFilter {
+        study,
+        reveal,
+        start,
+        size,
+        ..Default::default()
+    };
+
+    match backend.locus(&variant_id, window_kb, &filter) {
+        Ok(data) => match to_json(&data) {
+            Ok(data) => data,
+            Err(e) => format!("Error fetching locus: {e}"),
+        },
+        Err(e) => format!("Error fetching locus: {e}"),
+    }
+}
+
+/// One variant's association in one study, or a batch of them
+/// @param variant_id Variant ID (e.g. "rs7412"), or a vector of them
+/// @param study Study accession (e.g. "GCST90002357"), or a vector of them; a single
+///   variant or study is paired with each of the other
+/// @param dry_run Whether to return the request URLs instead of sending them
+/// @export
+#[extendr]
+fn gwas_variant_in_study(variant_id: Vec<String>, study: Vec<String>, dry_run: bool) -> String {
+    let pairs: Vec<(String, String)> = match (variant_id.len(), study.len()) {
+        (1, _) => study
+            .into_iter()
+            .map(|study| (variant_id[0].clone(), study))
+            .collect(),
+        (_, 1) => variant_id
+            .into_iter()
+            .map(|variant_id| (variant_id, study[0].clone()))
+            .collect(),
+        (v, s) if v == s => variant_id.into_iter().zip(study).collect(),
+        _ => {
+            return "Error: variant_id and study must have the same length, or one of them length 1"
+                .to_string()
+        }
+    };
+    let backend = match current_backend() {
+        Ok(b) => b,
+        Err(e) => return format!("Error creating client: {e}"),
+    };
+    if dry_run {
+        let urls = pairs
+            .iter()
+            .map(|(variant_id, study)| {
+                let filter = GwasFilter {
+                    study: Some(study.clone()),
+                    ..Default::default()
+                };
+                backend.association_urls(Some("variant"), Some(variant_id), &filter, 1)
+            })
+            .collect::<Result<Vec<Vec<String>>>>()
+            .map(|urls| urls.concat());
+        return dry_run_json(urls);
+    }
+    if let [(variant_id, study)] = pairs.as_slice() {
+        return match backend.variant_in_study(variant_id, study) {
+            Ok(association) => {
+                let row = VariantInStudy::new(variant_id, study, association);
+                match to_json(&row) {
+                    Ok(data) => data,
+                    Err(e) => format!("Error serializing association: {e}"),
+                }
+            }
+            Err(e) => format!("Error fetching variant in study: {e}"),
+        };
+    }
+
+    // Ctrl-C ends the batch with the rows looked up so far.
+    let lookup = || variants_in_studies(backend.as_ref(), &pairs);
+    let rows = match backend.interrupt() {
+        Some(interrupt) => with_interrupts(interrupt, lookup),
+        None => lookup(),
+    };
+    match to_json(&rows) {
+        Ok(data) => data,
+        Err(e) => format!("Error serializing associations: {e}"),
+    }
+}
+
+/// Associations around a gene resolved through the Ensembl REST API
+/// @param symbol Gene symbol (e.g. "APOE")
+/// @param flank_kb Flanking region in kilobases added on both sides of the gene
+/// @param p_value_min Optional minimum p-value threshold
+/// @param p_value_max Optional maximum p-value threshold
+/// @param study Optional study accession filter
+/// @param reveal Optional reveal mode ("raw" or "all")
+/// @param start Offset number (default: 0)
+/// @param size Number of items returned (default: 20)
+/// @export
+#[allow(clippy::too_many_arguments)]
+#[extendr]
+fn gwas_gene(
+    symbol: String,
+    flank_kb: f64,
+    p_value_min: Option<String>,
+    p_value_max: Option<String>,
+    study: Option<String>,
+    reveal: Option<String>,
+    start: Option<i32>,
+    size: Option<i32>,
+) -> String {
+    let backend = match current_backend() {
+        Ok(b) => b,
+        Err(e) => return format!("Error creating client: {e}"),
+    };
+
+    let p_value_range = match (p_value_min, p_value_max) {
+        (Some(min), Some(max)) => Some((min, max)),
+        (Some(min), None) => Some((min, "1.0".to_string())),
+        (None, Some(max)) => Some(("0.0".to_string(), max)),
+        (None, None) => None,
+    };
+
+    let filter = GwasFilter {
+        p_value_range,
+        study,
+        reveal,
+        start,
+        size,
+        ..Default::default()
+    };
+
+    match backend.gene_associations(&symbol, flank_kb, &filter) {
+        Ok(data) => match to_json(&data) {
+            Ok(data) => data,
+            Err(e) => format!("Error fetching associations for {symbol}: {e}"),
+        },
+        Err(e) => format!("Error fetching associations for {symbol}: {e}"),
+    }
+}
+
+/// Associations in a region of a remote bgzipped, tabix-indexed file
+/// @param file_url URL of a harmonised file with a .tbi index alongside
+/// @param chromosome Chromosome name
+/// @param bp_min Start of the region (1-based, inclusive)
+/// @param bp_max End of the region (inclusive)
+/// @param p_value_min Optional minimum p-value threshold
+/// @param p_value_max Optional maximum p-value threshold
+/// @param start Offset number (default: 0)
+/// @param size Optional number of items returned (default: all)
+/// @export
+#[allow(clippy::too_many_arguments)]
+#[extendr]
+fn gwas_region(
+    file_url: String,
+    chromosome: String,
+    bp_min: i64,
+    bp_max: i64,
+    p_value_min: Option<String>,
+    p_value_max: Option<String>,
+    start: Option<i32>,
+    size: Option<i32>,
+) -> String {
+    let file = match RemoteTabixFile::open(&file_url) {
+        Ok(file) => file,
+        Err(e) => return format!("Error opening {file_url}: {e}"),
+    };
+
+    let p_value_range = match (p_value_min, p_value_max) {
+        (Some(min), Some(max)) => Some((min, max)),
+        (Some(min), None) => Some((min, "1.0".to_string())),
+        (None, Some(max)) => Some(("0.0".to_string(), max)),
+        (None, None) => None,
+    };
+
+    let filter = GwasFilter {
+        p_value_range,
+        start,
+        size: size.or(Some(i32::MAX)),
+        ..Default::default()
+    };
+
+    match file.associations(&chromosome, bp_min, bp_max, &filter) {
+        Ok(data) => match to_json(&data) {
+            Ok(data) => data,
+            Err(e) => format!("Error serializing associations: {e}"),
+        },
+        Err(e) => format!("Error querying {file_url}: {e}"),
+    }
+}
+
+/// Distance-based clumping of association results
+/// @param associations JSON returned by gwas_associations() or a JSON array of associations
+/// @param p_threshold P-value threshold for index variants (default: 5e-8)
+/// @param window_kb Clumping window in kilobases (default: 500)
+/// @param ld_reference Optional PLINK binary fileset prefix used for LD pruning
+/// @param r2_threshold r² at or above which variants are clumped (default: 0.1)
+/// @export
+#[extendr]
+fn gwas_clump(
+    associations: String,
+    p_threshold: Option<f64>,
+    window_kb: Option<f64>,
+    ld_reference: Option<String>,
+    r2_threshold: Option<f64>,
+) -> String {
+    let defaults = ClumpSettings::default();
+    let settings = ClumpSettings {
+        p_threshold: p_threshold.unwrap_or(defaults.p_threshold),
+        window_bp: window_kb.map_or(defaults.window_bp, |kb| (kb * 1000.0).round() as i64),
+        r2_threshold: r2_threshold.unwrap_or(defaults.r2_threshold),
+    };
+
+    let associations = match associations_from_json(&associations) {
+        Ok(a) => a,
+        Err(e) => return format!("Error parsing associations: {e}"),
+    };
+
+    let reference = match ld_reference.as_deref().map(PlinkFileset::open).transpose() {
+        Ok(r) => r,
+        Err(e) => return format!("Error opening LD reference: {e}"),
+    };
+
+    let signals = match clump(&associations, &settings, reference.as_ref()) {
+        Ok(s) => s,
+        Err(e) => return format!("Error clumping associations: {e}"),
+    };
+
+    match to_json(&signals) {
+        Ok(data) => data,
+        Err(e) => format!("Error clumping associations: {e}"),
+    }
+}
+
+/// Attach LD (r²) with a lead variant from a local PLINK reference
+/// @param associations JSON returned by gwas_associations() or a JSON array of associations
+/// @param ld_reference PLINK binary fileset prefix (path without .bed/.bim/.fam)
+/// @param lead_variant Optional lead variant ID (default: smallest p-value)
+/// @export
+#[extendr]
+fn gwas_ld(associations: String, ld_reference: String, lead_variant: Option<String>) -> String {
+    let associations = match associations_from_json(&associations) {
+        Ok(a) => a,
+        Err(e) => return format!("Error parsing associations: {e}"),
+    };
+
+    let reference = match PlinkFileset::open(&ld_reference) {
+        Ok(r) => r,
+        Err(e) => return format!("Error opening LD reference: {e}"),
+    };
+
+    match annotate_r2(associations, &reference, lead_variant.as_deref()) {
+        Ok(annotated) => match to_json(&annotated) {
+            Ok(data) => data,
+            Err(e) => format!("Error serializing LD annotations: {e}"),
+        },
+        Err(e) => format!("Error computing LD: {e}"),
+    }
+}
+
+/// Annotate associations with Ensembl VEP consequences
+/// @param associations JSON returned by gwas_associations() or a JSON array of associations
+/// @param fields Optional vector of annotation fields: "most_severe_consequence", "gene_symbol", "impact"
+/// @export
+#[extendr]
+fn gwas_annotate(associations: String, fields: Option<Vec<String>>) -> String {
+    let associations = match associations_from_json(&associations) {
+        Ok(a) => a,
+        Err(e) => return format!("Error parsing associations: {e}"),
+    };
+    let fields =
+        fields.unwrap_or_else(|| VEP_FIELDS.iter().map(|field| field.to_string()).collect());
+
+    match annotate_with_vep(&EnsemblClient::new(), associations, &fields) {
+        Ok(annotated) => match to_json(&annotated) {
+            Ok(data) => data,
+            Err(e) => format!("Error serializing annotations: {e}"),
+        },
+        Err(e) => format!("Error annotating variants: {e}"),
+    }
+}
+
+/// Annotate associations with the nearest protein-coding gene
+/// @param associations JSON returned by gwas_associations() or a JSON array of associations
+/// @param gene_table Path to a GTF (GENCODE/Ensembl) or BED-like gene coordinate table
+/// @export
+#[extendr]
+fn gwas_nearest_gene(associations: String, gene_table: String) -> String {
+    let associations = match associations_from_json(&associations) {
+        Ok(a) => a,
+        Err(e) => return format!("Error parsing associations: {e}"),
+    };
+
+    let genes = match GeneTable::open(&gene_table) {
+        Ok(g) => g,
+        Err(e) => return format!("Error reading gene table: {e}"),
+    };
+
+    match to_json(&genes.annotate(associations)) {
+        Ok(data) => data,
+        Err(e) => format!("Error serializing annotations: {e}"),
+    }
+}
+
+/// Test whether GWAS hits are enriched in a BED annotation
+/// @param hits JSON returned by gwas_associations() or a JSON array of associations
+/// @param track Path to a BED file, plain or gzipped
+/// @param n_perm Number of circular shifts making up the background
+/// @param seed Optional non-negative random seed; the seed used is returned either way
+/// @export
+#[extendr]
+fn gwas_enrichment(hits: String, track: String, n_perm: i32, seed: Option<i32>) -> String {
+    let hits = match associations_from_json(&hits) {
+        Ok(a) => a,
+        Err(e) => return format!("Error parsing hits: {e}"),
+    };
+
+    let annotation = match enrichment::Track::open(&track) {
+        Ok(t) => t,
+        Err(e) => return format!("Error reading track: {e}"),
+    };
+
+    let seed = match seed {
+        Some(seed) if seed < 0 => return "Error: seed must not be negative".to_string(),
+        Some(seed) => seed as u64,
+        None => std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64 & 0x7fff_ffff),
+    };
+    let n_perm = n_perm.max(0) as usize;
+
+    match enrichment::enrichment(&hits, &annotation, &track, n_perm, seed) {
+        Ok(result) => match to_json(&result) {
+            Ok(data) => data,
+            Err(e) => format!("Error serializing enrichment: {e}"),
+        },
+        Err(e) => format!("Error testing enrichment: {e}"),
+    }
+}
+
+/// Look up Open Targets Genetics evidence for a variant
+/// @param variant rsID or Open Targets variant ID (chrom_pos_ref_alt, GRCh38)
+/// @param study_id Optional Open Targets study ID to restrict the study loci
+/// @export
+#[extendr]
+fn gwas_opentargets(variant: String, study_id: Option<String>) -> String {
+    let client = OpenTargetsClient::new();
+    match client.variant_evidence(&variant, study_id.as_deref()) {
+        Ok(evidence) => match to_json(&evidence) {
+            Ok(data) => data,
+            Err(e) => format!("Error serializing Open Targets evidence: {e}"),
+        },
+        Err(e) => format!("Error querying Open Targets Genetics: {e}"),
+    }
+}
+
+/// Rank the evidence linking a gene to a trait
+/// @param gene Gene symbol (e.g., "APOE")
+/// @param trait_id EFO trait ID (e.g., "EFO_0001645")
+/// @param flank_kb Optional kilobases around the gene to search for associations (default: 100)
+/// @param p_value_max Optional maximum association p-value (default: 5e-8)
+/// @param max_variants Optional number of most significant variants to look up in Open Targets (default: 10)
+/// @export
+#[extendr]
+fn gwas_gene_evidence(
+    gene: String,
+    trait_id: String,
+    flank_kb: Option<f64>,
+    p_value_max: Option<f64>,
+    max_variants: Option<usize>,
+) -> String {
+    let backend = match current_backend() {
+        Ok(b) => b,
+        Err(e) => return format!("Error creating client: {e}"),
+    };
+
+    match evidence::gene_evidence(
+        backend.as_ref(),
+        &EnsemblClient::new(),
+        &OpenTargetsClient::new(),
+        &gene,
+        &trait_id,
+        flank_kb.unwrap_or(100.0),
+        p_value_max.unwrap_or(5e-8),
+        max_variants.unwrap_or(10),
+    ) {
+        Ok(report) => match to_json(&report) {
+            Ok(data) => data,
+            Err(e) => format!("Error serializing gene evidence: {e}"),
+        },
+        Err(e) => format!("Error gathering evidence for {gene}: {e}"),
+    }
+}
+
+/// Scan the genes of a panel for significant trait associations
+/// @param panel_file Path to a panel file (tab-separated, first column gene)
+/// @param trait_id EFO trait ID (e.g., "EFO_0001645")
+/// @param flank_kb Optional kilobases around each gene to include (default: 0)
+/// @param p_value_max Optional p-value threshold for significance (default: 5e-8)
+/// @param missing_ok Whether a 404 for a gene window without records marks the gene "found": false rather than as failed
+/// @param significance Optional threshold in place of p_value_max: "genome_wide" (5e-8), "suggestive" (1e-5) or a number
+/// @export
+#[extendr]
+fn gwas_panel_scan(
+    panel_file: String,
+    trait_id: String,
+    flank_kb: Option<f64>,
+    p_value_max: Option<f64>,
+    missing_ok: bool,
+    significance: Option<String>,
+) -> String {
+    let p_value_max = match significance.as_deref().map(significance_threshold) {
+        Some(Ok(threshold)) => threshold.parse().ok(),
+        Some(Err(e)) => return format!("Error: {e}"),
+        None => p_value_max,
+    };
+    let panel = match panels::read_panel(&panel_file) {
+        Ok(panel) => panel,
+        Err(e) => return format!("Error reading panel: {e}"),
+    };
+    let backend = match current_backend() {
+        Ok(b) => b,
+        Err(e) => return format!("Error creating client: {e}"),
+    };
+
+    // Ctrl-C also cuts short waits for the Ensembl rate limit.
+    let ensembl = match backend.interrupt() {
+        Some(interrupt) => EnsemblClient::new().with_interrupt(interrupt.clone()),
+        None => EnsemblClient::new(),
+    };
+    let scan = || {
+        panels::panel_scan(
+            backend.as_ref(),
+            &ensembl,
+            &panel,
+            &trait_id,
+            flank_kb.unwrap_or(0.0),
+            p_value_max.unwrap_or(5e-8),
+            missing_ok,
+        )
+    };
+    // Ctrl-C ends the scan with the genes scanned so far.
+    let result = match backend.interrupt() {
+        Some(interrupt) => with_interrupts(interrupt, scan),
+        None => scan(),
+    };
+    match result {
+        Ok(scan) => match to_json(&scan) {
+            Ok(data) => data,
+            Err(e) => format!("Error serializing panel scan: {e}"),
+        },
+        Err(e) => format!("Error scanning panel {}: {e}", panel.name),
+    }
+}
+
+/// Polygenic scores from a summary statistics file and local genotypes
+/// @param sumstats_path Path to a (optionally gzipped) summary statistics file
+/// @param bfile PLINK binary fileset prefix with the target genotypes
+/// @param p_threshold Optional p-value threshold for including variants
+/// @export
+#[extendr]
+fn gwas_prs(sumstats_path: String, bfile: String, p_threshold: Option<f64>) -> String {
+    let genotypes = match PlinkFileset::open(&bfile) {
+        Ok(g) => g,
+        Err(e) => return format!("Error opening genotypes: {e}"),
+    };
+
+    match compute_prs(&sumstats_path, &genotypes, p_threshold) {
+        Ok(result) => match to_json(&result) {
+            Ok(data) => data,
+            Err(e) => format!("Error serializing scores: {e}"),
+        },
+        Err(e) => format!("Error computing polygenic scores: {e}"),
+    }
+}
+
+/// LDSC-style munging of a summary statistics file
+/// @param path Path to a (optionally gzipped) summary statistics file
+/// @param out Output prefix; writes `<out>.sumstats.gz`
+/// @param n Optional sample size used when the file has no N column
+/// @param info_min Minimum imputation INFO score (default: 0.9)
+/// @param maf_min Minimum minor allele frequency (default: 0.01)
+/// @param cache_dir Optional directory where the input's column statistics are cached
+/// @export
+#[extendr]
+fn gwas_munge(
+    path: String,
+    out: String,
+    n: Option<f64>,
+    info_min: Option<f64>,
+    maf_min: Option<f64>,
+    cache_dir: Option<String>,
+) -> String {
+    let defaults = MungeSettings::default();
+    let settings = MungeSettings {
+        n,
+        info_min: info_min.unwrap_or(defaults.info_min),
+        maf_min: maf_min.unwrap_or(defaults.maf_min),
+        cache_dir,
+    };
+
+    match munge(&path, &out, &settings) {
+        Ok(report) => match to_json(&report) {
+            Ok(data) => data,
+            Err(e) => format!("Error serializing munge report: {e}"),
+        },
+        Err(e) => format!("Error munging {path}: {e}"),
+    }
+}
+
+/// Compress and tabix-index a summary statistics file for region queries
+/// @param path Path to a (optionally gzipped) file sorted by chromosome and position
+/// @param output Optional output path (default: `path`, with ".gz" appended if needed)
+/// @export
+#[extendr]
+fn gwas_index(path: String, output: Option<String>) -> String {
+    let output = output.unwrap_or_else(|| {
+        if path.ends_with(".gz") {
+            path.clone()
+        } else {
+            format!("{path}.gz")
+        }
+    });
+
+    match build_index(&path, &output) {
+        Ok(report) => match to_json(&report) {
+            Ok(data) => data,
+            Err(e) => format!("Error serializing index report: {e}"),
+        },
+        Err(e) => format!("Error indexing {path}: {e}"),
+    }
+}
+
+/// Summarize the distributions of a summary statistics file
+/// @param path Path to a (optionally gzipped) summary statistics file
+/// @param cache_dir Optional directory where column statistics are cached
+/// @export
+#[extendr]
+fn gwas_summary(path: String, cache_dir: Option<String>) -> String {
+    match summarize_cached(&path, cache_dir.as_deref()) {
+        Ok(summary) => match to_json(&summary) {
+            Ok(data) => data,
+            Err(e) => format!("Error serializing summary: {e}"),
+        },
+        Err(e) => format!("Error summarizing {path}: {e}"),
+    }
+}
+
+/// Build a per-study variant membership filter from a local file
+/// @param study Study accession the file belongs to
+/// @param path Path to a (optionally gzipped) summary statistics file
+/// @param cache_dir Directory where filters are stored
+/// @export
+#[extendr]
+fn gwas_build_variant_filter(study: String, path: String, cache_dir: String) -> String {
+    match build_variant_filter(&cache_dir, &study, &path) {
+        Ok(n_variants) => serde_json::json!({
+            "study_accession": study,
+            "n_variants": n_variants,
+            "filter": filter_path(&cache_dir, &study),
+        })
+        .to_string(),
+        Err(e) => format!("Error building variant filter for {study}: {e}"),
+    }
+}
+
+/// Check whether variants are present in a study
+/// @param study Study accession
+/// @param variant_ids Vector of variant IDs
+/// @param cache_dir Directory where filters are stored
+/// @export
+#[extendr]
+fn gwas_has_variant(study: String, variant_ids: Vec<String>, cache_dir: String) -> String {
+    match has_variants(&cache_dir, &study, &variant_ids) {
+        Ok(results) => match to_json(&results) {
+            Ok(data) => data,
+            Err(e) => format!("Error serializing membership results: {e}"),
+        },
+        Err(e) => format!("Error checking variants: {e}"),
+    }
+}
+
+/// Estimate variant-set overlap between indexed studies
+/// @param accessions Vector of study accessions
+/// @param cache_dir Directory where filters are stored
+/// @export
+#[extendr]
+fn gwas_study_similarity(accessions: Vec<String>, cache_dir: String) -> String {
+    match study_similarity(&cache_dir, &accessions) {
+        Ok(results) => match to_json(&results) {
+            Ok(data) => data,
+            Err(e) => format!("Error serializing study similarity: {e}"),
+        },
+        Err(e) => format!("Error comparing studies: {e}"),
+    }
+}
+
+/// Deposit files on Zenodo
+/// @param files Vector of local file paths to upload
+/// @param title Deposition title
+/// @param creators Vector of creator names ("Family, Given")
+/// @param description Optional deposition description
+/// @param token Zenodo personal access token
+/// @param sandbox Use sandbox.zenodo.org instead of zenodo.org
+/// @param publish Publish the deposition and mint a DOI
+/// @export
+#[allow(clippy::too_many_arguments)]
+#[extendr]
+fn gwas_deposit(
+    files: Vec<String>,
+    title: String,
+    creators: Vec<String>,
+    description: Option<String>,
+    token: String,
+    sandbox: bool,
+    publish: bool,
+) -> String {
+    let client = ZenodoClient::new(token, sandbox);
+
+    let metadata = DepositMetadata {
+        title: title.clone(),
+        upload_type: "dataset".to_string(),
+        description: description.unwrap_or(title),
+        creators: creators.into_iter().map(|name| Creator { name }).collect(),
+    };
+
+    match client.deposit(&files, &metadata, publish) {
+        Ok(deposition) => match to_json(&deposition) {
+            Ok(data) => data,
+            Err(e) => format!("Error serializing deposition: {e}"),
+        },
+        Err(e) => format!("Error depositing files: {e}"),
+    }
+}
+
+/// Harvest traits, their studies and file listings into an offline index
+/// @param out_dir Directory for the CSV tables and index.json
+/// @param limit Optional maximum number of traits to harvest
+/// @export
+#[extendr]
+fn gwas_catalog_index(out_dir: String, limit: Option<i32>) -> String {
+    let client = match GwasClient::new() {
+        Ok(client) => client,
+        Err(e) => return format!("Error creating client: {e}"),
+    };
+    let limit = limit.map(|limit| limit.max(0) as usize);
+    let index = with_interrupts(client.interrupt(), || {
+        build_catalog_index(&client, &out_dir, limit)
+    });
+    match index {
+        Ok(index) => match to_json(&index) {
+            Ok(data) => data,
+            Err(e) => format!("Error serializing index: {e}"),
+        },
+        Err(e) => format!("Error building catalog index: {e}"),
+    }
+}
+
+/// Sync a local copy of the study catalog, looking up only new studies
+/// @param db_path JSON file holding the synced catalog
+/// @export
+#[extendr]
+fn gwas_sync_studies(db_path: String) -> String {
+    let client = match GwasClient::new() {
+        Ok(client) => client,
+        Err(e) => return format!("Error creating client: {e}"),
+    };
+    let report = with_interrupts(client.interrupt(), || sync_studies(&client, &db_path));
+    match report {
+        Ok(report) => match to_json(&report) {
+            Ok(data) => data,
+            Err(e) => format!("Error serializing sync report: {e}"),
+        },
+        Err(e) => format!("Error syncing studies: {e}"),
+    }
+}
+
+/// Every trait the API lists, with EFO labels from OLS, as a CSV lookup table
+/// @param output CSV file to write (trait_id, label)
+/// @param labels Whether to look up each trait's label in OLS
+/// @export
+#[extendr]
+fn gwas_all_traits(output: String, labels: bool) -> String {
+    let client = match GwasClient::new() {
+        Ok(client) => client,
+        Err(e) => return format!("Error creating client: {e}"),
+    };
+    let ols = match labels.then(OlsClient::new).transpose() {
+        Ok(ols) => ols,
+        Err(e) => return format!("Error creating OLS client: {e}"),
+    };
+    let dump = with_interrupts(client.interrupt(), || {
+        dump_traits(&client, ols.as_ref(), &output)
+    });
+    match dump {
+        Ok(dump) => match to_json(&dump) {
+            Ok(data) => data,
+            Err(e) => format!("Error serializing trait table: {e}"),
+        },
+        Err(e) => format!("Error listing traits: {e}"),
+    }
+}
+
+/// Match a trait name, typos included, against a trait table
+/// @param query Trait name or EFO ID
+/// @param table CSV trait table written by `gwas_all_traits()`
+/// @param n Optional number of candidates to return
+/// @param min_score Optional lowest similarity score, from 0 to 1
+/// @export
+#[extendr]
+fn gwas_match_trait(
+    query: String,
+    table: String,
+    n: Option<i32>,
+    min_score: Option<f64>,
+) -> String {
+    let traits = match load_trait_labels(&table) {
+        Ok(traits) => traits,
+        Err(e) => return format!("Error loading trait table: {e}"),
+    };
+    let n = n.map_or(DEFAULT_MATCHES, |n| n.max(0) as usize);
+    let matches = match_traits(&traits, &query, n, min_score.unwrap_or(DEFAULT_MIN_SCORE));
+    match to_json(&matches) {
+        Ok(data) => data,
+        Err(e) => format!("Error serializing matches: {e}"),
+    }
+}
+
+/// Studies of a trait, optionally with those of every trait below it in EFO
+/// @param trait_id EFO trait ID
+/// @param include_child_traits Whether to add the studies of every trait below it
+/// @export
+#[extendr]
+fn gwas_trait_studies(trait_id: String, include_child_traits: bool) -> String {
+    let client = match GwasClient::new() {
+        Ok(client) => client,
+        Err(e) => return format!("Error creating client: {e}"),
+    };
+    let studies = with_interrupts(client.interrupt(), || {
+        let trait_ids = if include_child_traits {
+            trait_subtree(&OlsClient::new()?, &trait_id)?
+        } else {
+            vec![trait_id.clone()]
+        };
+        subtree_studies(&client, &trait_ids)
+    });
+    match studies {
+        Ok(studies) => match to_json(&studies) {
+            Ok(data) => data,
+            Err(e) => format!("Error serializing studies: {e}"),
+        },
+        Err(e) => format!("Error listing trait studies: {e}"),
+    }
+}
+
+/// Manifest of study files on the GWAS Catalog FTP site
+/// @param studies Optional vector of study accessions; crawls the site when omitted
+/// @param limit Optional maximum number of studies to crawl when `studies` is omitted
+/// @param harmonised_only Only include files from `harmonised/` subfolders
+/// @export
+#[extendr]
+fn gwas_ftp_manifest(
+    studies: Option<Vec<String>>,
+    limit: Option<i32>,
+    harmonised_only: Option<bool>,
+) -> String {
+    let crawler = FtpCrawler::new();
+    let limit = limit.map(|limit| limit.max(0) as usize);
+
+    match crawler.manifest(studies, limit, harmonised_only.unwrap_or(false)) {
+        Ok(manifest) => match to_json(&manifest) {
+            Ok(data) => data,
+            Err(e) => format!("Error serializing manifest: {e}"),
+        },
+        Err(e) => format!("Error crawling FTP site: {e}"),
+    }
+}
+
+/// Unified file operations (list and download)
+/// @param operation Operation type: "list", "download", "download_study", "resume_queue", "verify" or "terms"
+/// @param entity_type Entity type: "study" or "trait"
+/// @param entity_id Primary entity ID
+/// @param secondary_id Optional secondary ID (for trait-study combinations)
+/// @param file_urls Optional vector of file URLs (for download)
+/// @param output_paths Optional vector of output paths (for download)
+/// @param max_concurrent Optional max concurrent downloads (default: 4)
+/// @param output_dir Optional directory for download_study, or to search for queues to resume (default: current directory)
+/// @param write_manifest Optional flag to write manifest.json next to the downloads (default: FALSE)
+/// @param mode Optional handling of existing files: "overwrite", "skip", "resume" or "verify" (default: "overwrite")
+/// @param max_bytes_per_second Optional limit on the combined download rate of all threads
+/// @param accept_terms Optional flag accepting the usage terms of restricted files (default: FALSE)
+/// @param decompress Optional flag to decompress .gz, .bgz and .zst files as they download (default: FALSE)
+/// @export
+#[allow(clippy::too_many_arguments)]
+#[extendr]
+fn gwas_files(
+    operation: String,
+    entity_type: Option<String>,
+    entity_id: Option<String>,
+    secondary_id: Option<String>,
+    file_urls: Option<Vec<String>>,
+    output_paths: Option<Vec<String>>,
+    max_concurrent: Option<usize>,
+    output_dir: Option<String>,
+    write_manifest: Option<bool>,
+    mode: Option<String>,
+    max_bytes_per_second: Option<f64>,
+    accept_terms: Option<bool>,
+    decompress: Option<bool>,
+) -> String {
+    if let Some(rate) = max_bytes_per_second.filter(|rate| rate.is_nan() || *rate <= 0.0) {
+        return format!("Error: max_bytes_per_second must be positive, got {rate}");
+    }
+    let client = match GwasClient::new() {
+        Ok(c) => c
+            .with_bandwidth_limit(max_bytes_per_second)
+            .with_decompression(decompress.unwrap_or(false)),
+        Err(e) => return format!("Error creating client: {e}"),
+    };
+    let mode = match DownloadMode::parse(mode.as_deref().unwrap_or("overwrite")) {
+        Ok(mode) => mode,
+        Err(e) => return format!("Error: {e}"),
+    };
+
+    let (urls, paths): (Vec<String>, Vec<String>) = match operation.as_str() {
+        "list" => {
+            return match (entity_type, entity_id) {
+                (Some(entity_type), Some(entity_id)) => {
+                    match client.list_files(&entity_type, &entity_id, secondary_id.as_deref()) {
+                        Ok(data) => data,
+                        Err(e) => format!("Error listing files: {e}"),
+                    }
+                }
+                _ => "Error: entity_type and entity_id required for list operation".to_string(),
+            }
+        }
+        // verify and terms check either the given files or a study's files.
+        "download" | "verify" | "terms" if operation == "download" || file_urls.is_some() => {
+            match (file_urls, output_paths) {
+                (Some(urls), Some(paths)) => {
+                    if urls.len() != paths.len() {
+                        return "Error: file_urls and output_paths must have the same length."
+                            .to_string();
+                    }
+                    (urls, paths)
+                }
+                _ => {
+                    return format!(
+                        "Error: file_urls and output_paths required for {operation} operation"
+                    )
+                }
+            }
+        }
+        "resume_queue" => {
+            let output_dir = output_dir.unwrap_or_else(|| ".".to_string());
+            let max_concurrent = max_concurrent.unwrap_or(4);
+            let results = with_interrupts(client.interrupt(), || {
+                client.resume_queues(Path::new(&output_dir), max_concurrent)
+            });
+            return match results {
+                Ok(results) => download_results_json(&results),
+                Err(e) => format!("Error resuming download queue: {e}"),
+            };
+        }
+        "download_study" | "verify" | "terms" => {
+            let study = match (entity_type.as_deref(), entity_id) {
+                (None | Some("study"), Some(study)) => study,
+                _ => return format!("Error: entity_id must be a study accession for {operation}"),
+            };
+            let output_dir = output_dir.unwrap_or_else(|| ".".to_string());
+            match client.study_downloads(&study, &output_dir) {
+                Ok(downloads) => downloads.into_iter().unzip(),
+                Err(e) => return format!("Error resolving files for {study}: {e}"),
+            }
+        }
+        _ => {
+            return format!(
+            "Invalid operation: {operation}. Use 'list', 'download', 'download_study', 'resume_queue', 'verify' or 'terms'"
+        )
+        }
+    };
+
+    let urls = client.use_download_mirror(urls);
+    let max_concurrent = max_concurrent.unwrap_or(4);
+    // Ctrl-C stops the remaining files; finished ones are still reported.
+    if operation == "verify" {
+        let results = with_interrupts(client.interrupt(), || {
+            client.verify_files(&urls, &paths, max_concurrent)
+        });
+        return match results {
+            Ok(results) => match to_json(&results) {
+                Ok(data) => data,
+                Err(e) => format!("Error serializing verification results: {e}"),
+            },
+            Err(e) => format!("Error creating thread pool: {e}"),
+        };
+    }
+
+    let terms = match client.files_terms(&urls, &paths, max_concurrent) {
+        Ok(terms) => terms,
+        Err(e) => return format!("Error creating thread pool: {e}"),
+    };
+    if operation == "terms" {
+        return match to_json(&terms) {
+            Ok(data) => data,
+            Err(e) => format!("Error serializing terms: {e}"),
+        };
+    }
+    if let Err(e) = require_acceptance(&terms, accept_terms.unwrap_or(false)) {
+        return format!("Error: {e}");
+    }
+
+    // Progress is saved to queue.json, so a crash can be recovered with
+    // the resume_queue operation.
+    let results = with_interrupts(client.interrupt(), || {
+        client.download_queued(
+            &urls,
+            &paths,
+            max_concurrent,
+            mode,
+            write_manifest.unwrap_or(false),
+            |_| {},
+        )
+    });
+    match results {
+        Ok(results) => download_results_json(&results),
+        Err(e) => format!("Error downloading files: {e}"),
+    }
+}
+
+fn download_results_json(results: &[download::DownloadResult]) -> String {
+    match to_json(results) {
+        Ok(data) => data,
+        Err(e) => format!("Error serializing download results: {e}"),
+    }
+}
+
+/// Start downloading files in the background
+/// @param file_urls Vector of file URLs
+/// @param output_paths Vector of output paths, one per URL
+/// @param max_concurrent Optional max concurrent downloads (default: 4)
+/// @param write_manifest Optional flag to write manifest.json when the job finishes (default: FALSE)
+/// @param mode Optional handling of existing files: "overwrite", "skip", "resume" or "verify" (default: "overwrite")
+/// @param max_bytes_per_second Optional limit on the combined download rate of all threads
+/// @param accept_terms Optional flag accepting the usage terms of restricted files (default: FALSE)
+/// @param decompress Optional flag to decompress .gz, .bgz and .zst files as they download (default: FALSE)
+/// @export
+#[allow(clippy::too_many_arguments)]
+#[extendr]
+fn gwas_download_async(
+    file_urls: Vec<String>,
+    output_paths: Vec<String>,
+    max_concurrent: Option<usize>,
+    write_manifest: Option<bool>,
+    mode: Option<String>,
+    max_bytes_per_second: Option<f64>,
+    accept_terms: Option<bool>,
+    decompress: Option<bool>,
+) -> String {
+    if file_urls.len() != output_paths.len() {
+        return "Error: file_urls and output_paths must have the same length.".to_string();
+    }
+    if let Some(rate) = max_bytes_per_second.filter(|rate| rate.is_nan() || *rate <= 0.0) {
+        return format!("Error: max_bytes_per_second must be positive, got {rate}");
+    }
+    let client = match GwasClient::new() {
+        Ok(c) => c
+            .with_bandwidth_limit(max_bytes_per_second)
+            .with_decompression(decompress.unwrap_or(false)),
+        Err(e) => return format!("Error creating client: {e}"),
+    };
+    let mode = match DownloadMode::parse(mode.as_deref().unwrap_or("overwrite")) {
+        Ok(mode) => mode,
+        Err(e) => return format!("Error: {e}"),
+    };
+
+    let urls = client.use_download_mirror(file_urls);
+    let max_concurrent = max_concurrent.unwrap_or(4);
+    // Terms are checked before returning, so a job never stops on them.
+    let terms = match client.files_terms(&urls, &output_paths, max_concurrent) {
+        Ok(terms) => terms,
+        Err(e) => return format!("Error creating thread pool: {e}"),
+    };
+    if let Err(e) = require_acceptance(&terms, accept_terms.unwrap_or(false)) {
+        return format!("Error: {e}");
+    }
+
+    match jobs::spawn_download(
+        client,
+        urls,
+        output_paths,
+        max_concurrent,
+        mode,
+        write_manifest.unwrap_or(false),
+    ) {
+        Ok(status) => match to_json(&status) {
+            Ok(data) => data,
+            Err(e) => format!("Error serializing job status: {e}"),
+        },
+        Err(e) => format!("Error starting job: {e}"),
+    }
+}
+
+/// Progress of background download jobs
+/// @param job Optional job ID; all jobs not yet collected when omitted
+/// @export
+#[extendr]
+fn gwas_job_status(job: Option<i32>) -> String {
+    let job = match job.map(u64::try_from).transpose() {
+        Ok(job) => job,
+        Err(_) => return "Error: job must be a positive job ID".to_string(),
+    };
+    match jobs::status(job) {
+        Ok(statuses) => match to_json(&statuses) {
+            Ok(data) => data,
+            Err(e) => format!("Error serializing job status: {e}"),
+        },
+        Err(e) => format!("Error: {e}"),
+    }
+}
+
+/// Collect the results of a background download job
+/// @param job Job ID returned by gwas_download_async
+/// @param wait Optional flag to wait for a running job to finish (default: FALSE)
+/// @export
+#[extendr]
+fn gwas_job_collect(job: i32, wait: Option<bool>) -> String {
+    let job = match u64::try_from(job) {
+        Ok(job) => job,
+        Err(_) => return "Error: job must be a positive job ID".to_string(),
+    };
+    match jobs::collect(job, wait.unwrap_or(false)) {
+        Ok(results) => match to_json(&results) {
+            Ok(data) => data,
+            Err(e) => format!("Error serializing download results: {e}"),
+        },
+        Err(e) => format!("Error: {e}"),
     }
 }
 
@@ -683,7 +1858,49 @@ fn gwas_files(
 // See corresponding C code in `entrypoint.c`.
 extendr_module! {
     mod iani;
+    fn gwas_configure;
+    fn gwas_progress;
+    fn gwas_set_verbose;
+    fn gwas_stats;
+    fn gwas_headers;
+    fn gwas_cassette;
     fn gwas_get;
+    fn gwas_raw;
+    fn gwas_follow;
+    fn gwas_study_info;
+    fn gwas_find_studies;
+    fn gwas_pgs;
     fn gwas_associations;
+    fn gwas_count;
+    fn gwas_liftover;
+    fn gwas_locus;
+    fn gwas_variant_in_study;
+    fn gwas_gene;
+    fn gwas_region;
+    fn gwas_clump;
+    fn gwas_ld;
+    fn gwas_nearest_gene;
+    fn gwas_enrichment;
+    fn gwas_annotate;
+    fn gwas_opentargets;
+    fn gwas_gene_evidence;
+    fn gwas_panel_scan;
+    fn gwas_prs;
+    fn gwas_munge;
+    fn gwas_index;
+    fn gwas_summary;
+    fn gwas_build_variant_filter;
+    fn gwas_has_variant;
+    fn gwas_study_similarity;
     fn gwas_files;
+    fn gwas_download_async;
+    fn gwas_job_status;
+    fn gwas_job_collect;
+    fn gwas_catalog_index;
+    fn gwas_sync_studies;
+    fn gwas_all_traits;
+    fn gwas_match_trait;
+    fn gwas_trait_studies;
+    fn gwas_ftp_manifest;
+    fn gwas_deposit;
 }